@@ -0,0 +1,92 @@
+use bedrock_build::{BuildInfoConfig, BuildInfoField, FieldPolicy, TimestampSource};
+use build_info_common::chrono::{DateTime, NaiveDate};
+use build_info_common::semver::Version;
+use build_info_common::{
+    BuildInfo, CompilerChannel, CompilerInfo, CpuInfo, CrateInfo, Endianness, GitInfo,
+    OptimizationLevel, TargetInfo, VersionControl,
+};
+use std::path::PathBuf;
+use std::{env, fs};
+
+fn main() {
+    let lint = env::var_os("CARGO_FEATURE_LINT").is_some();
+    let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    fs::copy("memory.x", out.join("memory.x")).unwrap();
+    // the RAM_LINK=1 linker script includes the interrupt vectors of a PAC, there are none here
+    fs::write(out.join("device.x"), "").unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+    println!("cargo:rerun-if-changed=memory.x");
+    println!("cargo:rerun-if-changed=memory_lint.x");
+
+    // fixed instead of collected by build-info-build, so that a rebuild gives the same build info
+    let mut config = BuildInfoConfig::new()
+        .field(BuildInfoField::Sources, FieldPolicy::Omit)
+        .timestamp(TimestampSource::BuildTime);
+    if lint {
+        // no defmt.x, memory regions in build info that the firmware is not linked for, and the stack over statics
+        println!("cargo:rustc-link-arg=-Tlink.x");
+        println!("cargo:rustc-link-arg=--nmagic");
+        println!("cargo:rustc-link-arg=--defsym=_stack_end=0x20000000");
+        config = config.memory_x("memory_lint.x");
+    } else {
+        bedrock_build::common();
+    }
+    match bedrock_build::serialize_build_info_with_config(build_info(), config) {
+        Ok(info) => fs::write(out.join("build_info.rs"), info).unwrap(),
+        Err(e) => e.report(),
+    }
+}
+
+fn crate_info(name: &str, version: Version, dependencies: Vec<CrateInfo>) -> CrateInfo {
+    CrateInfo {
+        name: name.into(),
+        version,
+        authors: vec![],
+        license: Some("MIT".into()),
+        enabled_features: vec![],
+        available_features: vec![],
+        dependencies,
+    }
+}
+
+fn build_info() -> BuildInfo {
+    BuildInfo {
+        timestamp: DateTime::from_timestamp(1_760_000_000, 0).unwrap(),
+        profile: "debug".into(),
+        optimization_level: OptimizationLevel::Os,
+        crate_info: crate_info(
+            "fixture",
+            Version::new(0, 1, 0),
+            vec![
+                crate_info("bedrock_rt", Version::new(0, 1, 0), vec![]),
+                crate_info("cnt", Version::new(0, 1, 0), vec![]),
+            ],
+        ),
+        target: TargetInfo {
+            triple: "thumbv7em-none-eabihf".into(),
+            family: "".into(),
+            os: "none".into(),
+            cpu: CpuInfo {
+                arch: "arm".into(),
+                pointer_width: 32,
+                endianness: Endianness::Little,
+                features: vec![],
+            },
+        },
+        compiler: CompilerInfo {
+            version: Version::new(1, 90, 0),
+            commit_id: None,
+            commit_date: NaiveDate::from_ymd_opt(2025, 9, 14),
+            channel: CompilerChannel::Stable,
+            host_triple: "x86_64-unknown-linux-gnu".into(),
+        },
+        version_control: Some(VersionControl::Git(GitInfo {
+            commit_id: "0f1e2d3c4b5a69788796a5b4c3d2e1f00f1e2d3c".into(),
+            commit_short_id: "0f1e2d3".into(),
+            commit_timestamp: DateTime::from_timestamp(1_759_990_000, 0).unwrap(),
+            dirty: false,
+            branch: Some("main".into()),
+            tags: vec![],
+        })),
+    }
+}
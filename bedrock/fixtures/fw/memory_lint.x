@@ -0,0 +1,6 @@
+/* Read into the build info of the `lint` feature, the firmware is linked with memory.x */
+MEMORY
+{
+  FLASH : ORIGIN = 0x08008000, LENGTH = 32K
+  RAM   : ORIGIN = 0x20000000, LENGTH = 16K
+}
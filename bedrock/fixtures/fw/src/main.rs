@@ -0,0 +1,85 @@
+//! Firmware analyzed by the `bedrock` tests, see `../README.md`.
+
+#![no_std]
+#![no_main]
+
+use bedrock_rt as _;
+use cnt::cnt_if;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+mod build_info {
+    include!(concat!(env!("OUT_DIR"), "/build_info.rs"));
+}
+
+/// Nothing is mapped there, reading it is a precise BusFault
+const SENSOR: *const u32 = 0x3000_0000 as *const u32;
+
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+pub enum Mode {
+    Off,
+    Low,
+    High = 7,
+}
+
+pub struct Limits {
+    pub min: i16,
+    pub max: i16,
+}
+
+pub struct Config {
+    pub mode: Mode,
+    pub enabled: bool,
+    pub gain: f32,
+    pub limits: [Limits; 3],
+}
+
+#[unsafe(no_mangle)]
+pub static mut CONFIG: Config = Config {
+    mode: Mode::Low,
+    enabled: true,
+    gain: 1.5,
+    limits: [
+        Limits { min: -10, max: 10 },
+        Limits { min: 0, max: 100 },
+        Limits {
+            min: -500,
+            max: 500,
+        },
+    ],
+};
+
+#[unsafe(no_mangle)]
+pub static SAMPLES: AtomicU32 = AtomicU32::new(0);
+
+/// Constant in FLASH
+#[unsafe(no_mangle)]
+pub static CALIBRATION: [u16; 4] = [100, 200, 300, 400];
+
+#[inline(never)]
+fn read_sensor(address: *const u32) -> u32 {
+    let raw = unsafe { address.read_volatile() };
+    raw.wrapping_mul(CALIBRATION[raw as usize & 3] as u32)
+}
+
+#[cortex_m_rt::entry]
+fn main() -> ! {
+    _ = bedrock_rt::fault::take_last();
+    bedrock_rt::faults::enable(bedrock_rt::faults::Traps::default());
+    if cfg!(not(feature = "no-compact-info")) {
+        _ = core::hint::black_box(build_info::compact());
+    }
+    _ = core::hint::black_box(build_info::full());
+    loop {
+        let config = unsafe { &*core::hint::black_box(&raw const CONFIG) };
+        cnt_if!(config.enabled, samples_taken: u32);
+        let value = read_sensor(core::hint::black_box(SENSOR));
+        cnt_if!(value > config.limits[0].max as u32, over_limit: u64);
+        SAMPLES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,223 @@
+//! `.bedlog` capture files: raw RTT or UART log bytes tagged with the identity of the device and firmware that
+//! produced them, so that they can be decoded long after the device was reflashed.
+//!
+//! Layout, all integers little endian:
+//! ```text
+//! "BEDLOG" version:u8 source:u8
+//! fw_sha:[u8; 32]                  all zeros if not known
+//! uid_len:u8 uid                   MCU unique ID, empty if not known
+//! record_len:u16 record            compact build info record as read from the device, empty if not known
+//! (host_time_us:u64 len:u32 bytes)* chunks as they were received, until the end of file
+//! ```
+//! Chunks are appended as they arrive, so a capture cut short by a crash or power loss is still readable up to the
+//! last complete chunk.
+
+use crate::Error;
+use bedrock_build_info::BedrockBuildInfoOwned;
+use bedrock_build_info::envelope::CompactEnvelope;
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MAGIC: &[u8; 6] = b"BEDLOG";
+const VERSION: u8 = 1;
+const CHUNK_HEADER_LEN: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Rtt,
+    Uart,
+}
+
+/// Identity of the device and firmware the log came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub source: Source,
+    pub fw_sha: Option<[u8; 32]>,
+    pub uid: Vec<u8>,
+    /// Compact build info record, as in FLASH
+    pub record: Vec<u8>,
+}
+
+impl Header {
+    pub fn build_info(&self) -> Option<BedrockBuildInfoOwned> {
+        let envelope = CompactEnvelope::parse(&self.record).ok()?;
+        Some(envelope.decode().ok()?.make_owned())
+    }
+
+    fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        let source = match self.source {
+            Source::Rtt => 0,
+            Source::Uart => 1,
+        };
+        w.write_all(&[VERSION, source])?;
+        w.write_all(&self.fw_sha.unwrap_or_default())?;
+        let uid = &self.uid[..self.uid.len().min(u8::MAX as usize)];
+        w.write_all(&[uid.len() as u8])?;
+        w.write_all(uid)?;
+        let record = &self.record[..self.record.len().min(u16::MAX as usize)];
+        w.write_all(&(record.len() as u16).to_le_bytes())?;
+        w.write_all(record)
+    }
+}
+
+/// Bytes received at once
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub host_time: SystemTime,
+    pub bytes: Vec<u8>,
+}
+
+/// Appends received bytes to a capture file
+pub struct BedlogWriter<W: Write> {
+    w: W,
+}
+
+impl<W: Write> BedlogWriter<W> {
+    pub fn new(mut w: W, header: &Header) -> io::Result<Self> {
+        header.write_to(&mut w)?;
+        w.flush()?;
+        Ok(BedlogWriter { w })
+    }
+
+    pub fn write_chunk(&mut self, host_time: SystemTime, bytes: &[u8]) -> io::Result<()> {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        let time_us = host_time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        self.w.write_all(&time_us.to_le_bytes())?;
+        self.w.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        self.w.write_all(bytes)?;
+        self.w.flush()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bedlog {
+    pub header: Header,
+    pub chunks: Vec<Chunk>,
+    /// Capture ended in the middle of a chunk, the incomplete chunk is dropped
+    pub truncated: bool,
+}
+
+impl Bedlog {
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut r = Reader { bytes };
+        if r.take(MAGIC.len()) != Some(&MAGIC[..]) {
+            return Err(Error::Bedlog("not a .bedlog file".into()));
+        }
+        let header = r.header()?;
+        let mut chunks = Vec::new();
+        while r.bytes.len() >= CHUNK_HEADER_LEN {
+            let time_us = u64::from_le_bytes(r.array()?);
+            let len = u32::from_le_bytes(r.array()?) as usize;
+            let Some(bytes) = r.take(len) else {
+                return Ok(Bedlog {
+                    header,
+                    chunks,
+                    truncated: true,
+                });
+            };
+            chunks.push(Chunk {
+                host_time: UNIX_EPOCH + Duration::from_micros(time_us),
+                bytes: bytes.to_vec(),
+            });
+        }
+        Ok(Bedlog {
+            header,
+            chunks,
+            truncated: !r.bytes.is_empty(),
+        })
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<Vec<u8>, Error> {
+        self.take(len)
+            .map(|bytes| bytes.to_vec())
+            .ok_or_else(|| Error::Bedlog("truncated".into()))
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        self.take(N)
+            .map(|bytes| bytes.try_into().unwrap())
+            .ok_or_else(|| Error::Bedlog("truncated".into()))
+    }
+
+    fn header(&mut self) -> Result<Header, Error> {
+        let [version, source] = self.array()?;
+        if version != VERSION {
+            return Err(Error::Bedlog(format!(
+                "format version {version} is not supported, update bedrock"
+            )));
+        }
+        let source = match source {
+            0 => Source::Rtt,
+            1 => Source::Uart,
+            _ => return Err(Error::Bedlog(format!("unknown source {source}"))),
+        };
+        let fw_sha: [u8; 32] = self.array()?;
+        let [uid_len] = self.array()?;
+        let uid = self.bytes(uid_len as usize)?;
+        let record_len = u16::from_le_bytes(self.array()?);
+        let record = self.bytes(record_len as usize)?;
+        Ok(Header {
+            source,
+            fw_sha: (fw_sha != [0; 32]).then_some(fw_sha),
+            uid,
+            record,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    fn write_and_parse() {
+        let info = fixture::build_info("logger");
+        let header = Header {
+            source: Source::Rtt,
+            fw_sha: Some([0xAB; 32]),
+            uid: vec![1, 2, 3],
+            record: fixture::compact_record(&info),
+        };
+        let t0 = UNIX_EPOCH + Duration::from_micros(1_760_000_000_000_000);
+        let mut file = Vec::new();
+        let mut w = BedlogWriter::new(&mut file, &header).unwrap();
+        w.write_chunk(t0, b"first").unwrap();
+        w.write_chunk(t0 + Duration::from_millis(5), b"").unwrap();
+        w.write_chunk(t0 + Duration::from_millis(10), b"second")
+            .unwrap();
+
+        let log = Bedlog::parse(&file).unwrap();
+        assert_eq!(log.header, header);
+        assert_eq!(log.header.build_info(), Some(info));
+        assert_eq!(log.chunks.len(), 2);
+        assert_eq!(log.chunks[1].host_time, t0 + Duration::from_millis(10));
+        assert_eq!(log.chunks[1].bytes, b"second");
+        assert!(!log.truncated);
+
+        let log = Bedlog::parse(&file[..file.len() - 3]).unwrap();
+        assert_eq!(log.chunks.len(), 1);
+        assert!(log.truncated);
+    }
+}
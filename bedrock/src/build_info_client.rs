@@ -0,0 +1,101 @@
+//! Host side of the [BuildInfoServer](bedrock_build_info::server::BuildInfoServer), asks a running firmware
+//! for its build info over any wire_weaver transport, no probe required.
+//!
+//! The client is generated by `ww_api!` from the same trait as the server. Requests go to the transport worker through
+//! `cmd_tx`, e.g. the one created by `wire_weaver_usb_host`.
+
+use bedrock_build_info::{BedrockBuildInfo, BedrockBuildInfoOwned};
+use std::time::Duration;
+use wire_weaver::prelude::*;
+use wire_weaver_client_common::CommandSender;
+
+pub struct BuildInfoClient {
+    args_scratch: [u8; 64],
+    cmd_tx: CommandSender,
+    timeout: Duration,
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    Transport(wire_weaver_client_common::Error),
+    /// Response could not be deserialized
+    Malformed,
+}
+
+impl BuildInfoClient {
+    pub fn new(cmd_tx: CommandSender) -> Self {
+        BuildInfoClient {
+            args_scratch: [0u8; 64],
+            cmd_tx,
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Request and decode compact build info
+    pub async fn compact_info(&mut self) -> Result<BedrockBuildInfoOwned, ClientError> {
+        let bytes = self.root().compact().await.map_err(ClientError::Transport)?;
+        let info = BedrockBuildInfo::from_ww_bytes(&bytes).map_err(|_| ClientError::Malformed)?;
+        Ok(info.make_owned())
+    }
+}
+
+ww_api!(
+    "../bedrock_build_info/src/traits.rs" as bedrock_build_info::BuildInfo for BuildInfoClient,
+    client = "async_worker",
+    no_alloc = false,
+    use_async = true,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bedrock_build_info::server::BuildInfoServer;
+    use tokio::sync::mpsc;
+    use wire_weaver::WireWeaverAsyncApiBackend;
+    use wire_weaver_client_common::Command;
+    use wire_weaver_client_common::rx_dispatcher::DispatcherMessage;
+
+    static COMPACT: &[u8] = include_bytes!("../../bedrock_build_info/fixtures/compact_v1.bin");
+    static FW_SHA: [u8; 32] = [0xAB; 32];
+
+    /// In-memory transport worker, passes requests straight to the server
+    fn loopback(mut server: BuildInfoServer) -> CommandSender {
+        let (transport_tx, mut transport_rx) = mpsc::unbounded_channel();
+        let (dispatcher_tx, dispatcher_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut args = [0u8; 256];
+            let mut event = [0u8; 256];
+            let mut err = [0u8; 32];
+            _ = dispatcher_tx.send(DispatcherMessage::Connected);
+            while let Some(command) = transport_rx.recv().await {
+                let Command::SendMessage { bytes } = command else {
+                    continue;
+                };
+                let response = server
+                    .process_bytes(&bytes, &mut args, &mut event, &mut err)
+                    .await
+                    .unwrap();
+                _ = dispatcher_tx.send(DispatcherMessage::MessageBytes(response.to_vec()));
+            }
+        });
+        CommandSender::new(transport_tx, dispatcher_rx)
+    }
+
+    #[tokio::test]
+    async fn loopback_end_to_end() {
+        let compact = &COMPACT[bedrock_build_info::envelope::VERSIONED_HEADER_LEN..];
+        let server = BuildInfoServer::new(compact).with_fw_sha(FW_SHA);
+        let mut client = BuildInfoClient::new(loopback(server));
+
+        assert_eq!(client.root().compact().await.unwrap(), compact);
+        let info = client.compact_info().await.unwrap();
+        assert_eq!(info.crate_info.name, "awesome");
+        assert_eq!(client.root().fw_sha().await.unwrap(), Some(FW_SHA.to_vec()));
+        assert_eq!(client.root().full().await.unwrap(), None);
+    }
+}
@@ -0,0 +1,676 @@
+//! Cortex-M core and chip family specifics: chip identification, read protection level and reset cause registers.
+
+use crate::Error;
+use crate::compact_info::flash_ranges;
+use crate::mem::Memory;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+/// CPUID base register
+pub const CPUID: u64 = 0xE000_ED00;
+/// Vector table offset register, not present on Armv6-M without the optional extension
+pub const VTOR: u64 = 0xE000_ED08;
+
+/// Cortex-M core, as identified from CPUID
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CoreType {
+    pub name: &'static str,
+    /// Target triple architectures this core can run, e.g. `thumbv7em`
+    pub archs: &'static [&'static str],
+}
+
+const ARMV6M: &[&str] = &["thumbv6m"];
+const ARMV7M: &[&str] = &["thumbv7m", "thumbv6m"];
+const ARMV7EM: &[&str] = &["thumbv7em", "thumbv7m", "thumbv6m"];
+const ARMV8M_BASE: &[&str] = &["thumbv8m.base", "thumbv6m"];
+const ARMV8M_MAIN: &[&str] = &[
+    "thumbv8m.main",
+    "thumbv8m.base",
+    "thumbv7em",
+    "thumbv7m",
+    "thumbv6m",
+];
+
+impl CoreType {
+    pub fn from_cpuid(cpuid: u32) -> Option<Self> {
+        let implementer = cpuid >> 24;
+        let part_no = (cpuid >> 4) & 0xFFF;
+        if implementer != 0x41 {
+            return None;
+        }
+        let (name, archs) = match part_no {
+            0xC20 => ("Cortex-M0", ARMV6M),
+            0xC60 => ("Cortex-M0+", ARMV6M),
+            0xC21 => ("Cortex-M1", ARMV6M),
+            0xC23 => ("Cortex-M3", ARMV7M),
+            0xC24 => ("Cortex-M4", ARMV7EM),
+            0xC27 => ("Cortex-M7", ARMV7EM),
+            0xD20 => ("Cortex-M23", ARMV8M_BASE),
+            0xD21 => ("Cortex-M33", ARMV8M_MAIN),
+            0xD31 => ("Cortex-M35P", ARMV8M_MAIN),
+            0xD22 => ("Cortex-M55", ARMV8M_MAIN),
+            0xD23 => ("Cortex-M85", ARMV8M_MAIN),
+            _ => return None,
+        };
+        Some(CoreType { name, archs })
+    }
+
+    pub fn read(mem: &mut impl Memory) -> Result<Option<Self>, Error> {
+        Ok(Self::from_cpuid(mem.read_u32(CPUID)?))
+    }
+
+    /// Whether code built for a target triple (e.g. `thumbv7em-none-eabihf`) runs on this core
+    pub fn runs(&self, triple: &str) -> bool {
+        let arch = triple.split('-').next().unwrap_or(triple);
+        self.archs.contains(&arch)
+    }
+
+    pub fn is_armv6m(&self) -> bool {
+        self.archs[0] == "thumbv6m"
+    }
+
+    pub fn is_armv8m(&self) -> bool {
+        self.archs[0].starts_with("thumbv8m")
+    }
+
+    /// Whether the probe-rs target has this core and no other, e.g. STM32H743 and not the dual core STM32H745
+    pub fn is_only_core_of(&self, target: &probe_rs::Target) -> bool {
+        let core_type = match self.archs[0] {
+            "thumbv6m" => probe_rs::CoreType::Armv6m,
+            "thumbv7m" => probe_rs::CoreType::Armv7m,
+            "thumbv7em" => probe_rs::CoreType::Armv7em,
+            _ => probe_rs::CoreType::Armv8m,
+        };
+        matches!(target.cores.as_slice(), [core] if core.core_type == core_type)
+    }
+
+    /// MemManage, BusFault and UsageFault with their status registers, Armv6-M and Armv8-M Baseline only have HardFault
+    pub fn has_configurable_faults(&self) -> bool {
+        !matches!(self.archs[0], "thumbv6m" | "thumbv8m.base")
+    }
+}
+
+/// STM32 product line sharing a DBGMCU DEV_ID
+struct Stm32Line {
+    dev_id: u16,
+    /// Common prefix of the part numbers, as in probe-rs target names
+    part: &'static str,
+    /// Narrower prefixes, if `part` is shared with other lines
+    targets: &'static [&'static str],
+    /// FLASH size in KiB, u16
+    flash_size: u64,
+    /// 96-bit unique device ID
+    uid: u64,
+    family: Option<Family>,
+}
+
+const fn line(
+    dev_id: u16,
+    part: &'static str,
+    flash_size: u64,
+    uid: u64,
+    family: Option<Family>,
+) -> Stm32Line {
+    Stm32Line {
+        dev_id,
+        part,
+        targets: &[],
+        flash_size,
+        uid,
+        family,
+    }
+}
+
+impl Stm32Line {
+    const fn targets(self, targets: &'static [&'static str]) -> Self {
+        Stm32Line { targets, ..self }
+    }
+}
+
+const F0: (u64, u64) = (0x1FFF_F7CC, 0x1FFF_F7AC);
+const F1: (u64, u64) = (0x1FFF_F7E0, 0x1FFF_F7E8);
+const F4: (u64, u64) = (0x1FFF_7A22, 0x1FFF_7A10);
+const F7: (u64, u64) = (0x1FF0_F442, 0x1FF0_F420);
+const H7: (u64, u64) = (0x1FF1_E880, 0x1FF1_E800);
+const G4: (u64, u64) = (0x1FFF_75E0, 0x1FFF_7590);
+const L0: (u64, u64) = (0x1FF8_007C, 0x1FF8_0050);
+
+const STM32_LINES: &[Stm32Line] = &[
+    line(0x440, "STM32F05", F0.0, F0.1, None),
+    line(0x444, "STM32F03", F0.0, F0.1, None),
+    line(0x445, "STM32F04", F0.0, F0.1, None),
+    line(0x448, "STM32F07", F0.0, F0.1, None),
+    line(0x410, "STM32F10", F1.0, F1.1, None),
+    line(0x414, "STM32F10", F1.0, F1.1, None),
+    line(0x422, "STM32F30", F0.0, F0.1, None),
+    line(0x438, "STM32F33", F0.0, F0.1, None),
+    line(0x411, "STM32F2", F4.0, F4.1, Some(Family::Stm32F4)),
+    line(0x413, "STM32F40", F4.0, F4.1, Some(Family::Stm32F4)),
+    line(0x419, "STM32F42", F4.0, F4.1, Some(Family::Stm32F4)),
+    line(0x423, "STM32F401", F4.0, F4.1, Some(Family::Stm32F4)),
+    line(0x433, "STM32F401", F4.0, F4.1, Some(Family::Stm32F4)),
+    line(0x431, "STM32F411", F4.0, F4.1, Some(Family::Stm32F4)),
+    line(0x441, "STM32F412", F4.0, F4.1, Some(Family::Stm32F4)),
+    line(0x421, "STM32F446", F4.0, F4.1, Some(Family::Stm32F4)),
+    line(0x434, "STM32F469", F4.0, F4.1, Some(Family::Stm32F4)),
+    line(0x463, "STM32F413", F4.0, F4.1, Some(Family::Stm32F4)),
+    line(0x449, "STM32F74", F7.0, F7.1, Some(Family::Stm32F4)),
+    line(0x451, "STM32F76", F7.0, F7.1, Some(Family::Stm32F4)),
+    line(
+        0x452,
+        "STM32F72",
+        0x1FF0_7A22,
+        0x1FF0_7A10,
+        Some(Family::Stm32F4),
+    ),
+    line(0x450, "STM32H7", H7.0, H7.1, Some(Family::Stm32H7)).targets(&["STM32H74", "STM32H75"]),
+    line(0x483, "STM32H72", H7.0, H7.1, Some(Family::Stm32H7)),
+    line(
+        0x480,
+        "STM32H7A",
+        0x08FF_F80C,
+        0x08FF_F800,
+        Some(Family::Stm32H7),
+    ),
+    line(0x415, "STM32L47", G4.0, G4.1, Some(Family::Stm32L4)),
+    line(0x435, "STM32L43", G4.0, G4.1, Some(Family::Stm32L4)),
+    line(0x462, "STM32L45", G4.0, G4.1, Some(Family::Stm32L4)),
+    line(0x464, "STM32L41", G4.0, G4.1, Some(Family::Stm32L4)),
+    line(0x461, "STM32L49", G4.0, G4.1, Some(Family::Stm32L4)),
+    line(0x470, "STM32L4R", G4.0, G4.1, Some(Family::Stm32L4)),
+    line(0x468, "STM32G43", G4.0, G4.1, Some(Family::Stm32L4)),
+    line(0x469, "STM32G47", G4.0, G4.1, Some(Family::Stm32L4)),
+    line(0x479, "STM32G49", G4.0, G4.1, Some(Family::Stm32L4)),
+    line(0x460, "STM32G07", G4.0, G4.1, None),
+    line(0x466, "STM32G03", G4.0, G4.1, None),
+    line(0x467, "STM32G0B", G4.0, G4.1, None),
+    line(0x417, "STM32L05", L0.0, L0.1, None),
+    line(0x447, "STM32L07", L0.0, L0.1, None),
+    line(0x495, "STM32WB55", G4.0, G4.1, None),
+];
+
+/// STM32 FLASH is always at this address, bank 2 follows bank 1
+const STM32_FLASH: u64 = 0x0800_0000;
+/// DBGMCU_IDCODE: Armv7-M parts, H7, Armv6-M parts, Armv8-M parts
+const STM32_IDCODE: [u64; 4] = [0xE004_2000, 0x5C00_1000, 0x4001_5800, 0xE004_4000];
+
+/// nRF52 factory information configuration registers
+const NRF_FICR: u64 = 0x1000_0000;
+/// RP2040 SYSINFO.CHIP_ID
+const RP2040_CHIP_ID: u64 = 0x4000_0000;
+
+/// Chip identified from its ID registers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChipId {
+    pub core: Option<CoreType>,
+    /// Part number, or the beginning of it shared by the product line, as in probe-rs target names,
+    /// e.g. `STM32H7`, `nRF52840_xxAA` or `RP2040`
+    pub part: String,
+    /// Beginnings of the probe-rs target names of the parts with this ID, where `part` matches other lines too
+    pub targets: &'static [&'static str],
+    pub family: Option<Family>,
+    /// Silicon revision
+    pub revision: u32,
+    /// On-chip FLASH, None if it is external, as on RP2040
+    pub flash: Option<Range<u64>>,
+    /// Unique device ID, empty if the chip does not have one
+    pub uid: Vec<u8>,
+}
+
+impl ChipId {
+    /// probe-rs target of the part with the same FLASH size. Parts of a line that only differ in package
+    /// and RAM size are good enough for flashing and memory access, so the first one matching is taken.
+    ///
+    /// Parts with only the identified core are preferred, so a single core STM32H743 is not mistaken for the dual core
+    /// STM32H745 sharing its DEV_ID. None if probe-rs does not know the chip, an error if the matching parts still
+    /// differ in cores or FLASH algorithms, `--chip` has to tell then.
+    pub fn probe_rs_target(&self) -> Result<Option<probe_rs::Target>, Error> {
+        let registry = probe_rs::config::Registry::from_builtin_families();
+        if let Ok(target) = registry.get_target_by_name(&self.part) {
+            return Ok(Some(target));
+        }
+        let prefixes = match self.targets {
+            [] => &[self.part.as_str()][..],
+            targets => targets,
+        };
+        let mut candidates: Vec<_> = prefixes
+            .iter()
+            .flat_map(|prefix| registry.search_chips(prefix))
+            .filter_map(|name| registry.get_target_by_name(name).ok())
+            .filter(|target| {
+                self.flash.as_ref().is_none_or(|flash| {
+                    contiguous_size(&flash_ranges(target), flash.start) == flash.end - flash.start
+                })
+            })
+            .collect();
+        if let Some(core) = self.core
+            && candidates.iter().any(|t| core.is_only_core_of(t))
+        {
+            candidates.retain(|t| core.is_only_core_of(t));
+        }
+        let Some(first) = candidates.first() else {
+            return Ok(None);
+        };
+        let kind = |target: &probe_rs::Target| {
+            let cores: Vec<_> = target.cores.iter().map(|c| c.core_type).collect();
+            let algorithms: Vec<_> = target
+                .flash_algorithms
+                .iter()
+                .map(|a| a.name.clone())
+                .collect();
+            (cores, algorithms)
+        };
+        if let Some(other) = candidates.iter().find(|t| kind(t) != kind(first)) {
+            return Err(Error::AmbiguousChip {
+                chip: self.to_string(),
+                targets: [first.name.clone(), other.name.clone()],
+            });
+        }
+        Ok(Some(first.clone()))
+    }
+}
+
+/// Size of the NVM ranges that follow each other from `start`, on-chip FLASH without external memories,
+/// OTP and option bytes that probe-rs targets also list
+fn contiguous_size(ranges: &[Range<u64>], start: u64) -> u64 {
+    let mut end = start;
+    while let Some(next) = ranges.iter().find(|r| r.start == end && r.end > end) {
+        end = next.end;
+    }
+    end - start
+}
+
+impl Display for ChipId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} rev 0x{:x}", self.part, self.revision)?;
+        if let Some(core) = &self.core {
+            write!(f, ", {}", core.name)?;
+        }
+        if let Some(flash) = &self.flash {
+            write!(f, ", {} KiB FLASH", (flash.end - flash.start) / 1024)?;
+        }
+        if !self.uid.is_empty() {
+            write!(f, ", UID {}", hex::encode(&self.uid))?;
+        }
+        Ok(())
+    }
+}
+
+/// Identify the chip from DBGMCU IDCODE, FLASH size and UID registers on STM32, FICR on nRF52 and SYSINFO on RP2040.
+///
+/// Works through a generic probe-rs target (e.g. `armv7em`), as only memory reads are needed. Registers that are
+/// not present on the chip are skipped, so the probe may report a bus fault for some of the reads.
+pub fn identify(mem: &mut impl Memory) -> Result<ChipId, Error> {
+    let core = CoreType::read(mem).ok().flatten();
+    if let Some(id) = identify_stm32(mem, core)? {
+        return Ok(id);
+    }
+    if let Ok(part) = mem.read_u32(NRF_FICR + 0x100)
+        && (0x52000..0x53000).contains(&part)
+    {
+        // INFO.VARIANT is ASCII, e.g. "AAD0", first two letters are the FLASH and RAM variant
+        let variant = mem.read_u32(NRF_FICR + 0x104)?.to_be_bytes();
+        let page_size = mem.read_u32(NRF_FICR + 0x10)? as u64;
+        let pages = mem.read_u32(NRF_FICR + 0x14)? as u64;
+        let mut uid = [0u8; 8];
+        mem.read(NRF_FICR + 0x60, &mut uid)?;
+        return Ok(ChipId {
+            core,
+            part: format!("nRF{part:x}_xx{}", String::from_utf8_lossy(&variant[..2])),
+            targets: &[],
+            family: Some(Family::Nrf52),
+            revision: u32::from_be_bytes(variant) & 0xFFFF,
+            flash: Some(0..page_size * pages),
+            uid: uid.to_vec(),
+        });
+    }
+    if let Ok(chip_id) = mem.read_u32(RP2040_CHIP_ID)
+        && chip_id & 0x0FFF_FFFF == 0x0000_2927
+    {
+        return Ok(ChipId {
+            core,
+            part: "RP2040".into(),
+            targets: &[],
+            family: Some(Family::Rp2040),
+            revision: chip_id >> 28,
+            flash: None,
+            uid: Vec::new(),
+        });
+    }
+    Err(Error::UnknownChip)
+}
+
+fn identify_stm32(mem: &mut impl Memory, core: Option<CoreType>) -> Result<Option<ChipId>, Error> {
+    for address in STM32_IDCODE {
+        let Ok(idcode) = mem.read_u32(address) else {
+            continue;
+        };
+        let dev_id = (idcode & 0xFFF) as u16;
+        let Some(line) = STM32_LINES.iter().find(|l| l.dev_id == dev_id) else {
+            continue;
+        };
+        let flash_kib = {
+            let mut buf = [0u8; 2];
+            mem.read(line.flash_size, &mut buf)?;
+            u16::from_le_bytes(buf) as u64
+        };
+        let mut uid = [0u8; 12];
+        mem.read(line.uid, &mut uid)?;
+        return Ok(Some(ChipId {
+            core,
+            part: line.part.into(),
+            targets: line.targets,
+            family: line.family,
+            revision: idcode >> 16,
+            flash: Some(STM32_FLASH..STM32_FLASH + flash_kib * 1024),
+            uid: uid.to_vec(),
+        }));
+    }
+    Ok(None)
+}
+
+/// Generic probe-rs target for a target triple, when the chip itself is not known
+pub fn generic_target(triple: &str) -> Option<&'static str> {
+    let arch = triple.split('-').next()?;
+    match arch {
+        "thumbv6m" => Some("armv6m"),
+        "thumbv7m" => Some("armv7m"),
+        "thumbv7em" => Some("armv7em"),
+        _ if arch.starts_with("thumbv8m") => Some("armv8m"),
+        _ => None,
+    }
+}
+
+/// Chip families with known option bytes and reset cause registers
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Family {
+    /// STM32F2, F4 and F7
+    Stm32F4,
+    Stm32H7,
+    /// STM32L4, L4+ and G4
+    Stm32L4,
+    Nrf52,
+    Rp2040,
+}
+
+impl Family {
+    /// Family from probe-rs target name, e.g. `STM32H743ZITx`
+    pub fn from_target_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_uppercase();
+        let family = match name.get(..7).unwrap_or(&name) {
+            "STM32F2" | "STM32F4" | "STM32F7" => Family::Stm32F4,
+            "STM32H7" => Family::Stm32H7,
+            "STM32L4" | "STM32G4" => Family::Stm32L4,
+            _ if name.starts_with("NRF52") => Family::Nrf52,
+            _ if name.starts_with("RP2040") => Family::Rp2040,
+            _ => return None,
+        };
+        Some(family)
+    }
+
+    /// Read protection level from option bytes, None if the family has no such concept
+    pub fn read_protection(&self, mem: &mut impl Memory) -> Result<Option<ReadProtection>, Error> {
+        let stm32_level = |rdp: u32| match rdp & 0xFF {
+            0xAA => ReadProtection::Level0,
+            0xCC => ReadProtection::Level2,
+            _ => ReadProtection::Level1,
+        };
+        let level = match self {
+            // FLASH_OPTCR
+            Family::Stm32F4 => stm32_level(mem.read_u32(0x4002_3C14)? >> 8),
+            // FLASH_OPTSR_CUR
+            Family::Stm32H7 => stm32_level(mem.read_u32(0x5200_201C)? >> 8),
+            // FLASH_OPTR
+            Family::Stm32L4 => stm32_level(mem.read_u32(0x4002_2020)?),
+            // UICR.APPROTECT, erased or HwDisabled
+            Family::Nrf52 => match mem.read_u32(0x1000_1208)? {
+                0xFFFF_FFFF | 0x5A => ReadProtection::Level0,
+                _ => ReadProtection::Level1,
+            },
+            Family::Rp2040 => return Ok(None),
+        };
+        Ok(Some(level))
+    }
+
+    /// Set reset cause flags. STM32 flags accumulate until firmware clears them with RMVF, so several can be set.
+    pub fn reset_cause(&self, mem: &mut impl Memory) -> Result<Vec<ResetCause>, Error> {
+        use ResetCause::*;
+        let (address, flags): (u64, &[(u32, ResetCause)]) = match self {
+            // RCC_CSR
+            Family::Stm32F4 => (
+                0x4002_3874,
+                &[
+                    (25, BrownOut),
+                    (26, Pin),
+                    (27, PowerOn),
+                    (28, Software),
+                    (29, IndependentWatchdog),
+                    (30, WindowWatchdog),
+                    (31, LowPower),
+                ],
+            ),
+            // RCC_RSR
+            Family::Stm32H7 => (
+                0x5802_44D0,
+                &[
+                    (17, CpuReset),
+                    (21, BrownOut),
+                    (22, Pin),
+                    (23, PowerOn),
+                    (24, Software),
+                    (26, IndependentWatchdog),
+                    (28, WindowWatchdog),
+                    (30, LowPower),
+                ],
+            ),
+            // RCC_CSR
+            Family::Stm32L4 => (
+                0x4002_1094,
+                &[
+                    (24, Firewall),
+                    (25, OptionBytes),
+                    (26, Pin),
+                    (27, BrownOut),
+                    (28, Software),
+                    (29, IndependentWatchdog),
+                    (30, WindowWatchdog),
+                    (31, LowPower),
+                ],
+            ),
+            // POWER.RESETREAS, all zeros after power-on
+            Family::Nrf52 => (
+                0x4000_0400,
+                &[
+                    (0, Pin),
+                    (1, IndependentWatchdog),
+                    (2, Software),
+                    (3, Lockup),
+                    (16, WakeUp),
+                    (17, WakeUp),
+                    (18, Debugger),
+                    (19, WakeUp),
+                ],
+            ),
+            // VREG_AND_CHIP_RESET.CHIP_RESET
+            Family::Rp2040 => (0x4006_4008, &[(8, PowerOn), (16, Pin), (20, Debugger)]),
+        };
+        let value = mem.read_u32(address)?;
+        let mut causes: Vec<ResetCause> = flags
+            .iter()
+            .filter(|(bit, _)| value & (1 << bit) != 0)
+            .map(|(_, cause)| *cause)
+            .collect();
+        causes.dedup();
+        if causes.is_empty() && *self == Family::Nrf52 {
+            causes.push(PowerOn);
+        }
+        Ok(causes)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReadProtection {
+    /// Debugger has full access
+    Level0,
+    /// FLASH is not readable by the debugger, regression to level 0 erases it
+    Level1,
+    /// Debug port is permanently disabled
+    Level2,
+}
+
+impl Display for ReadProtection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadProtection::Level0 => write!(f, "level 0, no protection"),
+            ReadProtection::Level1 => write!(f, "level 1, FLASH read out protected"),
+            ReadProtection::Level2 => write!(f, "level 2, debug permanently disabled"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResetCause {
+    PowerOn,
+    BrownOut,
+    Pin,
+    Software,
+    IndependentWatchdog,
+    WindowWatchdog,
+    LowPower,
+    Lockup,
+    CpuReset,
+    Firewall,
+    OptionBytes,
+    WakeUp,
+    Debugger,
+}
+
+impl ResetCause {
+    /// Resets that point at a firmware problem
+    pub fn is_abnormal(&self) -> bool {
+        matches!(
+            self,
+            ResetCause::BrownOut
+                | ResetCause::IndependentWatchdog
+                | ResetCause::WindowWatchdog
+                | ResetCause::Lockup
+                | ResetCause::Firewall
+        )
+    }
+}
+
+impl Display for ResetCause {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ResetCause::PowerOn => "power-on",
+            ResetCause::BrownOut => "brown-out",
+            ResetCause::Pin => "reset pin",
+            ResetCause::Software => "software",
+            ResetCause::IndependentWatchdog => "independent watchdog",
+            ResetCause::WindowWatchdog => "window watchdog",
+            ResetCause::LowPower => "low power management",
+            ResetCause::Lockup => "core lockup",
+            ResetCause::CpuReset => "CPU only reset",
+            ResetCause::Firewall => "firewall",
+            ResetCause::OptionBytes => "option bytes loading",
+            ResetCause::WakeUp => "wake-up from system OFF",
+            ResetCause::Debugger => "debugger",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::MemoryDump;
+
+    fn words(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn stm32h743() {
+        // recorded from a NUCLEO-H743ZI2, rev V
+        let mut mem = MemoryDump::new()
+            .with_region(CPUID, words(&[0x411F_C271]))
+            .with_region(0x5C00_1000, words(&[0x2003_6450]))
+            .with_region(0x1FF1_E800, words(&[0x0032_0029, 0x3234_5118, 0x3935_3532]))
+            .with_region(0x1FF1_E880, words(&[0xFFFF_0800]));
+        let id = identify(&mut mem).unwrap();
+        assert_eq!(id.part, "STM32H7");
+        assert_eq!(id.family, Some(Family::Stm32H7));
+        assert_eq!(id.revision, 0x2003);
+        assert_eq!(id.core.unwrap().name, "Cortex-M7");
+        assert_eq!(id.flash, Some(0x0800_0000..0x0820_0000));
+        assert_eq!(hex::encode(&id.uid), "290032001851343232353539");
+        // the dual core STM32H745 has the same DEV_ID and FLASH size, but not a single Cortex-M7
+        let target = id.probe_rs_target().unwrap().unwrap();
+        assert!(target.name.starts_with("STM32H74"), "{}", target.name);
+        assert!(id.core.unwrap().is_only_core_of(&target));
+
+        // dual core parts can only be told apart by the user
+        let dual_core = ChipId { core: None, ..id };
+        let err = dual_core.probe_rs_target().unwrap_err();
+        assert!(matches!(err, Error::AmbiguousChip { .. }), "{err}");
+        assert!(err.to_string().ends_with("pass --chip"));
+    }
+
+    #[test]
+    fn stm32f411() {
+        // Black Pill, DBGMCU is at the Armv7-M address, H7 address is not mapped
+        let mut mem = MemoryDump::new()
+            .with_region(CPUID, words(&[0x410F_C241]))
+            .with_region(0xE004_2000, words(&[0x1000_6431]))
+            .with_region(0x1FFF_7A10, words(&[0x0041_0037, 0x3430_5113, 0x3333_3036]))
+            .with_region(0x1FFF_7A20, words(&[0x0200_FFFF]));
+        let id = identify(&mut mem).unwrap();
+        assert_eq!(id.part, "STM32F411");
+        assert_eq!(id.flash, Some(0x0800_0000..0x0808_0000));
+        let target = id.probe_rs_target().unwrap().unwrap();
+        assert!(target.name.starts_with("STM32F411CE"));
+    }
+
+    #[test]
+    fn flash_without_other_nvm() {
+        // STM32H743 banks, FMC and QSPI memories
+        let ranges = [
+            0x0800_0000..0x0810_0000,
+            0x0810_0000..0x0820_0000,
+            0x6000_0000..0x6400_0000,
+            0x9000_0000..0x9800_0000,
+        ];
+        assert_eq!(contiguous_size(&ranges, 0x0800_0000), 0x20_0000);
+        // nRF52840 FLASH and UICR
+        let ranges = [0..0x10_0000, 0x1000_1000..0x1000_2000];
+        assert_eq!(contiguous_size(&ranges, 0), 0x10_0000);
+        assert_eq!(contiguous_size(&ranges, 0x0800_0000), 0);
+    }
+
+    #[test]
+    fn nrf52840() {
+        let mut ficr = vec![0xFFu8; 0x110];
+        ficr[0x10..0x18].copy_from_slice(&words(&[0x1000, 0x100]));
+        ficr[0x60..0x68].copy_from_slice(&words(&[0x8A2B_7C51, 0x1D0E_4F93]));
+        ficr[0x100..0x108].copy_from_slice(&words(&[0x0005_2840, 0x4141_4430]));
+        let mut mem = MemoryDump::new()
+            .with_region(CPUID, words(&[0x410F_C241]))
+            .with_region(NRF_FICR, ficr);
+        let id = identify(&mut mem).unwrap();
+        assert_eq!(id.part, "nRF52840_xxAA");
+        assert_eq!(id.flash, Some(0..0x10_0000));
+        assert_eq!(id.uid.len(), 8);
+    }
+
+    #[test]
+    fn rp2040() {
+        let mut mem = MemoryDump::new()
+            .with_region(CPUID, words(&[0x410C_C601]))
+            .with_region(RP2040_CHIP_ID, words(&[0x2000_2927]));
+        let id = identify(&mut mem).unwrap();
+        assert_eq!(id.part, "RP2040");
+        assert_eq!(id.revision, 2);
+        assert_eq!(id.flash, None);
+        assert!(id.core.unwrap().is_armv6m());
+    }
+}
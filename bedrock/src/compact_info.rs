@@ -0,0 +1,167 @@
+//! Locating compact build info record in FLASH of a target or in an ELF file.
+
+use crate::Error;
+use crate::mem::Memory;
+use bedrock_build_info::BedrockBuildInfoOwned;
+use bedrock_build_info::envelope::{CompactEnvelope, EnvelopeError, EnvelopeLayout};
+use probe_rs::config::MemoryRegion;
+use std::ops::Range;
+
+const SEARCH_CHUNK_SIZE_B: usize = 512;
+
+/// Compact build info found in memory
+#[derive(Debug, Clone)]
+pub struct FoundCompactInfo {
+    /// Address of the record magic
+    pub address: u64,
+    /// Whole record, including header
+    pub record: Vec<u8>,
+    pub info: BedrockBuildInfoOwned,
+}
+
+/// Lazily read memory region, read chunks are kept for later inspection
+struct FlashMemory {
+    bytes: Vec<u8>,
+    base_addr: u64,
+    len: usize,
+}
+
+impl FlashMemory {
+    fn new(base_addr: u64, len: usize) -> Self {
+        Self {
+            bytes: Vec::with_capacity(len),
+            base_addr,
+            len,
+        }
+    }
+
+    /// Read next chunk, returns false if whole region is already read
+    fn read_next(&mut self, mem: &mut impl Memory) -> Result<bool, Error> {
+        let remaining = self.len - self.bytes.len();
+        if remaining == 0 {
+            return Ok(false);
+        }
+        let mut buf = [0u8; SEARCH_CHUNK_SIZE_B];
+        let buf = &mut buf[..remaining.min(SEARCH_CHUNK_SIZE_B)];
+        mem.read(self.base_addr + self.bytes.len() as u64, buf)?;
+        self.bytes.extend_from_slice(buf);
+        Ok(true)
+    }
+}
+
+/// Search `len` bytes starting from `start` for a compact build info record, reading memory in small chunks
+/// and stopping as soon as a valid record is found.
+pub fn find_compact_info(
+    mem: &mut impl Memory,
+    start: u64,
+    len: usize,
+) -> Result<FoundCompactInfo, Error> {
+    let mut flash = FlashMemory::new(start, len);
+    let mut search_from = 0;
+    while flash.read_next(mem)? {
+        while let Some(offset) = find_magic(&flash.bytes[search_from..]) {
+            let at = search_from + offset;
+            search_from = at + 1;
+            loop {
+                match CompactEnvelope::parse(&flash.bytes[at..]) {
+                    // do not read the whole FLASH because of a false positive
+                    Err(EnvelopeError::Truncated { needed }) if at + needed <= flash.len => {
+                        if !flash.read_next(mem)? {
+                            break;
+                        }
+                    }
+                    Ok(envelope) => {
+                        return Ok(FoundCompactInfo {
+                            address: start + at as u64,
+                            record: flash.bytes[at..at + envelope.total_len()].to_vec(),
+                            info: envelope.decode()?.make_owned(),
+                        });
+                    }
+                    Err(_) => break,
+                }
+            }
+        }
+        // magic can span two chunks
+        search_from = search_from.max(flash.bytes.len().saturating_sub(3));
+    }
+    Err(Error::BuildInfoNotFound)
+}
+
+/// Search several memory ranges in order, e.g. all FLASH banks of a chip, see [flash_ranges]
+pub fn find_compact_info_in_ranges(
+    mem: &mut impl Memory,
+    ranges: &[Range<u64>],
+) -> Result<FoundCompactInfo, Error> {
+    for range in ranges {
+        match find_compact_info(mem, range.start, (range.end - range.start) as usize) {
+            Err(Error::BuildInfoNotFound) => continue,
+            result => return result,
+        }
+    }
+    Err(Error::BuildInfoNotFound)
+}
+
+/// FLASH ranges of a chip, as known to probe-rs
+pub fn flash_ranges(target: &probe_rs::Target) -> Vec<Range<u64>> {
+    target
+        .memory_map
+        .iter()
+        .filter_map(|region| match region {
+            MemoryRegion::Nvm(nvm) => Some(nvm.range.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// RAM ranges of a chip, as known to probe-rs
+pub fn ram_ranges(target: &probe_rs::Target) -> Vec<Range<u64>> {
+    target
+        .memory_map
+        .iter()
+        .filter_map(|region| match region {
+            MemoryRegion::Ram(ram) => Some(ram.range.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Search a byte slice (e.g. ELF section contents) for a compact build info record, returns offset and the record.
+pub fn find_compact_info_in(bytes: &[u8]) -> Option<(usize, CompactEnvelope<'_>)> {
+    let mut search_from = 0;
+    while let Some(offset) = find_magic(&bytes[search_from..]) {
+        let at = search_from + offset;
+        if let Ok(envelope) = CompactEnvelope::parse(&bytes[at..]) {
+            return Some((at, envelope));
+        }
+        search_from = at + 1;
+    }
+    None
+}
+
+fn find_magic(bytes: &[u8]) -> Option<usize> {
+    bytes
+        .windows(4)
+        .position(|b| EnvelopeLayout::from_magic([b[0], b[1], b[2], b[3]]).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::MemoryDump;
+
+    #[test]
+    fn found_across_chunk_boundary() {
+        let record = include_bytes!("../../bedrock_build_info/fixtures/compact_v1_legacy.bin");
+        let mut flash = vec![0xFFu8; 4096];
+        // magic split between first and second chunk, and a false positive before it
+        flash[100..104].copy_from_slice(&record[..4]);
+        let at = SEARCH_CHUNK_SIZE_B - 2;
+        flash[at..at + record.len()].copy_from_slice(record);
+        let mut mem = MemoryDump::new().with_region(0x0800_0000, flash);
+
+        let found = find_compact_info(&mut mem, 0x0800_0000, 4096).unwrap();
+        assert_eq!(found.address, 0x0800_0000 + at as u64);
+        assert_eq!(found.record, record);
+        assert_eq!(found.info.crate_info.name, "awesome");
+    }
+}
@@ -0,0 +1,478 @@
+//! Core dumps: RAM, core and fault registers of a target saved to an ELF core file, to be analyzed offline with the
+//! same code as a live target, e.g. a field return weeks after it failed.
+//!
+//! Memory is stored as `PT_LOAD` segments, System Control Block registers included, so fault status is read from a
+//! dump the same way as from a target. A `PT_NOTE` segment holds notes named `BEDROCK`:
+//! ```text
+//! 1  firmware SHA-256, identifies the ELF in the local registry
+//! 2  compact build info record, as read from the device FLASH
+//! 3  core registers, u32 each: r0-r12, sp, lr, pc, xpsr, msp, psp, CONTROL|FAULTMASK|BASEPRI|PRIMASK
+//! ```
+
+use crate::Error;
+use crate::counters::{Storage, buffer_range, counters};
+use crate::elf::FirmwareElf;
+use crate::mem::{Memory, MemoryDump};
+use bedrock_build_info::envelope::CompactEnvelope;
+use bedrock_build_info::{BedrockBuildInfoOwned, RegionKind};
+use object::LittleEndian;
+use object::elf::{self, FileHeader32};
+use object::read::elf::{FileHeader, ProgramHeader};
+use probe_rs::{Core, RegisterId};
+use std::ops::Range;
+use std::time::Duration;
+
+const NOTE_NAME: &[u8] = b"BEDROCK\0";
+const NT_FW_SHA: u32 = 1;
+const NT_BUILD_INFO: u32 = 2;
+const NT_REGISTERS: u32 = 3;
+const FILE_HEADER_LEN: usize = 52;
+const PROGRAM_HEADER_LEN: usize = 32;
+
+/// Always captured if readable: System Control Block with fault status and address registers, Armv8-M security fault
+/// status and address, FPU context control and address for lazily stacked FP registers
+const SYSTEM_REGIONS: &[Range<u64>] = &[
+    0xE000_ED00..0xE000_ED40,
+    0xE000_EDE4..0xE000_EDEC,
+    0xE000_EF34..0xE000_EF3C,
+];
+
+/// Core register selectors in DCRSR, in the order of [Registers::words]
+const REGISTER_IDS: [u16; 20] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 20,
+];
+
+/// Core registers, as they were when the core was halted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Registers {
+    /// r0-r12, sp, lr, pc
+    pub r: [u32; 16],
+    pub xpsr: u32,
+    pub msp: u32,
+    pub psp: u32,
+    /// CONTROL, FAULTMASK, BASEPRI and PRIMASK, a byte each from the top
+    pub special: u32,
+}
+
+impl Registers {
+    pub fn sp(&self) -> u32 {
+        self.r[13]
+    }
+
+    pub fn lr(&self) -> u32 {
+        self.r[14]
+    }
+
+    pub fn pc(&self) -> u32 {
+        self.r[15]
+    }
+
+    /// Read from a halted core
+    pub fn read(core: &mut Core) -> Result<Self, Error> {
+        let mut words = [0u32; REGISTER_IDS.len()];
+        for (word, id) in words.iter_mut().zip(REGISTER_IDS) {
+            *word = core.read_core_reg(RegisterId(id))?;
+        }
+        Ok(Self::from_words(words))
+    }
+
+    fn words(&self) -> [u32; REGISTER_IDS.len()] {
+        let mut words = [0u32; REGISTER_IDS.len()];
+        words[..16].copy_from_slice(&self.r);
+        words[16..].copy_from_slice(&[self.xpsr, self.msp, self.psp, self.special]);
+        words
+    }
+
+    fn from_words(words: [u32; REGISTER_IDS.len()]) -> Self {
+        Registers {
+            r: words[..16].try_into().unwrap(),
+            xpsr: words[16],
+            msp: words[17],
+            psp: words[18],
+            special: words[19],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CoreDump {
+    pub memory: MemoryDump,
+    pub registers: Registers,
+    pub fw_sha: Option<[u8; 32]>,
+    /// Compact build info record, empty if not known
+    pub record: Vec<u8>,
+}
+
+impl CoreDump {
+    /// Halt the core, read registers and memory, and resume it if it was running. Also returns the regions that could
+    /// not be read, e.g. RAM with its clock disabled.
+    pub fn capture(
+        core: &mut Core,
+        regions: &[Range<u64>],
+        fw_sha: Option<[u8; 32]>,
+        record: Vec<u8>,
+    ) -> Result<(Self, Vec<Range<u64>>), Error> {
+        let was_running = !core.core_halted()?;
+        if was_running {
+            core.halt(Duration::from_millis(100))?;
+        }
+        let captured = Self::read_halted(core, regions);
+        if was_running {
+            core.run()?;
+        }
+        let (memory, registers, unreadable) = captured?;
+        let dump = CoreDump {
+            memory,
+            registers,
+            fw_sha,
+            record,
+        };
+        Ok((dump, unreadable))
+    }
+
+    fn read_halted(
+        core: &mut Core,
+        regions: &[Range<u64>],
+    ) -> Result<(MemoryDump, Registers, Vec<Range<u64>>), Error> {
+        let registers = Registers::read(core)?;
+        let mut memory = MemoryDump::new();
+        let mut unreadable = Vec::new();
+        for range in merge(regions.iter().chain(SYSTEM_REGIONS).cloned().collect()) {
+            let mut bytes = vec![0u8; (range.end - range.start) as usize];
+            match core.read(range.start, &mut bytes) {
+                Ok(()) => memory.add_region(range.start, bytes),
+                Err(_) if SYSTEM_REGIONS.contains(&range) => {}
+                Err(_) => unreadable.push(range),
+            }
+        }
+        Ok((memory, registers, unreadable))
+    }
+
+    pub fn build_info(&self) -> Option<BedrockBuildInfoOwned> {
+        let envelope = CompactEnvelope::parse(&self.record).ok()?;
+        Some(envelope.decode().ok()?.make_owned())
+    }
+
+    /// Dump memory over the loaded contents of the firmware, so constants in FLASH can be read as well
+    pub fn memory_with_firmware(&self, elf: &FirmwareElf) -> MemoryDump {
+        let mut memory = MemoryDump::new();
+        if let Some(image) = elf.flash_image() {
+            memory.add_region(image.start, image.bytes);
+        }
+        for region in self.memory.regions() {
+            memory.add_region(region.address, region.bytes.clone());
+        }
+        memory
+    }
+
+    pub fn to_elf(&self) -> Vec<u8> {
+        let mut notes = Vec::new();
+        if let Some(sha) = &self.fw_sha {
+            push_note(&mut notes, NT_FW_SHA, sha);
+        }
+        if !self.record.is_empty() {
+            push_note(&mut notes, NT_BUILD_INFO, &self.record);
+        }
+        let registers: Vec<u8> = self
+            .registers
+            .words()
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect();
+        push_note(&mut notes, NT_REGISTERS, &registers);
+
+        let regions = self.memory.regions();
+        let phnum = 1 + regions.len();
+        let mut offset = FILE_HEADER_LEN + PROGRAM_HEADER_LEN * phnum;
+        let mut out = Vec::new();
+        out.extend_from_slice(&[0x7F, b'E', b'L', b'F']);
+        out.extend_from_slice(&[elf::ELFCLASS32, elf::ELFDATA2LSB, elf::EV_CURRENT, 0]);
+        out.extend_from_slice(&[0; 8]);
+        push_u16(&mut out, elf::ET_CORE);
+        push_u16(&mut out, elf::EM_ARM);
+        push_u32(&mut out, elf::EV_CURRENT as u32);
+        // entry, program headers, section headers, flags
+        for word in [0, FILE_HEADER_LEN as u32, 0, 0] {
+            push_u32(&mut out, word);
+        }
+        for half in [FILE_HEADER_LEN, PROGRAM_HEADER_LEN, phnum, 0, 0, 0] {
+            push_u16(&mut out, half as u16);
+        }
+
+        let mut push_segment = |out: &mut Vec<u8>, p_type, address, len: usize, flags| {
+            for word in [
+                p_type,
+                offset as u32,
+                address,
+                address,
+                len as u32,
+                len as u32,
+                flags,
+                4,
+            ] {
+                push_u32(out, word);
+            }
+            offset += len;
+        };
+        push_segment(&mut out, elf::PT_NOTE, 0, notes.len(), elf::PF_R);
+        for region in regions {
+            let flags = elf::PF_R | elf::PF_W;
+            push_segment(
+                &mut out,
+                elf::PT_LOAD,
+                region.address as u32,
+                region.bytes.len(),
+                flags,
+            );
+        }
+        out.extend_from_slice(&notes);
+        for region in regions {
+            out.extend_from_slice(&region.bytes);
+        }
+        out
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        let header = FileHeader32::<LittleEndian>::parse(data)?;
+        let endian = header.endian()?;
+        if header.e_type(endian) != elf::ET_CORE || header.e_machine(endian) != elf::EM_ARM {
+            return Err(Error::CoreDump("not an ARM ELF core file".into()));
+        }
+        let mut dump = CoreDump {
+            memory: MemoryDump::new(),
+            registers: Registers::default(),
+            fw_sha: None,
+            record: Vec::new(),
+        };
+        let mut has_registers = false;
+        for segment in header.program_headers(endian, data)? {
+            let bytes = segment
+                .data(endian, data)
+                .map_err(|()| Error::CoreDump("segment out of file bounds".into()))?;
+            match segment.p_type(endian) {
+                elf::PT_LOAD => dump
+                    .memory
+                    .add_region(segment.p_vaddr(endian) as u64, bytes.to_vec()),
+                elf::PT_NOTE => {
+                    let Some(mut notes) = segment.notes(endian, data)? else {
+                        continue;
+                    };
+                    while let Some(note) = notes.next()? {
+                        if note.name_bytes() != NOTE_NAME {
+                            continue;
+                        }
+                        let desc = note.desc();
+                        match note.n_type(endian) {
+                            NT_FW_SHA => dump.fw_sha = desc.try_into().ok(),
+                            NT_BUILD_INFO => dump.record = desc.to_vec(),
+                            NT_REGISTERS if desc.len() == REGISTER_IDS.len() * 4 => {
+                                let mut words = [0u32; REGISTER_IDS.len()];
+                                for (word, bytes) in words.iter_mut().zip(desc.chunks(4)) {
+                                    *word = u32::from_le_bytes(bytes.try_into().unwrap());
+                                }
+                                dump.registers = Registers::from_words(words);
+                                has_registers = true;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !has_registers {
+            return Err(Error::CoreDump(
+                "no bedrock register note, not made by bedrock coredump?".into(),
+            ));
+        }
+        Ok(dump)
+    }
+}
+
+/// Memory to capture: RAM and register regions of the firmware memory map, or `target_ram` if it has none, with counter
+/// buffers and the RTT control block and buffers, if they are elsewhere
+pub fn capture_regions(
+    mem: &mut impl Memory,
+    elf: Option<&FirmwareElf>,
+    target_ram: &[Range<u64>],
+) -> Vec<Range<u64>> {
+    let info = elf.and_then(|elf| elf.full_info().or_else(|| elf.compact_info()));
+    let mut regions: Vec<Range<u64>> = info
+        .iter()
+        .flat_map(|info| info.memory_map())
+        .filter(|region| matches!(region.kind, RegionKind::Ram | RegionKind::Register))
+        .map(|region| region.origin as u64..region.end())
+        .collect();
+    if regions.is_empty() {
+        regions = target_ram.to_vec();
+    }
+    let Some(elf) = elf else {
+        return merge(regions);
+    };
+    let counters = counters(elf);
+    for storage in [Storage::Ram, Storage::Bkp] {
+        regions.extend(buffer_range(elf, &counters, storage));
+    }
+    if let Some(control_block) = elf.symbol_address("_SEGGER_RTT") {
+        let ram = merge(regions.clone());
+        regions.extend(rtt_ranges(mem, control_block, &ram).unwrap_or_default());
+    }
+    merge(regions)
+}
+
+/// RTT control block and the buffers of its channels.
+///
+/// Buffer pointers and sizes are read from the target and may be garbage, e.g. if the control block is not initialized
+/// yet, so buffers are clipped to `ram` and skipped if they are outside of it.
+pub fn rtt_ranges(
+    mem: &mut impl Memory,
+    control_block: u64,
+    ram: &[Range<u64>],
+) -> Result<Vec<Range<u64>>, Error> {
+    const MAX_CHANNELS: u32 = 16;
+    const BUFFER_DESCRIPTOR_LEN: u64 = 24;
+    let up = mem.read_u32(control_block + 16)?;
+    let down = mem.read_u32(control_block + 20)?;
+    if up + down > MAX_CHANNELS {
+        return Err(Error::CoreDump(format!(
+            "implausible RTT channel count {up} up and {down} down, not initialized?"
+        )));
+    }
+    let descriptors = control_block + 24;
+    let mut ranges = Vec::with_capacity(1 + (up + down) as usize);
+    ranges.push(control_block..descriptors + (up + down) as u64 * BUFFER_DESCRIPTOR_LEN);
+    for channel in 0..(up + down) as u64 {
+        let descriptor = descriptors + channel * BUFFER_DESCRIPTOR_LEN;
+        let buffer = mem.read_u32(descriptor + 4)? as u64;
+        let size = mem.read_u32(descriptor + 8)? as u64;
+        let buffer = buffer..buffer + size;
+        ranges.extend(
+            ram.iter()
+                .map(|ram| buffer.start.max(ram.start)..buffer.end.min(ram.end))
+                .filter(|clipped| !clipped.is_empty()),
+        );
+    }
+    Ok(ranges)
+}
+
+/// Sorted, with overlapping and adjacent ranges joined
+fn merge(mut ranges: Vec<Range<u64>>) -> Vec<Range<u64>> {
+    ranges.retain(|r| !r.is_empty());
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<u64>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+fn push_note(notes: &mut Vec<u8>, n_type: u32, desc: &[u8]) {
+    push_u32(notes, NOTE_NAME.len() as u32);
+    push_u32(notes, desc.len() as u32);
+    push_u32(notes, n_type);
+    for field in [NOTE_NAME, desc] {
+        notes.extend_from_slice(field);
+        notes.resize(notes.len().next_multiple_of(4), 0);
+    }
+}
+
+fn push_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+    use std::slice;
+
+    #[test]
+    fn write_and_parse() {
+        let mut memory = MemoryDump::new()
+            .with_region(0x2000_0000, (0..=255).collect())
+            .with_region(0xE000_ED00, vec![0x5A; 0x40]);
+        memory.add_region(0x4002_4000, vec![1, 2, 3]);
+        let mut registers = Registers::default();
+        registers.r[15] = 0x0800_1234;
+        registers.psp = 0x2000_0100;
+        let info = fixture::build_info("crashed");
+        let dump = CoreDump {
+            memory,
+            registers,
+            fw_sha: Some([0xAB; 32]),
+            record: fixture::compact_record(&info),
+        };
+
+        let file = dump.to_elf();
+        let mut parsed = CoreDump::parse(&file).unwrap();
+        assert_eq!(parsed.registers, registers);
+        assert_eq!(parsed.fw_sha, dump.fw_sha);
+        assert_eq!(parsed.build_info(), Some(info));
+        assert_eq!(parsed.memory.regions().len(), 3);
+        assert_eq!(parsed.memory.read_u32(0x2000_0010).unwrap(), 0x1312_1110);
+        assert_eq!(parsed.memory.read_u32(0xE000_ED28).unwrap(), 0x5A5A_5A5A);
+        let mut bytes = [0u8; 3];
+        parsed.memory.read(0x4002_4000, &mut bytes).unwrap();
+        assert_eq!(bytes, [1, 2, 3]);
+        assert!(CoreDump::parse(&file[..40]).is_err());
+    }
+
+    #[test]
+    fn rtt_and_merged_regions() {
+        let mut cb = b"SEGGER RTT\0\0\0\0\0\0".to_vec();
+        cb.extend([1u32, 1].iter().flat_map(|w| w.to_le_bytes()));
+        for descriptor in [
+            [0, 0x2000_1000, 1024, 0, 0, 0],
+            [0, 0x2000_2000, 16, 0, 0, 0],
+        ] {
+            cb.extend(descriptor.iter().flat_map(|w: &u32| w.to_le_bytes()));
+        }
+        let mut mem = MemoryDump::new().with_region(0x2000_0400, cb);
+        let ram = 0x2000_0000..0x2000_4000;
+        let ranges = rtt_ranges(&mut mem, 0x2000_0400, slice::from_ref(&ram)).unwrap();
+        assert_eq!(
+            ranges,
+            [
+                0x2000_0400..0x2000_0448,
+                0x2000_1000..0x2000_1400,
+                0x2000_2000..0x2000_2010
+            ]
+        );
+        assert_eq!(
+            merge(vec![
+                0x10..0x20,
+                0x0..0x8,
+                0x18..0x30,
+                0x30..0x34,
+                0x40..0x40
+            ]),
+            [0x0..0x8, 0x10..0x34]
+        );
+    }
+
+    #[test]
+    fn rtt_buffers_clipped_to_ram() {
+        let mut cb = b"SEGGER RTT\0\0\0\0\0\0".to_vec();
+        cb.extend([2u32, 1].iter().flat_map(|w| w.to_le_bytes()));
+        for descriptor in [
+            // not initialized, garbage pointer and size
+            [0, 0xFFFF_F000, 0xFFFF_FFFF, 0, 0, 0],
+            // runs past the end of RAM
+            [0, 0x2000_3F00, 1024, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0],
+        ] {
+            cb.extend(descriptor.iter().flat_map(|w: &u32| w.to_le_bytes()));
+        }
+        let mut mem = MemoryDump::new().with_region(0x2000_0400, cb);
+        let ram = 0x2000_0000..0x2000_4000;
+        let ranges = rtt_ranges(&mut mem, 0x2000_0400, slice::from_ref(&ram)).unwrap();
+        assert_eq!(ranges, [0x2000_0400..0x2000_0460, 0x2000_3F00..0x2000_4000]);
+    }
+}
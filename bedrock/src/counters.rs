@@ -0,0 +1,203 @@
+//! Event counters from `cnt_if!` and `bkp_cnt_if!`: names from the ELF, values from the target.
+//!
+//! Every counter word is a 1 byte symbol in an INFO section (see cnt.x), so its address is the word index into
+//! `_CNT_RAM_BUFFER` or `_CNT_BKP_BUFFER`. Symbol names are JSON objects with `name:u32` or `name:u64,lo|hi` data.
+
+use crate::Error;
+use crate::elf::{FirmwareElf, symbol_json_field};
+use crate::mem::Memory;
+use object::{Object, ObjectSection, ObjectSymbol};
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Storage {
+    Ram,
+    /// Backup domain RAM, kept over resets
+    Bkp,
+}
+
+impl Storage {
+    fn section(self) -> &'static str {
+        match self {
+            Storage::Ram => ".counters_ram",
+            Storage::Bkp => ".counters_bkp",
+        }
+    }
+
+    fn buffer(self) -> &'static str {
+        match self {
+            Storage::Ram => "_CNT_RAM_BUFFER",
+            Storage::Bkp => "_CNT_BKP_BUFFER",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counter {
+    pub name: String,
+    pub storage: Storage,
+    /// Word index of a u32 counter, or of the low word of a u64 one
+    pub lo: u64,
+    /// Word index of the high word of a u64 counter
+    pub hi: Option<u64>,
+}
+
+impl Counter {
+    pub fn read(&self, mem: &mut impl Memory, elf: &FirmwareElf) -> Result<u64, Error> {
+        let (lo, hi) = self.word_addresses(elf)?;
+        read_words(mem, lo, hi)
+    }
+
+    /// Set the counter, e.g. back to 0. Not atomic: a concurrent increment of a u64 counter may be lost.
+    pub fn write(&self, mem: &mut impl Memory, elf: &FirmwareElf, value: u64) -> Result<(), Error> {
+        if self.hi.is_none() && value > u32::MAX as u64 {
+            return Err(Error::Symbol(format!(
+                "{value} does not fit u32 counter {}",
+                self.name
+            )));
+        }
+        let (lo, hi) = self.word_addresses(elf)?;
+        mem.write(lo, &(value as u32).to_le_bytes())?;
+        if let Some(hi) = hi {
+            mem.write(hi, &((value >> 32) as u32).to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Target addresses of the low and high words
+    pub fn word_addresses(&self, elf: &FirmwareElf) -> Result<(u64, Option<u64>), Error> {
+        let buffer = self.storage.buffer();
+        let base = elf.symbol_address(buffer).ok_or_else(|| {
+            Error::Symbol(format!("{buffer} not found, is the cnt crate linked?"))
+        })?;
+        Ok((base + self.lo * 4, self.hi.map(|hi| base + hi * 4)))
+    }
+}
+
+/// Counter value from the addresses of its words
+pub fn read_words(mem: &mut impl Memory, lo: u64, hi: Option<u64>) -> Result<u64, Error> {
+    let lo = mem.read_u32(lo)? as u64;
+    let hi = match hi {
+        Some(hi) => mem.read_u32(hi)? as u64,
+        None => 0,
+    };
+    Ok((hi << 32) | lo)
+}
+
+/// Target memory of a counter buffer, as far as counters use it
+pub fn buffer_range(
+    elf: &FirmwareElf,
+    counters: &[Counter],
+    storage: Storage,
+) -> Option<Range<u64>> {
+    let base = elf.symbol_address(storage.buffer())?;
+    let last = counters
+        .iter()
+        .filter(|c| c.storage == storage)
+        .flat_map(|c| [Some(c.lo), c.hi])
+        .flatten()
+        .max()?;
+    Some(base..base + (last + 1) * 4)
+}
+
+/// All counters of a firmware, in buffer order
+pub fn counters(elf: &FirmwareElf) -> Vec<Counter> {
+    let file = elf.file();
+    let mut counters: Vec<Counter> = Vec::new();
+    for storage in [Storage::Ram, Storage::Bkp] {
+        let Some(section) = file.section_by_name(storage.section()) else {
+            continue;
+        };
+        let mut words: Vec<(u64, &str)> = file
+            .symbols()
+            .filter(|s| s.section_index() == Some(section.index()))
+            .filter_map(|s| Some((s.address(), symbol_json_field(s.name().ok()?, "data")?)))
+            .collect();
+        words.sort();
+        let words: Vec<_> = words
+            .into_iter()
+            .filter_map(|(index, data)| Some((index, parse_data(data)?)))
+            .collect();
+        for (index, (name, word)) in &words {
+            if !matches!(word, Word::Hi) {
+                counters.push(Counter {
+                    name: name.to_string(),
+                    storage,
+                    lo: *index,
+                    hi: None,
+                });
+            }
+        }
+        for (index, (name, word)) in &words {
+            if !matches!(word, Word::Hi) {
+                continue;
+            }
+            if let Some(counter) = counters
+                .iter_mut()
+                .find(|c| c.storage == storage && c.name == *name)
+            {
+                counter.hi = Some(*index);
+            }
+        }
+    }
+    counters
+}
+
+enum Word {
+    U32,
+    Lo,
+    Hi,
+}
+
+/// `name:u32`, `name:u64,lo` or `name:u64,hi`
+fn parse_data(data: &str) -> Option<(&str, Word)> {
+    let (name, ty) = data.rsplit_once(':')?;
+    let word = match ty {
+        "u32" => Word::U32,
+        "u64,lo" => Word::Lo,
+        "u64,hi" => Word::Hi,
+        _ => return None,
+    };
+    Some((name, word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    fn counter_symbol_data() {
+        assert!(matches!(
+            parse_data("adc_overrun:u32"),
+            Some(("adc_overrun", Word::U32))
+        ));
+        assert!(matches!(
+            parse_data("rx:bytes:u64,hi"),
+            Some(("rx:bytes", Word::Hi))
+        ));
+        assert!(parse_data("rx").is_none());
+    }
+
+    #[test]
+    fn fixture_counters() {
+        let (mut mem, _, elf) = fixture::core_memory();
+        let counters = counters(&elf);
+        let names: Vec<_> = counters
+            .iter()
+            .map(|c| (c.name.as_str(), c.storage, c.hi.is_some()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("over_limit", Storage::Ram, true),
+                ("samples_taken", Storage::Ram, false)
+            ]
+        );
+        let values: Vec<_> = counters
+            .iter()
+            .map(|c| c.read(&mut mem, &elf).unwrap())
+            .collect();
+        assert_eq!(values, [fixture::OVER_LIMIT, fixture::SAMPLES_TAKEN]);
+    }
+}
@@ -0,0 +1,501 @@
+//! Live target diagnosis: core and chip vs firmware target, firmware in FLASH vs ELF, core state, read protection,
+//! reset cause, RTT control block and vector table.
+//!
+//! All the checks only read memory, so the same pipeline runs on a probe and on an offline [crate::mem::MemoryDump].
+
+use crate::Error;
+use crate::chip::{CoreType, Family, ReadProtection, VTOR, identify};
+use crate::compact_info::find_compact_info_in_ranges;
+use crate::elf::{FirmwareElf, FlashImage};
+use crate::mem::Memory;
+use crate::verify::verify_running_record;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+/// Debug halting control and status register
+const DHCSR: u64 = 0xE000_EDF0;
+const DHCSR_S_HALT: u32 = 1 << 17;
+const DHCSR_S_SLEEP: u32 = 1 << 18;
+const DHCSR_S_LOCKUP: u32 = 1 << 19;
+const DHCSR_S_RESET_ST: u32 = 1 << 25;
+
+const RTT_ID: &[u8; 16] = b"SEGGER RTT\0\0\0\0\0\0";
+const COMPARE_CHUNK_SIZE_B: usize = 4096;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Ok,
+    Info,
+    Warning,
+    Problem,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub topic: &'static str,
+    pub level: Level,
+    pub message: String,
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let level = match self.level {
+            Level::Ok => "ok",
+            Level::Info => "info",
+            Level::Warning => "warning",
+            Level::Problem => "PROBLEM",
+        };
+        write!(f, "{level:>7}  {:<9} {}", self.topic, self.message)
+    }
+}
+
+/// What the firmware ELF says the target should look like
+#[derive(Default)]
+pub struct Expected {
+    pub target_triple: Option<String>,
+    /// `-C target-cpu`, e.g. `cortex-m7`
+    pub target_cpu: Option<String>,
+    /// Compact build info address and record
+    pub compact_record: Option<(u64, Vec<u8>)>,
+    pub flash_image: Option<FlashImage>,
+    pub ram_linked: bool,
+    pub vector_table: Option<u64>,
+    /// `_SEGGER_RTT` address
+    pub rtt_control_block: Option<u64>,
+}
+
+impl Expected {
+    pub fn from_elf(elf: &FirmwareElf) -> Self {
+        let info = elf.full_info().or_else(|| elf.compact_info());
+        Expected {
+            target_triple: info.as_ref().and_then(|i| i.target_info.triple.clone()),
+            target_cpu: info
+                .as_ref()
+                .and_then(|i| i.compiler_info.target_cpu().map(str::to_string)),
+            compact_record: elf.compact_record(),
+            flash_image: elf.flash_image(),
+            ram_linked: elf.is_ram_linked(),
+            vector_table: elf.vector_table(),
+            rtt_control_block: elf.symbol_address("_SEGGER_RTT"),
+        }
+    }
+}
+
+pub struct Diagnosis {
+    pub findings: Vec<Finding>,
+}
+
+impl Diagnosis {
+    pub fn worst(&self) -> Level {
+        self.findings
+            .iter()
+            .map(|f| f.level)
+            .max()
+            .unwrap_or(Level::Ok)
+    }
+
+    fn push(&mut self, topic: &'static str, level: Level, message: impl Into<String>) {
+        self.findings.push(Finding {
+            topic,
+            level,
+            message: message.into(),
+        });
+    }
+
+    /// Checks continue if some memory is not readable, e.g. because of read protection
+    fn unreadable(&mut self, topic: &'static str, e: Error) {
+        self.push(topic, Level::Warning, format!("could not read: {e}"));
+    }
+}
+
+/// Run all the checks. `chip` is the probe-rs target name, `flash` its FLASH ranges, `expected` comes from
+/// the firmware ELF if it is known.
+pub fn diagnose(
+    mem: &mut impl Memory,
+    chip: &str,
+    flash: &[Range<u64>],
+    expected: Option<&Expected>,
+) -> Diagnosis {
+    let mut d = Diagnosis {
+        findings: Vec::new(),
+    };
+    let family = match identify(mem) {
+        Ok(id) => {
+            d.push("chip", Level::Info, id.to_string());
+            id.family
+        }
+        Err(_) => None,
+    }
+    .or_else(|| Family::from_target_name(chip));
+    core(mem, chip, expected, &mut d);
+    state(mem, &mut d);
+    if let Some(family) = family {
+        protection(mem, family, &mut d);
+        reset_cause(mem, family, &mut d);
+    } else {
+        d.push(
+            "chip",
+            Level::Info,
+            format!("{chip}: read protection and reset cause are not known for this chip family"),
+        );
+    }
+    match expected {
+        Some(expected) => {
+            firmware(mem, flash, expected, &mut d);
+            rtt(mem, expected, &mut d);
+            vector_table(mem, flash, expected, &mut d);
+        }
+        None => match find_compact_info_in_ranges(mem, flash) {
+            Ok(found) => d.push(
+                "firmware",
+                Level::Info,
+                format!("{}, no ELF given to compare with", found.info.crate_info),
+            ),
+            Err(e) => d.push("firmware", Level::Warning, e.to_string()),
+        },
+    }
+    d
+}
+
+fn core(mem: &mut impl Memory, chip: &str, expected: Option<&Expected>, d: &mut Diagnosis) {
+    let core = match CoreType::read(mem) {
+        Ok(Some(core)) => core,
+        Ok(None) => {
+            d.push("core", Level::Warning, "unknown core in CPUID");
+            return;
+        }
+        Err(e) => return d.unreadable("core", e),
+    };
+    let Some(triple) = expected.and_then(|e| e.target_triple.as_deref()) else {
+        d.push("core", Level::Info, format!("{chip}, {}", core.name));
+        return;
+    };
+    if !core.runs(triple) {
+        d.push(
+            "core",
+            Level::Problem,
+            format!(
+                "{chip}, {} cannot run firmware built for {triple}, is it the right board?",
+                core.name
+            ),
+        );
+        return;
+    }
+    let cpu = expected.and_then(|e| e.target_cpu.as_deref());
+    match cpu {
+        // rustc names are lower case, with `plus` for `+`
+        Some(cpu) if core.name.to_ascii_lowercase().replace('+', "plus") != cpu => d.push(
+            "core",
+            Level::Problem,
+            format!(
+                "{chip}, {} but firmware is built with -C target-cpu={cpu}",
+                core.name
+            ),
+        ),
+        _ => d.push(
+            "core",
+            Level::Ok,
+            format!("{chip}, {} runs {triple}", core.name),
+        ),
+    }
+}
+
+fn state(mem: &mut impl Memory, d: &mut Diagnosis) {
+    let dhcsr = match mem.read_u32(DHCSR) {
+        Ok(dhcsr) => dhcsr,
+        Err(e) => return d.unreadable("state", e),
+    };
+    if dhcsr & DHCSR_S_LOCKUP != 0 {
+        d.push(
+            "state",
+            Level::Problem,
+            "core is locked up: a fault happened while handling HardFault or NMI",
+        );
+    } else if dhcsr & DHCSR_S_HALT != 0 {
+        d.push("state", Level::Warning, "core is halted");
+    } else if dhcsr & DHCSR_S_SLEEP != 0 {
+        d.push("state", Level::Ok, "core is sleeping (WFI/WFE)");
+    } else {
+        d.push("state", Level::Ok, "core is running");
+    }
+    if dhcsr & DHCSR_S_RESET_ST != 0 {
+        d.push("state", Level::Info, "core was reset since the last check");
+    }
+}
+
+fn protection(mem: &mut impl Memory, family: Family, d: &mut Diagnosis) {
+    match family.read_protection(mem) {
+        Ok(Some(rdp)) => {
+            let level = match rdp {
+                ReadProtection::Level0 => Level::Ok,
+                ReadProtection::Level1 => Level::Warning,
+                ReadProtection::Level2 => Level::Problem,
+            };
+            d.push("rdp", level, rdp.to_string());
+        }
+        Ok(None) => {}
+        Err(e) => d.unreadable("rdp", e),
+    }
+}
+
+fn reset_cause(mem: &mut impl Memory, family: Family, d: &mut Diagnosis) {
+    let causes = match family.reset_cause(mem) {
+        Ok(causes) => causes,
+        Err(e) => return d.unreadable("reset", e),
+    };
+    let level = if causes.iter().any(|c| c.is_abnormal()) {
+        Level::Warning
+    } else {
+        Level::Info
+    };
+    let causes: Vec<String> = causes.iter().map(|c| c.to_string()).collect();
+    let message = if causes.is_empty() {
+        "no reset flags set".to_string()
+    } else {
+        causes.join(", ")
+    };
+    d.push("reset", level, message);
+}
+
+fn firmware(mem: &mut impl Memory, flash: &[Range<u64>], expected: &Expected, d: &mut Diagnosis) {
+    if expected.ram_linked {
+        d.push(
+            "firmware",
+            Level::Info,
+            "ELF is linked to RAM, FLASH contents are not compared",
+        );
+        return;
+    }
+    let Some((address, record)) = &expected.compact_record else {
+        d.push(
+            "firmware",
+            Level::Warning,
+            "ELF has no compact build info, cannot tell which firmware is running",
+        );
+        return;
+    };
+    let search = expected
+        .flash_image
+        .as_ref()
+        .map(|image| image.start..image.start + image.bytes.len() as u64)
+        .or_else(|| flash.first().cloned())
+        .unwrap_or(*address..*address + record.len() as u64);
+    let check = match verify_running_record(mem, *address, record, search) {
+        Ok(check) => check,
+        Err(Error::BuildInfoNotFound) => {
+            d.push(
+                "firmware",
+                Level::Problem,
+                "no build info in FLASH, firmware is not flashed or was built without bedrock_build",
+            );
+            return;
+        }
+        Err(e) => return d.unreadable("firmware", e),
+    };
+    if !check.is_match() {
+        d.push(
+            "firmware",
+            Level::Problem,
+            format!(
+                "FLASH holds a different firmware (ELF != device):\n{}",
+                check.diff
+            ),
+        );
+        return;
+    }
+    let Some(image) = &expected.flash_image else {
+        d.push("firmware", Level::Ok, "build info matches the ELF");
+        return;
+    };
+    match first_difference(mem, image) {
+        Ok(None) => d.push("firmware", Level::Ok, "FLASH contents match the ELF"),
+        Ok(Some(address)) => d.push(
+            "firmware",
+            Level::Problem,
+            format!(
+                "build info matches, but FLASH differs from the ELF at 0x{address:08x}, was it patched or signed after flashing?"
+            ),
+        ),
+        Err(e) => d.unreadable("firmware", e),
+    }
+}
+
+fn first_difference(mem: &mut impl Memory, image: &FlashImage) -> Result<Option<u64>, Error> {
+    let mut buf = vec![0u8; COMPARE_CHUNK_SIZE_B];
+    for (idx, expected) in image.bytes.chunks(COMPARE_CHUNK_SIZE_B).enumerate() {
+        let address = image.start + (idx * COMPARE_CHUNK_SIZE_B) as u64;
+        let buf = &mut buf[..expected.len()];
+        mem.read(address, buf)?;
+        if let Some(offset) = buf.iter().zip(expected).position(|(a, b)| a != b) {
+            return Ok(Some(address + offset as u64));
+        }
+    }
+    Ok(None)
+}
+
+fn rtt(mem: &mut impl Memory, expected: &Expected, d: &mut Diagnosis) {
+    let Some(address) = expected.rtt_control_block else {
+        d.push("rtt", Level::Info, "firmware does not use RTT");
+        return;
+    };
+    let mut id = [0u8; 16];
+    match mem.read(address, &mut id) {
+        Ok(()) if &id == RTT_ID => d.push(
+            "rtt",
+            Level::Ok,
+            format!("control block found at 0x{address:08x}"),
+        ),
+        Ok(()) => d.push(
+            "rtt",
+            Level::Problem,
+            format!(
+                "no control block at 0x{address:08x}, firmware did not initialize RTT yet or is not the one from the ELF"
+            ),
+        ),
+        Err(e) => d.unreadable("rtt", e),
+    }
+}
+
+fn vector_table(
+    mem: &mut impl Memory,
+    flash: &[Range<u64>],
+    expected: &Expected,
+    d: &mut Diagnosis,
+) {
+    let Some(vector_table) = expected.vector_table else {
+        return;
+    };
+    let vtor = match mem.read_u32(VTOR) {
+        Ok(vtor) => vtor as u64,
+        Err(e) => return d.unreadable("vtor", e),
+    };
+    if vtor == vector_table {
+        d.push("vtor", Level::Ok, format!("0x{vtor:08x}"));
+    } else if vtor == 0 && flash.first().is_some_and(|f| f.start == vector_table) {
+        d.push(
+            "vtor",
+            Level::Info,
+            "0x00000000, relies on FLASH being aliased at address 0 by the boot configuration",
+        );
+    } else {
+        d.push(
+            "vtor",
+            Level::Problem,
+            format!(
+                "0x{vtor:08x}, but the image expects 0x{vector_table:08x}: interrupts go to another image's handlers, did the bootloader set VTOR?"
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+    use crate::mem::MemoryDump;
+    use std::slice;
+
+    const FLASH: u64 = 0x0800_0000;
+    const RAM: u64 = 0x2400_0000;
+    const RECORD_AT: usize = 0x400;
+
+    fn word(regs: &mut [u8], offset: usize, value: u32) {
+        regs[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// STM32H743 running the firmware, as recorded from a probe
+    fn target() -> (MemoryDump, Expected) {
+        let record = fixture::compact_record(&fixture::build_info("h7_app"));
+        let mut flash = vec![0xFFu8; 4096];
+        flash[RECORD_AT..RECORD_AT + record.len()].copy_from_slice(&record);
+        let mut ram = vec![0u8; 1024];
+        ram[..16].copy_from_slice(RTT_ID);
+
+        let mut scb = vec![0u8; 0x100];
+        word(&mut scb, 0x00, 0x411F_C272); // CPUID: Cortex-M7 r1p2
+        word(&mut scb, 0x08, FLASH as u32); // VTOR
+        word(&mut scb, 0xF0, DHCSR_S_SLEEP); // DHCSR
+        let mut rdp = vec![0u8; 4];
+        word(&mut rdp, 0, 0x1AAF_0000 | (0xAA << 8));
+        let mut rsr = vec![0u8; 4];
+        word(&mut rsr, 0, (1 << 22) | (1 << 26)); // pin, IWDG1
+
+        let mem = MemoryDump::new()
+            .with_region(FLASH, flash.clone())
+            .with_region(RAM, ram)
+            .with_region(0xE000_ED00, scb)
+            .with_region(0x5200_201C, rdp)
+            .with_region(0x5802_44D0, rsr);
+        let expected = Expected {
+            target_triple: Some("thumbv7em-none-eabihf".into()),
+            target_cpu: Some("cortex-m7".into()),
+            compact_record: Some((FLASH + RECORD_AT as u64, record)),
+            flash_image: Some(FlashImage {
+                start: FLASH,
+                bytes: flash,
+            }),
+            ram_linked: false,
+            vector_table: Some(FLASH),
+            rtt_control_block: Some(RAM),
+        };
+        (mem, expected)
+    }
+
+    fn level(d: &Diagnosis, topic: &str) -> Vec<Level> {
+        d.findings
+            .iter()
+            .filter(|f| f.topic == topic)
+            .map(|f| f.level)
+            .collect()
+    }
+
+    #[test]
+    fn healthy_target() {
+        let (mut mem, expected) = target();
+        let flash = FLASH..FLASH + 0x20_0000;
+        let d = diagnose(
+            &mut mem,
+            "STM32H743ZITx",
+            slice::from_ref(&flash),
+            Some(&expected),
+        );
+        for topic in ["core", "state", "rdp", "firmware", "rtt", "vtor"] {
+            assert_eq!(level(&d, topic), [Level::Ok], "{topic}: {:?}", d.findings);
+        }
+        // watchdog reset is a warning
+        assert_eq!(level(&d, "reset"), [Level::Warning]);
+        assert_eq!(d.worst(), Level::Warning);
+    }
+
+    #[test]
+    fn problems_are_found() {
+        let (mut mem, mut expected) = target();
+        let flash = FLASH..FLASH + 0x20_0000;
+        expected.target_triple = Some("thumbv8m.main-none-eabihf".into());
+        expected.vector_table = Some(FLASH + 0x2_0000);
+        // firmware changed after flashing, build info is the same
+        expected.flash_image.as_mut().unwrap().bytes[0x10] = 0;
+        mem.add_region(RAM, vec![0u8; 16]);
+        mem.add_region(
+            DHCSR,
+            (DHCSR_S_LOCKUP | DHCSR_S_HALT).to_le_bytes().to_vec(),
+        );
+
+        let d = diagnose(
+            &mut mem,
+            "STM32H743ZITx",
+            slice::from_ref(&flash),
+            Some(&expected),
+        );
+        for topic in ["core", "state", "firmware", "rtt", "vtor"] {
+            assert_eq!(
+                level(&d, topic),
+                [Level::Problem],
+                "{topic}: {:?}",
+                d.findings
+            );
+        }
+        let firmware = d.findings.iter().find(|f| f.topic == "firmware").unwrap();
+        assert!(firmware.message.contains("0x08000010"));
+    }
+}
@@ -0,0 +1,739 @@
+//! Statics and their types from DWARF debug info, as far as needed to print and modify them, and code addresses
+//! mapped back to functions and source lines.
+//!
+//! Only statics and functions are indexed up front, types are parsed when a static is looked up, since a firmware
+//! with embassy easily has thousands of deeply nested types.
+
+use crate::Error;
+use crate::elf::FirmwareElf;
+use gimli::{
+    AttributeValue, DebuggingInformationEntry, DwAte, Dwarf, EndianSlice, LittleEndian, Operation,
+    Unit, UnitOffset, constants,
+};
+use object::{Object, ObjectSection};
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+type R<'elf> = EndianSlice<'elf, LittleEndian>;
+
+/// Types nested deeper than this are not expanded
+const MAX_TYPE_DEPTH: usize = 12;
+
+/// Static variable found in the debug info
+#[derive(Debug, Clone)]
+pub struct Static {
+    /// Path with modules and enclosing functions, e.g. `app::config::CONFIG` or `app::__embassy_main::CELL`
+    pub name: String,
+    pub address: u64,
+    unit: usize,
+    ty: Option<UnitOffset>,
+}
+
+/// Function with code, not inlined everywhere
+#[derive(Debug, Clone)]
+struct Function {
+    name: String,
+    ranges: Vec<Range<u64>>,
+}
+
+/// Where a code address comes from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u64>,
+}
+
+impl Display for SourceLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.function.as_deref().unwrap_or("??"))?;
+        if let Some(file) = &self.file {
+            write!(f, " at {file}")?;
+            if let Some(line) = self.line {
+                write!(f, ":{line}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Signed,
+    Unsigned,
+    Float,
+    Bool,
+    Char,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Base {
+        name: String,
+        encoding: Encoding,
+        size: u64,
+    },
+    Pointer {
+        name: String,
+        size: u64,
+    },
+    /// Structs, unions and tuples
+    Struct {
+        name: String,
+        size: u64,
+        members: Vec<Member>,
+    },
+    /// Fieldless enum
+    Enumeration {
+        name: String,
+        size: u64,
+        enumerators: Vec<(u64, String)>,
+    },
+    /// Enum with fields, the active variant is selected by the discriminant, or by a niche in one of the fields
+    Variants {
+        name: String,
+        size: u64,
+        /// Offset and size of the discriminant, None if there is only one variant
+        discriminant: Option<(u64, u64)>,
+        variants: Vec<Variant>,
+    },
+    Array {
+        element: Box<Type>,
+        count: u64,
+    },
+    /// Not expanded: nested too deep or not supported
+    Opaque {
+        name: String,
+        size: u64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Member {
+    pub name: String,
+    /// Offset from the start of the containing struct or enum
+    pub offset: u64,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub name: String,
+    /// None for the variant used when no other one matches
+    pub discriminant: Option<u64>,
+    /// Fields, with offsets from the start of the enum
+    pub fields: Vec<Member>,
+}
+
+impl Type {
+    pub fn name(&self) -> Cow<'_, str> {
+        match self {
+            Type::Base { name, .. }
+            | Type::Pointer { name, .. }
+            | Type::Struct { name, .. }
+            | Type::Enumeration { name, .. }
+            | Type::Variants { name, .. }
+            | Type::Opaque { name, .. } => Cow::Borrowed(name),
+            Type::Array { element, count } => Cow::Owned(format!("[{}; {count}]", element.name())),
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        match self {
+            Type::Base { size, .. }
+            | Type::Pointer { size, .. }
+            | Type::Struct { size, .. }
+            | Type::Enumeration { size, .. }
+            | Type::Variants { size, .. }
+            | Type::Opaque { size, .. } => *size,
+            Type::Array { element, count } => element.size() * count,
+        }
+    }
+}
+
+/// Debug info of a firmware ELF
+pub struct DebugInfo<'elf> {
+    dwarf: Dwarf<R<'elf>>,
+    units: Vec<Unit<R<'elf>>>,
+    statics: Vec<Static>,
+    functions: Vec<Function>,
+}
+
+impl<'elf> DebugInfo<'elf> {
+    pub fn load(elf: &'elf FirmwareElf) -> Result<Self, Error> {
+        let file = elf.file();
+        let dwarf = Dwarf::load(|id| -> Result<R<'elf>, Error> {
+            let data = match file.section_by_name(id.name()) {
+                Some(section) => match section.uncompressed_data()? {
+                    Cow::Borrowed(data) => data,
+                    Cow::Owned(_) => {
+                        return Err(Error::Dwarf(format!(
+                            "{} is compressed, build without debug info compression",
+                            id.name()
+                        )));
+                    }
+                },
+                None => &[],
+            };
+            Ok(EndianSlice::new(data, LittleEndian))
+        })?;
+        let mut units = Vec::new();
+        let mut headers = dwarf.units();
+        while let Some(header) = headers.next()? {
+            units.push(dwarf.unit(header)?);
+        }
+        let mut info = DebugInfo {
+            dwarf,
+            units,
+            statics: Vec::new(),
+            functions: Vec::new(),
+        };
+        for idx in 0..info.units.len() {
+            let mut index = Index::default();
+            info.index_unit(idx, &mut index)?;
+            info.statics.extend(index.statics);
+            info.functions.extend(index.functions);
+        }
+        if info.statics.is_empty() && info.units.is_empty() {
+            return Err(Error::Dwarf(
+                "no debug info, build with `debug = 2` or `debug = \"line-tables-only\"` removed"
+                    .into(),
+            ));
+        }
+        Ok(info)
+    }
+
+    pub fn statics(&self) -> &[Static] {
+        &self.statics
+    }
+
+    /// Static by full path, or by the end of it if that is unique, e.g. `CONFIG` or `config::CONFIG`
+    pub fn find(&self, name: &str) -> Result<&Static, Error> {
+        if let Some(exact) = self.statics.iter().find(|s| s.name == name) {
+            return Ok(exact);
+        }
+        let suffix = format!("::{name}");
+        let mut matches = self.statics.iter().filter(|s| s.name.ends_with(&suffix));
+        match (matches.next(), matches.next()) {
+            (Some(found), None) => Ok(found),
+            (Some(first), Some(second)) => Err(Error::Symbol(format!(
+                "{name} is ambiguous: {}, {}{}, use a longer path",
+                first.name,
+                second.name,
+                if matches.next().is_some() {
+                    ", ..."
+                } else {
+                    ""
+                }
+            ))),
+            (None, _) => Err(Error::Symbol(format!(
+                "static {name} not found in debug info"
+            ))),
+        }
+    }
+
+    pub fn type_of(&self, s: &Static) -> Result<Type, Error> {
+        let unit = &self.units[s.unit];
+        match s.ty {
+            Some(offset) => self.parse_type(unit, offset, 0),
+            None => Ok(Type::Opaque {
+                name: "?".into(),
+                size: 0,
+            }),
+        }
+    }
+
+    /// Function and source line of a code address, as far as the debug info tells
+    pub fn locate(&self, pc: u64) -> Result<SourceLocation, Error> {
+        let function = self
+            .functions
+            .iter()
+            .find(|f| f.ranges.iter().any(|r| r.contains(&pc)))
+            .map(|f| f.name.clone());
+        let mut location = SourceLocation {
+            function,
+            ..Default::default()
+        };
+        for unit in &self.units {
+            if let Some((file, line)) = self.line(unit, pc)? {
+                location.file = Some(file);
+                location.line = line;
+                break;
+            }
+        }
+        Ok(location)
+    }
+
+    /// File and line of the line table row covering `pc`
+    fn line(&self, unit: &Unit<R<'elf>>, pc: u64) -> Result<Option<(String, Option<u64>)>, Error> {
+        let Some(program) = unit.line_program.clone() else {
+            return Ok(None);
+        };
+        let mut rows = program.rows();
+        let mut previous: Option<(u64, u64, Option<u64>)> = None;
+        while let Some((header, row)) = rows.next_row()? {
+            if let Some((address, file, line)) = previous
+                && (address..row.address()).contains(&pc)
+            {
+                let path = header.file(file).map(|file| {
+                    let name = self.attr_lossy(unit, file.path_name());
+                    match file.directory(header).map(|dir| self.attr_lossy(unit, dir)) {
+                        Some(dir) if !dir.is_empty() && !name.starts_with('/') => {
+                            format!("{dir}/{name}")
+                        }
+                        _ => name,
+                    }
+                });
+                return Ok(path.map(|path| (path, line)));
+            }
+            previous = (!row.end_sequence())
+                .then(|| (row.address(), row.file_index(), row.line().map(|l| l.get())));
+        }
+        Ok(None)
+    }
+
+    fn attr_lossy(&self, unit: &Unit<R<'elf>>, value: AttributeValue<R<'elf>>) -> String {
+        match self.dwarf.attr_string(unit, value) {
+            Ok(s) => String::from_utf8_lossy(s.slice()).into_owned(),
+            Err(_) => String::new(),
+        }
+    }
+
+    fn index_unit(&self, unit_idx: usize, index: &mut Index) -> Result<(), Error> {
+        let unit = &self.units[unit_idx];
+        let mut tree = unit.entries_tree(None)?;
+        let mut path = Vec::new();
+        self.walk(unit, unit_idx, tree.root()?, &mut path, index)
+    }
+
+    fn walk(
+        &self,
+        unit: &Unit<R<'elf>>,
+        unit_idx: usize,
+        node: gimli::EntriesTreeNode<'_, '_, R<'elf>>,
+        path: &mut Vec<String>,
+        index: &mut Index,
+    ) -> Result<(), Error> {
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            match entry.tag() {
+                constants::DW_TAG_variable => {
+                    let (Some(name), Some(address)) =
+                        (self.name(unit, entry), static_address(unit, entry))
+                    else {
+                        continue;
+                    };
+                    let mut full = path.clone();
+                    full.push(name);
+                    index.statics.push(Static {
+                        name: full.join("::"),
+                        address,
+                        unit: unit_idx,
+                        ty: type_ref(entry),
+                    });
+                }
+                constants::DW_TAG_namespace
+                | constants::DW_TAG_subprogram
+                | constants::DW_TAG_lexical_block
+                | constants::DW_TAG_structure_type => {
+                    let name = self.name(unit, entry);
+                    if entry.tag() == constants::DW_TAG_subprogram {
+                        self.index_function(unit, entry, path, name.as_deref(), index)?;
+                    }
+                    if let Some(name) = &name {
+                        path.push(name.clone());
+                    }
+                    self.walk(unit, unit_idx, child, path, index)?;
+                    if name.is_some() {
+                        path.pop();
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Record a function with code, named after its declaration for out of line copies of inlined functions
+    fn index_function(
+        &self,
+        unit: &Unit<R<'elf>>,
+        entry: &DebuggingInformationEntry<R<'elf>>,
+        path: &[String],
+        name: Option<&str>,
+        index: &mut Index,
+    ) -> Result<(), Error> {
+        let mut ranges = Vec::new();
+        let mut iter = self.dwarf.die_ranges(unit, entry)?;
+        while let Some(range) = iter.next()? {
+            if range.begin < range.end {
+                ranges.push(range.begin..range.end);
+            }
+        }
+        if ranges.is_empty() {
+            return Ok(());
+        }
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => [
+                constants::DW_AT_abstract_origin,
+                constants::DW_AT_specification,
+            ]
+            .into_iter()
+            .find_map(|attr| match entry.attr_value(attr)? {
+                AttributeValue::UnitRef(offset) => self.name(unit, &unit.entry(offset).ok()?),
+                _ => None,
+            })
+            .unwrap_or_else(|| "??".into()),
+        };
+        let mut full = path.to_vec();
+        full.push(name);
+        index.functions.push(Function {
+            name: full.join("::"),
+            ranges,
+        });
+        Ok(())
+    }
+
+    fn name(
+        &self,
+        unit: &Unit<R<'elf>>,
+        entry: &DebuggingInformationEntry<R<'elf>>,
+    ) -> Option<String> {
+        let value = entry.attr_value(constants::DW_AT_name)?;
+        let name = self.dwarf.attr_string(unit, value).ok()?;
+        Some(String::from_utf8_lossy(name.slice()).into_owned())
+    }
+
+    fn parse_type(
+        &self,
+        unit: &Unit<R<'elf>>,
+        offset: UnitOffset,
+        depth: usize,
+    ) -> Result<Type, Error> {
+        let entry = unit.entry(offset)?;
+        let name = self.name(unit, &entry).unwrap_or_default();
+        let size = udata(&entry, constants::DW_AT_byte_size).unwrap_or(0);
+        let ty = match entry.tag() {
+            constants::DW_TAG_base_type => {
+                let encoding = match entry.attr_value(constants::DW_AT_encoding) {
+                    Some(AttributeValue::Encoding(encoding)) => base_encoding(encoding),
+                    _ => None,
+                };
+                match encoding {
+                    Some(encoding) => Type::Base {
+                        name,
+                        encoding,
+                        size,
+                    },
+                    None => Type::Opaque { name, size },
+                }
+            }
+            constants::DW_TAG_pointer_type | constants::DW_TAG_reference_type => Type::Pointer {
+                name,
+                size: if size == 0 { 4 } else { size },
+            },
+            constants::DW_TAG_typedef
+            | constants::DW_TAG_const_type
+            | constants::DW_TAG_volatile_type
+            | constants::DW_TAG_atomic_type => match type_ref(&entry) {
+                Some(inner) => self.parse_type(unit, inner, depth)?,
+                None => Type::Opaque { name, size },
+            },
+            constants::DW_TAG_enumeration_type => {
+                let mut enumerators = Vec::new();
+                let mut tree = unit.entries_tree(Some(offset))?;
+                let mut children = tree.root()?.children();
+                while let Some(child) = children.next()? {
+                    let entry = child.entry();
+                    if entry.tag() != constants::DW_TAG_enumerator {
+                        continue;
+                    }
+                    let value = entry
+                        .attr_value(constants::DW_AT_const_value)
+                        .and_then(|v| v.udata_value().or(v.sdata_value().map(|v| v as u64)));
+                    if let (Some(value), Some(name)) = (value, self.name(unit, entry)) {
+                        enumerators.push((value, name));
+                    }
+                }
+                Type::Enumeration {
+                    name,
+                    size,
+                    enumerators,
+                }
+            }
+            constants::DW_TAG_array_type => {
+                let Some(element) = type_ref(&entry) else {
+                    return Ok(Type::Opaque { name, size });
+                };
+                let element = self.parse_type(unit, element, depth + 1)?;
+                let mut count = 0;
+                let mut tree = unit.entries_tree(Some(offset))?;
+                let mut children = tree.root()?.children();
+                while let Some(child) = children.next()? {
+                    let entry = child.entry();
+                    if entry.tag() == constants::DW_TAG_subrange_type {
+                        count = udata(entry, constants::DW_AT_count)
+                            .or(udata(entry, constants::DW_AT_upper_bound).map(|upper| upper + 1))
+                            .unwrap_or(0);
+                    }
+                }
+                Type::Array {
+                    element: Box::new(element),
+                    count,
+                }
+            }
+            constants::DW_TAG_structure_type
+            | constants::DW_TAG_union_type
+            | constants::DW_TAG_class_type
+                if depth < MAX_TYPE_DEPTH =>
+            {
+                self.parse_struct(unit, offset, name, size, depth)?
+            }
+            _ => Type::Opaque { name, size },
+        };
+        Ok(ty)
+    }
+
+    fn parse_struct(
+        &self,
+        unit: &Unit<R<'elf>>,
+        offset: UnitOffset,
+        name: String,
+        size: u64,
+        depth: usize,
+    ) -> Result<Type, Error> {
+        let mut members = Vec::new();
+        let mut tree = unit.entries_tree(Some(offset))?;
+        let mut children = tree.root()?.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            match entry.tag() {
+                constants::DW_TAG_member => {
+                    if let Some(member) = self.member(unit, entry, depth)? {
+                        members.push(member);
+                    }
+                }
+                constants::DW_TAG_variant_part => {
+                    let part = child.entry().offset();
+                    return self.parse_variant_part(unit, part, name, size, depth);
+                }
+                _ => {}
+            }
+        }
+        Ok(Type::Struct {
+            name,
+            size,
+            members,
+        })
+    }
+
+    /// Rust enum with fields: a struct holding a variant part, with the discriminant member and one variant
+    /// per enum variant, each holding a struct with the variant fields
+    fn parse_variant_part(
+        &self,
+        unit: &Unit<R<'elf>>,
+        part: UnitOffset,
+        name: String,
+        size: u64,
+        depth: usize,
+    ) -> Result<Type, Error> {
+        let discriminant_member = match unit.entry(part)?.attr_value(constants::DW_AT_discr) {
+            Some(AttributeValue::UnitRef(offset)) => Some(offset),
+            _ => None,
+        };
+        let mut discriminant = None;
+        let mut variants = Vec::new();
+        let mut tree = unit.entries_tree(Some(part))?;
+        let mut children = tree.root()?.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            match entry.tag() {
+                constants::DW_TAG_member if Some(entry.offset()) == discriminant_member => {
+                    let offset = udata(entry, constants::DW_AT_data_member_location).unwrap_or(0);
+                    let ty = match type_ref(entry) {
+                        Some(ty) => self.parse_type(unit, ty, depth + 1)?,
+                        None => continue,
+                    };
+                    discriminant = Some((offset, ty.size()));
+                }
+                constants::DW_TAG_variant => {
+                    let value = udata(entry, constants::DW_AT_discr_value);
+                    let mut variant_children = child.children();
+                    while let Some(variant_member) = variant_children.next()? {
+                        let entry = variant_member.entry();
+                        if entry.tag() != constants::DW_TAG_member {
+                            continue;
+                        }
+                        let Some(member) = self.member(unit, entry, depth)? else {
+                            continue;
+                        };
+                        let fields = match member.ty {
+                            Type::Struct { members, .. } => members
+                                .into_iter()
+                                .map(|m| Member {
+                                    offset: m.offset + member.offset,
+                                    ..m
+                                })
+                                .collect(),
+                            _ => Vec::new(),
+                        };
+                        variants.push(Variant {
+                            name: member.name,
+                            discriminant: value,
+                            fields,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(Type::Variants {
+            name,
+            size,
+            discriminant,
+            variants,
+        })
+    }
+
+    fn member(
+        &self,
+        unit: &Unit<R<'elf>>,
+        entry: &DebuggingInformationEntry<R<'elf>>,
+        depth: usize,
+    ) -> Result<Option<Member>, Error> {
+        let Some(ty) = type_ref(entry) else {
+            return Ok(None);
+        };
+        Ok(Some(Member {
+            name: self.name(unit, entry).unwrap_or_default(),
+            offset: udata(entry, constants::DW_AT_data_member_location).unwrap_or(0),
+            ty: self.parse_type(unit, ty, depth + 1)?,
+        }))
+    }
+}
+
+/// Statics and functions of one unit
+#[derive(Default)]
+struct Index {
+    statics: Vec<Static>,
+    functions: Vec<Function>,
+}
+
+fn udata(entry: &DebuggingInformationEntry<R<'_>>, attr: constants::DwAt) -> Option<u64> {
+    entry.attr_value(attr)?.udata_value()
+}
+
+fn type_ref(entry: &DebuggingInformationEntry<R<'_>>) -> Option<UnitOffset> {
+    match entry.attr_value(constants::DW_AT_type)? {
+        AttributeValue::UnitRef(offset) => Some(offset),
+        _ => None,
+    }
+}
+
+/// Address of a static, given as a single DW_OP_addr location
+fn static_address(unit: &Unit<R<'_>>, entry: &DebuggingInformationEntry<R<'_>>) -> Option<u64> {
+    let AttributeValue::Exprloc(expr) = entry.attr_value(constants::DW_AT_location)? else {
+        return None;
+    };
+    match expr.operations(unit.encoding()).next() {
+        Ok(Some(Operation::Address { address })) => Some(address),
+        _ => None,
+    }
+}
+
+fn base_encoding(encoding: DwAte) -> Option<Encoding> {
+    Some(match encoding {
+        constants::DW_ATE_signed | constants::DW_ATE_signed_char => Encoding::Signed,
+        constants::DW_ATE_unsigned | constants::DW_ATE_unsigned_char => Encoding::Unsigned,
+        constants::DW_ATE_float => Encoding::Float,
+        constants::DW_ATE_boolean => Encoding::Bool,
+        constants::DW_ATE_UTF => Encoding::Char,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+    use crate::inspect::{Item, lookup, resolve};
+    use crate::mem::MemoryDump;
+
+    #[test]
+    fn fixture_statics() {
+        let elf = fixture::elf();
+        let debug = DebugInfo::load(&elf).unwrap();
+        let config = debug.find("CONFIG").unwrap();
+        assert_eq!(config.name, "fixture::CONFIG");
+        assert_eq!(Some(config.address), elf.symbol_address("CONFIG"));
+        assert!(debug.find("NO_SUCH_STATIC").is_err());
+
+        let Type::Struct {
+            name,
+            size,
+            members,
+        } = debug.type_of(config).unwrap()
+        else {
+            panic!("Config is not a struct");
+        };
+        assert_eq!((name.as_str(), size), ("Config", 20));
+        let mut fields: Vec<_> = members
+            .iter()
+            .map(|m| (m.name.as_str(), m.offset, m.ty.size()))
+            .collect();
+        fields.sort_by_key(|(_, offset, _)| *offset);
+        assert_eq!(
+            fields,
+            [
+                ("gain", 0, 4),
+                ("limits", 4, 12),
+                ("mode", 16, 1),
+                ("enabled", 17, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn fixture_locate() {
+        let elf = fixture::elf();
+        let debug = DebugInfo::load(&elf).unwrap();
+        // the faulting load is inlined read_volatile, line info is of core, the function the one it is inlined into
+        let location = debug.locate(fixture::FAULT_PC as u64).unwrap();
+        assert_eq!(location.function.as_deref(), Some("fixture::read_sensor"));
+        assert!(location.file.unwrap().ends_with("ptr/mod.rs"));
+        let entry = debug.locate(fixture::FAULT_PC as u64 - 4).unwrap();
+        assert_eq!(entry.function.as_deref(), Some("fixture::read_sensor"));
+        assert!(entry.file.unwrap().ends_with("src/main.rs"));
+        assert_eq!(entry.line, Some(60));
+    }
+
+    #[test]
+    fn fixture_resolve_and_read() {
+        let elf = fixture::elf();
+        let debug = DebugInfo::load(&elf).unwrap();
+        let address = elf.symbol_address("CONFIG").unwrap();
+        let initial = elf
+            .read(elf.symbol_address("__sidata").unwrap(), 20)
+            .unwrap();
+        let mut mem = MemoryDump::new().with_region(address, initial.to_vec());
+
+        let place = resolve(&debug, "CONFIG.limits[1].max").unwrap();
+        assert_eq!(place.address, address + 4 + 4 + 2);
+        assert_eq!(place.read(&mut mem).unwrap(), "100");
+        place.write(&mut mem, "-7").unwrap();
+        assert_eq!(place.read(&mut mem).unwrap(), "-7");
+        let mode = resolve(&debug, "CONFIG.mode").unwrap();
+        mode.write(&mut mem, "High").unwrap();
+        assert_eq!(mode.read(&mut mem).unwrap(), "High");
+        assert!(resolve(&debug, "CONFIG.limits[3]").is_err());
+        assert!(resolve(&debug, "CONFIG.nothing").is_err());
+
+        let Item::Static(place) = lookup(&elf, "CONFIG.gain").unwrap() else {
+            panic!("CONFIG.gain is not a static");
+        };
+        assert_eq!(place.read(&mut mem).unwrap(), "1.5");
+    }
+}
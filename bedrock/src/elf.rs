@@ -0,0 +1,256 @@
+//! Firmware ELF file access: build info, FLASH image range.
+
+use crate::Error;
+use crate::compact_info::find_compact_info_in;
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use bedrock_build_info::{BedrockBuildInfo, BedrockBuildInfoOwned, LinkMode, RegionKind};
+use object::read::elf::{ElfFile32, ProgramHeader};
+use object::{Endianness, Object, ObjectSection, ObjectSymbol, SectionKind, elf};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use wire_weaver::prelude::DeserializeShrinkWrap;
+
+/// Prefix of the defmt interned string holding full build info, see `bedrock_build::serialize_build_info`
+const FULL_INFO_PREFIX: &str = "build_info:";
+
+/// SRAM region of the Armv7-M / Armv8-M address map
+pub(crate) const CORTEX_M_SRAM: std::ops::Range<u64> = 0x2000_0000..0x4000_0000;
+
+pub struct FirmwareElf {
+    data: Vec<u8>,
+}
+
+impl FirmwareElf {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let data = fs::read(path)?;
+        Self::from_bytes(data)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, Error> {
+        // fail early on non-ELF files
+        object::File::parse(&*data)?;
+        Ok(FirmwareElf { data })
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn file(&self) -> object::File<'_> {
+        object::File::parse(&*self.data).expect("checked in constructor")
+    }
+
+    /// Compact build info as stored in FLASH, returns address and the whole record
+    pub fn compact_record(&self) -> Option<(u64, Vec<u8>)> {
+        let file = self.file();
+        for section in file.sections() {
+            if !matches!(
+                section.kind(),
+                SectionKind::ReadOnlyData | SectionKind::ReadOnlyString | SectionKind::Text
+            ) {
+                continue;
+            }
+            let Ok(data) = section.data() else {
+                continue;
+            };
+            if let Some((offset, envelope)) = find_compact_info_in(data) {
+                let record = data[offset..offset + envelope.total_len()].to_vec();
+                return Some((section.address() + offset as u64, record));
+            }
+        }
+        None
+    }
+
+    pub fn compact_info(&self) -> Option<BedrockBuildInfoOwned> {
+        let (_, record) = self.compact_record()?;
+        let envelope = bedrock_build_info::envelope::CompactEnvelope::parse(&record).ok()?;
+        envelope.decode().ok().map(|info| info.make_owned())
+    }
+
+    /// Full build info, saved in the ELF through defmt string interning
+    pub fn full_info(&self) -> Option<BedrockBuildInfoOwned> {
+        let file = self.file();
+        let defmt_section = file.section_by_name(".defmt")?.index();
+        for symbol in file.symbols() {
+            if symbol.section_index() != Some(defmt_section) {
+                continue;
+            }
+            let Ok(name) = symbol.name() else {
+                continue;
+            };
+            let Some(encoded) =
+                defmt_symbol_data(name).and_then(|data| data.strip_prefix(FULL_INFO_PREFIX))
+            else {
+                continue;
+            };
+            let Ok(bytes) = BASE64_STANDARD.decode(encoded) else {
+                continue;
+            };
+            if let Ok(info) = BedrockBuildInfo::from_ww_bytes(&bytes) {
+                return Some(info.make_owned());
+            }
+        }
+        None
+    }
+
+    /// Address of a named symbol, e.g. one defined in the linker script
+    pub fn symbol_address(&self, name: &str) -> Option<u64> {
+        self.file()
+            .symbols()
+            .find(|s| s.name() == Ok(name))
+            .map(|s| s.address())
+    }
+
+    /// Address of the `.vector_table` section, as placed by cortex-m-rt
+    pub fn vector_table(&self) -> Option<u64> {
+        self.file()
+            .section_by_name(".vector_table")
+            .map(|s| s.address())
+    }
+
+    /// Whether the firmware is linked to run from RAM (`RAM_LINK=1`), flashing such an image leaves the target
+    /// without a valid vector table.
+    ///
+    /// Uses build info if it is there, otherwise the vector table address and the Cortex-M SRAM address range.
+    pub fn is_ram_linked(&self) -> bool {
+        let info = self.full_info().or_else(|| self.compact_info());
+        if let Some(link_mode) = info.as_ref().and_then(|i| i.compiler_info.link_mode()) {
+            return link_mode == LinkMode::Ram;
+        }
+        let Some(vector_table) = self.vector_table() else {
+            return false;
+        };
+        // link_ram_cortex_m.x places the vector table at ORIGIN(RAM)
+        if self.symbol_address("_ram_start") == Some(vector_table) {
+            return true;
+        }
+        match info.filter(|i| !i.memory_map().is_empty()) {
+            Some(info) => info
+                .memory_map()
+                .iter()
+                .any(|r| r.kind == RegionKind::Ram && r.contains(vector_table)),
+            None => CORTEX_M_SRAM.contains(&vector_table),
+        }
+    }
+
+    /// Load address range of all the loadable data, i.e. FLASH image for a FLASH linked firmware
+    pub fn load_range(&self) -> Option<(u64, u64)> {
+        let elf = ElfFile32::<Endianness>::parse(&*self.data).ok()?;
+        let endian = elf.endian();
+        elf.elf_program_headers()
+            .iter()
+            .filter(|ph| ph.p_type(endian) == elf::PT_LOAD && ph.p_filesz(endian) > 0)
+            .map(|ph| {
+                let start = ph.p_paddr(endian) as u64;
+                (start, start + ph.p_filesz(endian) as u64)
+            })
+            .reduce(|(a_start, a_end), (b_start, b_end)| (a_start.min(b_start), a_end.max(b_end)))
+    }
+
+    /// Loadable segments as (load address, file offset, size)
+    pub(crate) fn load_segments(&self) -> Vec<(u64, usize, usize)> {
+        let Ok(elf) = ElfFile32::<Endianness>::parse(&*self.data) else {
+            return Vec::new();
+        };
+        let endian = elf.endian();
+        elf.elf_program_headers()
+            .iter()
+            .filter(|ph| ph.p_type(endian) == elf::PT_LOAD && ph.p_filesz(endian) > 0)
+            .map(|ph| {
+                (
+                    ph.p_paddr(endian) as u64,
+                    ph.p_offset(endian) as usize,
+                    ph.p_filesz(endian) as usize,
+                )
+            })
+            .collect()
+    }
+
+    /// Loaded bytes at an address, as they are after flashing or loading into RAM
+    pub fn read(&self, address: u64, len: usize) -> Option<&[u8]> {
+        self.load_segments()
+            .into_iter()
+            .find(|(start, _, size)| {
+                address >= *start && address + len as u64 <= start + *size as u64
+            })
+            .map(|(start, offset, _)| {
+                let at = offset + (address - start) as usize;
+                &self.data[at..at + len]
+            })
+    }
+
+    /// FLASH image from the first to the last loaded byte
+    pub fn flash_image(&self) -> Option<FlashImage> {
+        let (start, end) = self.load_range()?;
+        let mut bytes = vec![0xFF; (end - start) as usize];
+        for (address, offset, size) in self.load_segments() {
+            let at = (address - start) as usize;
+            bytes[at..at + size].copy_from_slice(&self.data[offset..offset + size]);
+        }
+        Some(FlashImage { start, bytes })
+    }
+
+    /// Firmware SHA: SHA-256 of the FLASH image, identifies the firmware in the local registry
+    pub fn fw_sha(&self) -> Option<[u8; 32]> {
+        let image = self.flash_image()?;
+        Some(Sha256::digest(&image.bytes).into())
+    }
+
+    /// Overwrite loaded bytes at a FLASH address, e.g. to fill in a signature after linking
+    pub fn patch(&mut self, address: u64, bytes: &[u8]) -> Result<(), Error> {
+        let (segment_address, offset, _) = self
+            .load_segments()
+            .into_iter()
+            .find(|(start, _, size)| {
+                address >= *start && address + bytes.len() as u64 <= start + *size as u64
+            })
+            .ok_or(Error::Unmapped {
+                address,
+                len: bytes.len(),
+            })?;
+        let at = offset + (address - segment_address) as usize;
+        self.data[at..at + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+/// Contents of FLASH as programmed from an ELF, gaps between segments are filled with 0xFF
+pub struct FlashImage {
+    pub start: u64,
+    pub bytes: Vec<u8>,
+}
+
+/// Extract `data` field from a defmt symbol name, which is a JSON object
+fn defmt_symbol_data(name: &str) -> Option<&str> {
+    symbol_json_field(name, "data")
+}
+
+/// String field of a symbol name that is a JSON object, as made by defmt and cnt macros
+pub(crate) fn symbol_json_field<'a>(name: &'a str, field: &str) -> Option<&'a str> {
+    let key = format!(r#""{field}":""#);
+    let start = name.find(&key)? + key.len();
+    let len = name[start..].find('"')?;
+    Some(&name[start..start + len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    fn fw_sha_covers_what_bedrock_rt_hashes() {
+        let elf = fixture::elf();
+        let symbol = |name| elf.symbol_address(name).unwrap();
+        // bedrock_rt::fault hashes from the vector table to the end of the .data initial values
+        let start = symbol("__vector_table");
+        let end = symbol("__sidata") + symbol("__edata") - symbol("__sdata");
+        let image = elf.flash_image().unwrap();
+        assert_eq!(image.start, start);
+        assert_eq!(image.bytes.len() as u64, end - start);
+        let hashed: [u8; 32] = Sha256::digest(&image.bytes).into();
+        assert_eq!(Some(hashed), elf.fw_sha());
+    }
+}
@@ -0,0 +1,187 @@
+use bedrock_build_info::BedrockBuildInfoOwned;
+use bedrock_build_info::envelope::EnvelopeError;
+use bedrock_build_info::signature::SignatureError;
+use probe_rs::flashing::FileDownloadError;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug)]
+pub enum Error {
+    Probe(probe_rs::Error),
+    Io(std::io::Error),
+    Elf(object::Error),
+    /// Memory range is not available in an offline memory dump or ELF image
+    Unmapped {
+        address: u64,
+        len: usize,
+    },
+    /// Compact build info was not found in the searched memory range
+    BuildInfoNotFound,
+    BuildInfo(EnvelopeError),
+    /// ELF does not contain full build info (e.g. stripped or built without bedrock_build)
+    FullInfoNotFound,
+    Registry(fw_registry::Error),
+    Signature(SignatureError),
+    /// Signing or public key file could not be read or has wrong format
+    KeyFile {
+        path: String,
+        reason: String,
+    },
+    /// Chip ID registers did not match any known chip
+    UnknownChip,
+    /// Chip ID is shared by parts that need different probe-rs targets, e.g. single and dual core
+    AmbiguousChip {
+        chip: String,
+        targets: [String; 2],
+    },
+    /// cargo build failed or did not produce a binary
+    Build(String),
+    Flash(FileDownloadError),
+    /// Firmware is linked for the other way of running it: flashing or loading into RAM
+    LinkMode {
+        ram_linked: bool,
+    },
+    Rtt(probe_rs::rtt::Error),
+    /// Firmware has no `_SEGGER_RTT` control block or up channel for logs
+    RttNotFound,
+    Defmt(String),
+    /// No registry entry has the compact build info read from the target
+    NotInRegistry(Box<BedrockBuildInfoOwned>),
+    /// Malformed `.bedlog` capture file
+    Bedlog(String),
+    /// Debug info missing or not understood
+    Dwarf(String),
+    /// Static, field or counter not found, or value that does not fit its type
+    Symbol(String),
+    /// DWT watchpoint cannot be set up
+    Watchpoint(String),
+    /// Malformed core dump file
+    CoreDump(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Probe(e) => write!(f, "probe: {e}"),
+            Error::Io(e) => write!(f, "io: {e}"),
+            Error::Elf(e) => write!(f, "elf: {e}"),
+            Error::Unmapped { address, len } => {
+                write!(
+                    f,
+                    "{len}B at 0x{address:08x} are not present in memory dump or ELF image"
+                )
+            }
+            Error::BuildInfoNotFound => write!(
+                f,
+                "build info not found, ensure firmware calls core::hint::black_box(build_info::compact())"
+            ),
+            Error::BuildInfo(e) => write!(f, "build info: {e:?}"),
+            Error::FullInfoNotFound => write!(
+                f,
+                "full build info not found in ELF, ensure it is not stripped and firmware uses build_info::full()"
+            ),
+            Error::Registry(e) => write!(f, "{e}"),
+            Error::Signature(e) => write!(f, "signature: {e}"),
+            Error::KeyFile { path, reason } => write!(f, "key file '{path}': {reason}"),
+            Error::UnknownChip => write!(f, "chip could not be identified, pass --chip"),
+            Error::AmbiguousChip {
+                chip,
+                targets: [a, b],
+            } => write!(
+                f,
+                "{chip} could be {a} or {b}, which have different cores or FLASH algorithms, pass --chip"
+            ),
+            Error::Build(reason) => write!(f, "{reason}"),
+            Error::Flash(e) => write!(f, "flashing: {e}"),
+            Error::LinkMode { ram_linked: true } => write!(
+                f,
+                "firmware is linked to run from RAM (RAM_LINK=1), use `bedrock run --ram` instead of flashing it"
+            ),
+            Error::LinkMode { ram_linked: false } => write!(
+                f,
+                "firmware is linked to run from FLASH, rebuild with RAM_LINK=1 to load it into RAM"
+            ),
+            Error::Rtt(e) => write!(f, "rtt: {e}"),
+            Error::RttNotFound => write!(
+                f,
+                "RTT control block not found, ensure firmware uses defmt-rtt or rtt-target"
+            ),
+            Error::Defmt(reason) => write!(f, "defmt: {reason}"),
+            Error::NotInRegistry(info) => {
+                write!(
+                    f,
+                    "firmware running on the target is not in the local registry: {}",
+                    info.crate_info
+                )?;
+                if let Some(vc) = &info.version_control {
+                    write!(f, ", commit {vc}")?;
+                }
+                write!(
+                    f,
+                    ", built at {:?}; pass its ELF file, or flash with `bedrock flash` to have it registered",
+                    info.timestamp
+                )
+            }
+            Error::Bedlog(reason) => write!(f, "log capture: {reason}"),
+            Error::Dwarf(reason) => write!(f, "debug info: {reason}"),
+            Error::Symbol(reason) => write!(f, "{reason}"),
+            Error::Watchpoint(reason) => write!(f, "watchpoint: {reason}"),
+            Error::CoreDump(reason) => write!(f, "core dump: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<probe_rs::Error> for Error {
+    fn from(e: probe_rs::Error) -> Self {
+        Error::Probe(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<object::Error> for Error {
+    fn from(e: object::Error) -> Self {
+        Error::Elf(e)
+    }
+}
+
+impl From<EnvelopeError> for Error {
+    fn from(e: EnvelopeError) -> Self {
+        Error::BuildInfo(e)
+    }
+}
+
+impl From<fw_registry::Error> for Error {
+    fn from(e: fw_registry::Error) -> Self {
+        Error::Registry(e)
+    }
+}
+
+impl From<SignatureError> for Error {
+    fn from(e: SignatureError) -> Self {
+        Error::Signature(e)
+    }
+}
+
+impl From<FileDownloadError> for Error {
+    fn from(e: FileDownloadError) -> Self {
+        Error::Flash(e)
+    }
+}
+
+impl From<probe_rs::rtt::Error> for Error {
+    fn from(e: probe_rs::rtt::Error) -> Self {
+        Error::Rtt(e)
+    }
+}
+
+impl From<gimli::Error> for Error {
+    fn from(e: gimli::Error) -> Self {
+        Error::Dwarf(e.to_string())
+    }
+}
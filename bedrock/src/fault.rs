@@ -0,0 +1,594 @@
+//! `bedrock fault`: why a halted or dumped core faulted, from the fault status registers in plain English, and where,
+//! from the stacked exception frame and a backtrace symbolized with the firmware debug info.
+
+use crate::Error;
+use crate::chip::CoreType;
+use crate::coredump::Registers;
+use crate::dwarf::{DebugInfo, SourceLocation};
+use crate::elf::FirmwareElf;
+use crate::mem::Memory;
+use crate::unwind::{Backtrace, ExceptionFrame, FpContext, stacked_size};
+use std::fmt::{Display, Formatter};
+
+/// Configurable fault status register: MMFSR, BFSR and UFSR a byte, byte and halfword
+const CFSR: u64 = 0xE000_ED28;
+/// HardFault status register
+const HFSR: u64 = 0xE000_ED2C;
+/// MemManage fault address register
+const MMFAR: u64 = 0xE000_ED34;
+/// BusFault address register
+const BFAR: u64 = 0xE000_ED38;
+/// SecureFault status and address registers, Armv8-M with the Security Extension
+const SFSR: u64 = 0xE000_EDE4;
+const SFAR: u64 = 0xE000_EDE8;
+
+const MMARVALID: u32 = 1 << 7;
+const BFARVALID: u32 = 1 << 15;
+const SFARVALID: u32 = 1 << 6;
+/// Fault addresses below this are likely a null pointer with a field or index offset
+const NULL_PAGE: u32 = 0x1000;
+
+/// Fault record kept over reset by the `bedrock_rt` fault handlers, layout in `bedrock_rt/src/fault.rs`
+const RECORD_SYMBOL: &str = "_BEDROCK_FAULT_RECORD";
+const RECORD_LEN: usize = 112;
+const RECORD_MAGIC: u32 = 0xB17D_FA17;
+const RECORD_PENDING: u32 = 1;
+const RECORD_REPORTED: u32 = 2;
+const RECORD_FRAME: u32 = 1 << 0;
+const RECORD_STATUS: u32 = 1 << 1;
+const RECORD_FW_SHA: u32 = 1 << 2;
+
+const MMFSR_BITS: &[(u32, &str, &str)] = &[
+    (
+        0,
+        "IACCVIOL",
+        "instruction fetch from memory the MPU forbids or that is never executable, e.g. a corrupt function pointer or return address",
+    ),
+    (
+        1,
+        "DACCVIOL",
+        "data access forbidden by the MPU, e.g. a stack guard region",
+    ),
+    (
+        3,
+        "MUNSTKERR",
+        "MPU violation when unstacking on exception return, the stack pointer was corrupted",
+    ),
+    (
+        4,
+        "MSTKERR",
+        "MPU violation when stacking on exception entry, likely a stack overflow into a guard region",
+    ),
+    (
+        5,
+        "MLSPERR",
+        "MPU violation during lazy floating point state preservation",
+    ),
+];
+
+const BFSR_BITS: &[(u32, &str, &str)] = &[
+    (8, "IBUSERR", "bus error on instruction fetch"),
+    (
+        9,
+        "PRECISERR",
+        "bus error on a data access by the stacked PC, e.g. an unmapped address or a peripheral with its clock disabled",
+    ),
+    (
+        10,
+        "IMPRECISERR",
+        "bus error on a buffered write, the stacked PC is somewhat after the writing instruction, often a peripheral with its clock disabled",
+    ),
+    (
+        11,
+        "UNSTKERR",
+        "bus error when unstacking on exception return, the stack pointer was corrupted",
+    ),
+    (
+        12,
+        "STKERR",
+        "bus error when stacking on exception entry, the stack pointer is outside of RAM: stack overflow",
+    ),
+    (
+        13,
+        "LSPERR",
+        "bus error during lazy floating point state preservation",
+    ),
+];
+
+const UFSR_BITS: &[(u32, &str, &str)] = &[
+    (
+        16,
+        "UNDEFINSTR",
+        "undefined instruction, e.g. executing data, or an instruction this core does not have (wrong target triple)",
+    ),
+    (
+        17,
+        "INVSTATE",
+        "jump to an address with bit 0 clear, e.g. a corrupt function pointer or vector table entry",
+    ),
+    (
+        18,
+        "INVPC",
+        "invalid EXC_RETURN on exception return, the stack or LR of a handler was corrupted",
+    ),
+    (
+        19,
+        "NOCP",
+        "floating point or coprocessor instruction with the coprocessor disabled, e.g. a hard float build without FPU enabled",
+    ),
+    (
+        20,
+        "STKOF",
+        "stack pointer went below its limit register (MSPLIM or PSPLIM): stack overflow",
+    ),
+    (
+        24,
+        "UNALIGNED",
+        "unaligned access with unaligned trapping enabled, or by an instruction that never allows it (LDM, STM, LDRD, STRD)",
+    ),
+    (25, "DIVBYZERO", "integer division by zero"),
+];
+
+const HFSR_BITS: &[(u32, &str, &str)] = &[
+    (
+        1,
+        "VECTTBL",
+        "bus error reading the vector table on exception entry, VTOR or the vector table is wrong",
+    ),
+    (
+        30,
+        "FORCED",
+        "escalated from a configurable fault whose handler is disabled or could not run, the configurable fault status tells which",
+    ),
+    (
+        31,
+        "DEBUGEVT",
+        "debug event without a debugger, e.g. a BKPT instruction",
+    ),
+];
+
+const SFSR_BITS: &[(u32, &str, &str)] = &[
+    (
+        0,
+        "INVEP",
+        "Non-secure code called a Secure address that is no valid entry point (SG instruction in NSC memory)",
+    ),
+    (
+        1,
+        "INVIS",
+        "invalid integrity signature on exception return, the Secure stack was corrupted",
+    ),
+    (
+        2,
+        "INVER",
+        "exception return from Non-secure state with an invalid EXC_RETURN",
+    ),
+    (
+        3,
+        "AUVIOL",
+        "Non-secure access to Secure memory, check the SAU and IDAU configuration",
+    ),
+    (
+        4,
+        "INVTRAN",
+        "branch to Non-secure code without BXNS or BLXNS",
+    ),
+    (
+        5,
+        "LSPERR",
+        "SAU or IDAU violation during lazy floating point state preservation",
+    ),
+    (7, "LSERR", "lazy floating point state preservation error"),
+];
+
+/// Fault status and address registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultStatus {
+    pub cfsr: u32,
+    pub hfsr: u32,
+    pub mmfar: u32,
+    pub bfar: u32,
+    /// Armv8-M with the Security Extension only
+    pub sfsr: Option<u32>,
+    pub sfar: Option<u32>,
+}
+
+impl FaultStatus {
+    pub fn read(mem: &mut impl Memory, core_type: Option<CoreType>) -> Result<Self, Error> {
+        let secure = core_type.is_some_and(|core_type| core_type.is_armv8m());
+        Ok(FaultStatus {
+            cfsr: mem.read_u32(CFSR)?,
+            hfsr: mem.read_u32(HFSR)?,
+            mmfar: mem.read_u32(MMFAR)?,
+            bfar: mem.read_u32(BFAR)?,
+            // not accessible from Non-secure debug, and not captured in every core dump
+            sfsr: secure.then(|| mem.read_u32(SFSR).ok()).flatten(),
+            sfar: secure.then(|| mem.read_u32(SFAR).ok()).flatten(),
+        })
+    }
+
+    /// Every fault status bit set, as `NAME: explanation`, HardFault first
+    pub fn causes(&self) -> Vec<String> {
+        let mut causes = Vec::new();
+        let mut explain = |register: u32, bits: &[(u32, &str, &str)]| {
+            for &(bit, name, explanation) in bits {
+                if register & (1 << bit) != 0 {
+                    causes.push(format!("{name}: {explanation}"));
+                }
+            }
+        };
+        explain(self.hfsr, HFSR_BITS);
+        explain(self.cfsr, MMFSR_BITS);
+        explain(self.cfsr, BFSR_BITS);
+        explain(self.cfsr, UFSR_BITS);
+        explain(self.sfsr.unwrap_or(0), SFSR_BITS);
+        let addresses = [
+            (self.cfsr & MMARVALID != 0, "MMFAR", self.mmfar),
+            (self.cfsr & BFARVALID != 0, "BFAR", self.bfar),
+            (
+                self.sfsr.unwrap_or(0) & SFARVALID != 0,
+                "SFAR",
+                self.sfar.unwrap_or(0),
+            ),
+        ];
+        for (valid, name, address) in addresses {
+            if !valid {
+                continue;
+            }
+            let null = if address < NULL_PAGE {
+                ", close to 0: likely a null pointer dereference"
+            } else {
+                ""
+            };
+            causes.push(format!("faulting address 0x{address:08x} ({name}){null}"));
+        }
+        causes
+    }
+}
+
+impl Display for FaultStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CFSR 0x{:08x}  HFSR 0x{:08x}", self.cfsr, self.hfsr)?;
+        if let Some(sfsr) = self.sfsr {
+            write!(f, "  SFSR 0x{sfsr:08x}")?;
+        }
+        writeln!(f)?;
+        let causes = self.causes();
+        if causes.is_empty() {
+            writeln!(f, "  no fault status bits set")?;
+        }
+        for cause in causes {
+            writeln!(f, "  {cause}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Name of the active exception, from IPSR
+pub fn exception_name(exception: u32) -> String {
+    match exception {
+        0 => "thread mode".into(),
+        1 => "Reset".into(),
+        2 => "NMI".into(),
+        3 => "HardFault".into(),
+        4 => "MemManage".into(),
+        5 => "BusFault".into(),
+        6 => "UsageFault".into(),
+        7 => "SecureFault".into(),
+        11 => "SVCall".into(),
+        12 => "DebugMonitor".into(),
+        14 => "PendSV".into(),
+        15 => "SysTick".into(),
+        16.. => format!("interrupt {}", exception - 16),
+        _ => format!("reserved exception {exception}"),
+    }
+}
+
+fn is_fault(exception: u32) -> bool {
+    (3..=7).contains(&exception)
+}
+
+pub struct FaultReport {
+    /// Active exception number, from IPSR
+    pub exception: u32,
+    pub core_type: Option<CoreType>,
+    /// None on cores with HardFault only, they have no fault status registers
+    pub status: Option<FaultStatus>,
+    pub backtrace: Backtrace,
+}
+
+/// Fault status and backtrace of a halted core or a core dump
+pub fn analyze(
+    mem: &mut impl Memory,
+    elf: &FirmwareElf,
+    registers: &Registers,
+) -> Result<FaultReport, Error> {
+    let core_type = CoreType::read(mem)?;
+    let status = match core_type {
+        Some(core_type) if !core_type.has_configurable_faults() => None,
+        _ => Some(FaultStatus::read(mem, core_type)?),
+    };
+    let debug = DebugInfo::load(elf).ok();
+    let armv8m = core_type.is_some_and(|core_type| core_type.is_armv8m());
+    let backtrace = Backtrace::capture(mem, elf, debug.as_ref(), registers, armv8m);
+    Ok(FaultReport {
+        exception: registers.xpsr & 0x1FF,
+        core_type,
+        status,
+        backtrace,
+    })
+}
+
+impl Display for FaultReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let exception = exception_name(self.exception);
+        if is_fault(self.exception) {
+            writeln!(f, "Core is in the {exception} handler")?;
+        } else {
+            writeln!(
+                f,
+                "Core is in {exception}, not in a fault handler, fault status may be from an earlier fault"
+            )?;
+        }
+        match (&self.status, self.core_type) {
+            (Some(status), _) => write!(f, "{status}")?,
+            (None, Some(core_type)) => writeln!(
+                f,
+                "{} has no fault status registers, the cause has to be found from the faulting code: \
+                 common ones are unaligned accesses, bus errors, undefined instructions and SVC in a handler",
+                core_type.name
+            )?,
+            (None, None) => {}
+        }
+        // the innermost exception entry is where the fault happened
+        if let Some(faulted) = self
+            .backtrace
+            .frames
+            .iter()
+            .find(|frame| frame.interrupted.is_some())
+            && let Some(exception_frame) = &faulted.interrupted
+        {
+            writeln!(f, "\nFaulting code: {faulted}")?;
+            write!(f, "{exception_frame}")?;
+        }
+        write!(f, "\nBacktrace:\n{}", self.backtrace)
+    }
+}
+
+/// Fault saved by the `bedrock_rt` fault handlers before they reset, read after reboot
+#[derive(Debug, Clone)]
+pub struct LastFault {
+    /// Resets since power-on, now and when the fault happened
+    pub resets: u32,
+    pub resets_at_fault: u32,
+    /// Firmware logged the fault on boot with `bedrock_rt::fault::report_last`
+    pub reported: bool,
+    pub exception: u32,
+    /// None if stacking the frame failed, e.g. on stack overflow
+    pub frame: Option<ExceptionFrame>,
+    /// None on cores with HardFault only
+    pub status: Option<FaultStatus>,
+    /// Firmware that faulted, may differ from the one running now
+    pub fw_sha: Option<[u8; 32]>,
+    pub pc_location: Option<SourceLocation>,
+    pub lr_location: Option<SourceLocation>,
+}
+
+impl LastFault {
+    /// Fault record of a firmware using `bedrock_rt`, None if there was no fault since power-on
+    pub fn read(mem: &mut impl Memory, elf: &FirmwareElf) -> Result<Option<Self>, Error> {
+        let address = elf.symbol_address(RECORD_SYMBOL).ok_or_else(|| {
+            Error::Symbol(format!(
+                "no {RECORD_SYMBOL}, firmware does not use the bedrock_rt fault handlers"
+            ))
+        })?;
+        let mut bytes = [0u8; RECORD_LEN];
+        mem.read(address, &mut bytes)?;
+        Ok(Self::parse(&bytes))
+    }
+
+    pub fn parse(bytes: &[u8; RECORD_LEN]) -> Option<Self> {
+        let word = |n: usize| u32::from_le_bytes(bytes[n * 4..n * 4 + 4].try_into().unwrap());
+        let state = word(2);
+        if word(0) != RECORD_MAGIC || !matches!(state, RECORD_PENDING | RECORD_REPORTED) {
+            return None;
+        }
+        let (exc_return, address, flags) = (word(5), word(6), word(19));
+        let frame = (flags & RECORD_FRAME != 0).then(|| {
+            let stacked: [u32; 8] = std::array::from_fn(|n| word(7 + n));
+            ExceptionFrame {
+                address,
+                exc_return,
+                stacked,
+                // lazy stacking is not told apart by the handler
+                fp: if exc_return & (1 << 4) == 0 {
+                    FpContext::Stacked
+                } else {
+                    FpContext::None
+                },
+                caller_sp: address + stacked_size(exc_return, stacked[7]),
+            }
+        });
+        let status = (flags & RECORD_STATUS != 0).then(|| FaultStatus {
+            cfsr: word(15),
+            hfsr: word(16),
+            mmfar: word(17),
+            bfar: word(18),
+            sfsr: None,
+            sfar: None,
+        });
+        Some(LastFault {
+            resets: word(1),
+            resets_at_fault: word(3),
+            reported: state == RECORD_REPORTED,
+            exception: word(4),
+            frame,
+            status,
+            fw_sha: (flags & RECORD_FW_SHA != 0).then(|| bytes[80..112].try_into().unwrap()),
+            pc_location: None,
+            lr_location: None,
+        })
+    }
+
+    /// Source locations of the stacked PC and LR, with the debug info of the firmware that faulted
+    pub fn symbolize(&mut self, debug: &DebugInfo) {
+        if let Some(frame) = &self.frame {
+            self.pc_location = debug.locate(frame.pc() as u64).ok();
+            // return address, after the call
+            self.lr_location = debug
+                .locate(((frame.lr() & !1) as u64).saturating_sub(1))
+                .ok();
+        }
+    }
+}
+
+impl Display for LastFault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Last fault: {}", exception_name(self.exception))?;
+        match self.resets.wrapping_sub(self.resets_at_fault) {
+            0 => writeln!(f, ", the firmware has not booted since")?,
+            1 => writeln!(f, ", caused the last reset")?,
+            n => writeln!(f, ", {n} resets ago")?,
+        }
+        writeln!(
+            f,
+            "{} resets since power-on before the fault",
+            self.resets_at_fault
+        )?;
+        if !self.reported {
+            writeln!(
+                f,
+                "Not reported on boot, call bedrock_rt::fault::report_last() early in main"
+            )?;
+        }
+        match &self.status {
+            Some(status) => write!(f, "{status}")?,
+            None => writeln!(f, "Core has no fault status registers")?,
+        }
+        let Some(frame) = &self.frame else {
+            return writeln!(
+                f,
+                "Exception frame not saved, stacking it failed: the stack pointer was out of RAM, likely a stack overflow"
+            );
+        };
+        write!(f, "\nFaulting code: 0x{:08x}", frame.pc())?;
+        if let Some(location) = &self.pc_location {
+            write!(f, " {location}")?;
+        }
+        write!(f, "\nLR:            0x{:08x}", frame.lr())?;
+        if let Some(location) = &self.lr_location {
+            write!(f, " {location}")?;
+        }
+        writeln!(f)?;
+        write!(f, "{frame}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    fn fault_causes() {
+        // imprecise bus error escalated to HardFault
+        let status = FaultStatus {
+            cfsr: 1 << 10,
+            hfsr: 1 << 30,
+            mmfar: 0,
+            bfar: 0,
+            sfsr: None,
+            sfar: None,
+        };
+        let causes = status.causes();
+        assert_eq!(causes.len(), 2);
+        assert!(causes[0].starts_with("FORCED"));
+        assert!(causes[1].starts_with("IMPRECISERR"));
+
+        // precise read of a null pointer field
+        let status = FaultStatus {
+            cfsr: (1 << 9) | BFARVALID,
+            hfsr: 0,
+            mmfar: 0,
+            bfar: 0x10,
+            sfsr: None,
+            sfar: None,
+        };
+        let causes = status.causes();
+        assert!(causes[0].starts_with("PRECISERR"));
+        assert_eq!(
+            causes[1],
+            "faulting address 0x00000010 (BFAR), close to 0: likely a null pointer dereference"
+        );
+
+        assert_eq!(exception_name(5), "BusFault");
+    }
+
+    #[test]
+    fn last_fault_record() {
+        let mut words = [0u32; RECORD_LEN / 4];
+        words[..7].copy_from_slice(&[
+            RECORD_MAGIC,
+            4,
+            RECORD_REPORTED,
+            3,
+            6,
+            0xFFFF_FFFD,
+            0x2000_1000,
+        ]);
+        words[7..15].copy_from_slice(&[0, 1, 2, 3, 12, 0x0800_0121, 0x0800_0200, 0x0100_0000]);
+        words[15] = 1 << 25;
+        words[19] = RECORD_FRAME | RECORD_STATUS;
+        let mut bytes = [0u8; RECORD_LEN];
+        for (chunk, word) in bytes.chunks_mut(4).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        let last = LastFault::parse(&bytes).unwrap();
+        assert!(last.reported);
+        assert_eq!(last.fw_sha, None);
+        let frame = last.frame.as_ref().unwrap();
+        assert!(frame.process_stack());
+        assert_eq!(frame.pc(), 0x0800_0200);
+        assert!(last.status.unwrap().causes()[0].starts_with("DIVBYZERO"));
+        assert!(
+            last.to_string()
+                .starts_with("Last fault: UsageFault, caused the last reset")
+        );
+
+        bytes[8] = 0;
+        assert!(LastFault::parse(&bytes).is_none());
+        assert_eq!(exception_name(16 + 37), "interrupt 37");
+    }
+
+    #[test]
+    fn fixture_bus_fault() {
+        let (mut mem, dump, elf) = fixture::core_memory();
+        let report = analyze(&mut mem, &elf, &dump.registers).unwrap();
+        assert_eq!(report.exception, 5);
+        assert_eq!(report.core_type.unwrap().name, "Cortex-M4");
+        let causes = report.status.as_ref().unwrap().causes();
+        assert!(causes[0].starts_with("PRECISERR"));
+        assert_eq!(causes[1], "faulting address 0x30000000 (BFAR)");
+        let text = report.to_string();
+        assert!(text.starts_with("Core is in the BusFault handler\n"));
+        assert!(text.contains("Faulting code: 0x08000474 fixture::read_sensor at "));
+    }
+
+    #[test]
+    fn fixture_last_fault() {
+        let (mut mem, _, elf) = fixture::core_memory();
+        let mut last = LastFault::read(&mut mem, &elf).unwrap().unwrap();
+        assert!(last.reported);
+        assert_eq!((last.resets, last.resets_at_fault), (3, 1));
+        assert_eq!(last.exception, 5);
+        assert_eq!(last.fw_sha, elf.fw_sha());
+        assert_eq!(last.frame.as_ref().unwrap().pc(), fixture::FAULT_PC);
+        last.symbolize(&DebugInfo::load(&elf).unwrap());
+        let function = |location: &Option<SourceLocation>| location.clone().unwrap().function;
+        assert_eq!(function(&last.pc_location).unwrap(), "fixture::read_sensor");
+        assert_eq!(
+            function(&last.lr_location).unwrap(),
+            "fixture::__cortex_m_rt_main"
+        );
+    }
+}
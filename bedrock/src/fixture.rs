@@ -0,0 +1,215 @@
+//! Test fixtures: the firmware built from `fixtures/fw` and a core dump of it, see `fixtures/README.md`.
+//!
+//! The core dump is put together here from the ELF, so that it matches the checked in firmware. The code addresses
+//! and the stack frames follow the disassembly and the call frame information of `fixtures/fixture.elf`: the core
+//! halted at the entry of BusFault, raised by the load from the sensor address in `read_sensor`, called from `main`.
+
+use crate::coredump::{CoreDump, Registers};
+use crate::counters::counters;
+use crate::elf::FirmwareElf;
+use crate::mem::{Memory, MemoryDump};
+use crate::stack::PAINT;
+use bedrock_build_info::envelope::{self, EnvelopeFlags};
+use bedrock_build_info::{
+    BedrockBuildInfoOwned, CompilerChannel, CompilerInfoOwned, CrateInfoOwned, OptimizationLevel,
+    Profile, TargetInfoOwned,
+};
+use ww_date_time::DateTime;
+use ww_version::VersionOwned;
+
+pub const ELF: &[u8] = include_bytes!("../fixtures/fixture.elf");
+pub const CORE: &[u8] = include_bytes!("../fixtures/fixture.core");
+/// The firmware built with the pitfalls `bedrock elf lint` reports
+pub const LINT_ELF: &[u8] = include_bytes!("../fixtures/fixture_lint.elf");
+pub const RAM_ELF: &[u8] = include_bytes!("../fixtures/fixture_ram.elf");
+
+/// Address `read_sensor` loads from, nothing is mapped there
+pub const SENSOR: u32 = 0x3000_0000;
+pub const BUS_FAULT: u32 = 0x0800_1820;
+/// The faulting `ldr r0, [r0]` in `read_sensor`
+pub const FAULT_PC: u32 = 0x0800_0474;
+/// After the call of `read_sensor` in `__cortex_m_rt_main`, after the call of that in `main`, and after the call of
+/// `main` in `Reset`
+pub const RETURN_TO_RT_MAIN: u32 = 0x0800_076E;
+pub const RETURN_TO_MAIN: u32 = 0x0800_07E4;
+pub const RETURN_TO_RESET: u32 = 0x0800_044C;
+/// Stack pointer at the exception entry, below the frame of `read_sensor`
+pub const MSP: u32 = 0x2000_3EF8;
+/// Lowest address the stack reached before, paint left below it
+pub const PEAK: u32 = 0x2000_3C00;
+
+pub const SAMPLES_TAKEN: u64 = 42;
+pub const OVER_LIMIT: u64 = (1 << 32) + 5;
+
+const CFSR_PRECISERR_BFARVALID: u32 = 0x8200;
+
+pub fn elf() -> FirmwareElf {
+    FirmwareElf::from_bytes(ELF.to_vec()).unwrap()
+}
+
+pub fn core() -> CoreDump {
+    CoreDump::parse(CORE).unwrap()
+}
+
+/// The checked in core dump and the firmware it was taken from, as `--core` analyzes them
+pub fn core_memory() -> (MemoryDump, CoreDump, FirmwareElf) {
+    let (dump, elf) = (core(), elf());
+    (dump.memory_with_firmware(&elf), dump, elf)
+}
+
+/// Build info of the firmware `name` with only the fields every build has
+pub fn build_info(name: &str) -> BedrockBuildInfoOwned {
+    BedrockBuildInfoOwned {
+        timestamp: DateTime::from_ymd_hms_utc_opt(2025, 10, 9, 8, 30, 0, 0).unwrap(),
+        profile: Profile::Release,
+        optimization_level: OptimizationLevel::Oz,
+        crate_info: CrateInfoOwned {
+            name: name.into(),
+            version: VersionOwned::new(1, 2, 3),
+            authors: vec![],
+            enabled_features: vec![],
+            dependencies: vec![],
+            ext: None,
+        },
+        target_info: TargetInfoOwned {
+            triple: Some("thumbv7em-none-eabihf".into()),
+            arch: Some("arm".into()),
+        },
+        compiler_info: CompilerInfoOwned {
+            version: VersionOwned::new(1, 90, 0),
+            channel: CompilerChannel::Stable,
+            host_triple: None,
+            commit_date: None,
+            flip_link: false,
+            ext: None,
+        },
+        version_control: None,
+        ext: None,
+    }
+}
+
+/// Compact record of `info`, as bedrock_build places it into FLASH
+pub fn compact_record(info: &BedrockBuildInfoOwned) -> Vec<u8> {
+    envelope::encode(&info.to_ww_vec().unwrap(), EnvelopeFlags(0))
+}
+
+fn write(mem: &mut MemoryDump, address: u64, words: &[u32]) {
+    let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+    mem.write(address, &bytes).unwrap();
+}
+
+/// Core dump of the fixture firmware, as `bedrock coredump` would save it when halted on the BusFault
+pub fn synthesize(elf: &FirmwareElf) -> CoreDump {
+    let symbol = |name| elf.symbol_address(name).unwrap();
+    let (ram_start, ram_end) = (0x2000_0000u64, symbol("_stack_start"));
+    let mut memory = MemoryDump::new()
+        .with_region(ram_start, vec![0; (ram_end - ram_start) as usize])
+        .with_region(0xE000_ED00, vec![0; 0x40])
+        .with_region(0xE000_EF34, vec![0; 8]);
+
+    // statics: .data as initialized, then the samples and counters so far
+    let len = symbol("__edata") - symbol("__sdata");
+    let initial = elf.read(symbol("__sidata"), len as usize).unwrap();
+    memory.write(symbol("__sdata"), initial).unwrap();
+    write(&mut memory, symbol("SAMPLES"), &[SAMPLES_TAKEN as u32 - 1]);
+    for counter in counters(elf) {
+        let value = match counter.name.as_str() {
+            "samples_taken" => SAMPLES_TAKEN,
+            "over_limit" => OVER_LIMIT,
+            name => panic!("unexpected counter {name}"),
+        };
+        counter.write(&mut memory, elf, value).unwrap();
+    }
+
+    // paint up to the peak, then the stack from the top: Reset calls main, main calls __cortex_m_rt_main, which
+    // reserves 208 bytes for locals and calls read_sensor. Each pushes {r7, lr} and points r7 at the pushed r7.
+    let stack_end = symbol("_stack_end");
+    write(
+        &mut memory,
+        stack_end,
+        &vec![PAINT; (PEAK as u64 - stack_end) as usize / 4],
+    );
+    let main_r7 = ram_end as u32 - 8;
+    write(&mut memory, main_r7 as u64, &[0, RETURN_TO_RESET | 1]);
+    let rt_main_r7 = main_r7 - 8;
+    write(
+        &mut memory,
+        rt_main_r7 as u64,
+        &[main_r7, RETURN_TO_MAIN | 1],
+    );
+    let read_sensor_r7 = rt_main_r7 - 208 - 8;
+    write(
+        &mut memory,
+        read_sensor_r7 as u64,
+        &[rt_main_r7, RETURN_TO_RT_MAIN | 1],
+    );
+    // r0-r3, r12, lr, pc, xpsr in Thread mode
+    let stacked = [
+        SENSOR,
+        0,
+        0,
+        0,
+        0,
+        RETURN_TO_RT_MAIN | 1,
+        FAULT_PC,
+        0x0100_0000,
+    ];
+    assert_eq!(MSP + 32, read_sensor_r7);
+    write(&mut memory, MSP as u64, &stacked);
+
+    // Cortex-M4 with FPU: CPUID, ICSR with BusFault active, SHCSR with the faults enabled, CFSR and BFAR, FPCCR
+    write(&mut memory, 0xE000_ED00, &[0x410F_C241, 5]);
+    write(
+        &mut memory,
+        0xE000_ED24,
+        &[0x0007_0002, CFSR_PRECISERR_BFARVALID, 0, 0, 0, SENSOR],
+    );
+    write(&mut memory, 0xE000_EF34, &[0xC000_0000]);
+
+    // the same fault in an earlier boot, recorded by the bedrock_rt handlers and reported on the boot after it
+    let mut record = vec![0xB17D_FA17, 3, 2, 1, 5, 0xFFFF_FFF9, MSP];
+    record.extend(stacked);
+    record.extend([CFSR_PRECISERR_BFARVALID, 0, 0, SENSOR, 0b111]);
+    let fw_sha = elf.fw_sha().unwrap();
+    let address = symbol("_BEDROCK_FAULT_RECORD");
+    write(&mut memory, address, &record);
+    memory.write(address + 80, &fw_sha).unwrap();
+
+    let mut r = [0; 16];
+    r[0] = SENSOR;
+    r[7] = read_sensor_r7;
+    r[13] = MSP;
+    r[14] = 0xFFFF_FFF9;
+    r[15] = BUS_FAULT;
+    CoreDump {
+        memory,
+        registers: Registers {
+            r,
+            xpsr: 0x0100_0005,
+            msp: MSP,
+            psp: 0,
+            special: 0,
+        },
+        fw_sha: Some(fw_sha),
+        record: elf.compact_record().unwrap().1,
+    }
+}
+
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_is_up_to_date() {
+        assert!(
+            synthesize(&elf()).to_elf() == CORE,
+            "fixtures/fixture.core does not match fixtures/fixture.elf, run the ignored write_core test"
+        );
+    }
+
+    #[test]
+    #[ignore = "writes fixtures/fixture.core"]
+    fn write_core() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/fixture.core");
+        std::fs::write(path, synthesize(&elf()).to_elf()).unwrap();
+    }
+}
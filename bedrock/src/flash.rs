@@ -0,0 +1,81 @@
+//! `bedrock flash`: build with cargo, store the firmware in the local registry, flash and check what was flashed.
+//!
+//! Every flashed image goes through the registry, so that logs and build info of a device can always be decoded later.
+
+use crate::Error;
+use crate::elf::FirmwareElf;
+use fw_registry::{Entry, Registry};
+use probe_rs::Session;
+use probe_rs::flashing::{FormatKind, download_file};
+use serde_json::Value;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BuildOptions {
+    pub release: bool,
+    /// Build the bootloader crate from the `bootloader` directory instead of the application
+    pub bootloader: bool,
+    /// Link to run from RAM, see `bedrock run --ram`
+    pub ram_link: bool,
+}
+
+/// Run `cargo build` in the current directory and return the path of the built binary.
+///
+/// Compiler messages are shown as usual, the binary is found from cargo JSON messages.
+pub fn cargo_build(options: BuildOptions) -> Result<PathBuf, Error> {
+    let mut cmd = Command::new(std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into()));
+    cmd.args(["build", "--message-format=json-render-diagnostics"]);
+    if options.release {
+        cmd.arg("--release");
+    }
+    if options.bootloader {
+        cmd.args(["--manifest-path", "bootloader/Cargo.toml"]);
+    }
+    if options.ram_link {
+        cmd.env("RAM_LINK", "1");
+    }
+    let mut child = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let mut executable = None;
+    for line in BufReader::new(stdout).lines() {
+        let line = line?;
+        let Ok(message) = serde_json::from_str::<Value>(&line) else {
+            // rendered diagnostics and build script output are passed through
+            println!("{line}");
+            continue;
+        };
+        if message["reason"] == "compiler-artifact"
+            && let Some(path) = message["executable"].as_str()
+        {
+            executable = Some(PathBuf::from(path));
+        }
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(Error::Build(format!("cargo build failed: {status}")));
+    }
+    executable.ok_or_else(|| Error::Build("cargo build did not produce a binary".into()))
+}
+
+/// Store ELF and its full build info in the registry under the firmware SHA
+pub fn register(elf: &FirmwareElf, registry: &Registry) -> Result<Entry, Error> {
+    let sha = elf.fw_sha().ok_or(Error::BuildInfoNotFound)?;
+    let build_info = elf.full_info().and_then(|info| info.to_ww_vec().ok());
+    Ok(registry.push(&hex::encode(sha), elf.bytes(), build_info.as_deref())?)
+}
+
+/// Erase and program FLASH from an ELF file, then reset the target.
+/// RAM linked firmware is refused, its FLASH image would not boot.
+pub fn flash(session: &mut Session, elf: &FirmwareElf, elf_path: &Path) -> Result<(), Error> {
+    if elf.is_ram_linked() {
+        return Err(Error::LinkMode { ram_linked: true });
+    }
+    download_file(session, elf_path, FormatKind::Elf)?;
+    session.core(0)?.reset()?;
+    Ok(())
+}
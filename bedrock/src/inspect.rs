@@ -0,0 +1,701 @@
+//! `bedrock read` and `bedrock write`: statics printed with their DWARF types, and raw memory as hexdump.
+//!
+//! Everything goes through [Memory], so the same works on a live target and on an offline dump.
+
+use crate::Error;
+use crate::counters::{Counter, counters};
+use crate::dwarf::{DebugInfo, Encoding, Member, Type};
+use crate::elf::FirmwareElf;
+use crate::mem::Memory;
+use object::{Object, ObjectSymbol};
+use std::fmt::Write;
+
+/// Arrays longer than this are cut short when printed
+const MAX_ARRAY_ELEMENTS: u64 = 64;
+/// Structs rendered shorter than this are printed on one line
+const ONE_LINE_WIDTH: usize = 80;
+
+/// What `bedrock read` and `bedrock write` act on
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Counter(Counter),
+    /// Static with debug info
+    Static(Place),
+    /// ELF symbol without debug info, e.g. defined in a linker script
+    Symbol {
+        name: String,
+        address: u64,
+        size: u64,
+    },
+}
+
+/// Raw address, `0x2000_0000` or decimal
+pub fn parse_address(what: &str) -> Option<u64> {
+    if !what.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    parse_uint(what).and_then(|v| u64::try_from(v).ok())
+}
+
+/// Find a counter by name, a static (field) path in debug info, or else a plain ELF symbol
+pub fn lookup(elf: &FirmwareElf, what: &str) -> Result<Item, Error> {
+    if let Some(counter) = counters(elf).into_iter().find(|c| c.name == what) {
+        return Ok(Item::Counter(counter));
+    }
+    let resolved = DebugInfo::load(elf).and_then(|debug| resolve(&debug, what));
+    let Err(e) = resolved else {
+        return resolved.map(Item::Static);
+    };
+    let file = elf.file();
+    let Some(symbol) = file.symbols().find(|s| s.name() == Ok(what)) else {
+        return Err(e);
+    };
+    Ok(Item::Symbol {
+        name: what.to_string(),
+        address: symbol.address(),
+        size: symbol.size(),
+    })
+}
+
+impl Item {
+    /// Value as printed by `bedrock read`, symbols without a size are dumped for `len` bytes
+    pub fn read(
+        &self,
+        mem: &mut impl Memory,
+        elf: &FirmwareElf,
+        len: u64,
+    ) -> Result<String, Error> {
+        match self {
+            Item::Counter(counter) => {
+                Ok(format!("{} = {}\n", counter.name, counter.read(mem, elf)?))
+            }
+            Item::Static(place) => Ok(format!("{} = {}\n", place.path, place.read(mem)?)),
+            Item::Symbol { address, size, .. } => {
+                let len = if *size == 0 { len } else { *size };
+                let mut bytes = vec![0u8; len as usize];
+                mem.read(*address, &mut bytes)?;
+                Ok(hexdump(*address, &bytes))
+            }
+        }
+    }
+
+    pub fn write(
+        &self,
+        mem: &mut impl Memory,
+        elf: &FirmwareElf,
+        value: &str,
+    ) -> Result<(), Error> {
+        match self {
+            Item::Counter(counter) => {
+                let value = parse_uint(value)
+                    .and_then(|v| u64::try_from(v).ok())
+                    .ok_or_else(|| Error::Symbol(format!("{value} is not a counter value")))?;
+                counter.write(mem, elf, value)
+            }
+            Item::Static(place) => place.write(mem, value),
+            Item::Symbol { name, .. } => Err(Error::Symbol(format!(
+                "{name} has no debug info, its type is not known"
+            ))),
+        }
+    }
+}
+
+/// Static, or a field or element of it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Place {
+    pub path: String,
+    pub address: u64,
+    pub ty: Type,
+}
+
+/// Resolve `NAME`, `NAME.field.0` or `NAME.table[3].x`, where NAME is a static path or the end of it
+pub fn resolve(debug: &DebugInfo, path: &str) -> Result<Place, Error> {
+    let split = path.find(['.', '[']).unwrap_or(path.len());
+    let found = debug.find(&path[..split])?;
+    let mut place = Place {
+        path: found.name.clone(),
+        address: found.address,
+        ty: debug.type_of(found)?,
+    };
+    let mut rest = &path[split..];
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            place = place.field(&after[..end])?;
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after
+                .find(']')
+                .ok_or_else(|| Error::Symbol(format!("missing ] in {path}")))?;
+            let index = parse_uint(&after[..end])
+                .ok_or_else(|| Error::Symbol(format!("bad index in {path}")))?;
+            place = place.element(index as u64)?;
+            rest = &after[end + 1..];
+        } else {
+            return Err(Error::Symbol(format!("cannot parse {path}")));
+        }
+    }
+    Ok(place)
+}
+
+impl Place {
+    fn field(self, name: &str) -> Result<Place, Error> {
+        let ty = transparent(&self.ty);
+        let Type::Struct { members, .. } = ty else {
+            return Err(Error::Symbol(format!(
+                "{} is {}, it has no fields",
+                self.path,
+                ty.name()
+            )));
+        };
+        // tuple fields are named __0, __1, ...
+        let tuple_name = format!("__{name}");
+        let member = members
+            .iter()
+            .find(|m| m.name == name || m.name == tuple_name)
+            .ok_or_else(|| Error::Symbol(format!("{} has no field {name}", self.path)))?;
+        Ok(Place {
+            path: format!("{}.{name}", self.path),
+            address: self.address + member.offset,
+            ty: member.ty.clone(),
+        })
+    }
+
+    fn element(self, index: u64) -> Result<Place, Error> {
+        let Type::Array { element, count } = &self.ty else {
+            return Err(Error::Symbol(format!(
+                "{} is {}, not an array",
+                self.path,
+                self.ty.name()
+            )));
+        };
+        if index >= *count {
+            return Err(Error::Symbol(format!(
+                "index {index} is out of bounds, {} has {count} elements",
+                self.path
+            )));
+        }
+        Ok(Place {
+            path: format!("{}[{index}]", self.path),
+            address: self.address + index * element.size(),
+            ty: (**element).clone(),
+        })
+    }
+
+    pub fn read(&self, mem: &mut impl Memory) -> Result<String, Error> {
+        let mut bytes = vec![0u8; self.ty.size() as usize];
+        mem.read(self.address, &mut bytes)?;
+        Ok(format_value(&self.ty, &bytes))
+    }
+
+    /// Write a number, bool, char or enumerator name, also through atomics and cells wrapping them
+    pub fn write(&self, mem: &mut impl Memory, value: &str) -> Result<(), Error> {
+        let (offset, bytes) = encode(&self.ty, value)?;
+        mem.write(self.address + offset, &bytes)
+    }
+}
+
+/// Value formatted like `{:#?}` would, small structs on one line
+pub fn format_value(ty: &Type, bytes: &[u8]) -> String {
+    let mut out = String::new();
+    format_into(&mut out, ty, bytes, 0);
+    out
+}
+
+fn format_into(out: &mut String, ty: &Type, bytes: &[u8], indent: usize) {
+    match ty {
+        Type::Base { encoding, size, .. } => {
+            let raw = uint(bytes, 0, *size);
+            let _ = match encoding {
+                Encoding::Unsigned => write!(out, "{raw}"),
+                Encoding::Signed => write!(out, "{}", sign_extend(raw, *size)),
+                Encoding::Float if *size == 4 => write!(out, "{}", f32::from_bits(raw as u32)),
+                Encoding::Float => write!(out, "{}", f64::from_bits(raw as u64)),
+                Encoding::Bool if raw <= 1 => write!(out, "{}", raw == 1),
+                Encoding::Bool => write!(out, "<invalid bool 0x{raw:02x}>"),
+                Encoding::Char => match char::from_u32(raw as u32) {
+                    Some(c) => write!(out, "{c:?}"),
+                    None => write!(out, "<invalid char 0x{raw:x}>"),
+                },
+            };
+        }
+        Type::Pointer { size, .. } => {
+            let _ = write!(out, "0x{:08x}", uint(bytes, 0, *size));
+        }
+        Type::Enumeration {
+            name,
+            size,
+            enumerators,
+        } => {
+            let raw = uint(bytes, 0, *size) as u64;
+            let _ = match enumerators.iter().find(|(value, _)| *value == raw) {
+                Some((_, variant)) => write!(out, "{variant}"),
+                None => write!(out, "<invalid {name} {raw}>"),
+            };
+        }
+        Type::Variants {
+            name,
+            discriminant,
+            variants,
+            ..
+        } => {
+            let value = discriminant.map(|(offset, size)| uint(bytes, offset, size) as u64);
+            let variant = variants
+                .iter()
+                .find(|v| v.discriminant.is_some() && v.discriminant == value)
+                .or_else(|| variants.iter().find(|v| v.discriminant.is_none()));
+            match variant {
+                Some(variant) => format_fields(out, &variant.name, &variant.fields, bytes, indent),
+                None => {
+                    let _ = write!(out, "<invalid {name} {}>", value.unwrap_or_default());
+                }
+            }
+        }
+        Type::Struct { name, members, .. } => {
+            if let [inner] = members.as_slice() {
+                if is_wrapper(name) {
+                    return format_into(out, &inner.ty, slice(bytes, inner.offset), indent);
+                }
+                if is_atomic(name) {
+                    let short = name.rsplit("::").next().unwrap_or(name);
+                    let _ = write!(out, "{short}(");
+                    let bytes = slice(bytes, inner.offset);
+                    match short {
+                        // stored as u8
+                        "AtomicBool" => {
+                            let _ = write!(out, "{}", bytes.first().copied().unwrap_or(0) != 0);
+                        }
+                        _ => format_into(out, &inner.ty, bytes, indent),
+                    }
+                    out.push(')');
+                    return;
+                }
+            }
+            format_fields(out, name, members, bytes, indent);
+        }
+        Type::Array { element, count } => format_array(out, element, *count, bytes, indent),
+        Type::Opaque { name, size } => {
+            let shown = &bytes[..bytes.len().min(16)];
+            let _ = write!(
+                out,
+                "<{name}, {size}B: {}{}>",
+                hex::encode(shown),
+                if bytes.len() > shown.len() { "..." } else { "" }
+            );
+        }
+    }
+}
+
+fn format_fields(out: &mut String, name: &str, fields: &[Member], bytes: &[u8], indent: usize) {
+    // module path and generics are left out, as in `{:?}` output
+    let name = short_type_name(name);
+    if fields.is_empty() {
+        out.push_str(name);
+        return;
+    }
+    let tuple = fields.iter().all(|f| f.name.starts_with("__"));
+    let values: Vec<String> = fields
+        .iter()
+        .map(|f| {
+            let mut value = String::new();
+            format_into(&mut value, &f.ty, slice(bytes, f.offset), indent + 1);
+            value
+        })
+        .collect();
+    let one_line_len: usize = values.iter().map(|v| v.len() + 2).sum::<usize>() + name.len();
+    let one_line = one_line_len < ONE_LINE_WIDTH && values.iter().all(|v| !v.contains('\n'));
+    let pad = "    ".repeat(indent + 1);
+    let (open, close) = if tuple { ("(", ")") } else { (" { ", " }") };
+    out.push_str(name);
+    if one_line {
+        out.push_str(open);
+        for (idx, (field, value)) in fields.iter().zip(&values).enumerate() {
+            if idx > 0 {
+                out.push_str(", ");
+            }
+            if !tuple {
+                let _ = write!(out, "{}: ", field.name);
+            }
+            out.push_str(value);
+        }
+        out.push_str(close);
+        return;
+    }
+    out.push_str(open.trim_end());
+    out.push('\n');
+    for (field, value) in fields.iter().zip(&values) {
+        out.push_str(&pad);
+        if !tuple {
+            let _ = write!(out, "{}: ", field.name);
+        }
+        out.push_str(value);
+        out.push_str(",\n");
+    }
+    out.push_str(&"    ".repeat(indent));
+    out.push_str(close.trim_start());
+}
+
+fn format_array(out: &mut String, element: &Type, count: u64, bytes: &[u8], indent: usize) {
+    let size = element.size();
+    let shown = count.min(MAX_ARRAY_ELEMENTS);
+    if let Type::Base {
+        encoding: Encoding::Unsigned,
+        size: 1,
+        ..
+    } = element
+    {
+        let bytes = bytes.get(..shown as usize).unwrap_or(bytes);
+        if bytes.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+            let _ = write!(out, "b\"{}\"", String::from_utf8_lossy(bytes));
+        } else {
+            let _ = write!(out, "[{}]", hex_bytes(bytes));
+        }
+    } else {
+        let values: Vec<String> = (0..shown)
+            .map(|idx| {
+                let mut value = String::new();
+                format_into(&mut value, element, slice(bytes, idx * size), indent + 1);
+                value
+            })
+            .collect();
+        let one_line = values.iter().map(|v| v.len() + 2).sum::<usize>() < ONE_LINE_WIDTH
+            && values.iter().all(|v| !v.contains('\n'));
+        if one_line {
+            let _ = write!(out, "[{}]", values.join(", "));
+        } else {
+            let pad = "    ".repeat(indent + 1);
+            out.push_str("[\n");
+            for value in values {
+                let _ = writeln!(out, "{pad}{value},");
+            }
+            out.push_str(&"    ".repeat(indent));
+            out.push(']');
+        }
+    }
+    if count > shown {
+        let _ = write!(out, " ... {} more", count - shown);
+    }
+}
+
+/// Types that only add interior mutability, printed as their contents
+fn is_wrapper(name: &str) -> bool {
+    ["UnsafeCell<", "SyncUnsafeCell<", "Cell<", "ManuallyDrop<"]
+        .iter()
+        .any(|wrapper| name.starts_with(wrapper))
+}
+
+fn is_atomic(name: &str) -> bool {
+    name.rsplit("::")
+        .next()
+        .unwrap_or(name)
+        .starts_with("Atomic")
+}
+
+/// Type seen through wrappers, for field access
+fn transparent(ty: &Type) -> &Type {
+    match ty {
+        Type::Struct { name, members, .. } if is_wrapper(name) && members.len() == 1 => {
+            transparent(&members[0].ty)
+        }
+        ty => ty,
+    }
+}
+
+fn short_type_name(name: &str) -> &str {
+    let generic = name.find('<').unwrap_or(name.len());
+    match name[..generic].rfind("::") {
+        Some(at) => &name[at + 2..],
+        None => name,
+    }
+}
+
+/// Bytes to write and their offset in the type
+fn encode(ty: &Type, value: &str) -> Result<(u64, Vec<u8>), Error> {
+    let invalid = || Error::Symbol(format!("{value} is not a valid {}", ty.name()));
+    let bytes = match ty {
+        Type::Base { encoding, size, .. } => {
+            let size = *size as usize;
+            let raw: u128 = match encoding {
+                Encoding::Unsigned => {
+                    let v = parse_uint(value).ok_or_else(invalid)?;
+                    if size < 16 && v >> (size * 8) != 0 {
+                        return Err(invalid());
+                    }
+                    v
+                }
+                Encoding::Signed => {
+                    let v = parse_int(value).ok_or_else(invalid)?;
+                    let bits = size as u32 * 8;
+                    if bits < 128 && (v < -(1i128 << (bits - 1)) || v >= 1i128 << (bits - 1)) {
+                        return Err(invalid());
+                    }
+                    v as u128
+                }
+                Encoding::Float if size == 4 => {
+                    value.parse::<f32>().map_err(|_| invalid())?.to_bits() as u128
+                }
+                Encoding::Float => value.parse::<f64>().map_err(|_| invalid())?.to_bits() as u128,
+                Encoding::Bool => match value {
+                    "true" | "1" => 1,
+                    "false" | "0" => 0,
+                    _ => return Err(invalid()),
+                },
+                Encoding::Char => {
+                    let mut chars = value.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) => c as u128,
+                        _ => return Err(invalid()),
+                    }
+                }
+            };
+            raw.to_le_bytes()[..size].to_vec()
+        }
+        Type::Enumeration {
+            size, enumerators, ..
+        } => {
+            let raw = enumerators
+                .iter()
+                .find(|(_, name)| name == value)
+                .map(|(raw, _)| *raw as u128)
+                .or_else(|| parse_uint(value))
+                .ok_or_else(invalid)?;
+            raw.to_le_bytes()[..*size as usize].to_vec()
+        }
+        Type::Struct { name, members, .. }
+            if members.len() == 1 && (is_wrapper(name) || is_atomic(name)) =>
+        {
+            let (offset, bytes) = encode(&members[0].ty, value)?;
+            return Ok((members[0].offset + offset, bytes));
+        }
+        _ => {
+            return Err(Error::Symbol(format!(
+                "{} cannot be written as a whole, select a number, bool or fieldless enum in it, e.g. NAME.field",
+                ty.name()
+            )));
+        }
+    };
+    Ok((0, bytes))
+}
+
+fn parse_uint(s: &str) -> Option<u128> {
+    let s = s.replace('_', "");
+    if let Some(hex) = s.strip_prefix("0x") {
+        u128::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = s.strip_prefix("0b") {
+        u128::from_str_radix(bin, 2).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+fn parse_int(s: &str) -> Option<i128> {
+    match s.strip_prefix('-') {
+        Some(abs) => parse_uint(abs)
+            .and_then(|v| i128::try_from(v).ok())
+            .map(|v| -v),
+        None => parse_uint(s).and_then(|v| i128::try_from(v).ok()),
+    }
+}
+
+fn slice(bytes: &[u8], offset: u64) -> &[u8] {
+    bytes.get(offset as usize..).unwrap_or(&[])
+}
+
+/// Little endian unsigned value of up to 16 bytes at `offset`, missing bytes read as 0
+fn uint(bytes: &[u8], offset: u64, size: u64) -> u128 {
+    let mut buf = [0u8; 16];
+    let bytes = slice(bytes, offset);
+    let len = bytes.len().min(size as usize).min(16);
+    buf[..len].copy_from_slice(&bytes[..len]);
+    u128::from_le_bytes(buf)
+}
+
+fn sign_extend(raw: u128, size: u64) -> i128 {
+    let shift = 128 - (size.min(16) as u32) * 8;
+    ((raw << shift) as i128) >> shift
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("0x{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Classic 16 bytes per line hexdump with ASCII column
+pub fn hexdump(address: u64, bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (idx, line) in bytes.chunks(16).enumerate() {
+        let _ = write!(out, "{:08x} ", address + idx as u64 * 16);
+        for col in 0..16 {
+            if col == 8 {
+                out.push(' ');
+            }
+            match line.get(col) {
+                Some(b) => {
+                    let _ = write!(out, " {b:02x}");
+                }
+                None => out.push_str("   "),
+            }
+        }
+        out.push_str("  |");
+        out.extend(line.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        }));
+        out.push_str("|\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dwarf::Variant;
+    use crate::fixture;
+
+    fn base(name: &str, encoding: Encoding, size: u64) -> Type {
+        Type::Base {
+            name: name.into(),
+            encoding,
+            size,
+        }
+    }
+
+    fn member(name: &str, offset: u64, ty: Type) -> Member {
+        Member {
+            name: name.into(),
+            offset,
+            ty,
+        }
+    }
+
+    #[test]
+    fn values() {
+        let atomic = Type::Struct {
+            name: "core::sync::atomic::AtomicU32".into(),
+            size: 4,
+            members: vec![member(
+                "v",
+                0,
+                Type::Struct {
+                    name: "UnsafeCell<u32>".into(),
+                    size: 4,
+                    members: vec![member("value", 0, base("u32", Encoding::Unsigned, 4))],
+                },
+            )],
+        };
+        let option = Type::Variants {
+            name: "Option<i16>".into(),
+            size: 4,
+            discriminant: Some((0, 2)),
+            variants: vec![
+                Variant {
+                    name: "None".into(),
+                    discriminant: Some(0),
+                    fields: vec![],
+                },
+                Variant {
+                    name: "Some".into(),
+                    discriminant: Some(1),
+                    fields: vec![member("__0", 2, base("i16", Encoding::Signed, 2))],
+                },
+            ],
+        };
+        let config = Type::Struct {
+            name: "app::Config".into(),
+            size: 16,
+            members: vec![
+                member("hits", 0, atomic.clone()),
+                member("trim", 4, option.clone()),
+                member(
+                    "name",
+                    8,
+                    Type::Array {
+                        element: Box::new(base("u8", Encoding::Unsigned, 1)),
+                        count: 4,
+                    },
+                ),
+                member(
+                    "mode",
+                    12,
+                    Type::Enumeration {
+                        name: "app::Mode".into(),
+                        size: 1,
+                        enumerators: vec![(0, "Idle".into()), (5, "Run".into())],
+                    },
+                ),
+                member("on", 13, base("bool", Encoding::Bool, 1)),
+            ],
+        };
+        let bytes = [
+            42, 0, 0, 0, 1, 0, 0xFE, 0xFF, b'a', b'b', b'c', b'd', 5, 1, 0, 0,
+        ];
+        assert_eq!(
+            format_value(&config, &bytes),
+            r#"Config { hits: AtomicU32(42), trim: Some(-2), name: b"abcd", mode: Run, on: true }"#
+        );
+        assert_eq!(format_value(&option, &[0, 0, 1, 1]), "None");
+        assert_eq!(encode(&atomic, "0x10").unwrap(), (0, vec![0x10, 0, 0, 0]));
+        assert!(encode(&atomic, "0x1_0000_0000").is_err());
+        assert!(encode(&config, "1").is_err());
+    }
+
+    #[test]
+    fn hexdump_lines() {
+        let dump = hexdump(0x2000_0000, b"0123456789abcdef\x00\x01");
+        assert_eq!(
+            dump,
+            "20000000  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  |0123456789abcdef|\n\
+             20000010  00 01                                             |..|\n"
+        );
+    }
+
+    #[test]
+    fn short_byte_array() {
+        let array = Type::Array {
+            element: Box::new(base("u8", Encoding::Unsigned, 1)),
+            count: 8,
+        };
+        // e.g. a dump that ends inside the array
+        assert_eq!(format_value(&array, b"ab"), r#"b"ab""#);
+    }
+
+    #[test]
+    fn fixture_read() {
+        let (mut mem, _, elf) = fixture::core_memory();
+        let mut read = |what: &str| {
+            let item = lookup(&elf, what).unwrap();
+            item.read(&mut mem, &elf, 0).unwrap()
+        };
+        assert_eq!(
+            read("CONFIG"),
+            "fixture::CONFIG = Config {
+    mode: Low,
+    enabled: true,
+    gain: 1.5,
+    limits: [
+        Limits { min: -10, max: 10 },
+        Limits { min: 0, max: 100 },
+        Limits { min: -500, max: 500 },
+    ],
+}
+"
+        );
+        assert_eq!(
+            read("CONFIG.limits[2].min"),
+            "fixture::CONFIG.limits[2].min = -500\n"
+        );
+        assert_eq!(read("SAMPLES"), "fixture::SAMPLES = AtomicU32(41)\n");
+        // constant in FLASH, from the firmware under the dump
+        assert_eq!(
+            read("CALIBRATION"),
+            "fixture::CALIBRATION = [100, 200, 300, 400]\n"
+        );
+        assert_eq!(read("over_limit"), "over_limit = 4294967301\n");
+    }
+}
@@ -1 +1,29 @@
+pub mod bedlog;
+pub mod build_info_client;
+pub mod chip;
+pub mod compact_info;
+pub mod coredump;
+pub mod counters;
+pub mod doctor;
+pub mod dwarf;
+pub mod elf;
+mod error;
+pub mod fault;
+#[cfg(test)]
+mod fixture;
+pub mod flash;
+pub mod inspect;
+pub mod lint;
+pub mod mem;
 pub mod nm;
+pub mod ram;
+pub mod rtt;
+pub mod running;
+pub mod sbom;
+pub mod signing;
+pub mod stack;
+pub mod unwind;
+pub mod verify;
+pub mod watch;
+
+pub use error::Error;
@@ -0,0 +1,578 @@
+//! Offline checks of a firmware ELF for common pitfalls, see `book/issues.md`. Each problem comes with a hint on
+//! how to fix it.
+
+use crate::elf::{CORTEX_M_SRAM, FirmwareElf};
+use bedrock_build_info::{BedrockBuildInfoOwned, RegionKind};
+use object::{Object, ObjectSection, ObjectSymbol, SectionFlags, SectionKind, SymbolKind, elf};
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+/// NVIC interrupt set-enable registers ISER0..ISER15
+const NVIC_ISER: Range<u64> = 0xE000_E100..0xE000_E140;
+/// Exceptions before IRQ0 in the vector table, including initial SP
+const VECTOR_TABLE_EXCEPTIONS: usize = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Check {
+    Defmt,
+    CompactInfo,
+    FullInfo,
+    Counters,
+    RamLinked,
+    DefaultHandler,
+    MemoryRegions,
+    StackHeap,
+}
+
+impl Check {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Check::Defmt => "defmt",
+            Check::CompactInfo => "compact-info",
+            Check::FullInfo => "full-info",
+            Check::Counters => "counters",
+            Check::RamLinked => "ram-linked",
+            Check::DefaultHandler => "default-handler",
+            Check::MemoryRegions => "memory-regions",
+            Check::StackHeap => "stack-heap",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Problem {
+    pub check: Check,
+    pub message: String,
+    pub hint: String,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "[{}] {}", self.check.name(), self.message)?;
+        write!(f, "  hint: {}", self.hint)
+    }
+}
+
+/// Run all the checks, `ram_load` must be true if the image is meant to be loaded into RAM by the debugger
+/// instead of being flashed.
+pub fn lint(elf: &FirmwareElf, ram_load: bool) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let info = elf.full_info().or_else(|| elf.compact_info());
+    defmt(elf, &mut problems);
+    build_info(elf, &mut problems);
+    counters(elf, &mut problems);
+    if !ram_load && elf.is_ram_linked() {
+        problems.push(Problem {
+            check: Check::RamLinked,
+            message: "firmware is linked to run from RAM, it will not boot if flashed".into(),
+            hint: "rebuild without RAM_LINK=1 before flashing, RAM linked images are only for loading through the debugger".into(),
+        });
+    }
+    default_handlers(elf, &mut problems);
+    if let Some(info) = &info {
+        memory_regions(elf, info, &mut problems);
+    }
+    stack_and_heap(elf, &mut problems);
+    problems
+}
+
+fn defmt(elf: &FirmwareElf, problems: &mut Vec<Problem>) {
+    let file = elf.file();
+    if file.section_by_name(".defmt").is_some() {
+        return;
+    }
+    // without defmt.x, .defmt.* input sections end up as orphan output sections
+    let uses_defmt = file
+        .sections()
+        .any(|s| s.name().is_ok_and(|n| n.starts_with(".defmt.")))
+        || file
+            .symbols()
+            .any(|s| s.name().is_ok_and(|n| n.starts_with("_defmt_")));
+    problems.push(if uses_defmt {
+        Problem {
+            check: Check::Defmt,
+            message: "defmt is used, but there is no `.defmt` section, logs cannot be decoded".into(),
+            hint: "add defmt.x to the linker arguments: `println!(\"cargo:rustc-link-arg-bins=-Tdefmt.x\")` in build.rs or `-C link-arg=-Tdefmt.x` in .cargo/config.toml".into(),
+        }
+    } else {
+        Problem {
+            check: Check::Defmt,
+            message: "no `.defmt` section, full build info is not available".into(),
+            hint: "add defmt as a dependency and link with -Tdefmt.x, full build info is stored through defmt string interning".into(),
+        }
+    });
+}
+
+fn build_info(elf: &FirmwareElf, problems: &mut Vec<Problem>) {
+    if elf.compact_record().is_none() {
+        problems.push(Problem {
+            check: Check::CompactInfo,
+            message: "no compact build info in FLASH, the linker removed it or bedrock_build is not used".into(),
+            hint: "call `_ = core::hint::black_box(build_info::compact());` from main, or print it via defmt".into(),
+        });
+    }
+    if elf.file().section_by_name(".defmt").is_some() && elf.full_info().is_none() {
+        problems.push(Problem {
+            check: Check::FullInfo,
+            message: "no full build info in the `.defmt` section".into(),
+            hint: "call `_ = core::hint::black_box(build_info::full());` from main".into(),
+        });
+    }
+}
+
+fn counters(elf: &FirmwareElf, problems: &mut Vec<Problem>) {
+    let file = elf.file();
+    for (section, buffer, env) in [
+        (
+            ".counters_ram",
+            "_CNT_RAM_BUFFER",
+            "CNT_RAM_BUFFER_SIZE_WORDS",
+        ),
+        (
+            ".counters_bkp",
+            "_CNT_BKP_BUFFER",
+            "CNT_BKP_BUFFER_SIZE_WORDS",
+        ),
+    ] {
+        // every counter word is a 1 byte symbol in an INFO section, see cnt.x
+        let Some(used) = file.section_by_name(section).map(|s| s.size()) else {
+            continue;
+        };
+        let capacity = file
+            .symbols()
+            .find(|s| s.name() == Ok(buffer))
+            .map(|s| s.size() / 4)
+            .unwrap_or(0);
+        if used > capacity {
+            problems.push(Problem {
+                check: Check::Counters,
+                message: format!(
+                    "{used} counter words are used, but `{buffer}` only holds {capacity}, increments past the end are lost"
+                ),
+                hint: format!(
+                    "set {env}={} in the [env] section of .cargo/config.toml and rebuild",
+                    used.next_power_of_two()
+                ),
+            });
+        }
+    }
+}
+
+/// IRQs enabled in code, that are left to `DefaultHandler` in the vector table
+fn default_handlers(elf: &FirmwareElf, problems: &mut Vec<Problem>) {
+    let file = elf.file();
+    let (Some(vector_table), Some(default_handler)) = (
+        file.section_by_name(".vector_table"),
+        elf.symbol_address("DefaultHandler"),
+    ) else {
+        return;
+    };
+    let Ok(vectors) = vector_table.data() else {
+        return;
+    };
+    let vectors: Vec<u64> = vectors
+        .chunks_exact(4)
+        .map(|v| u32::from_le_bytes(v.try_into().unwrap()) as u64)
+        .collect();
+    for irq in enabled_irqs(&file) {
+        let Some(handler) = vectors.get(VECTOR_TABLE_EXCEPTIONS + irq as usize) else {
+            continue;
+        };
+        if handler & !1 == default_handler & !1 {
+            problems.push(Problem {
+                check: Check::DefaultHandler,
+                message: format!(
+                    "IRQ{irq} is enabled in NVIC, but its vector points at DefaultHandler"
+                ),
+                hint: "define the handler with #[interrupt], bind it (e.g. embassy bind_interrupts!) or do not enable the interrupt".into(),
+            });
+        }
+    }
+}
+
+/// IRQ numbers enabled through stores of constants into NVIC_ISERn, e.g. an inlined `NVIC::unmask(Interrupt::X)`.
+///
+/// This is a heuristic: only the instructions that load constants into registers are followed, IRQs enabled with
+/// a number computed at runtime are not found.
+fn enabled_irqs(file: &object::File) -> BTreeSet<u32> {
+    let mut irqs = BTreeSet::new();
+    for section in file.sections().filter(|s| s.kind() == SectionKind::Text) {
+        let Ok(code) = section.data() else {
+            continue;
+        };
+        let base = section.address();
+        for function in file.symbols().filter(|s| {
+            s.kind() == SymbolKind::Text
+                && s.section_index() == Some(section.index())
+                && s.size() > 0
+        }) {
+            let start = ((function.address() & !1) - base) as usize;
+            let end = (start + function.size() as usize).min(code.len());
+            let mut scanner = ThumbScanner {
+                code,
+                base,
+                regs: [None; 16],
+            };
+            scanner.scan(start..end, &mut irqs);
+        }
+    }
+    irqs
+}
+
+/// Tracks registers holding known constants through a Thumb function
+struct ThumbScanner<'a> {
+    code: &'a [u8],
+    base: u64,
+    regs: [Option<u32>; 16],
+}
+
+impl ThumbScanner<'_> {
+    fn halfword(&self, at: usize) -> Option<u16> {
+        Some(u16::from_le_bytes(
+            self.code.get(at..at + 2)?.try_into().unwrap(),
+        ))
+    }
+
+    fn literal(&self, address: u64) -> Option<u32> {
+        let at = address.checked_sub(self.base)? as usize;
+        Some(u32::from_le_bytes(
+            self.code.get(at..at + 4)?.try_into().unwrap(),
+        ))
+    }
+
+    fn scan(&mut self, range: Range<usize>, irqs: &mut BTreeSet<u32>) {
+        let mut at = range.start;
+        while at + 2 <= range.end {
+            let Some(hw1) = self.halfword(at) else {
+                return;
+            };
+            // PC reads as the current instruction + 4, aligned down to a word for literal loads
+            let pc = (self.base + at as u64 + 4) & !3;
+            if matches!(hw1 >> 11, 0b11101..=0b11111) {
+                let Some(hw2) = self.halfword(at + 2) else {
+                    return;
+                };
+                self.step32(hw1, hw2, pc, irqs);
+                at += 4;
+            } else {
+                self.step16(hw1, pc, irqs);
+                at += 2;
+            }
+        }
+    }
+
+    fn step16(&mut self, hw: u16, pc: u64, irqs: &mut BTreeSet<u32>) {
+        let low = |shift: u16| ((hw >> shift) & 7) as usize;
+        match hw & 0xF800 {
+            // LDR Rt, [PC, #imm8 * 4]
+            0x4800 => self.regs[low(8)] = self.literal(pc + (hw & 0xFF) as u64 * 4),
+            // MOVS Rd, #imm8
+            0x2000 => self.regs[low(8)] = Some((hw & 0xFF) as u32),
+            // LSLS Rd, Rm, #imm5, used on Armv6-M to make IRQ masks
+            0x0000 => {
+                let shift = (hw >> 6) & 0x1F;
+                self.regs[low(0)] = self.regs[low(3)].map(|v| v << shift);
+            }
+            // STR Rt, [Rn, #imm5 * 4]
+            0x6000 => {
+                let offset = ((hw >> 6) & 0x1F) as u32 * 4;
+                self.store(self.regs[low(3)], offset, self.regs[low(0)], irqs);
+            }
+            _ => {}
+        }
+    }
+
+    fn step32(&mut self, hw1: u16, hw2: u16, pc: u64, irqs: &mut BTreeSet<u32>) {
+        let rd = ((hw2 >> 8) & 0xF) as usize;
+        let rt = (hw2 >> 12) as usize;
+        let imm16 = (((hw1 & 0xF) as u32) << 12)
+            | (((hw1 >> 10) & 1) as u32 * 0x800)
+            | ((hw2 >> 4) & 0x700) as u32
+            | (hw2 & 0xFF) as u32;
+        if hw1 & 0xFF7F == 0xF85F {
+            // LDR.W Rt, [PC, #+/-imm12]
+            let offset = (hw2 & 0xFFF) as u64;
+            let address = if hw1 & 0x80 != 0 {
+                pc + offset
+            } else {
+                pc.wrapping_sub(offset)
+            };
+            self.regs[rt] = self.literal(address);
+        } else if hw1 & 0xFBF0 == 0xF240 {
+            // MOVW Rd, #imm16
+            self.regs[rd] = Some(imm16);
+        } else if hw1 & 0xFBF0 == 0xF2C0 {
+            // MOVT Rd, #imm16
+            self.regs[rd] = Some((self.regs[rd].unwrap_or(0) & 0xFFFF) | (imm16 << 16));
+        } else if hw1 & 0xFBEF == 0xF04F {
+            // MOV.W Rd, #modified immediate
+            let imm12 = (((hw1 >> 10) & 1) << 11) | ((hw2 >> 4) & 0x700) | (hw2 & 0xFF);
+            self.regs[rd] = Some(thumb_expand_imm(imm12 as u32));
+        } else if hw1 & 0xFFF0 == 0xF8C0 {
+            // STR.W Rt, [Rn, #imm12]
+            let rn = (hw1 & 0xF) as usize;
+            self.store(self.regs[rn], (hw2 & 0xFFF) as u32, self.regs[rt], irqs);
+        }
+    }
+
+    fn store(&self, base: Option<u32>, offset: u32, value: Option<u32>, irqs: &mut BTreeSet<u32>) {
+        let (Some(base), Some(value)) = (base, value) else {
+            return;
+        };
+        let address = base.wrapping_add(offset) as u64;
+        if !NVIC_ISER.contains(&address) || !address.is_multiple_of(4) {
+            return;
+        }
+        let bank = ((address - NVIC_ISER.start) / 4) as u32;
+        for bit in (0..32).filter(|bit| value & (1 << bit) != 0) {
+            irqs.insert(bank * 32 + bit);
+        }
+    }
+}
+
+/// Decode Thumb-2 modified immediate constant
+fn thumb_expand_imm(imm12: u32) -> u32 {
+    let imm8 = imm12 & 0xFF;
+    if imm12 >> 10 == 0 {
+        match (imm12 >> 8) & 3 {
+            0 => imm8,
+            1 => (imm8 << 16) | imm8,
+            2 => (imm8 << 24) | (imm8 << 8),
+            _ => imm8 * 0x0101_0101,
+        }
+    } else {
+        (0x80 | (imm12 & 0x7F)).rotate_right(imm12 >> 7)
+    }
+}
+
+fn is_alloc(section: &object::Section) -> bool {
+    match section.flags() {
+        SectionFlags::Elf { sh_flags } => sh_flags & elf::SHF_ALLOC as u64 != 0,
+        _ => false,
+    }
+}
+
+/// Sections and load addresses outside `memory.x` regions recorded in build info
+fn memory_regions(elf: &FirmwareElf, info: &BedrockBuildInfoOwned, problems: &mut Vec<Problem>) {
+    let regions = info.memory_map();
+    if regions.is_empty() {
+        return;
+    }
+    let fits = |range: Range<u64>| {
+        regions
+            .iter()
+            .any(|r| range.start >= r.origin as u64 && range.end <= r.end())
+    };
+    let hint = "check memory.x ORIGIN and LENGTH against the reference manual, and that the chip is the one memory.x is written for";
+    // compact build info only records FLASH regions, RAM placement cannot be checked then
+    if regions.iter().any(|r| r.kind == RegionKind::Ram) {
+        for section in elf.file().sections() {
+            if !is_alloc(&section) || section.size() == 0 {
+                continue;
+            }
+            let range = section.address()..section.address() + section.size();
+            if !fits(range.clone()) {
+                problems.push(Problem {
+                    check: Check::MemoryRegions,
+                    message: format!(
+                        "section `{}` at 0x{:08x}..0x{:08x} is outside of memory.x regions",
+                        section.name().unwrap_or("?"),
+                        range.start,
+                        range.end
+                    ),
+                    hint: hint.into(),
+                });
+            }
+        }
+    }
+    for (address, _, size) in elf.load_segments() {
+        let range = address..address + size as u64;
+        if !fits(range.clone()) {
+            problems.push(Problem {
+                check: Check::MemoryRegions,
+                message: format!(
+                    "data loaded at 0x{:08x}..0x{:08x} is outside of memory.x regions, probe-rs will refuse to flash it",
+                    range.start, range.end
+                ),
+                hint: hint.into(),
+            });
+        }
+    }
+}
+
+fn overlaps(a: &Range<u64>, b: &Range<u64>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+/// Stack placed over statics or the heap.
+///
+/// The stack range is known from cortex-m-rt `_stack_end..._stack_start`, the heap from `__sheap..__eheap` or
+/// a static buffer with HEAP in its name (as in embedded-alloc examples).
+fn stack_and_heap(elf: &FirmwareElf, problems: &mut Vec<Problem>) {
+    let file = elf.file();
+    let Some(stack_top) = elf.symbol_address("_stack_start") else {
+        return;
+    };
+    let stack = elf
+        .symbol_address("_stack_end")
+        .filter(|bottom| *bottom < stack_top)
+        .map(|bottom| bottom..stack_top)
+        .unwrap_or(stack_top.saturating_sub(1)..stack_top);
+
+    for section in file.sections() {
+        if !is_alloc(&section)
+            || section.size() == 0
+            || section.name().is_ok_and(|n| n == ".stack")
+            || !CORTEX_M_SRAM.contains(&section.address())
+        {
+            continue;
+        }
+        let range = section.address()..section.address() + section.size();
+        if overlaps(&range, &stack) {
+            problems.push(Problem {
+                check: Check::StackHeap,
+                message: format!(
+                    "stack 0x{:08x}..0x{:08x} overlaps section `{}` at 0x{:08x}..0x{:08x}",
+                    stack.start,
+                    stack.end,
+                    section.name().unwrap_or("?"),
+                    range.start,
+                    range.end
+                ),
+                hint: "move _stack_start in memory.x to the end of RAM, or link with flip-link to put the stack below statics".into(),
+            });
+        }
+    }
+
+    let heap = match (elf.symbol_address("__sheap"), elf.symbol_address("__eheap")) {
+        (Some(start), Some(end)) if start < end => Some(start..end),
+        _ => file
+            .symbols()
+            .find(|s| {
+                s.kind() == SymbolKind::Data
+                    && s.size() > 0
+                    && s.name().is_ok_and(|n| n.contains("HEAP"))
+            })
+            .map(|s| s.address()..s.address() + s.size()),
+    };
+    if let Some(heap) = heap.filter(|heap| overlaps(heap, &stack)) {
+        problems.push(Problem {
+            check: Check::StackHeap,
+            message: format!(
+                "heap 0x{:08x}..0x{:08x} overlaps stack 0x{:08x}..0x{:08x}",
+                heap.start, heap.end, stack.start, stack.end
+            ),
+            hint: "reduce the heap size or reserve the stack with _stack_end in memory.x".into(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+
+    fn problems(elf: &[u8], check: Check, ram_load: bool) -> Vec<Problem> {
+        let elf = FirmwareElf::from_bytes(elf.to_vec()).unwrap();
+        lint(&elf, ram_load)
+            .into_iter()
+            .filter(|p| p.check == check)
+            .collect()
+    }
+
+    #[test]
+    fn fixture_has_no_problems() {
+        assert_eq!(lint(&fixture::elf(), false), []);
+    }
+
+    #[test]
+    fn defmt_without_linker_script() {
+        let problems = problems(fixture::LINT_ELF, Check::Defmt, false);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].message.starts_with("defmt is used"));
+        assert!(problems[0].hint.contains("-Tdefmt.x"));
+    }
+
+    #[test]
+    fn missing_compact_info() {
+        assert_eq!(
+            problems(fixture::RAM_ELF, Check::CompactInfo, false).len(),
+            1
+        );
+        assert_eq!(problems(fixture::LINT_ELF, Check::CompactInfo, false), []);
+    }
+
+    #[test]
+    fn counter_buffer_overflow() {
+        let problems = problems(fixture::LINT_ELF, Check::Counters, false);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(
+            problems[0].message,
+            "3 counter words are used, but `_CNT_RAM_BUFFER` only holds 2, increments past the end are lost"
+        );
+        assert!(problems[0].hint.contains("CNT_RAM_BUFFER_SIZE_WORDS=4"));
+    }
+
+    #[test]
+    fn ram_linked() {
+        assert_eq!(problems(fixture::RAM_ELF, Check::RamLinked, false).len(), 1);
+        assert_eq!(problems(fixture::RAM_ELF, Check::RamLinked, true), []);
+    }
+
+    #[test]
+    fn linked_outside_memory_regions() {
+        // build info has FLASH at 0x08008000, the firmware is linked at 0x08000000
+        let problems = problems(fixture::LINT_ELF, Check::MemoryRegions, false);
+        assert!(!problems.is_empty());
+        assert_eq!(
+            problems[0].message,
+            "data loaded at 0x08000000..0x08000400 is outside of memory.x regions, probe-rs will refuse to flash it"
+        );
+    }
+
+    #[test]
+    fn stack_over_statics() {
+        let problems = problems(fixture::LINT_ELF, Check::StackHeap, false);
+        let messages: Vec<_> = problems.iter().map(|p| p.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "stack 0x20000000..0x20004000 overlaps section `.data` at 0x20000000..0x20000014",
+                "stack 0x20000000..0x20004000 overlaps section `.bss` at 0x20000014..0x20000020",
+                "stack 0x20000000..0x20004000 overlaps section `.uninit` at 0x20000020..0x20000090",
+            ]
+        );
+    }
+
+    #[test]
+    fn modified_immediates() {
+        // mov.w r1, #0x10000000, bit 28 of a NVIC mask
+        assert_eq!(thumb_expand_imm(0x580), 0x1000_0000);
+        assert_eq!(thumb_expand_imm(0x0FF), 0xFF);
+        assert_eq!(thumb_expand_imm(0x1AB), 0x00AB_00AB);
+        assert_eq!(thumb_expand_imm(0x3AB), 0xABAB_ABAB);
+    }
+
+    #[test]
+    fn nvic_unmask_is_found() {
+        // movw r0, #0xe104; movt r0, #0xe000; mov.w r1, #0x10000000; str r1, [r0]
+        let code: Vec<u8> = [0xF24E_1004u32, 0xF2CE_0000, 0xF04F_5180]
+            .iter()
+            .flat_map(|insn| {
+                let [a, b, c, d] = insn.to_be_bytes();
+                [b, a, d, c]
+            })
+            .chain([0x01, 0x60])
+            .collect();
+        let mut scanner = ThumbScanner {
+            code: &code,
+            base: 0x0800_0000,
+            regs: [None; 16],
+        };
+        let mut irqs = BTreeSet::new();
+        scanner.scan(0..code.len(), &mut irqs);
+        // ISER1 bit 28
+        assert_eq!(irqs.into_iter().collect::<Vec<_>>(), [60]);
+    }
+}
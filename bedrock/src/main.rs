@@ -1,153 +1,1271 @@
-use bedrock_build_info::{BedrockBuildInfo, COMPACT_INFO_MAGIC, build_info_crc};
+use bedrock::Error;
+use bedrock::bedlog::{Bedlog, BedlogWriter, Header, Source};
+use bedrock::chip::{self, CoreType};
+use bedrock::compact_info::{find_compact_info_in_ranges, flash_ranges, ram_ranges};
+use bedrock::coredump::{CoreDump, Registers, capture_regions};
+use bedrock::counters::Storage;
+use bedrock::doctor::{Expected, Level, diagnose};
+use bedrock::dwarf::DebugInfo;
+use bedrock::elf::FirmwareElf;
+use bedrock::fault::{self, LastFault};
+use bedrock::flash::BuildOptions;
+use bedrock::inspect;
+use bedrock::mem::Memory;
+use bedrock::rtt::Logs;
+use bedrock::running::{find_by_record, find_running};
+use bedrock::sbom::SbomFormat;
+use bedrock::signing;
+use bedrock::stack::StackUsage;
+use bedrock::unwind::Backtrace;
+use bedrock::verify::{device_sha, verify_running};
+use bedrock::watch::{self, Watched};
+use bedrock_build_info::{BedrockBuildInfoOwned, RegionKind};
+use clap::{Parser, Subcommand};
+use fw_registry::Registry;
+use probe_rs::config::TargetSelector;
 use probe_rs::probe::WireProtocol;
-use probe_rs::{MemoryInterface, Session, SessionConfig};
-use std::path::Path;
-use wire_weaver::prelude::DeserializeShrinkWrap;
+use probe_rs::{Core, Permissions, Session, SessionConfig};
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
 
-struct FlashMemory {
-    bytes: Vec<u8>,
-    base_addr: u64,
+/// Set on Ctrl-C, for commands that must clean up on the target before exiting
+static STOP: AtomicBool = AtomicBool::new(false);
+
+#[derive(Parser)]
+#[command(version, about = "Embedded bedrock debug tool")]
+struct Cli {
+    /// Target chip name, as known to probe-rs, identified from chip ID registers if not set
+    #[arg(long, global = true)]
+    chip: Option<String>,
+    /// SWD speed in kHz
+    #[arg(long, global = true, default_value_t = 30_000)]
+    speed: u32,
+    /// Allow erasing all FLASH, e.g. to unlock a read protected nRF
+    #[arg(long, global = true)]
+    allow_erase_all: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Build, store in the local registry, flash and verify. Also works as cargo runner, with the ELF path given
+    Flash {
+        /// Flash this ELF instead of building, as passed by cargo to a runner
+        elf: Option<PathBuf>,
+        #[arg(long)]
+        release: bool,
+        /// Build and flash the bootloader crate
+        #[arg(long)]
+        bootloader: bool,
+    },
+    /// Build, flash and print firmware logs over RTT. Also works as cargo runner, with the ELF path given
+    Run {
+        /// Run this ELF instead of building, as passed by cargo to a runner
+        elf: Option<PathBuf>,
+        #[arg(long)]
+        release: bool,
+        /// Link with RAM_LINK=1 and load into RAM instead of flashing
+        #[arg(long)]
+        ram: bool,
+        /// Also save raw logs with the firmware identity to a `.bedlog` file, see `bedrock log decode`
+        #[arg(long)]
+        capture: Option<PathBuf>,
+    },
+    /// Attach to a running target without reset and print its logs, with the ELF found in the local registry
+    Attach {
+        /// ELF file path or firmware SHA (prefix), instead of looking up what the target runs
+        firmware: Option<String>,
+        /// Decode raw RTT bytes captured to a file instead of attaching, the firmware must be given
+        #[arg(long, requires = "firmware")]
+        raw: Option<PathBuf>,
+        /// Also save raw logs with the firmware identity to a `.bedlog` file, see `bedrock log decode`
+        #[arg(long, conflicts_with = "raw")]
+        capture: Option<PathBuf>,
+    },
+    /// Show build info from connected target
+    Info,
+    /// Diagnose target state and common pitfalls, comparing with the firmware ELF if given
+    Doctor {
+        /// ELF file path or firmware SHA (prefix) in the local registry
+        firmware: Option<String>,
+    },
+    /// Show FLASH partitions and memory regions recorded in the firmware running on connected target
+    Partitions,
+    /// Check that the connected target is running the firmware from the ELF file
+    VerifyRunning { elf: PathBuf },
+    /// Print a counter, a static (`CONFIG`, `CONFIG.limits[2]`) with its type layout, or hexdump a symbol or address
+    Read {
+        /// Counter name, static path, symbol name or address
+        what: String,
+        /// ELF file path or firmware SHA (prefix), instead of looking up what the target runs
+        #[arg(long)]
+        firmware: Option<String>,
+        /// Read from a core dump instead of the target, see `bedrock coredump`
+        #[arg(long)]
+        core: Option<PathBuf>,
+        /// Bytes to dump from an address or a symbol without size
+        #[arg(long, default_value_t = 64)]
+        len: u64,
+    },
+    /// Print all event counters with their values
+    Counters {
+        /// ELF file path or firmware SHA (prefix), instead of looking up what the target runs
+        #[arg(long)]
+        firmware: Option<String>,
+        /// Read from a core dump instead of the target, see `bedrock coredump`
+        #[arg(long)]
+        core: Option<PathBuf>,
+    },
+    /// Explain why the core faulted, from the fault status registers, with a backtrace of the faulting code
+    Fault {
+        /// ELF file path or firmware SHA (prefix), instead of looking up what the target runs
+        #[arg(long)]
+        firmware: Option<String>,
+        /// Analyze a core dump instead of the target, see `bedrock coredump`
+        #[arg(long)]
+        core: Option<PathBuf>,
+        /// Print the fault saved over reset by the bedrock_rt fault handlers, without halting
+        #[arg(long)]
+        last: bool,
+    },
+    /// Print a backtrace of where the core is, halting it for a moment if it is running
+    Trace {
+        /// ELF file path or firmware SHA (prefix), instead of looking up what the target runs
+        #[arg(long)]
+        firmware: Option<String>,
+        /// Read from a core dump instead of the target, see `bedrock coredump`
+        #[arg(long)]
+        core: Option<PathBuf>,
+    },
+    /// Show main stack usage now and its peak since reset, from the cortex-m-rt `paint-stack` paint
+    Stack {
+        /// ELF file path or firmware SHA (prefix), instead of looking up what the target runs
+        #[arg(long)]
+        firmware: Option<String>,
+        /// Read from a core dump instead of the target, see `bedrock coredump`
+        #[arg(long)]
+        core: Option<PathBuf>,
+    },
+    /// Save RAM, core and fault registers, RTT and counter buffers to an ELF core file for offline analysis
+    Coredump {
+        output: PathBuf,
+        /// ELF file path or firmware SHA (prefix), instead of looking up what the target runs
+        #[arg(long)]
+        firmware: Option<String>,
+    },
+    /// Print changes of a counter, static or memory range, polling it or halting on every write to it
+    Watch {
+        /// Counter name, static path, symbol name or address
+        what: String,
+        /// ELF file path or firmware SHA (prefix), instead of looking up what the target runs
+        #[arg(long)]
+        firmware: Option<String>,
+        /// Polls per second
+        #[arg(long, default_value_t = 10)]
+        rate: u32,
+        /// Bytes to watch at an address or a symbol without size
+        #[arg(long, default_value_t = 4)]
+        len: u64,
+        /// Halt on every write with a DWT watchpoint and show which code wrote, instead of polling
+        #[arg(long)]
+        halt_on_write: bool,
+    },
+    /// Set a counter, or a number, bool, char or enum static or field, also inside atomics and cells
+    Write {
+        /// Counter name or static path
+        what: String,
+        value: String,
+        /// ELF file path or firmware SHA (prefix), instead of looking up what the target runs
+        #[arg(long)]
+        firmware: Option<String>,
+    },
+    /// Export software bill of materials of a firmware, from ELF file or registry by firmware SHA
+    Sbom {
+        /// ELF file path or firmware SHA (prefix) in the local registry
+        firmware: String,
+        #[arg(long, value_enum, default_value_t = SbomFormat::CycloneDx)]
+        format: SbomFormat,
+        /// Output file, stdout if not set
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Create a new signing key pair
+    Keygen {
+        /// Secret key output, keep it out of the repository
+        secret: PathBuf,
+        /// Public key output, to be compiled into the bootloader
+        public: PathBuf,
+        /// Overwrite existing key files
+        #[arg(long)]
+        force: bool,
+    },
+    /// Sign firmware ELF in place, it must be built with BuildInfoConfig::signature_slot(true)
+    Sign {
+        elf: PathBuf,
+        #[arg(long)]
+        key: PathBuf,
+    },
+    /// Check firmware ELF signature
+    Verify {
+        elf: PathBuf,
+        /// Public key file
+        #[arg(long)]
+        key: PathBuf,
+    },
+    /// List counters from the ELF file
+    Nm { elf: PathBuf },
+    /// Offline ELF file checks
+    Elf {
+        #[command(subcommand)]
+        command: ElfCommand,
+    },
+    /// Captured log files
+    Log {
+        #[command(subcommand)]
+        command: LogCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum ElfCommand {
+    /// Check firmware for common pitfalls: missing defmt or build info, counters overflow, memory layout issues
+    Lint {
+        elf: PathBuf,
+        /// Image is meant to be loaded into RAM by the debugger, not flashed
+        #[arg(long)]
+        ram: bool,
+    },
 }
 
-impl FlashMemory {
-    pub fn with_capacity(len: u32, base_addr: u64) -> Self {
-        Self {
-            bytes: Vec::with_capacity(len as usize),
-            base_addr,
+#[derive(Subcommand)]
+enum LogCommand {
+    /// Decode a `.bedlog` capture, with the firmware ELF found in the local registry
+    Decode {
+        file: PathBuf,
+        /// ELF file path or firmware SHA (prefix), if the capture does not identify it or it is not in the registry
+        #[arg(long)]
+        firmware: Option<String>,
+    },
+    /// Save logs a firmware writes to a serial port to a `.bedlog` file, printing them if the firmware is given
+    Capture {
+        /// Output `.bedlog` file
+        file: PathBuf,
+        /// Serial port, e.g. /dev/ttyACM0 or COM3
+        #[arg(long)]
+        uart: String,
+        #[arg(long, default_value_t = 115_200)]
+        baud: u32,
+        /// ELF file path or firmware SHA (prefix) of what the device runs, recorded in the capture
+        #[arg(long)]
+        firmware: Option<String>,
+    },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match &cli.command {
+        Command::Flash {
+            elf,
+            release,
+            bootloader,
+        } => flash(
+            &cli,
+            elf.as_deref(),
+            BuildOptions {
+                release: *release,
+                bootloader: *bootloader,
+                ram_link: false,
+            },
+        ),
+        Command::Run {
+            elf,
+            release,
+            ram,
+            capture,
+        } => run(
+            &cli,
+            elf.as_deref(),
+            BuildOptions {
+                release: *release,
+                ram_link: *ram,
+                ..Default::default()
+            },
+            capture.as_deref(),
+        ),
+        Command::Attach {
+            firmware,
+            raw,
+            capture,
+        } => attach_logs(
+            &cli,
+            firmware.as_deref(),
+            raw.as_deref(),
+            capture.as_deref(),
+        ),
+        Command::Info => info(&cli),
+        Command::Doctor { firmware } => doctor(&cli, firmware.as_deref()),
+        Command::Partitions => partitions(&cli),
+        Command::VerifyRunning { elf } => verify_running_cmd(&cli, elf),
+        Command::Read {
+            what,
+            firmware,
+            core,
+            len,
+        } => read(&cli, what, firmware.as_deref(), core.as_deref(), *len),
+        Command::Counters { firmware, core } => {
+            counters(&cli, firmware.as_deref(), core.as_deref())
+        }
+        Command::Fault {
+            firmware,
+            core,
+            last,
+        } => fault(&cli, firmware.as_deref(), core.as_deref(), *last),
+        Command::Trace { firmware, core } => trace(&cli, firmware.as_deref(), core.as_deref()),
+        Command::Stack { firmware, core } => stack(&cli, firmware.as_deref(), core.as_deref()),
+        Command::Coredump { output, firmware } => coredump(&cli, output, firmware.as_deref()),
+        Command::Watch {
+            what,
+            firmware,
+            rate,
+            len,
+            halt_on_write,
+        } => watch(&cli, what, firmware.as_deref(), *rate, *len, *halt_on_write),
+        Command::Write {
+            what,
+            value,
+            firmware,
+        } => write(&cli, what, value, firmware.as_deref()),
+        Command::Sbom {
+            firmware,
+            format,
+            output,
+        } => sbom(firmware, *format, output.as_deref()),
+        Command::Keygen {
+            secret,
+            public,
+            force,
+        } => keygen(secret, public, *force),
+        Command::Sign { elf, key } => sign(elf, key),
+        Command::Verify { elf, key } => verify(elf, key),
+        Command::Nm { elf } => {
+            bedrock::nm::nm_test(elf);
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Elf {
+            command: ElfCommand::Lint { elf, ram },
+        } => lint(elf, *ram),
+        Command::Log {
+            command: LogCommand::Decode { file, firmware },
+        } => log_decode(file, firmware.as_deref()),
+        Command::Log {
+            command:
+                LogCommand::Capture {
+                    file,
+                    uart,
+                    baud,
+                    firmware,
+                },
+        } => log_capture(file, uart, *baud, firmware.as_deref()),
+    };
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
         }
     }
+}
+
+/// Attached target with its FLASH ranges
+struct Target {
+    session: Session,
+    /// probe-rs target name, or generic architecture name if the chip is not known
+    name: String,
+    flash: Vec<Range<u64>>,
+}
+
+fn open_session(cli: &Cli, target: impl Into<TargetSelector>) -> Result<Session, Error> {
+    let session_config = SessionConfig {
+        speed: Some(cli.speed),
+        protocol: Some(WireProtocol::Swd),
+        permissions: if cli.allow_erase_all {
+            Permissions::new().allow_erase_all()
+        } else {
+            Permissions::default()
+        },
+    };
+    Ok(Session::auto_attach(target, session_config)?)
+}
 
-    pub fn push_chunk(&mut self, bytes: &[u8]) {
-        if self.bytes.len() >= self.bytes.capacity() {
-            return;
+/// Attach to the chip given with `--chip`, or identify it through a generic target for the ELF architecture.
+/// If the chip is not known, stay attached through the generic target, with FLASH ranges from the ELF build info.
+fn attach(cli: &Cli, elf: Option<&FirmwareElf>) -> Result<Target, Error> {
+    if let Some(chip) = &cli.chip {
+        let session = open_session(cli, chip.as_str())?;
+        let flash = flash_ranges(session.target());
+        return Ok(Target {
+            session,
+            name: chip.clone(),
+            flash,
+        });
+    }
+    let info = elf.and_then(|elf| elf.full_info().or_else(|| elf.compact_info()));
+    let generic = info
+        .as_ref()
+        .and_then(|info| info.target_info.triple.as_deref())
+        .and_then(chip::generic_target)
+        .unwrap_or("armv6m");
+    let mut session = open_session(cli, generic)?;
+    let id = chip::identify(&mut session.core(0)?);
+    let mut ambiguous = None;
+    let target = match &id {
+        Ok(id) => match id.probe_rs_target() {
+            Err(e @ Error::AmbiguousChip { .. }) => {
+                ambiguous = Some(e);
+                None
+            }
+            result => result?,
+        },
+        Err(_) => None,
+    };
+    if let Some(target) = target {
+        drop(session);
+        let name = target.name.clone();
+        let session = open_session(cli, target)?;
+        let flash = flash_ranges(session.target());
+        return Ok(Target {
+            session,
+            name,
+            flash,
+        });
+    }
+    let flash: Vec<_> = info
+        .iter()
+        .flat_map(|info| info.memory_map())
+        .filter(|region| region.kind == RegionKind::Flash)
+        .map(|region| region.origin as u64..region.end())
+        .collect();
+    match (id, ambiguous) {
+        (_, Some(e)) => eprintln!(
+            "warning: {e}, using generic {generic} target with the FLASH regions of the firmware"
+        ),
+        (Ok(id), None) => {
+            eprintln!("warning: {id} is not known to probe-rs, using generic {generic} target")
         }
-        self.bytes.extend_from_slice(bytes);
+        (Err(e), None) => eprintln!("warning: {e}, using generic {generic} target"),
     }
+    Ok(Target {
+        session,
+        name: generic.to_string(),
+        flash,
+    })
+}
 
-    pub fn next_missing_addr(&self) -> Option<u64> {
-        if self.bytes.len() >= self.bytes.capacity() {
-            None
-        } else {
-            Some(self.base_addr + self.bytes.len() as u64)
+/// Build unless the ELF is given
+fn build(elf: Option<&Path>, options: BuildOptions) -> Result<(PathBuf, FirmwareElf), Error> {
+    let elf_path = match elf {
+        Some(elf) => elf.to_path_buf(),
+        None => bedrock::flash::cargo_build(options)?,
+    };
+    let elf = FirmwareElf::open(&elf_path)?;
+    Ok((elf_path, elf))
+}
+
+fn register(elf: &FirmwareElf) -> Result<(), Error> {
+    let entry = bedrock::flash::register(elf, &Registry::open_default())?;
+    println!("Firmware {} stored in the registry", entry.sha);
+    Ok(())
+}
+
+/// Flash and read the compact build info back, false if it does not match
+fn flash_and_verify(
+    target: &mut Target,
+    elf: &FirmwareElf,
+    elf_path: &Path,
+) -> Result<bool, Error> {
+    println!("Flashing {} to {}", elf_path.display(), target.name);
+    bedrock::flash::flash(&mut target.session, elf, elf_path)?;
+    let mut core = target.session.core(0)?;
+    let (Some(image), Some(sha)) = (elf.flash_image(), elf.fw_sha()) else {
+        eprintln!(
+            "warning: firmware has nothing to load into FLASH, flashed image cannot be verified"
+        );
+        return Ok(true);
+    };
+    // the whole image, as the firmware SHA in the registry is computed
+    let device = device_sha(&mut core, &image)?;
+    if device == sha {
+        println!(
+            "Flashed and verified, target FLASH has the firmware SHA {}",
+            hex::encode(sha)
+        );
+        return Ok(true);
+    }
+    println!(
+        "Target FLASH does not match the ELF, firmware SHA {} was read back instead of {}",
+        hex::encode(device),
+        hex::encode(sha)
+    );
+    // build info tells a different firmware from a corrupted copy of this one
+    if elf.compact_record().is_some()
+        && let Ok(check) = verify_running(&mut core, elf)
+        && !check.identical
+    {
+        println!("Build info read back from the target does not match the ELF:");
+        println!("{}", check.diff);
+    }
+    Ok(false)
+}
+
+fn flash(cli: &Cli, elf: Option<&Path>, options: BuildOptions) -> Result<ExitCode, Error> {
+    let (elf_path, elf) = build(elf, options)?;
+    if elf.is_ram_linked() {
+        return Err(Error::LinkMode { ram_linked: true });
+    }
+    register(&elf)?;
+    let mut target = attach(cli, Some(&elf))?;
+    if flash_and_verify(&mut target, &elf, &elf_path)? {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+fn run(
+    cli: &Cli,
+    elf: Option<&Path>,
+    options: BuildOptions,
+    capture: Option<&Path>,
+) -> Result<ExitCode, Error> {
+    let (elf_path, elf) = build(elf, options)?;
+    // `RAM_LINK=1 cargo run` passes a RAM linked ELF to the runner
+    let ram = options.ram_link || elf.is_ram_linked();
+    if ram && !elf.is_ram_linked() {
+        return Err(Error::LinkMode { ram_linked: false });
+    }
+    register(&elf)?;
+    let logs = Logs::from_elf(&elf, io::stdout().is_terminal())?;
+    let mut target = attach(cli, Some(&elf))?;
+    if ram {
+        println!("Loading {} into RAM of {}", elf_path.display(), target.name);
+        let start = bedrock::ram::load_and_run(&mut target.session.core(0)?, &elf)?;
+        println!(
+            "Started from vector table at 0x{:08x}, reset vector 0x{:08x}",
+            start.vector_table, start.reset_vector
+        );
+    } else if !flash_and_verify(&mut target, &elf, &elf_path)? {
+        return Ok(ExitCode::FAILURE);
+    }
+    let mut core = target.session.core(0)?;
+    let record = elf.compact_record().map(|(_, record)| record);
+    let mut capture = create_capture(capture, &mut core, elf.fw_sha(), record)?;
+    let mut rtt = bedrock::rtt::attach(&mut core, &elf)?;
+    bedrock::rtt::follow(
+        &mut core,
+        &mut rtt,
+        &logs,
+        &mut io::stdout().lock(),
+        capture.as_mut(),
+    )?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Create a `.bedlog` file for RTT logs, with the UID of the chip if it can be identified
+fn create_capture(
+    path: Option<&Path>,
+    core: &mut Core,
+    fw_sha: Option<[u8; 32]>,
+    record: Option<Vec<u8>>,
+) -> Result<Option<BedlogWriter<fs::File>>, Error> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let header = Header {
+        source: Source::Rtt,
+        fw_sha,
+        uid: chip::identify(core).map(|id| id.uid).unwrap_or_default(),
+        record: record.unwrap_or_default(),
+    };
+    Ok(Some(BedlogWriter::new(fs::File::create(path)?, &header)?))
+}
+
+fn attach_logs(
+    cli: &Cli,
+    firmware: Option<&str>,
+    raw: Option<&Path>,
+    capture: Option<&Path>,
+) -> Result<ExitCode, Error> {
+    if let Some(raw) = raw {
+        let elf = open_firmware(firmware.expect("required by clap"))?;
+        let logs = Logs::from_elf(&elf, io::stdout().is_terminal())?;
+        let mut file = fs::File::open(raw)?;
+        logs.print(|buf| Ok(file.read(buf)?), &mut io::stdout().lock())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let mut target = attach(cli, None)?;
+    let mut core = target.session.core(0)?;
+    let (elf, fw_sha, record) = match firmware {
+        Some(firmware) => {
+            let elf = open_firmware(firmware)?;
+            let (fw_sha, record) = match verify_running(&mut core, &elf) {
+                Ok(check) if check.identical => (elf.fw_sha(), Some(check.device.record)),
+                Ok(check) => {
+                    eprintln!(
+                        "WARNING: target is not running this firmware, logs may be decoded incorrectly:\n{}",
+                        check.diff
+                    );
+                    (None, Some(check.device.record))
+                }
+                Err(e) => {
+                    eprintln!("WARNING: cannot check what the target is running: {e}");
+                    (None, None)
+                }
+            };
+            (elf, fw_sha, record)
+        }
+        None => {
+            let running = find_running(&mut core, &target.flash, &Registry::open_default())?;
+            println!(
+                "Target is running {} from the registry: {}",
+                running.entry.sha, running.device.info.crate_info
+            );
+            for warning in running.warnings() {
+                eprintln!("WARNING: {warning}");
+            }
+            let fw_sha = running.sha_matches.then(|| running.elf.fw_sha()).flatten();
+            (running.elf, fw_sha, Some(running.device.record))
+        }
+    };
+    let logs = Logs::from_elf(&elf, io::stdout().is_terminal())?;
+    let mut capture = create_capture(capture, &mut core, fw_sha, record)?;
+    let mut rtt = bedrock::rtt::attach(&mut core, &elf)?;
+    bedrock::rtt::follow(
+        &mut core,
+        &mut rtt,
+        &logs,
+        &mut io::stdout().lock(),
+        capture.as_mut(),
+    )?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// There is no probe to read the device identity from, the firmware is taken as given
+fn log_capture(
+    file: &Path,
+    uart: &str,
+    baud: u32,
+    firmware: Option<&str>,
+) -> Result<ExitCode, Error> {
+    let elf = firmware.map(open_firmware).transpose()?;
+    if elf.is_none() {
+        eprintln!(
+            "WARNING: capture does not identify the firmware without --firmware, `bedrock log decode` will need it"
+        );
+    }
+    let header = Header {
+        source: Source::Uart,
+        fw_sha: elf.as_ref().and_then(|elf| elf.fw_sha()),
+        uid: Vec::new(),
+        record: elf
+            .as_ref()
+            .and_then(|elf| elf.compact_record())
+            .map(|(_, record)| record)
+            .unwrap_or_default(),
+    };
+    let mut port = serialport::new(uart, baud)
+        .timeout(Duration::from_millis(100))
+        .open()
+        .map_err(io::Error::from)?;
+    let mut capture = BedlogWriter::new(fs::File::create(file)?, &header)?;
+    eprintln!("Capturing {uart} to {}", file.display());
+    let read = |buf: &mut [u8]| loop {
+        match port.read(buf) {
+            Ok(n) => {
+                capture.write_chunk(SystemTime::now(), &buf[..n])?;
+                return Ok(n);
+            }
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+            Err(e) => return Err(Error::from(e)),
+        }
+    };
+    match &elf {
+        Some(elf) => Logs::from_elf(elf, io::stdout().is_terminal())?
+            .print(read, &mut io::stdout().lock())?,
+        None => {
+            let mut read = read;
+            let mut buf = [0u8; 1024];
+            while read(&mut buf)? > 0 {}
         }
     }
+    Ok(ExitCode::SUCCESS)
+}
 
-    pub fn slice_from(&self, addr: u64) -> &[u8] {
-        let Some(idx) = addr.checked_sub(self.base_addr) else {
-            return &[];
-        };
-        if idx < self.bytes.len() as u64 {
-            &self.bytes[idx as usize..]
+fn log_decode(file: &Path, firmware: Option<&str>) -> Result<ExitCode, Error> {
+    let log = Bedlog::parse(&fs::read(file)?)?;
+    let header = &log.header;
+    let info = header.build_info();
+    match &info {
+        Some(info) => println!("Captured from {}", info.crate_info),
+        None => eprintln!("WARNING: capture has no build info of the device"),
+    }
+    if let Some(sha) = header.fw_sha {
+        println!("Firmware {}", hex::encode(sha));
+    }
+    if !header.uid.is_empty() {
+        println!("Device UID {}", hex::encode(&header.uid));
+    }
+    let version_control = info.as_ref().and_then(|info| info.version_control.as_ref());
+    if let Some(vc) = version_control.filter(|vc| vc.dirty) {
+        eprintln!(
+            "WARNING: firmware was built from a dirty working tree ({vc}), uncommitted changes are not recorded anywhere"
+        );
+    }
+    let elf = match firmware {
+        Some(firmware) => open_firmware(firmware)?,
+        None => find_firmware(header.fw_sha, &header.record, info, || {
+            Error::Bedlog("capture does not identify the firmware, pass it with --firmware".into())
+        })?,
+    };
+    let logs = Logs::from_elf(&elf, io::stdout().is_terminal())?;
+    let mut stream = logs.stream();
+    let mut out = io::stdout().lock();
+    for chunk in &log.chunks {
+        let time = chrono::DateTime::<chrono::Local>::from(chunk.host_time);
+        let prefix = format!("{} ", time.format("%Y-%m-%d %H:%M:%S%.3f"));
+        stream.feed(&chunk.bytes, &prefix, &mut out)?;
+    }
+    if log.truncated {
+        eprintln!("warning: capture ends with an incomplete chunk, it was cut short");
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Firmware from the local registry by SHA, or else by compact build info record
+fn find_firmware(
+    fw_sha: Option<[u8; 32]>,
+    record: &[u8],
+    info: Option<BedrockBuildInfoOwned>,
+    unidentified: impl FnOnce() -> Error,
+) -> Result<FirmwareElf, Error> {
+    let registry = Registry::open_default();
+    if let Some(sha) = fw_sha {
+        let sha = hex::encode(sha);
+        match registry.find(&sha) {
+            Ok(entry) => return FirmwareElf::open(&entry.elf_path()),
+            Err(fw_registry::Error::NotFound(_)) => {
+                eprintln!(
+                    "WARNING: firmware {sha} is not in the registry, looking for the same build info"
+                )
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let info = info.ok_or_else(unidentified)?;
+    match find_by_record(&registry, record)?.into_iter().next() {
+        Some((entry, elf)) => {
+            if fw_sha.is_some() {
+                eprintln!(
+                    "WARNING: using {} with the same build info, FLASH contents may differ",
+                    entry.sha
+                );
+            }
+            Ok(elf)
+        }
+        None => Err(Error::NotInRegistry(Box::new(info))),
+    }
+}
+
+fn info(cli: &Cli) -> Result<ExitCode, Error> {
+    let mut target = attach(cli, None)?;
+    let mut core = target.session.core(0)?;
+    let found = find_compact_info_in_ranges(&mut core, &target.flash)?;
+    println!("Build info at 0x{:08x}:", found.address);
+    println!("{}", found.info);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn doctor(cli: &Cli, firmware: Option<&str>) -> Result<ExitCode, Error> {
+    let elf = firmware.map(open_firmware).transpose()?;
+    let expected = elf.as_ref().map(Expected::from_elf);
+    let mut target = attach(cli, elf.as_ref())?;
+    let mut core = target.session.core(0)?;
+    let diagnosis = diagnose(&mut core, &target.name, &target.flash, expected.as_ref());
+    for finding in &diagnosis.findings {
+        println!("{finding}");
+    }
+    if diagnosis.worst() == Level::Problem {
+        Ok(ExitCode::FAILURE)
+    } else {
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+fn partitions(cli: &Cli) -> Result<ExitCode, Error> {
+    let mut target = attach(cli, None)?;
+    let mut core = target.session.core(0)?;
+    let found = find_compact_info_in_ranges(&mut core, &target.flash)?;
+    if found.info.memory_map().is_empty() {
+        println!(
+            "Firmware does not record its memory map, rebuild with a newer bedrock_build and memory.x next to Cargo.toml"
+        );
+        return Ok(ExitCode::FAILURE);
+    }
+    for region in found.info.memory_map() {
+        println!("{region}");
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn verify_running_cmd(cli: &Cli, elf: &Path) -> Result<ExitCode, Error> {
+    let elf = FirmwareElf::open(elf)?;
+    let mut target = attach(cli, Some(&elf))?;
+    let mut core = target.session.core(0)?;
+    let check = verify_running(&mut core, &elf)?;
+    if check.is_match() {
+        if check.identical {
+            println!("Device is running this build");
+        } else if !check.dirty() {
+            println!(
+                "Device is running the same sources and configuration, but built at a different time"
+            );
+        } else if let Some((device, elf)) = check.content_ids()
+            && device != elf
+        {
+            println!(
+                "Device is running a build of the same commit with uncommitted changes, but content ID \
+                {elf:016x} != {device:016x} (ELF != device): the sources cannot be confirmed as the same"
+            );
+            return Ok(ExitCode::FAILURE);
         } else {
-            &[]
+            println!(
+                "Device is running a build of the same commit and configuration, with uncommitted changes that build \
+                info does not record: the sources cannot be confirmed as the same"
+            );
         }
+        Ok(ExitCode::SUCCESS)
+    } else {
+        println!("Device is running a different firmware (ELF != device):");
+        println!("{}", check.diff);
+        println!("\nDevice firmware:\n{}", check.device.info);
+        Ok(ExitCode::FAILURE)
     }
 }
 
-fn main() -> Result<(), probe_rs::Error> {
-    bedrock::nm::nm_test(Path::new(
-        "/Users/roman/git/h7_test/target/thumbv7em-none-eabihf/debug/h7_test",
-    ));
+fn read(
+    cli: &Cli,
+    what: &str,
+    firmware: Option<&str>,
+    core_file: Option<&Path>,
+    len: u64,
+) -> Result<ExitCode, Error> {
+    if let Some(core_file) = core_file {
+        let (dump, elf) = open_core(core_file, firmware)?;
+        return print_value(&mut dump.memory_with_firmware(&elf), Some(&elf), what, len);
+    }
+    let mut target = attach(cli, None)?;
+    let mut core = target.session.core(0)?;
+    let elf = match inspect::parse_address(what) {
+        Some(_) => None,
+        None => Some(target_firmware(&mut core, &target.flash, firmware)?),
+    };
+    print_value(&mut core, elf.as_ref(), what, len)
+}
 
-    // Attach to a chip.
-    let speed = Some(30_000);
-    let protocol = Some(WireProtocol::Swd);
-    let session_config = SessionConfig {
-        speed,
-        protocol,
-        ..Default::default()
+fn print_value(
+    mem: &mut impl Memory,
+    elf: Option<&FirmwareElf>,
+    what: &str,
+    len: u64,
+) -> Result<ExitCode, Error> {
+    if let Some(address) = inspect::parse_address(what) {
+        let mut bytes = vec![0u8; len as usize];
+        mem.read(address, &mut bytes)?;
+        print!("{}", inspect::hexdump(address, &bytes));
+        return Ok(ExitCode::SUCCESS);
+    }
+    let elf = elf.ok_or_else(|| Error::Symbol(format!("firmware needed to look up {what}")))?;
+    let item = inspect::lookup(elf, what)?;
+    print!("{}", item.read(mem, elf, len)?);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn counters(
+    cli: &Cli,
+    firmware: Option<&str>,
+    core_file: Option<&Path>,
+) -> Result<ExitCode, Error> {
+    if let Some(core_file) = core_file {
+        let (dump, elf) = open_core(core_file, firmware)?;
+        return print_counters(&mut dump.memory_with_firmware(&elf), &elf);
+    }
+    let mut target = attach(cli, None)?;
+    let mut core = target.session.core(0)?;
+    let elf = target_firmware(&mut core, &target.flash, firmware)?;
+    print_counters(&mut core, &elf)
+}
+
+fn print_counters(mem: &mut impl Memory, elf: &FirmwareElf) -> Result<ExitCode, Error> {
+    let counters = bedrock::counters::counters(elf);
+    if counters.is_empty() {
+        println!("Firmware has no counters");
+        return Ok(ExitCode::SUCCESS);
+    }
+    let width = counters.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    for counter in &counters {
+        let storage = match counter.storage {
+            Storage::Ram => "",
+            Storage::Bkp => " (backup RAM)",
+        };
+        println!(
+            "{:width$} {}{storage}",
+            counter.name,
+            counter.read(mem, elf)?
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn fault(
+    cli: &Cli,
+    firmware: Option<&str>,
+    core_file: Option<&Path>,
+    last: bool,
+) -> Result<ExitCode, Error> {
+    if let Some(core_file) = core_file {
+        let (dump, elf) = open_core(core_file, firmware)?;
+        if last {
+            return print_last_fault(&mut dump.memory_with_firmware(&elf), &elf);
+        }
+        let report = fault::analyze(&mut dump.memory_with_firmware(&elf), &elf, &dump.registers)?;
+        print!("{report}");
+        return Ok(ExitCode::SUCCESS);
+    }
+    let mut target = attach(cli, None)?;
+    let mut core = target.session.core(0)?;
+    let elf = target_firmware(&mut core, &target.flash, firmware)?;
+    if last {
+        return print_last_fault(&mut core, &elf);
+    }
+    let was_running = !core.core_halted()?;
+    if was_running {
+        core.halt(Duration::from_millis(100))?;
+    }
+    let report = Registers::read(&mut core)
+        .and_then(|registers| fault::analyze(&mut core, &elf, &registers));
+    if was_running {
+        core.run()?;
+    }
+    print!("{}", report?);
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The record is found with the running firmware, the faulting code symbolized with the one that faulted
+fn print_last_fault(mem: &mut impl Memory, elf: &FirmwareElf) -> Result<ExitCode, Error> {
+    let Some(mut last) = LastFault::read(mem, elf)? else {
+        println!("No fault since power-on");
+        return Ok(ExitCode::SUCCESS);
+    };
+    let other;
+    let faulted = match last.fw_sha {
+        Some(sha) if elf.fw_sha() != Some(sha) => {
+            let sha = hex::encode(sha);
+            other = open_firmware(&sha)
+                .inspect_err(|e| {
+                    eprintln!(
+                        "WARNING: fault happened in firmware {sha}, not the one running now: {e}, code addresses not symbolized"
+                    )
+                })
+                .ok();
+            other.as_ref()
+        }
+        _ => Some(elf),
     };
+    if let Some(debug) = faulted.and_then(|elf| DebugInfo::load(elf).ok()) {
+        last.symbolize(&debug);
+    }
+    print!("{last}");
+    Ok(ExitCode::SUCCESS)
+}
 
-    let mut session = Session::auto_attach("STM32H743ZI", session_config)?;
+fn trace(cli: &Cli, firmware: Option<&str>, core_file: Option<&Path>) -> Result<ExitCode, Error> {
+    if let Some(core_file) = core_file {
+        let (dump, elf) = open_core(core_file, firmware)?;
+        print_backtrace(&mut dump.memory_with_firmware(&elf), &elf, &dump.registers)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let mut target = attach(cli, None)?;
+    let mut core = target.session.core(0)?;
+    let elf = target_firmware(&mut core, &target.flash, firmware)?;
+    let was_running = !core.core_halted()?;
+    if was_running {
+        core.halt(Duration::from_millis(100))?;
+    }
+    let printed = Registers::read(&mut core)
+        .and_then(|registers| print_backtrace(&mut core, &elf, &registers));
+    if was_running {
+        core.run()?;
+    }
+    printed?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn print_backtrace(
+    mem: &mut impl Memory,
+    elf: &FirmwareElf,
+    registers: &Registers,
+) -> Result<(), Error> {
+    let armv8m = CoreType::read(mem)?.is_some_and(|core_type| core_type.is_armv8m());
+    let debug = DebugInfo::load(elf).ok();
+    print!(
+        "{}",
+        Backtrace::capture(mem, elf, debug.as_ref(), registers, armv8m)
+    );
+    Ok(())
+}
 
-    // Select a core.
-    let mut core = session.core(0)?;
+fn stack(cli: &Cli, firmware: Option<&str>, core_file: Option<&Path>) -> Result<ExitCode, Error> {
+    if let Some(core_file) = core_file {
+        let (dump, elf) = open_core(core_file, firmware)?;
+        let usage = StackUsage::read(
+            &mut dump.memory_with_firmware(&elf),
+            &elf,
+            dump.registers.msp,
+        )?;
+        print!("{usage}");
+        return Ok(ExitCode::SUCCESS);
+    }
+    let mut target = attach(cli, None)?;
+    let mut core = target.session.core(0)?;
+    let elf = target_firmware(&mut core, &target.flash, firmware)?;
+    let was_running = !core.core_halted()?;
+    if was_running {
+        core.halt(Duration::from_millis(100))?;
+    }
+    let usage = Registers::read(&mut core)
+        .and_then(|registers| StackUsage::read(&mut core, &elf, registers.msp));
+    if was_running {
+        core.run()?;
+    }
+    print!("{}", usage?);
+    Ok(ExitCode::SUCCESS)
+}
 
-    let flash_size_bytes = 2048 * 1024;
-    let flash_start = 0x0800_0000;
-    let mut flash_mem = FlashMemory::with_capacity(flash_size_bytes, flash_start);
+fn coredump(cli: &Cli, output: &Path, firmware: Option<&str>) -> Result<ExitCode, Error> {
+    let mut target = attach(cli, None)?;
+    let ram = ram_ranges(target.session.target());
+    let mut core = target.session.core(0)?;
+    let record = find_compact_info_in_ranges(&mut core, &target.flash)
+        .map(|found| found.record)
+        .unwrap_or_default();
+    let elf = match target_firmware(&mut core, &target.flash, firmware) {
+        Ok(elf) => Some(elf),
+        Err(e) => {
+            eprintln!(
+                "WARNING: {e}, only RAM known to probe-rs is saved and the firmware SHA is not recorded"
+            );
+            None
+        }
+    };
+    let fw_sha = elf.as_ref().and_then(|elf| {
+        let sha = elf.fw_sha()?;
+        let device = device_sha(&mut core, &elf.flash_image()?).ok()?;
+        if device != sha {
+            eprintln!(
+                "WARNING: target FLASH differs from the firmware, the firmware SHA is not recorded"
+            );
+        }
+        (device == sha).then_some(sha)
+    });
+    let regions = capture_regions(&mut core, elf.as_ref(), &ram);
+    let (dump, unreadable) = CoreDump::capture(&mut core, &regions, fw_sha, record)?;
+    for range in unreadable {
+        eprintln!(
+            "WARNING: 0x{:08x}..0x{:08x} could not be read, clock disabled?",
+            range.start, range.end
+        );
+    }
+    fs::write(output, dump.to_elf())?;
+    let bytes: usize = dump.memory.regions().iter().map(|r| r.bytes.len()).sum();
+    println!(
+        "Saved {bytes}B of memory in {} regions with registers to {}, PC 0x{:08x}",
+        dump.memory.regions().len(),
+        output.display(),
+        dump.registers.pc()
+    );
+    Ok(ExitCode::SUCCESS)
+}
 
-    const SEARCH_CHUNK_SIZE_B: usize = 512;
-    let mut buf = [0u8; SEARCH_CHUNK_SIZE_B];
-    for _ in 0..flash_size_bytes as usize / SEARCH_CHUNK_SIZE_B {
-        let Some(addr) = flash_mem.next_missing_addr() else {
-            break;
-        };
-        println!("reading: {addr:02x?}");
-        core.read(addr, &mut buf)?;
-        flash_mem.push_chunk(&buf);
-
-        let Some(potential_match) = buf
-            .windows(4)
-            .enumerate()
-            .find(|(_, b)| b == &COMPACT_INFO_MAGIC.to_be_bytes())
-            .map(|(i, _)| i)
-        else {
-            continue;
-        };
-        let matched_at_addr = addr + potential_match as u64;
-        println!("potential match: 0x{:02x?}", matched_at_addr);
-
-        let tail = flash_mem.slice_from(matched_at_addr + 4);
-        if tail.len() < 6 {
-            let Some(addr) = flash_mem.next_missing_addr() else {
-                println!("run out of flash, not found");
-                break;
-            };
-            println!("reading one more chunk");
-            core.read(addr, &mut buf)?;
-            flash_mem.push_chunk(&buf);
+/// Core dump and the firmware it was taken from, given or found in the local registry
+fn open_core(path: &Path, firmware: Option<&str>) -> Result<(CoreDump, FirmwareElf), Error> {
+    let dump = CoreDump::parse(&fs::read(path)?)?;
+    let elf = match firmware {
+        Some(firmware) => {
+            let elf = open_firmware(firmware)?;
+            if dump.fw_sha.is_some_and(|sha| elf.fw_sha() != Some(sha)) {
+                eprintln!("WARNING: core dump was taken from a different firmware");
+            }
+            elf
         }
-        let tail = flash_mem.slice_from(matched_at_addr + 4);
+        None => find_firmware(dump.fw_sha, &dump.record, dump.build_info(), || {
+            Error::CoreDump("dump does not identify the firmware, pass it with --firmware".into())
+        })?,
+    };
+    Ok((dump, elf))
+}
 
-        let len = u16::from_le_bytes([tail[0], tail[1]]) as usize;
-        if tail.len() < 6 + len {
-            let Some(addr) = flash_mem.next_missing_addr() else {
-                println!("run out of flash, not found");
-                break;
+fn watch(
+    cli: &Cli,
+    what: &str,
+    firmware: Option<&str>,
+    rate: u32,
+    len: u64,
+    halt_on_write: bool,
+) -> Result<ExitCode, Error> {
+    let mut target = attach(cli, None)?;
+    let mut core = target.session.core(0)?;
+    let (elf, watched) = match inspect::parse_address(what) {
+        Some(address) => {
+            // only needed to show which code wrote
+            let elf = if halt_on_write || firmware.is_some() {
+                target_firmware(&mut core, &target.flash, firmware)
+                    .inspect_err(|e| eprintln!("warning: code addresses not symbolized: {e}"))
+                    .ok()
+            } else {
+                None
             };
-            println!("reading one more chunk");
-            core.read(addr, &mut buf)?;
-            flash_mem.push_chunk(&buf);
-        }
-
-        let tail = flash_mem.slice_from(matched_at_addr + 4);
-        let expected_crc = u32::from_le_bytes([tail[2], tail[3], tail[4], tail[5]]);
-        let data = &tail[6..(6 + len)];
-        let crc = build_info_crc(&data);
-        if crc == expected_crc {
-            println!("found build info! {data:02x?}");
-            let build_info = BedrockBuildInfo::from_ww_bytes(data).unwrap();
-            println!("{build_info:#?}");
-            break;
-        } else {
-            println!("CRC mismatch");
+            (elf, Watched::raw(format!("0x{address:08x}"), address, len))
+        }
+        None => {
+            let elf = target_firmware(&mut core, &target.flash, firmware)?;
+            let watched = Watched::new(&inspect::lookup(&elf, what)?, &elf, len)?;
+            (Some(elf), watched)
         }
+    };
+    ctrlc::set_handler(|| STOP.store(true, Ordering::Relaxed)).map_err(io::Error::other)?;
+    let out = &mut io::stdout().lock();
+    if halt_on_write {
+        let core_type = CoreType::read(&mut core)?
+            .ok_or_else(|| Error::Watchpoint("core not identified from CPUID".into()))?;
+        let debug = elf.as_ref().and_then(|elf| DebugInfo::load(elf).ok());
+        watch::halt_on_write(&mut core, core_type, &watched, debug.as_ref(), out, &STOP)?;
+    } else {
+        let interval = Duration::from_secs(1) / rate.max(1);
+        watch::poll(&mut core, &watched, interval, out, &STOP)?;
     }
+    Ok(ExitCode::SUCCESS)
+}
 
-    // read out the remaining flash chunks, TODO: readout only used number of bytes as in ELF
-    // while let Some(addr) = flash_mem.next_missing_addr() {
-    //     println!("reading remaining: {addr:02x?}");
-    //     core.read(addr, &mut buf)?;
-    //     flash_mem.push_chunk(&buf);
-    // }
+fn write(cli: &Cli, what: &str, value: &str, firmware: Option<&str>) -> Result<ExitCode, Error> {
+    let mut target = attach(cli, None)?;
+    let mut core = target.session.core(0)?;
+    let elf = target_firmware(&mut core, &target.flash, firmware)?;
+    let item = inspect::lookup(&elf, what)?;
+    item.write(&mut core, &elf, value)?;
+    print!("{}", item.read(&mut core, &elf, 0)?);
+    Ok(ExitCode::SUCCESS)
+}
 
-    // Read a block of 50 32 bit words.
-    // let mut buff = [0u32; 50];
-    // core.read_32(0x2000_0000, &mut buff)?;
-    // println!("{:02x?}", buff);
+/// Firmware given by path or SHA, or else what the target runs, found in the local registry
+fn target_firmware(
+    core: &mut Core,
+    flash: &[Range<u64>],
+    firmware: Option<&str>,
+) -> Result<FirmwareElf, Error> {
+    if let Some(firmware) = firmware {
+        return open_firmware(firmware);
+    }
+    let running = find_running(core, flash, &Registry::open_default())?;
+    for warning in running.warnings() {
+        eprintln!("WARNING: {warning}");
+    }
+    Ok(running.elf)
+}
 
-    // Read a single 32 bit word.
-    // let word = core.read_word_32(0x2000_0000)?;
-    // println!("{:02x?}", word);
+/// ELF file path, or SHA of a firmware in the local registry
+fn open_firmware(firmware: &str) -> Result<FirmwareElf, Error> {
+    let path = Path::new(firmware);
+    if path.exists() {
+        return FirmwareElf::open(path);
+    }
+    let entry = Registry::open_default().find(firmware)?;
+    FirmwareElf::open(&entry.elf_path())
+}
 
-    // Writing is just as simple.
-    // let buff = [0u32; 50];
-    // core.write_32(0x2000_0000, &buff)?;
+fn sbom(firmware: &str, format: SbomFormat, output: Option<&Path>) -> Result<ExitCode, Error> {
+    let elf = open_firmware(firmware)?;
+    let info = elf.full_info().ok_or(Error::FullInfoNotFound)?;
+    if info.crate_info.dependencies.is_empty() {
+        eprintln!(
+            "warning: no dependencies recorded, use DependencyDepth::Full in build.rs and keep BuildInfoField::Dependencies"
+        );
+    }
+    let created = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let bom = bedrock::sbom::generate(&info, format, &created);
+    let json = serde_json::to_string_pretty(&bom).expect("JSON values always serialize");
+    match output {
+        Some(path) => fs::write(path, json)?,
+        None => println!("{json}"),
+    }
+    Ok(ExitCode::SUCCESS)
+}
 
-    // of course we can also write 8bit words.
-    // let buff = [0u8; 50];
-    // core.write_8(0x2000_0000, &buff)?;
+fn keygen(secret: &Path, public: &Path, force: bool) -> Result<ExitCode, Error> {
+    let public_key = signing::generate_key(secret, public, force)?;
+    println!("Public key: {}", hex::encode(public_key));
+    Ok(ExitCode::SUCCESS)
+}
 
-    Ok(())
+fn sign(elf_path: &Path, key: &Path) -> Result<ExitCode, Error> {
+    let secret_key = signing::read_key(key)?;
+    let mut elf = FirmwareElf::open(elf_path)?;
+    let record = signing::sign_elf(&mut elf, &secret_key)?;
+    fs::write(elf_path, elf.bytes())?;
+    println!(
+        "Signed {}B image with key {}, image SHA-256 {}",
+        record.image_len,
+        hex::encode(record.key_id),
+        hex::encode(record.image_hash)
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+fn verify(elf_path: &Path, key: &Path) -> Result<ExitCode, Error> {
+    let public_key = signing::read_key(key)?;
+    let elf = FirmwareElf::open(elf_path)?;
+    match signing::verify_elf(&elf, &public_key) {
+        Ok(record) => {
+            println!(
+                "Signature OK, key {}, image SHA-256 {}",
+                hex::encode(record.key_id),
+                hex::encode(record.image_hash)
+            );
+            Ok(ExitCode::SUCCESS)
+        }
+        Err(Error::Signature(e)) => {
+            println!("Signature check failed: {e}");
+            Ok(ExitCode::FAILURE)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn lint(elf_path: &Path, ram: bool) -> Result<ExitCode, Error> {
+    let elf = FirmwareElf::open(elf_path)?;
+    let problems = bedrock::lint::lint(&elf, ram);
+    for problem in &problems {
+        println!("{problem}");
+    }
+    if problems.is_empty() {
+        println!("No problems found");
+        Ok(ExitCode::SUCCESS)
+    } else {
+        println!("\n{} problem(s) found", problems.len());
+        Ok(ExitCode::FAILURE)
+    }
 }
@@ -0,0 +1,88 @@
+//! Memory access abstraction, so that the same analysis code works with a live target and offline memory dumps.
+
+use crate::Error;
+use probe_rs::MemoryInterface;
+
+pub trait Memory {
+    fn read(&mut self, address: u64, buf: &mut [u8]) -> Result<(), Error>;
+
+    fn write(&mut self, address: u64, data: &[u8]) -> Result<(), Error>;
+
+    fn read_u32(&mut self, address: u64) -> Result<u32, Error> {
+        let mut buf = [0u8; 4];
+        self.read(address, &mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+}
+
+impl Memory for probe_rs::Core<'_> {
+    fn read(&mut self, address: u64, buf: &mut [u8]) -> Result<(), Error> {
+        MemoryInterface::read(self, address, buf)?;
+        Ok(())
+    }
+
+    fn write(&mut self, address: u64, data: &[u8]) -> Result<(), Error> {
+        MemoryInterface::write_8(self, address, data)?;
+        Ok(())
+    }
+}
+
+/// Offline memory contents, e.g. loaded from a file or recorded from a target earlier
+#[derive(Default, Debug, Clone)]
+pub struct MemoryDump {
+    regions: Vec<DumpRegion>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DumpRegion {
+    pub address: u64,
+    pub bytes: Vec<u8>,
+}
+
+impl MemoryDump {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a region, later regions take precedence if overlapping
+    pub fn add_region(&mut self, address: u64, bytes: Vec<u8>) {
+        self.regions.push(DumpRegion { address, bytes });
+    }
+
+    pub fn with_region(mut self, address: u64, bytes: Vec<u8>) -> Self {
+        self.add_region(address, bytes);
+        self
+    }
+
+    pub fn regions(&self) -> &[DumpRegion] {
+        &self.regions
+    }
+}
+
+impl MemoryDump {
+    fn region_mut(&mut self, address: u64, len: usize) -> Result<(&mut Vec<u8>, usize), Error> {
+        let region = self.regions.iter_mut().rev().find(|r| {
+            address >= r.address && address + len as u64 <= r.address + r.bytes.len() as u64
+        });
+        let Some(region) = region else {
+            return Err(Error::Unmapped { address, len });
+        };
+        let offset = (address - region.address) as usize;
+        Ok((&mut region.bytes, offset))
+    }
+}
+
+impl Memory for MemoryDump {
+    fn read(&mut self, address: u64, buf: &mut [u8]) -> Result<(), Error> {
+        let (bytes, offset) = self.region_mut(address, buf.len())?;
+        buf.copy_from_slice(&bytes[offset..offset + buf.len()]);
+        Ok(())
+    }
+
+    /// Changes the dump only, e.g. to see how a patched value would be decoded
+    fn write(&mut self, address: u64, data: &[u8]) -> Result<(), Error> {
+        let (bytes, offset) = self.region_mut(address, data.len())?;
+        bytes[offset..offset + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+}
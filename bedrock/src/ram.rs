@@ -0,0 +1,53 @@
+//! Load a RAM linked firmware (`RAM_LINK=1`) into RAM and start it, without touching FLASH.
+//!
+//! The core is reset and halted first, so that peripherals are in their reset state, as they would be
+//! when booting from FLASH. Firmware already in FLASH only runs up to its reset vector.
+
+use crate::Error;
+use crate::chip::VTOR;
+use crate::elf::FirmwareElf;
+use probe_rs::{Core, MemoryInterface};
+use std::time::Duration;
+
+/// Where a RAM loaded firmware was started from
+#[derive(Debug, Clone, Copy)]
+pub struct RamStart {
+    pub vector_table: u64,
+    pub stack_pointer: u32,
+    pub reset_vector: u32,
+}
+
+/// Initial SP and reset vector from the vector table in the ELF image
+pub fn ram_start(elf: &FirmwareElf) -> Result<RamStart, Error> {
+    let Some(vector_table) = elf.vector_table().filter(|_| elf.is_ram_linked()) else {
+        return Err(Error::LinkMode { ram_linked: false });
+    };
+    let words = elf.read(vector_table, 8).ok_or(Error::Unmapped {
+        address: vector_table,
+        len: 8,
+    })?;
+    let word = |i: usize| u32::from_le_bytes(words[i * 4..i * 4 + 4].try_into().unwrap());
+    Ok(RamStart {
+        vector_table,
+        stack_pointer: word(0),
+        reset_vector: word(1),
+    })
+}
+
+/// Reset and halt, write all loadable segments, point VTOR, SP and PC to the RAM image and resume
+pub fn load_and_run(core: &mut Core, elf: &FirmwareElf) -> Result<RamStart, Error> {
+    let start = ram_start(elf)?;
+    core.reset_and_halt(Duration::from_millis(500))?;
+    for (address, offset, size) in elf.load_segments() {
+        core.write_8(address, &elf.bytes()[offset..offset + size])?;
+    }
+    // .bss is zeroed by the firmware Reset handler, same as when booting from FLASH
+    core.write_word_32(VTOR, start.vector_table as u32)?;
+    let sp = core.stack_pointer().id();
+    core.write_core_reg(sp, start.stack_pointer)?;
+    let pc = core.program_counter().id();
+    // Thumb bit is kept in EPSR, not in PC
+    core.write_core_reg(pc, start.reset_vector & !1)?;
+    core.run()?;
+    Ok(start)
+}
@@ -0,0 +1,171 @@
+//! Firmware logs over RTT: attach to the control block and print defmt frames, or plain text if the firmware
+//! does not use defmt.
+
+use crate::Error;
+use crate::bedlog::BedlogWriter;
+use crate::elf::FirmwareElf;
+use defmt_decoder::{DecodeError, Locations, StreamDecoder, Table};
+use probe_rs::Core;
+use probe_rs::rtt::{Rtt, ScanRegion};
+use std::fs::File;
+use std::io::Write;
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Up channel used by defmt-rtt and rtt-target for logs
+const LOG_CHANNEL: usize = 0;
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Log decoder for one firmware
+pub struct Logs {
+    /// None if the firmware does not use defmt, logs are then printed as text
+    table: Option<Table>,
+    locations: Option<Locations>,
+    colored: bool,
+}
+
+impl Logs {
+    pub fn from_elf(elf: &FirmwareElf, colored: bool) -> Result<Self, Error> {
+        let table = Table::parse(elf.bytes()).map_err(|e| Error::Defmt(e.to_string()))?;
+        let locations = table
+            .as_ref()
+            .and_then(|table| table.get_locations(elf.bytes()).ok())
+            .filter(|locations| !locations.is_empty());
+        Ok(Logs {
+            table,
+            locations,
+            colored,
+        })
+    }
+
+    /// Decode and print everything `read` returns, until it returns 0 bytes
+    pub fn print(
+        &self,
+        mut read: impl FnMut(&mut [u8]) -> Result<usize, Error>,
+        out: &mut impl Write,
+    ) -> Result<(), Error> {
+        let mut stream = self.stream();
+        let mut buf = [0u8; 1024];
+        loop {
+            let n = read(&mut buf)?;
+            if n == 0 {
+                return Ok(());
+            }
+            stream.feed(&buf[..n], "", out)?;
+        }
+    }
+
+    /// Incremental decoder, for bytes arriving in chunks
+    pub fn stream(&self) -> LogStream<'_> {
+        LogStream {
+            logs: self,
+            decoder: self.table.as_ref().map(|table| table.new_stream_decoder()),
+        }
+    }
+}
+
+pub struct LogStream<'a> {
+    logs: &'a Logs,
+    decoder: Option<Box<dyn StreamDecoder + Send + Sync + 'a>>,
+}
+
+impl LogStream<'_> {
+    /// Print all frames completed by `bytes`, each prefixed with `prefix` (e.g. host time). Text logs are printed
+    /// as they are.
+    pub fn feed(&mut self, bytes: &[u8], prefix: &str, out: &mut impl Write) -> Result<(), Error> {
+        let (Some(decoder), Some(table)) = (&mut self.decoder, &self.logs.table) else {
+            out.write_all(bytes)?;
+            out.flush()?;
+            return Ok(());
+        };
+        decoder.received(bytes);
+        loop {
+            match decoder.decode() {
+                Ok(frame) => {
+                    writeln!(out, "{prefix}{}", frame.display(self.logs.colored))?;
+                    let location = self
+                        .logs
+                        .locations
+                        .as_ref()
+                        .and_then(|locations| locations.get(&frame.index()));
+                    if let Some(location) = location {
+                        writeln!(
+                            out,
+                            "└─ {} @ {}:{}",
+                            location.module,
+                            location.file.display(),
+                            location.line
+                        )?;
+                    }
+                }
+                Err(DecodeError::UnexpectedEof) => return Ok(()),
+                Err(DecodeError::Malformed) if table.encoding().can_recover() => {
+                    writeln!(out, "{prefix}(malformed frame skipped)")?;
+                }
+                Err(DecodeError::Malformed) => {
+                    return Err(Error::Defmt(
+                        "malformed frame, stream cannot be decoded further".into(),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Attach to the RTT control block at `_SEGGER_RTT`, waiting for a just started firmware to initialize it
+pub fn attach(core: &mut Core, elf: &FirmwareElf) -> Result<Rtt, Error> {
+    let address = elf
+        .symbol_address("_SEGGER_RTT")
+        .ok_or(Error::RttNotFound)?;
+    let deadline = Instant::now() + Duration::from_secs(1);
+    loop {
+        match Rtt::attach_region(core, &ScanRegion::Exact(address)) {
+            Ok(rtt) => return Ok(rtt),
+            Err(e) if Instant::now() > deadline => return Err(e.into()),
+            Err(_) => sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+/// Print logs from the running target until interrupted, saving the raw bytes to a capture file if given
+pub fn follow(
+    core: &mut Core,
+    rtt: &mut Rtt,
+    logs: &Logs,
+    out: &mut impl Write,
+    mut capture: Option<&mut BedlogWriter<File>>,
+) -> Result<(), Error> {
+    let channel = rtt.up_channel(LOG_CHANNEL).ok_or(Error::RttNotFound)?;
+    logs.print(
+        |buf| loop {
+            let n = channel.read(core, buf)?;
+            if n > 0 {
+                if let Some(capture) = capture.as_mut() {
+                    capture.write_chunk(SystemTime::now(), &buf[..n])?;
+                }
+                return Ok(n);
+            }
+            sleep(POLL_INTERVAL);
+        },
+        out,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_logs_are_passed_through() {
+        let logs = Logs {
+            table: None,
+            locations: None,
+            colored: false,
+        };
+        let mut input: &[u8] = b"boot\nready\n";
+        let mut out = Vec::new();
+        logs.print(|buf| Ok(std::io::Read::read(&mut input, buf)?), &mut out)
+            .unwrap();
+        assert_eq!(out, b"boot\nready\n");
+    }
+}
@@ -0,0 +1,165 @@
+//! Find the ELF of the firmware running on a target in the local registry, to decode its logs without having the
+//! build at hand.
+//!
+//! Candidates are registry entries with a compact build info record equal to the one read from the target, the
+//! firmware SHA of the target FLASH then tells which of them is exactly what is running.
+
+use crate::Error;
+use crate::compact_info::{FoundCompactInfo, find_compact_info_in_ranges};
+use crate::elf::FirmwareElf;
+use crate::mem::Memory;
+use crate::verify::device_sha;
+use fw_registry::{Entry, Registry};
+use std::ops::Range;
+
+pub struct RunningFirmware {
+    pub entry: Entry,
+    pub elf: FirmwareElf,
+    /// Compact build info read from the target
+    pub device: FoundCompactInfo,
+    /// Target FLASH has the same SHA as the registry entry, not only the same build info
+    pub sha_matches: bool,
+}
+
+impl RunningFirmware {
+    /// Reasons why the logs may not be decoded correctly or cannot be traced back to sources
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        match &self.device.info.version_control {
+            Some(vc) if vc.dirty => warnings.push(format!(
+                "firmware was built from a dirty working tree ({vc}), uncommitted changes are not recorded anywhere"
+            )),
+            Some(_) => {}
+            None => warnings.push(
+                "firmware has no version control info, it cannot be traced back to sources".into(),
+            ),
+        }
+        if !self.sha_matches {
+            warnings.push(format!(
+                "target FLASH differs from registry firmware {} with the same build info, was it patched or \
+                 signed after flashing? Log locations may be wrong",
+                self.entry.sha
+            ));
+        }
+        warnings
+    }
+}
+
+/// Read compact build info from the target and find the matching firmware in the registry
+pub fn find_running(
+    mem: &mut impl Memory,
+    flash: &[Range<u64>],
+    registry: &Registry,
+) -> Result<RunningFirmware, Error> {
+    let device = find_compact_info_in_ranges(mem, flash)?;
+    let mut same_info = None;
+    for (entry, elf) in find_by_record(registry, &device.record)? {
+        let sha_matches = match elf.flash_image() {
+            Some(image) => hex::encode(device_sha(mem, &image)?) == entry.sha,
+            None => false,
+        };
+        let found = RunningFirmware {
+            entry,
+            elf,
+            device: device.clone(),
+            sha_matches,
+        };
+        if sha_matches {
+            return Ok(found);
+        }
+        same_info.get_or_insert(found);
+    }
+    same_info.ok_or_else(|| Error::NotInRegistry(Box::new(device.info)))
+}
+
+/// Registry entries with the same compact build info record, byte to byte
+pub fn find_by_record(
+    registry: &Registry,
+    record: &[u8],
+) -> Result<Vec<(Entry, FirmwareElf)>, Error> {
+    let mut found = Vec::new();
+    for entry in registry.entries()? {
+        let Ok(elf) = FirmwareElf::open(&entry.elf_path()) else {
+            continue;
+        };
+        if elf
+            .compact_record()
+            .is_some_and(|(_, elf_record)| elf_record == record)
+        {
+            found.push((entry, elf));
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+    use crate::mem::MemoryDump;
+    use std::{env, fs};
+
+    /// FLASH of a target running the fixture firmware and the range to look for build info in
+    fn target(patch: impl FnOnce(&mut [u8])) -> (MemoryDump, Vec<Range<u64>>) {
+        let image = fixture::elf().flash_image().unwrap();
+        let mut bytes = image.bytes;
+        patch(&mut bytes);
+        let flash = image.start..image.start + bytes.len() as u64;
+        (
+            MemoryDump::new().with_region(image.start, bytes),
+            vec![flash],
+        )
+    }
+
+    fn registry(test: &str) -> Registry {
+        let root = env::temp_dir().join(format!("bedrock_running_{test}_{}", std::process::id()));
+        _ = fs::remove_dir_all(&root);
+        Registry::open(root)
+    }
+
+    #[test]
+    fn sha_match_is_preferred() {
+        let registry = registry("sha_match");
+        let sha = hex::encode(fixture::elf().fw_sha().unwrap());
+        // same build info, but a different FLASH image
+        registry.push(&"0".repeat(64), fixture::ELF, None).unwrap();
+        registry.push(&sha, fixture::ELF, None).unwrap();
+
+        let (mut mem, flash) = target(|_| {});
+        let running = find_running(&mut mem, &flash, &registry).unwrap();
+        assert!(running.sha_matches);
+        assert_eq!(running.entry.sha, sha);
+        assert_eq!(running.warnings(), Vec::<String>::new());
+        fs::remove_dir_all(registry.root()).unwrap();
+    }
+
+    #[test]
+    fn same_build_info_different_sha() {
+        let registry = registry("different_sha");
+        let sha = hex::encode(fixture::elf().fw_sha().unwrap());
+        registry.push(&sha, fixture::ELF, None).unwrap();
+
+        // a reserved vector table entry, outside of the build info record
+        let (mut mem, flash) = target(|bytes| bytes[0x1C] ^= 1);
+        let running = find_running(&mut mem, &flash, &registry).unwrap();
+        assert!(!running.sha_matches);
+        assert_eq!(running.entry.sha, sha);
+        let warnings = running.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with(&format!(
+            "target FLASH differs from registry firmware {sha} with the same build info"
+        )));
+        fs::remove_dir_all(registry.root()).unwrap();
+    }
+
+    #[test]
+    fn not_in_registry() {
+        let registry = registry("not_in_registry");
+        let (mut mem, flash) = target(|_| {});
+        let err = find_running(&mut mem, &flash, &registry).err().unwrap();
+        let Error::NotInRegistry(info) = err else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!(info.crate_info.name, "fixture");
+    }
+}
@@ -0,0 +1,323 @@
+//! Software bill of materials from full build info, in CycloneDX or SPDX JSON format.
+
+use bedrock_build_info::{BedrockBuildInfoOwned, CrateInfoOwned, CrateSourceOwned};
+use serde_json::{Value, json};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum SbomFormat {
+    /// CycloneDX 1.5
+    CycloneDx,
+    /// SPDX 2.3
+    Spdx,
+}
+
+/// Unique crate in the dependency tree, with its direct dependencies
+struct Package<'a> {
+    info: &'a CrateInfoOwned,
+    depends_on: Vec<String>,
+}
+
+impl Package<'_> {
+    fn id(&self) -> String {
+        crate_id(self.info)
+    }
+}
+
+fn crate_id(info: &CrateInfoOwned) -> String {
+    let v = &info.version;
+    format!("{}@{}.{}.{}", info.name, v.major.0, v.minor.0, v.patch.0)
+}
+
+/// Flatten dependency tree, the same crate can appear many times in it, first is the firmware crate itself
+fn packages(root: &CrateInfoOwned) -> Vec<Package<'_>> {
+    fn walk<'a>(info: &'a CrateInfoOwned, out: &mut Vec<Package<'a>>) {
+        let id = crate_id(info);
+        if out.iter().any(|p| p.id() == id) {
+            return;
+        }
+        out.push(Package {
+            info,
+            depends_on: info.dependencies.iter().map(crate_id).collect(),
+        });
+        for dep in &info.dependencies {
+            walk(dep, out);
+        }
+    }
+    let mut out = Vec::new();
+    walk(root, &mut out);
+    out
+}
+
+fn version(info: &CrateInfoOwned) -> String {
+    let v = &info.version;
+    let mut version = format!("{}.{}.{}", v.major.0, v.minor.0, v.patch.0);
+    if let Some(pre) = &v.pre {
+        version = format!("{version}-{pre}");
+    }
+    if let Some(build) = &v.build {
+        version = format!("{version}+{build}");
+    }
+    version
+}
+
+/// Package URL, see https://github.com/package-url/purl-spec
+fn purl(info: &CrateInfoOwned) -> Option<String> {
+    let base = format!(
+        "pkg:cargo/{}@{}",
+        info.name,
+        version(info).replace('+', "%2B")
+    );
+    match info.source()? {
+        CrateSourceOwned::CratesIo => Some(base),
+        CrateSourceOwned::Registry(url) => Some(format!("{base}?repository_url={url}")),
+        CrateSourceOwned::Git(url) => {
+            Some(format!("{base}?vcs_url=git+{}", url.replace('#', "%23")))
+        }
+        CrateSourceOwned::Path(_) => None,
+    }
+}
+
+fn download_location(info: &CrateInfoOwned) -> String {
+    match info.source() {
+        Some(CrateSourceOwned::CratesIo) => format!(
+            "https://crates.io/api/v1/crates/{}/{}/download",
+            info.name,
+            version(info)
+        ),
+        Some(CrateSourceOwned::Git(url)) => format!("git+{url}"),
+        _ => "NOASSERTION".to_string(),
+    }
+}
+
+pub fn generate(info: &BedrockBuildInfoOwned, format: SbomFormat, created: &str) -> Value {
+    match format {
+        SbomFormat::CycloneDx => cyclonedx(info, created),
+        SbomFormat::Spdx => spdx(info, created),
+    }
+}
+
+fn cyclonedx(info: &BedrockBuildInfoOwned, created: &str) -> Value {
+    let packages = packages(&info.crate_info);
+    let component = |package: &Package, kind: &str| {
+        let c = package.info;
+        let mut component = json!({
+            "type": kind,
+            "bom-ref": package.id(),
+            "name": c.name,
+            "version": version(c),
+        });
+        if let Some(license) = c.license() {
+            component["licenses"] = json!([{ "expression": license }]);
+        }
+        if !c.authors.is_empty() {
+            component["author"] = json!(c.authors.join(", "));
+        }
+        if let Some(purl) = purl(c) {
+            component["purl"] = json!(purl);
+        }
+        match c.source() {
+            Some(CrateSourceOwned::Git(url)) => {
+                component["externalReferences"] = json!([{ "type": "vcs", "url": url }]);
+            }
+            Some(CrateSourceOwned::Path(path)) => {
+                component["properties"] = json!([{ "name": "cargo:path", "value": path }]);
+            }
+            _ => {}
+        }
+        component
+    };
+
+    let (root, deps) = packages
+        .split_first()
+        .expect("root crate is always present");
+    let mut firmware = component(root, "firmware");
+    if let Some(vc) = &info.version_control {
+        let mut properties = vec![json!({ "name": "git:dirty", "value": vc.dirty.to_string() })];
+        if let Some(commit) = vc.commit_id.as_ref().or(vc.commit_short_id.as_ref()) {
+            properties.push(json!({ "name": "git:commit", "value": commit }));
+        }
+        firmware["properties"] = json!(properties);
+    }
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "timestamp": created,
+            "tools": {
+                "components": [{
+                    "type": "application",
+                    "name": "bedrock",
+                    "version": env!("CARGO_PKG_VERSION"),
+                }]
+            },
+            "component": firmware,
+        },
+        "components": deps.iter().map(|p| component(p, "library")).collect::<Vec<_>>(),
+        "dependencies": packages.iter().map(|p| json!({
+            "ref": p.id(),
+            "dependsOn": p.depends_on,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+fn spdx(info: &BedrockBuildInfoOwned, created: &str) -> Value {
+    let packages = packages(&info.crate_info);
+    // SPDX IDs only allow letters, numbers, '.' and '-'
+    let spdx_id = |idx: usize| format!("SPDXRef-Package-{idx}");
+    let index_of = |id: &str| packages.iter().position(|p| p.id() == id);
+
+    let spdx_packages: Vec<Value> = packages
+        .iter()
+        .enumerate()
+        .map(|(idx, package)| {
+            let c = package.info;
+            let mut p = json!({
+                "SPDXID": spdx_id(idx),
+                "name": c.name,
+                "versionInfo": version(c),
+                "downloadLocation": download_location(c),
+                "licenseConcluded": "NOASSERTION",
+                "licenseDeclared": c.license().unwrap_or("NOASSERTION"),
+                "copyrightText": "NOASSERTION",
+            });
+            if !c.authors.is_empty() {
+                p["originator"] = json!(format!("Person: {}", c.authors.join(", ")));
+            }
+            if let Some(purl) = purl(c) {
+                p["externalRefs"] = json!([{
+                    "referenceCategory": "PACKAGE-MANAGER",
+                    "referenceType": "purl",
+                    "referenceLocator": purl,
+                }]);
+            }
+            p
+        })
+        .collect();
+
+    let mut relationships = vec![json!({
+        "spdxElementId": "SPDXRef-DOCUMENT",
+        "relationshipType": "DESCRIBES",
+        "relatedSpdxElement": spdx_id(0),
+    })];
+    for (idx, package) in packages.iter().enumerate() {
+        for dep in package.depends_on.iter().filter_map(|id| index_of(id)) {
+            relationships.push(json!({
+                "spdxElementId": spdx_id(idx),
+                "relationshipType": "DEPENDS_ON",
+                "relatedSpdxElement": spdx_id(dep),
+            }));
+        }
+    }
+
+    let root = crate_id(&info.crate_info);
+    let unique = info
+        .content_id()
+        .map(|id| format!("{id:016x}"))
+        .unwrap_or_else(|| created.to_string());
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": root,
+        "documentNamespace": format!("https://spdx.org/spdxdocs/{}-{unique}", root.replace('@', "-")),
+        "creationInfo": {
+            "created": created,
+            "creators": [format!("Tool: bedrock-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": spdx_packages,
+        "relationships": relationships,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+    use bedrock_build_info::CrateInfoExtOwned;
+
+    fn krate(
+        name: &str,
+        template: &CrateInfoOwned,
+        source: CrateSourceOwned,
+        deps: Vec<CrateInfoOwned>,
+    ) -> CrateInfoOwned {
+        CrateInfoOwned {
+            name: name.into(),
+            version: template.version.clone(),
+            authors: vec![],
+            enabled_features: vec![],
+            dependencies: deps,
+            ext: Some(CrateInfoExtOwned {
+                license: Some("MIT OR Apache-2.0".into()),
+                source: Some(source),
+            }),
+        }
+    }
+
+    #[test]
+    fn pre_release_and_build_metadata() {
+        let mut v = ww_version::VersionOwned::new(1, 0, 0);
+        v.pre = Some("rc.1".into());
+        v.build = Some("zstd.1.5.0".into());
+        let template = CrateInfoOwned {
+            name: "zstd-sys".into(),
+            version: v,
+            authors: vec![],
+            enabled_features: vec![],
+            dependencies: vec![],
+            ext: None,
+        };
+        let info = krate("zstd-sys", &template, CrateSourceOwned::CratesIo, vec![]);
+        assert_eq!(version(&info), "1.0.0-rc.1+zstd.1.5.0");
+        assert_eq!(
+            purl(&info).unwrap(),
+            "pkg:cargo/zstd-sys@1.0.0-rc.1%2Bzstd.1.5.0"
+        );
+    }
+
+    #[test]
+    fn shared_dependencies_are_listed_once() {
+        let mut info = fixture::build_info("awesome");
+        let root = info.crate_info.clone();
+        let shared = krate("heapless", &root, CrateSourceOwned::CratesIo, vec![]);
+        info.crate_info.dependencies = vec![
+            krate(
+                "embassy-sync",
+                &root,
+                CrateSourceOwned::Git("https://github.com/embassy-rs/embassy#0123abcd".into()),
+                vec![shared.clone()],
+            ),
+            krate(
+                "local",
+                &root,
+                CrateSourceOwned::Path("libs/local".into()),
+                vec![shared],
+            ),
+        ];
+
+        let bom = generate(&info, SbomFormat::CycloneDx, "2025-07-13T16:20:00Z");
+        let names: Vec<_> = bom["components"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["embassy-sync", "heapless", "local"]);
+        assert_eq!(
+            bom["components"][0]["purl"],
+            "pkg:cargo/embassy-sync@1.2.3?vcs_url=git+https://github.com/embassy-rs/embassy%230123abcd"
+        );
+        assert_eq!(bom["metadata"]["component"]["name"], "awesome");
+        assert_eq!(bom["dependencies"][3]["dependsOn"][0], "heapless@1.2.3");
+
+        let spdx = generate(&info, SbomFormat::Spdx, "2025-07-13T16:20:00Z");
+        assert_eq!(spdx["packages"].as_array().unwrap().len(), 4);
+        // DESCRIBES + awesome -> 2 deps + each of them -> heapless
+        assert_eq!(spdx["relationships"].as_array().unwrap().len(), 5);
+        assert_eq!(
+            spdx["packages"][2]["downloadLocation"],
+            "https://crates.io/api/v1/crates/heapless/1.2.3/download"
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! Signing firmware ELF files after linking and checking their signatures, see `bedrock_build_info::signature`.
+//!
+//! Key files hold 32 bytes in hex: ed25519 secret key (seed) for signing, public key for verification.
+
+use crate::Error;
+use crate::elf::FirmwareElf;
+use bedrock_build_info::signature::{
+    self, SIGNATURE_RECORD_LEN, SignatureError, SignatureRecord, SignedImage,
+};
+use ed25519_dalek::SigningKey;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+pub fn read_key(path: &Path) -> Result<[u8; 32], Error> {
+    let key_file_error = |reason: String| Error::KeyFile {
+        path: path.display().to_string(),
+        reason,
+    };
+    let contents = fs::read_to_string(path).map_err(|e| key_file_error(e.to_string()))?;
+    let bytes = hex::decode(contents.trim()).map_err(|e| key_file_error(e.to_string()))?;
+    bytes
+        .try_into()
+        .map_err(|_| key_file_error("expected 32 bytes in hex".into()))
+}
+
+/// Create a new signing key, returns the public key. Existing key files are only overwritten with `force`, the secret
+/// key file is readable by the owner only.
+pub fn generate_key(
+    secret_path: &Path,
+    public_path: &Path,
+    force: bool,
+) -> Result<[u8; 32], Error> {
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).map_err(|e| Error::KeyFile {
+        path: secret_path.display().to_string(),
+        reason: e.to_string(),
+    })?;
+    let public_key = SigningKey::from_bytes(&seed).verifying_key().to_bytes();
+    write_key(secret_path, &seed, force, 0o600)?;
+    write_key(public_path, &public_key, force, 0o644)?;
+    Ok(public_key)
+}
+
+fn write_key(path: &Path, key: &[u8; 32], force: bool, mode: u32) -> Result<(), Error> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, mode);
+    let mut file = options.open(path).map_err(|e| Error::KeyFile {
+        path: path.display().to_string(),
+        reason: match e.kind() {
+            io::ErrorKind::AlreadyExists => "already exists, use --force to overwrite it".into(),
+            _ => e.to_string(),
+        },
+    })?;
+    // the mode of open only applies to new files, not to one overwritten with force
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(mode))?;
+    #[cfg(not(unix))]
+    let _ = mode;
+    file.write_all((hex::encode(key) + "\n").as_bytes())?;
+    Ok(())
+}
+
+/// Fill in the signature slot of the firmware, covering the whole FLASH image
+pub fn sign_elf(elf: &mut FirmwareElf, secret_key: &[u8; 32]) -> Result<SignatureRecord, Error> {
+    let mut image = elf.flash_image().ok_or(Error::BuildInfoNotFound)?;
+    let record = signature::sign_image(&mut image.bytes, secret_key)?;
+    let slot = signature::find_slot(&image.bytes).ok_or(SignatureError::NoSlot)?;
+    elf.patch(
+        image.start + slot as u64,
+        &image.bytes[slot..slot + SIGNATURE_RECORD_LEN],
+    )?;
+    Ok(record)
+}
+
+/// Check firmware signature with the same code the bootloader uses
+pub fn verify_elf(elf: &FirmwareElf, public_key: &[u8; 32]) -> Result<SignatureRecord, Error> {
+    let image = elf.flash_image().ok_or(Error::BuildInfoNotFound)?;
+    let SignedImage { record, .. } = signature::verify_image(&image.bytes, public_key)?;
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn keygen_does_not_overwrite() {
+        let dir = env::temp_dir().join(format!("bedrock_keygen_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (secret, public) = (dir.join("secret.key"), dir.join("public.key"));
+        let public_key = generate_key(&secret, &public, false).unwrap();
+        assert_eq!(read_key(&public).unwrap(), public_key);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&secret).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let Err(Error::KeyFile { reason, .. }) = generate_key(&secret, &public, false) else {
+            panic!("existing key overwritten");
+        };
+        assert!(reason.contains("--force"));
+        assert_eq!(read_key(&public).unwrap(), public_key);
+        assert_ne!(generate_key(&secret, &public, true).unwrap(), public_key);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
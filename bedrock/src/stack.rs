@@ -0,0 +1,150 @@
+//! `bedrock stack`: stack usage of a halted or dumped core, the current depth from SP and the peak from the paint that
+//! `cortex-m-rt` leaves with its `paint-stack` feature, `0xCCCCCCCC` from `_stack_end` to `_stack_start` at reset.
+
+use crate::Error;
+use crate::elf::FirmwareElf;
+use crate::mem::Memory;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+/// Value `cortex-m-rt` paints the stack with
+pub const PAINT: u32 = 0xCCCC_CCCC;
+
+pub struct StackUsage {
+    /// `_stack_end.._stack_start`, the stack grows down from the end
+    pub stack: Range<u32>,
+    pub sp: u32,
+    /// Lowest address the stack reached since reset, None if the bottom of the stack is not painted
+    pub peak: Option<u32>,
+}
+
+impl StackUsage {
+    /// Main stack usage of the firmware, with SP of the halted or dumped core
+    pub fn read(mem: &mut impl Memory, elf: &FirmwareElf, sp: u32) -> Result<Self, Error> {
+        let symbol = |name: &str| {
+            elf.symbol_address(name)
+                .map(|address| address as u32)
+                .ok_or_else(|| {
+                    Error::Symbol(format!(
+                        "no {name}, firmware is not linked with cortex-m-rt"
+                    ))
+                })
+        };
+        Self::scan(mem, symbol("_stack_end")?..symbol("_stack_start")?, sp)
+    }
+
+    /// Find the peak from the paint left at the bottom of `stack`
+    pub fn scan(mem: &mut impl Memory, stack: Range<u32>, sp: u32) -> Result<Self, Error> {
+        let mut bytes = vec![0u8; stack.len()];
+        mem.read(stack.start as u64, &mut bytes)?;
+        let painted = bytes
+            .chunks_exact(4)
+            .take_while(|word| u32::from_le_bytes((*word).try_into().unwrap()) == PAINT)
+            .count() as u32;
+        Ok(StackUsage {
+            peak: (painted > 0).then_some(stack.start + painted * 4),
+            stack,
+            sp,
+        })
+    }
+
+    pub fn size(&self) -> u32 {
+        self.stack.end - self.stack.start
+    }
+
+    /// Bytes in use now, None if SP is not on the main stack, e.g. in a thread on the process stack
+    pub fn used(&self) -> Option<u32> {
+        (self.stack.start..=self.stack.end)
+            .contains(&self.sp)
+            .then(|| self.stack.end - self.sp)
+    }
+
+    /// Most bytes used since reset
+    pub fn peak_used(&self) -> Option<u32> {
+        self.peak.map(|peak| self.stack.end - peak)
+    }
+}
+
+impl Display for StackUsage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let size = self.size();
+        let percent = |used: u32| used as u64 * 100 / size.max(1) as u64;
+        writeln!(
+            f,
+            "Stack 0x{:08x}..0x{:08x}, {size}B",
+            self.stack.start, self.stack.end
+        )?;
+        match self.used() {
+            Some(used) => writeln!(
+                f,
+                "Used now:  {used}B ({}%), SP 0x{:08x}",
+                percent(used),
+                self.sp
+            )?,
+            None => writeln!(
+                f,
+                "Used now:  unknown, SP 0x{:08x} is not on the main stack",
+                self.sp
+            )?,
+        }
+        match self.peak_used() {
+            Some(peak) => writeln!(f, "Peak:      {peak}B ({}%) since reset", percent(peak)),
+            None => writeln!(
+                f,
+                "Peak:      unknown, the bottom of the stack is not painted: the firmware is not built with the \
+                 cortex-m-rt `paint-stack` feature, or the stack has overflowed"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+    use crate::mem::MemoryDump;
+
+    fn usage(painted: usize, stack: Range<u32>, sp: u32) -> StackUsage {
+        let len = stack.len();
+        let bytes = PAINT
+            .to_le_bytes()
+            .into_iter()
+            .cycle()
+            .take(painted)
+            .chain(std::iter::repeat(0x55))
+            .take(len)
+            .collect();
+        let mut mem = MemoryDump::new().with_region(stack.start as u64, bytes);
+        StackUsage::scan(&mut mem, stack, sp).unwrap()
+    }
+
+    #[test]
+    fn peak_from_paint() {
+        let usage = usage(0x300, 0x2000_0000..0x2000_0400, 0x2000_03E0);
+        assert_eq!(usage.used(), Some(0x20));
+        assert_eq!(usage.peak_used(), Some(0x100));
+        assert!(
+            usage
+                .to_string()
+                .contains("Peak:      256B (25%) since reset")
+        );
+    }
+
+    #[test]
+    fn not_painted() {
+        let usage = usage(0, 0x2000_0000..0x2000_0400, 0x1000_0000);
+        assert_eq!(usage.used(), None);
+        assert_eq!(usage.peak_used(), None);
+        assert!(usage.to_string().contains("not painted"));
+    }
+
+    #[test]
+    fn fixture_stack() {
+        let (mut mem, dump, elf) = fixture::core_memory();
+        let usage = StackUsage::read(&mut mem, &elf, dump.registers.msp).unwrap();
+        assert_eq!(usage.stack, 0x2000_0188..0x2000_4000);
+        assert_eq!(usage.used(), Some(0x2000_4000 - fixture::MSP));
+        assert_eq!(usage.peak, Some(fixture::PEAK));
+        assert_eq!(usage.peak_used(), Some(1024));
+    }
+}
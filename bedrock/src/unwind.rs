@@ -0,0 +1,422 @@
+//! Stack unwinding of a halted or dumped core with DWARF call frame information from `.debug_frame`, through exception
+//! entries, so a backtrace from inside a fault handler continues into the code that faulted.
+
+use crate::Error;
+use crate::coredump::Registers;
+use crate::dwarf::{DebugInfo, SourceLocation};
+use crate::elf::FirmwareElf;
+use crate::mem::Memory;
+use gimli::{
+    BaseAddresses, CfaRule, DebugFrame, EndianSlice, LittleEndian, Register, RegisterRule,
+    UnwindContext, UnwindSection,
+};
+use object::{Object, ObjectSection};
+use std::fmt::{Display, Formatter};
+
+/// FPU context control register
+const FPCCR: u64 = 0xE000_EF34;
+const FPCCR_LSPACT: u32 = 1 << 0;
+/// FPU context address register, where lazily preserved FP registers go
+const FPCAR: u64 = 0xE000_EF38;
+const MAX_FRAMES: usize = 64;
+const SP: usize = 13;
+const LR: usize = 14;
+const PC: usize = 15;
+
+/// Registers stacked by the core on exception entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExceptionFrame {
+    /// Where the frame is on the stack
+    pub address: u32,
+    pub exc_return: u32,
+    /// r0-r3, r12, lr, pc, xpsr
+    pub stacked: [u32; 8],
+    pub fp: FpContext,
+    /// Stack pointer of the interrupted code, after the frame is popped
+    pub caller_sp: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FpContext {
+    None,
+    /// s0-s15 and FPSCR stacked after the basic frame
+    Stacked,
+    /// Space reserved by lazy stacking, FP registers are still in the FPU and not in the frame
+    Reserved,
+}
+
+impl ExceptionFrame {
+    pub fn pc(&self) -> u32 {
+        self.stacked[6]
+    }
+
+    pub fn lr(&self) -> u32 {
+        self.stacked[5]
+    }
+
+    pub fn xpsr(&self) -> u32 {
+        self.stacked[7]
+    }
+
+    /// Frame was stacked on PSP, by thread mode code using the process stack, e.g. an RTOS task
+    pub fn process_stack(&self) -> bool {
+        self.exc_return & (1 << 2) != 0
+    }
+}
+
+impl Display for ExceptionFrame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let stack = if self.process_stack() { "PSP" } else { "MSP" };
+        writeln!(f, "Exception frame on {stack} at 0x{:08x}:", self.address)?;
+        let names = ["r0", "r1", "r2", "r3", "r12", "lr", "pc", "xpsr"];
+        for (row, values) in names.chunks(4).zip(self.stacked.chunks(4)) {
+            let line: Vec<_> = row
+                .iter()
+                .zip(values)
+                .map(|(name, value)| format!("{name:>4} 0x{value:08x}"))
+                .collect();
+            writeln!(f, " {}", line.join("  "))?;
+        }
+        match self.fp {
+            FpContext::None => Ok(()),
+            FpContext::Stacked => writeln!(f, " s0-s15 and FPSCR stacked after it"),
+            FpContext::Reserved => writeln!(
+                f,
+                " space for s0-s15 and FPSCR reserved by lazy stacking, not written yet"
+            ),
+        }
+    }
+}
+
+/// EXC_RETURN value in LR or PC, instead of a code address
+pub fn is_exc_return(value: u32) -> bool {
+    value >> 24 == 0xFF
+}
+
+/// Read the frame the core stacked when entering an exception, `sp` is MSP at the time the handler was entered
+pub fn exception_frame(
+    mem: &mut impl Memory,
+    exc_return: u32,
+    sp: u32,
+    psp: u32,
+    armv8m: bool,
+) -> Result<ExceptionFrame, Error> {
+    let mut address = if exc_return & (1 << 2) != 0 { psp } else { sp };
+    // Armv8-M with the Security Extension: integrity signature and r4-r11 stacked before the basic frame
+    if armv8m && exc_return & (1 << 5) == 0 {
+        address += 40;
+    }
+    let mut stacked = [0u32; 8];
+    for (idx, word) in stacked.iter_mut().enumerate() {
+        *word = mem.read_u32(address as u64 + idx as u64 * 4)?;
+    }
+    let fp = if exc_return & (1 << 4) == 0 {
+        let lazy = mem
+            .read_u32(FPCCR)
+            .is_ok_and(|fpccr| fpccr & FPCCR_LSPACT != 0)
+            && mem.read_u32(FPCAR).is_ok_and(|fpcar| fpcar == address + 32);
+        if lazy {
+            FpContext::Reserved
+        } else {
+            FpContext::Stacked
+        }
+    } else {
+        FpContext::None
+    };
+    Ok(ExceptionFrame {
+        address,
+        exc_return,
+        stacked,
+        fp,
+        caller_sp: address + stacked_size(exc_return, stacked[7]),
+    })
+}
+
+/// Bytes stacked on exception entry, from the frame up to the stack pointer of the interrupted code
+pub(crate) fn stacked_size(exc_return: u32, xpsr: u32) -> u32 {
+    let mut size = 32;
+    if exc_return & (1 << 4) == 0 {
+        size += 72;
+    }
+    // xPSR bit 9: the stack was realigned to 8 bytes on entry
+    if xpsr & (1 << 9) != 0 {
+        size += 4;
+    }
+    size
+}
+
+/// Code location in a backtrace, the innermost first
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub pc: u32,
+    /// Code at `pc` was interrupted by an exception with this frame, `pc` is where it resumes
+    pub interrupted: Option<ExceptionFrame>,
+    pub location: Option<SourceLocation>,
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "0x{:08x}", self.pc)?;
+        if let Some(location) = &self.location {
+            write!(f, " {location}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Call frame information of a firmware
+pub struct Unwinder<'elf> {
+    debug_frame: DebugFrame<EndianSlice<'elf, LittleEndian>>,
+    bases: BaseAddresses,
+}
+
+impl<'elf> Unwinder<'elf> {
+    pub fn new(elf: &'elf FirmwareElf) -> Result<Self, Error> {
+        let file = elf.file();
+        let section = file.section_by_name(".debug_frame").ok_or_else(|| {
+            Error::Dwarf("no .debug_frame, build with debug info to unwind the stack".into())
+        })?;
+        let data = match section.data()? {
+            [] => return Err(Error::Dwarf(".debug_frame is empty".into())),
+            data => data,
+        };
+        let mut debug_frame = DebugFrame::new(data, LittleEndian);
+        debug_frame.set_address_size(4);
+        Ok(Unwinder {
+            debug_frame,
+            bases: BaseAddresses::default(),
+        })
+    }
+
+    /// Registers of the caller, None if there is no unwind info for `address`, the code `r` were taken at
+    fn caller(
+        &self,
+        mem: &mut impl Memory,
+        ctx: &mut UnwindContext<usize>,
+        r: &[u32; 16],
+        address: u32,
+    ) -> Result<Option<[u32; 16]>, Error> {
+        let row = match self.debug_frame.unwind_info_for_address(
+            &self.bases,
+            ctx,
+            address as u64,
+            DebugFrame::cie_from_offset,
+        ) {
+            Ok(row) => row,
+            Err(gimli::Error::NoUnwindInfoForAddress) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let cfa = match row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => {
+                (r[register.0 as usize & 15] as i64 + offset) as u32
+            }
+            CfaRule::Expression(_) => {
+                return Err(Error::Dwarf("CFA expressions are not supported".into()));
+            }
+        };
+        let mut caller = *r;
+        for n in 0..=LR {
+            caller[n] = match row.register(Register(n as u16)) {
+                Some(RegisterRule::Offset(offset)) => mem.read_u32((cfa as i64 + offset) as u64)?,
+                Some(RegisterRule::ValOffset(offset)) => (cfa as i64 + offset) as u32,
+                Some(RegisterRule::Register(other)) => r[other.0 as usize & 15],
+                _ => r[n],
+            };
+        }
+        caller[SP] = cfa;
+        caller[PC] = caller[LR];
+        Ok(Some(caller))
+    }
+}
+
+/// Backtrace of a halted or dumped core, as far as it could be unwound
+pub struct Backtrace {
+    pub frames: Vec<Frame>,
+    /// Why the backtrace is missing or ends early
+    pub error: Option<Error>,
+}
+
+impl Backtrace {
+    /// Unwind with the call frame information of the firmware, only the current PC without it
+    pub fn capture(
+        mem: &mut impl Memory,
+        elf: &FirmwareElf,
+        debug: Option<&DebugInfo>,
+        registers: &Registers,
+        armv8m: bool,
+    ) -> Self {
+        let (frames, error) = match Unwinder::new(elf) {
+            Ok(unwinder) => backtrace(mem, &unwinder, debug, registers, armv8m),
+            Err(e) => (vec![frame(debug, registers.pc(), None, false)], Some(e)),
+        };
+        Backtrace { frames, error }
+    }
+}
+
+impl Display for Backtrace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (n, frame) in self.frames.iter().enumerate() {
+            if frame.interrupted.is_some() {
+                writeln!(f, "     <exception entry>")?;
+            }
+            writeln!(f, "{n:>3}: {frame}")?;
+        }
+        if let Some(e) = &self.error {
+            writeln!(f, "Backtrace incomplete: {e}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Walk the stack from the registers of a halted core, through exception entries, up to the reset handler or as far as
+/// the unwind info goes. A corrupt stack ends the walk early, with the frames found up to there and the error.
+pub fn backtrace(
+    mem: &mut impl Memory,
+    unwinder: &Unwinder,
+    debug: Option<&DebugInfo>,
+    registers: &Registers,
+    armv8m: bool,
+) -> (Vec<Frame>, Option<Error>) {
+    let mut frames = vec![frame(debug, registers.pc(), None, false)];
+    let error = walk(mem, unwinder, debug, registers, armv8m, &mut frames).err();
+    (frames, error)
+}
+
+fn walk(
+    mem: &mut impl Memory,
+    unwinder: &Unwinder,
+    debug: Option<&DebugInfo>,
+    registers: &Registers,
+    armv8m: bool,
+    frames: &mut Vec<Frame>,
+) -> Result<(), Error> {
+    let mut ctx = UnwindContext::new();
+    let mut r = registers.r;
+    // a return address may be right after a call to a function that does not return, outside of the caller
+    let mut lookup = r[PC];
+    while frames.len() < MAX_FRAMES {
+        let mut caller = match unwinder.caller(mem, &mut ctx, &r, lookup)? {
+            Some(caller) => caller,
+            // no unwind info, e.g. an assembly trampoline: only a leaf function that did not touch SP or LR is known
+            None if frames.len() == 1 || is_exc_return(r[LR]) => {
+                let mut caller = r;
+                caller[PC] = r[LR];
+                caller
+            }
+            None => break,
+        };
+        // end of stack: cortex-m-rt enters main with LR 0xFFFFFFFF, which is no valid EXC_RETURN
+        if caller[PC] == u32::MAX {
+            break;
+        }
+        if is_exc_return(caller[PC]) {
+            let exception = exception_frame(mem, caller[PC], caller[SP], registers.psp, armv8m)?;
+            r = caller;
+            for (idx, n) in [0, 1, 2, 3, 12, LR, PC].into_iter().enumerate() {
+                r[n] = exception.stacked[idx];
+            }
+            r[SP] = exception.caller_sp;
+            lookup = r[PC];
+            frames.push(frame(debug, r[PC], Some(exception), false));
+            continue;
+        }
+        caller[PC] &= !1;
+        // unwinding went wrong
+        if caller[PC] == 0 || (caller[PC] == r[PC] && caller[SP] == r[SP]) {
+            break;
+        }
+        r = caller;
+        lookup = r[PC] - 1;
+        frames.push(frame(debug, r[PC], None, true));
+    }
+    Ok(())
+}
+
+/// Return addresses point after the call, symbolize the call instruction
+fn frame(
+    debug: Option<&DebugInfo>,
+    pc: u32,
+    interrupted: Option<ExceptionFrame>,
+    return_address: bool,
+) -> Frame {
+    let lookup = if return_address { pc - 1 } else { pc };
+    Frame {
+        pc,
+        interrupted,
+        location: debug.and_then(|debug| debug.locate(lookup as u64).ok()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+    use crate::mem::MemoryDump;
+
+    fn stack(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn exception_frames() {
+        let basic = [1, 2, 3, 4, 12, 0x0800_0101, 0x0800_0200, 0x0100_0000];
+        let mut mem = MemoryDump::new().with_region(0x2000_0100, stack(&basic));
+        let frame = exception_frame(&mut mem, 0xFFFF_FFF9, 0x2000_0100, 0, false).unwrap();
+        assert_eq!(frame.pc(), 0x0800_0200);
+        assert!(!frame.process_stack());
+        assert_eq!(frame.fp, FpContext::None);
+        assert_eq!(frame.caller_sp, 0x2000_0120);
+
+        // thread mode on PSP with FP context, lazily reserved, and realigned stack
+        let mut realigned = basic;
+        realigned[7] |= 1 << 9;
+        let mut mem = MemoryDump::new()
+            .with_region(0x2000_0400, stack(&realigned))
+            .with_region(FPCCR, stack(&[FPCCR_LSPACT | 0xC000_0000, 0x2000_0420]));
+        let frame =
+            exception_frame(&mut mem, 0xFFFF_FFED, 0x2000_0100, 0x2000_0400, false).unwrap();
+        assert!(frame.process_stack());
+        assert_eq!(frame.fp, FpContext::Reserved);
+        assert_eq!(frame.caller_sp, 0x2000_0400 + 32 + 72 + 4);
+
+        assert!(is_exc_return(0xFFFF_FFF1));
+        assert!(!is_exc_return(0x0800_0101));
+    }
+
+    #[test]
+    fn fixture_backtrace() {
+        let (mut mem, dump, elf) = fixture::core_memory();
+        let debug = DebugInfo::load(&elf).unwrap();
+        let backtrace = Backtrace::capture(&mut mem, &elf, Some(&debug), &dump.registers, false);
+        assert!(backtrace.error.is_none());
+        let frames: Vec<_> = backtrace
+            .frames
+            .iter()
+            .map(|frame| {
+                let function = frame.location.as_ref().and_then(|l| l.function.clone());
+                (frame.pc, frame.interrupted.is_some(), function)
+            })
+            .collect();
+        let function = |name: &str| Some(name.to_string());
+        assert_eq!(
+            frames,
+            [
+                (fixture::BUS_FAULT, false, None),
+                (fixture::FAULT_PC, true, function("fixture::read_sensor")),
+                (
+                    fixture::RETURN_TO_RT_MAIN,
+                    false,
+                    function("fixture::__cortex_m_rt_main")
+                ),
+                (
+                    fixture::RETURN_TO_MAIN,
+                    false,
+                    function("fixture::__cortex_m_rt_main_trampoline")
+                ),
+                (fixture::RETURN_TO_RESET, false, None),
+            ]
+        );
+        let text = backtrace.to_string();
+        assert!(text.contains("     <exception entry>\n  1: 0x08000474 fixture::read_sensor at "));
+    }
+}
@@ -0,0 +1,144 @@
+//! "Is the device running my build?" check.
+
+use crate::Error;
+use crate::compact_info::{FoundCompactInfo, find_compact_info};
+use crate::elf::{FirmwareElf, FlashImage};
+use crate::mem::Memory;
+use bedrock_build_info::BedrockBuildInfoOwned;
+use bedrock_build_info::diff::BuildInfoDiff;
+use bedrock_build_info::envelope::CompactEnvelope;
+use sha2::{Digest, Sha256};
+use std::ops::Range;
+
+const SHA_CHUNK_SIZE_B: usize = 4096;
+
+pub struct RunningCheck {
+    /// Compact info read from the device
+    pub device: FoundCompactInfo,
+    /// Compact info from the ELF
+    pub elf: BedrockBuildInfoOwned,
+    /// Differences, ignoring build timestamp
+    pub diff: BuildInfoDiff,
+    /// Whether device record is byte to byte equal to the one in the ELF, including timestamp
+    pub identical: bool,
+}
+
+impl RunningCheck {
+    pub fn is_match(&self) -> bool {
+        self.diff.is_empty()
+    }
+
+    /// Whether either firmware was built from a working tree with uncommitted changes, which build info does not record
+    pub fn dirty(&self) -> bool {
+        [&self.device.info, &self.elf]
+            .iter()
+            .any(|info| info.version_control.as_ref().is_some_and(|vc| vc.dirty))
+    }
+
+    /// Content IDs of the device and the ELF firmware, computed from the compact info if a record has none
+    pub fn content_ids(&self) -> Option<(u64, u64)> {
+        let id = |info: &BedrockBuildInfoOwned| {
+            info.content_id().or_else(|| info.compute_content_id().ok())
+        };
+        Some((id(&self.device.info)?, id(&self.elf)?))
+    }
+}
+
+/// Read compact build info from the device and compare it with the one in the ELF.
+///
+/// Record is first looked up at the same address as in the ELF, then the whole ELF load range is searched,
+/// in case a different firmware with a different layout is running.
+pub fn verify_running(mem: &mut impl Memory, elf: &FirmwareElf) -> Result<RunningCheck, Error> {
+    let (elf_address, elf_record) = elf.compact_record().ok_or(Error::BuildInfoNotFound)?;
+    let (start, end) = elf.load_range().ok_or(Error::BuildInfoNotFound)?;
+    verify_running_record(mem, elf_address, &elf_record, start..end)
+}
+
+/// Same as [verify_running], for a record already taken out of an ELF
+pub fn verify_running_record(
+    mem: &mut impl Memory,
+    elf_address: u64,
+    elf_record: &[u8],
+    search: Range<u64>,
+) -> Result<RunningCheck, Error> {
+    let elf_info = CompactEnvelope::parse(elf_record)?.decode()?.make_owned();
+
+    let device = match find_compact_info(mem, elf_address, elf_record.len()) {
+        Ok(found) if found.address == elf_address => found,
+        _ => find_compact_info(mem, search.start, (search.end - search.start) as usize)?,
+    };
+    Ok(RunningCheck {
+        diff: elf_info.diff(&device.info),
+        identical: device.record == elf_record,
+        elf: elf_info,
+        device,
+    })
+}
+
+/// Firmware SHA of the device, computed over the same range as [FirmwareElf::fw_sha] of the given image
+pub fn device_sha(mem: &mut impl Memory, image: &FlashImage) -> Result<[u8; 32], Error> {
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; SHA_CHUNK_SIZE_B];
+    let mut address = image.start;
+    let end = image.start + image.bytes.len() as u64;
+    while address < end {
+        let buf = &mut buf[..SHA_CHUNK_SIZE_B.min((end - address) as usize)];
+        mem.read(address, buf)?;
+        hasher.update(&*buf);
+        address += buf.len() as u64;
+    }
+    Ok(hasher.finalize().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+    use crate::mem::MemoryDump;
+    use bedrock_build_info::VersionControlOwned;
+    use ww_date_time::DateTime;
+
+    const FLASH: u64 = 0x0800_0000;
+
+    /// Device running `device`, checked against an ELF with `elf`
+    fn check(device: &BedrockBuildInfoOwned, elf: &BedrockBuildInfoOwned) -> RunningCheck {
+        let mut mem = MemoryDump::new().with_region(FLASH, fixture::compact_record(device));
+        let elf_record = fixture::compact_record(elf);
+        verify_running_record(&mut mem, FLASH, &elf_record, FLASH..FLASH + 0x1000).unwrap()
+    }
+
+    #[test]
+    fn dirty_builds_with_different_content() {
+        let mut device = fixture::build_info("app");
+        device.version_control = Some(VersionControlOwned {
+            dirty: true,
+            commit_id: None,
+            commit_short_id: Some("0123abc".into()),
+            commit_timestamp: device.timestamp,
+            branch: None,
+            tags: vec![],
+        });
+        device.ext_mut().content_id = Some(0x1111);
+        let mut elf = device.clone();
+        elf.timestamp = DateTime::from_ymd_hms_utc_opt(2025, 10, 10, 9, 0, 0, 0).unwrap();
+        elf.ext_mut().content_id = Some(0x2222);
+
+        let check = check(&device, &elf);
+        assert!(check.is_match());
+        assert!(!check.identical);
+        assert!(check.dirty());
+        assert_eq!(check.content_ids(), Some((0x1111, 0x2222)));
+    }
+
+    #[test]
+    fn content_id_computed_without_one_recorded() {
+        let device = fixture::build_info("app");
+        let mut elf = device.clone();
+        elf.timestamp = DateTime::from_ymd_hms_utc_opt(2025, 10, 10, 9, 0, 0, 0).unwrap();
+
+        let check = check(&device, &elf);
+        assert!(!check.dirty());
+        let (device_id, elf_id) = check.content_ids().unwrap();
+        assert_eq!(device_id, elf_id);
+    }
+}
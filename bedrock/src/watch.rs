@@ -0,0 +1,337 @@
+//! `bedrock watch`: changes of a static, counter or memory range over time, by polling or with a DWT data watchpoint
+//! that halts the core on every write, to see which code wrote.
+
+use crate::Error;
+use crate::chip::CoreType;
+use crate::counters::read_words;
+use crate::dwarf::{DebugInfo, Type};
+use crate::elf::FirmwareElf;
+use crate::inspect::{Item, format_value};
+use crate::mem::Memory;
+use probe_rs::Core;
+use std::io::Write;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::sleep;
+use std::time::Duration;
+
+/// Debug exception and monitor control register
+const DEMCR: u64 = 0xE000_EDFC;
+const DEMCR_TRCENA: u32 = 1 << 24;
+/// Debug fault status register
+const DFSR: u64 = 0xE000_ED30;
+const DFSR_DWTTRAP: u32 = 1 << 2;
+const DWT_CTRL: u64 = 0xE000_1000;
+const HALT_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Memory being watched and how to show it
+pub struct Watched {
+    pub label: String,
+    /// Bytes covered by a watchpoint, the low word of a u64 counter
+    pub range: Range<u64>,
+    value: Value,
+}
+
+enum Value {
+    Typed(Type),
+    Raw,
+    Counter { hi: Option<u64> },
+}
+
+impl Watched {
+    pub fn new(item: &Item, elf: &FirmwareElf, len: u64) -> Result<Self, Error> {
+        Ok(match item {
+            Item::Counter(counter) => {
+                let (lo, hi) = counter.word_addresses(elf)?;
+                Watched {
+                    label: counter.name.clone(),
+                    range: lo..lo + 4,
+                    value: Value::Counter { hi },
+                }
+            }
+            Item::Static(place) => Watched {
+                label: place.path.clone(),
+                range: place.address..place.address + place.ty.size(),
+                value: Value::Typed(place.ty.clone()),
+            },
+            Item::Symbol {
+                name,
+                address,
+                size,
+            } => Watched::raw(name.clone(), *address, if *size == 0 { len } else { *size }),
+        })
+    }
+
+    pub fn raw(label: String, address: u64, len: u64) -> Self {
+        Watched {
+            label,
+            range: address..address + len,
+            value: Value::Raw,
+        }
+    }
+
+    /// Current value, formatted: changes are detected on this, so padding bytes do not count
+    pub fn read(&self, mem: &mut impl Memory) -> Result<String, Error> {
+        if let Value::Counter { hi } = self.value {
+            return Ok(read_words(mem, self.range.start, hi)?.to_string());
+        }
+        let mut bytes = vec![0u8; (self.range.end - self.range.start) as usize];
+        mem.read(self.range.start, &mut bytes)?;
+        Ok(match &self.value {
+            Value::Typed(ty) => format_value(ty, &bytes),
+            _ => bytes
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<Vec<_>>()
+                .join(" "),
+        })
+    }
+
+    fn print_change(
+        &self,
+        out: &mut impl Write,
+        old: &str,
+        new: &str,
+        writer: Option<&str>,
+    ) -> Result<(), Error> {
+        let time = chrono::Local::now().format("%H:%M:%S%.3f");
+        if old.contains('\n') || new.contains('\n') {
+            writeln!(out, "{time} {}:\n  was {old}\n  now {new}", self.label)?;
+        } else {
+            writeln!(out, "{time} {}: {old} -> {new}", self.label)?;
+        }
+        if let Some(writer) = writer {
+            writeln!(out, "  written by {writer}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Print every change seen when reading at `interval`, until `stop` is set. Changes in between are missed.
+pub fn poll(
+    mem: &mut impl Memory,
+    watched: &Watched,
+    interval: Duration,
+    out: &mut impl Write,
+    stop: &AtomicBool,
+) -> Result<(), Error> {
+    let mut old = watched.read(mem)?;
+    writeln!(out, "{} = {old}", watched.label)?;
+    while !stop.load(Ordering::Relaxed) {
+        sleep(interval);
+        let new = watched.read(mem)?;
+        if new != old {
+            watched.print_change(out, &old, &new, None)?;
+            old = new;
+        }
+    }
+    Ok(())
+}
+
+/// Halt on every write with a DWT watchpoint and print the value with the code that wrote it, until `stop` is set.
+/// Writes of the same value are shown too. The core is resumed right away, but every write costs a round trip to
+/// the probe, firmware writing often slows down noticeably.
+pub fn halt_on_write(
+    core: &mut Core,
+    core_type: CoreType,
+    watched: &Watched,
+    debug: Option<&DebugInfo>,
+    out: &mut impl Write,
+    stop: &AtomicBool,
+) -> Result<(), Error> {
+    let watchpoint = Watchpoint::arm(core, core_type, watched.range.clone())?;
+    if watchpoint.range != watched.range {
+        writeln!(
+            out,
+            "Watchpoint covers 0x{:08x}..0x{:08x}, writes next to {} halt too",
+            watchpoint.range.start, watchpoint.range.end, watched.label
+        )?;
+    }
+    let result = follow_writes(core, watched, debug, out, stop);
+    let disarmed = watchpoint.disarm(core);
+    result.and(disarmed)
+}
+
+fn follow_writes(
+    core: &mut Core,
+    watched: &Watched,
+    debug: Option<&DebugInfo>,
+    out: &mut impl Write,
+    stop: &AtomicBool,
+) -> Result<(), Error> {
+    let mut old = watched.read(core)?;
+    writeln!(out, "{} = {old}", watched.label)?;
+    if core.core_halted()? {
+        core.run()?;
+    }
+    while !stop.load(Ordering::Relaxed) {
+        if !core.core_halted()? {
+            sleep(HALT_POLL_INTERVAL);
+            continue;
+        }
+        if core.read_u32(DFSR)? & DFSR_DWTTRAP == 0 {
+            writeln!(
+                out,
+                "Core halted by something else than the watchpoint, stopping"
+            )?;
+            return Ok(());
+        }
+        Memory::write(core, DFSR, &DFSR_DWTTRAP.to_le_bytes())?;
+        let pc = core.program_counter().id();
+        let pc: u32 = core.read_core_reg(pc)?;
+        let new = watched.read(core)?;
+        core.run()?;
+        // the watchpoint halts a few instructions after the write, not at the writing instruction
+        let location = debug.and_then(|debug| debug.locate(pc as u64).ok());
+        let writer = match location {
+            Some(location) => format!("code shortly before 0x{pc:08x}, {location}"),
+            None => format!("code shortly before 0x{pc:08x}"),
+        };
+        watched.print_change(out, &old, &new, Some(&writer))?;
+        old = new;
+    }
+    Ok(())
+}
+
+/// DWT comparators programmed to halt on writes to a range
+struct Watchpoint {
+    /// Range actually covered, may be larger than asked on Armv6-M and Armv7-M
+    range: Range<u64>,
+    comparators: usize,
+}
+
+impl Watchpoint {
+    fn arm(mem: &mut impl Memory, core_type: CoreType, range: Range<u64>) -> Result<Self, Error> {
+        if range.is_empty() {
+            return Err(Error::Watchpoint(
+                "nothing to watch, the type has no size".into(),
+            ));
+        }
+        let demcr = mem.read_u32(DEMCR)?;
+        mem.write(DEMCR, &(demcr | DEMCR_TRCENA).to_le_bytes())?;
+        let available = (mem.read_u32(DWT_CTRL)? >> 28) as usize;
+        let (covered, registers) = if core_type.is_armv8m() {
+            (range.clone(), armv8m_comparators(&range))
+        } else {
+            armv7m_comparators(&range)
+        };
+        let comparators = registers.len();
+        if comparators > available {
+            return Err(Error::Watchpoint(format!(
+                "watching {} bytes takes {comparators} DWT comparators, {} has {available}",
+                range.end - range.start,
+                core_type.name
+            )));
+        }
+        for (n, comparator) in registers.iter().enumerate() {
+            let base = comparator_base(n);
+            mem.write(base, &comparator.comp.to_le_bytes())?;
+            if let Some(mask) = comparator.mask {
+                mem.write(base + 4, &mask.to_le_bytes())?;
+            }
+            mem.write(base + 8, &comparator.function.to_le_bytes())?;
+        }
+        Ok(Watchpoint {
+            range: covered,
+            comparators,
+        })
+    }
+
+    fn disarm(self, mem: &mut impl Memory) -> Result<(), Error> {
+        for n in 0..self.comparators {
+            mem.write(comparator_base(n) + 8, &0u32.to_le_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Comparator {
+    comp: u32,
+    /// Armv6-M and Armv7-M only
+    mask: Option<u32>,
+    function: u32,
+}
+
+fn comparator_base(n: usize) -> u64 {
+    0xE000_1020 + 16 * n as u64
+}
+
+/// One comparator matching an aligned power of two block around the range, the smallest one that fits it
+fn armv7m_comparators(range: &Range<u64>) -> (Range<u64>, Vec<Comparator>) {
+    const WATCH_WRITES: u32 = 0b0110;
+    let mut mask = 0;
+    while (range.start >> mask) != ((range.end - 1) >> mask) {
+        mask += 1;
+    }
+    let start = (range.start >> mask) << mask;
+    let comparator = Comparator {
+        comp: start as u32,
+        mask: Some(mask),
+        function: WATCH_WRITES,
+    };
+    (start..start + (1 << mask), vec![comparator])
+}
+
+/// One comparator for an aligned byte, halfword or word, else a base and limit pair
+fn armv8m_comparators(range: &Range<u64>) -> Vec<Comparator> {
+    const MATCH_WRITE: u32 = 0b0101;
+    const MATCH_LIMIT: u32 = 0b0111;
+    const ACTION_HALT: u32 = 0b01 << 4;
+    let len = range.end - range.start;
+    let size = match len {
+        1 => Some(0),
+        2 => Some(1),
+        4 => Some(2),
+        _ => None,
+    };
+    match size {
+        Some(size) if range.start.is_multiple_of(len) => vec![Comparator {
+            comp: range.start as u32,
+            mask: None,
+            function: MATCH_WRITE | ACTION_HALT | (size << 10),
+        }],
+        _ => vec![
+            Comparator {
+                comp: range.start as u32,
+                mask: None,
+                function: MATCH_WRITE | ACTION_HALT,
+            },
+            Comparator {
+                comp: (range.end - 1) as u32,
+                mask: None,
+                function: MATCH_LIMIT | ACTION_HALT,
+            },
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comparators() {
+        let (covered, c) = armv7m_comparators(&(0x2000_0004..0x2000_0008));
+        assert_eq!(covered, 0x2000_0004..0x2000_0008);
+        assert_eq!(c[0].mask, Some(2));
+        let (covered, c) = armv7m_comparators(&(0x2000_0006..0x2000_000A));
+        assert_eq!(covered, 0x2000_0000..0x2000_0010);
+        assert_eq!((c[0].comp, c[0].mask), (0x2000_0000, Some(4)));
+        let (covered, _) = armv7m_comparators(&(0x2000_0003..0x2000_0004));
+        assert_eq!(covered, 0x2000_0003..0x2000_0004);
+
+        let c = armv8m_comparators(&(0x2000_0004..0x2000_0008));
+        assert_eq!(
+            c,
+            [Comparator {
+                comp: 0x2000_0004,
+                mask: None,
+                function: 0x815
+            }]
+        );
+        let c = armv8m_comparators(&(0x2000_0002..0x2000_0006));
+        assert_eq!(c.len(), 2);
+        assert_eq!(c[1].comp, 0x2000_0005);
+    }
+}
@@ -191,8 +191,9 @@ SECTIONS
     __euninit = .;
   } > RAM
 
-  /* Place the heap right after `.uninit` in RAM */
+  /* Place the heap and the stack end (cortex-m-rt >= 0.7.5, `paint-stack`) right after `.uninit` in RAM */
   PROVIDE(__sheap = __euninit);
+  PROVIDE(_stack_end = __euninit);
 
   /* ## .got */
   /* Dynamic relocations are unsupported. This section is only used to detect relocatable code in
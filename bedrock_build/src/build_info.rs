@@ -1,29 +1,143 @@
+use bedrock_build_info::envelope::VERSIONED_HEADER_LEN;
 use bedrock_build_info::{
-    BedrockBuildInfoOwned, CompilerChannel, CompilerInfoOwned, CrateInfoOwned, OptimizationLevel,
-    Profile, TargetInfoOwned, VersionControlOwned,
+    BedrockBuildInfoOwned, BuildInfoExtOwned, CompilerChannel, CompilerInfoExtOwned,
+    CompilerInfoOwned, CrateInfoExtOwned, CrateInfoOwned, ExtraValueOwned, KeyValueOwned,
+    OptimizationLevel, Profile, TargetInfoOwned, VersionControlOwned,
 };
 use build_info_common::BuildInfo;
-use shrink_wrap::prelude::*;
 
-pub fn shrink_wrap_build_info(info: BuildInfo) -> (Vec<u8>, Vec<u8>) {
+use crate::Error;
+use crate::config::{BuildInfoConfig, BuildInfoField, FieldPolicy, OnOverflow, TimestampSource};
+use crate::{linker, memory_map, sources};
+use chrono::{DateTime, Utc};
+use std::path::{Path, PathBuf};
+
+const ALL_FIELDS: [BuildInfoField; 17] = [
+    BuildInfoField::Authors,
+    BuildInfoField::DependencyAuthors,
+    BuildInfoField::EnabledFeatures,
+    BuildInfoField::Dependencies,
+    BuildInfoField::CommitId,
+    BuildInfoField::CommitShortId,
+    BuildInfoField::Branch,
+    BuildInfoField::Tags,
+    BuildInfoField::TargetTriple,
+    BuildInfoField::TargetArch,
+    BuildInfoField::HostTriple,
+    BuildInfoField::CompilerCommitDate,
+    BuildInfoField::ContentId,
+    BuildInfoField::FlashRegions,
+    BuildInfoField::OtherRegions,
+    BuildInfoField::Licenses,
+    BuildInfoField::Sources,
+];
+
+/// Where a user-defined build info entry is stored
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Placement {
+    /// Only in the compact info in FLASH
+    Compact,
+    /// Only in the full info in ELF
+    Full,
+    /// In both compact and full info
+    Both,
+}
+
+impl Placement {
+    fn in_compact(&self) -> bool {
+        matches!(self, Placement::Compact | Placement::Both)
+    }
+
+    fn in_full(&self) -> bool {
+        matches!(self, Placement::Full | Placement::Both)
+    }
+}
+
+/// User-defined build info entries, e.g. hardware revision, product ID or protocol version.
+///
+/// Example (in build.rs):
+/// ```ignore
+/// use bedrock_build::Placement;
+///
+/// let extra = bedrock_build::ExtraFields::new()
+///     .add("hw_rev", "C", Placement::Both)
+///     .add("product_id", 0x1234_u32, Placement::Compact)
+///     .add("feature_set", "pro", Placement::Full);
+/// let info = bedrock_build::serialize_build_info_with_extra(info, extra);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ExtraFields {
+    entries: Vec<(KeyValueOwned, Placement)>,
+}
+
+impl ExtraFields {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<ExtraValueOwned>,
+        placement: Placement,
+    ) -> Self {
+        self.entries.push((
+            KeyValueOwned {
+                key: key.into(),
+                value: value.into(),
+            },
+            placement,
+        ));
+        self
+    }
+
+    pub(crate) fn select(&self, f: impl Fn(&Placement) -> bool) -> Vec<KeyValueOwned> {
+        self.entries
+            .iter()
+            .filter(|(_, placement)| f(placement))
+            .map(|(kv, _)| kv.clone())
+            .collect()
+    }
+}
+
+pub fn shrink_wrap_build_info(
+    info: BuildInfo,
+    config: &BuildInfoConfig,
+) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    let out_dir = std::env::var_os("OUT_DIR").map(PathBuf::from);
+    let (profile, profile_name) = profile(&info.profile, out_dir.as_deref());
+    let build_timestamp = timestamp(&info, config.timestamp_source())?;
+    let linker = linker::detect();
     let mut info = BedrockBuildInfoOwned {
-        timestamp: info.timestamp.into(),
-        profile: profile(info.profile),
+        timestamp: build_timestamp.into(),
+        profile,
         optimization_level: optimization_level(info.optimization_level),
-        crate_info: crate_info(info.crate_info),
+        crate_info: crate_info(info.crate_info)?,
         target_info: TargetInfoOwned {
             triple: Some(info.target.triple),
             arch: Some(info.target.cpu.arch),
         },
         compiler_info: CompilerInfoOwned {
-            version: info.compiler.version.try_into().unwrap(),
+            version: info
+                .compiler
+                .version
+                .clone()
+                .try_into()
+                .map_err(|_| Error::Version {
+                    what: "rustc".into(),
+                    value: info.compiler.version.to_string(),
+                })?,
             channel: compiler_info(info.compiler.channel),
             host_triple: Some(info.compiler.host_triple),
             commit_date: info.compiler.commit_date.map(|date| date.into()),
-            #[cfg(feature = "flip-link")]
-            flip_link: true,
-            #[cfg(not(feature = "flip-link"))]
-            flip_link: false,
+            flip_link: linker.is_flip_link(),
+            ext: Some(CompilerInfoExtOwned {
+                linker: linker.linker,
+                link_mode: linker.link_mode,
+                target_cpu: linker.target_cpu,
+                target_features: linker.target_features,
+                build_std: linker.build_std,
+            }),
         },
         version_control: info.version_control.map(|v| {
             let build_info_common::VersionControl::Git(git) = v;
@@ -31,11 +145,17 @@ pub fn shrink_wrap_build_info(info: BuildInfo) -> (Vec<u8>, Vec<u8>) {
                 dirty: git.dirty,
                 commit_id: Some(git.commit_id),
                 commit_short_id: Some(git.commit_short_id),
-                commit_timestamp: git.commit_timestamp.try_into().unwrap(),
+                commit_timestamp: git.commit_timestamp.into(),
                 branch: git.branch,
                 tags: git.tags,
             }
         }),
+        ext: Some(BuildInfoExtOwned {
+            extra: config.extra.select(Placement::in_full),
+            profile_name,
+            content_id: None,
+            memory_map: memory_map::read_memory_x(&config.memory_x_path())?,
+        }),
     };
     // remove nanoseconds for both
     info.timestamp.time.frac = None;
@@ -43,48 +163,163 @@ pub fn shrink_wrap_build_info(info: BuildInfo) -> (Vec<u8>, Vec<u8>) {
     if let Some(vc) = &mut info.version_control {
         vc.commit_timestamp.time.frac = None;
     }
-    for dep in &mut info.crate_info.dependencies {
-        dep.authors = Vec::new();
+    if config.policy(BuildInfoField::Sources) != FieldPolicy::Omit {
+        sources::add_licenses_and_sources(&mut info.crate_info);
+    }
+    for field in ALL_FIELDS {
+        if config.policy(field) == FieldPolicy::Omit {
+            field.remove(&mut info);
+        }
+    }
+    if config.policy(BuildInfoField::ContentId) != FieldPolicy::Omit {
+        info.ext_mut().content_id =
+            Some(info.compute_content_id().map_err(|_| Error::Serialize {
+                what: "content ID",
+                buffer_size: 1024 * 1024,
+            })?);
     }
 
     // let build_info_debug = format!("{:#?}", info);
-    let mut buf = [0u8; 16_384];
-    let mut wr = BufWriter::new(&mut buf);
-    info.ser_shrink_wrap(&mut wr).unwrap();
-    let info_full = wr.finish_and_take().unwrap().to_vec();
+    let info_full = serialize(&mut info, "full")?;
 
-    info.crate_info.dependencies = Vec::new();
-    if let Some(vc) = &mut info.version_control {
-        vc.commit_id = None;
+    for field in ALL_FIELDS {
+        if config.policy(field) == FieldPolicy::Full {
+            field.remove(&mut info);
+        }
+    }
+    info.ext_mut().extra = config.extra.select(Placement::in_compact);
+    let mut info_pruned = serialize(&mut info, "compact")?;
+
+    if let Some((max_size, on_overflow)) = config.limit() {
+        let size = |payload: &[u8]| payload.len() + VERSIONED_HEADER_LEN;
+        let initial_size = size(&info_pruned);
+        let mut shed = Vec::new();
+        let mut candidates = config.shed_candidates();
+        while size(&info_pruned) > max_size && on_overflow == OnOverflow::Shed {
+            let Some(field) = candidates.next() else {
+                break;
+            };
+            if !field.remove(&mut info) {
+                continue;
+            }
+            shed.push(field);
+            info_pruned = serialize(&mut info, "compact")?;
+        }
+        if size(&info_pruned) > max_size {
+            return Err(Error::CompactTooBig {
+                size: size(&info_pruned),
+                max_size,
+                shed,
+            });
+        }
+        if !shed.is_empty() {
+            println!(
+                "cargo::warning=Compact build info shrunk from {initial_size}B to {}B to fit into {max_size}B, removed: {shed:?}",
+                size(&info_pruned)
+            );
+        }
     }
-    // let mut buf = [0u8; 16_384]; // TODO: remove
-    let mut wr = BufWriter::new(&mut buf);
-    info.ser_shrink_wrap(&mut wr).unwrap();
-    let info_pruned = wr.finish_and_take().unwrap().to_vec();
 
-    (info_full, info_pruned)
+    Ok((info_full, info_pruned))
+}
+
+fn serialize(info: &mut BedrockBuildInfoOwned, what: &'static str) -> Result<Vec<u8>, Error> {
+    info.drop_empty_ext();
+    info.to_ww_vec().map_err(|_| Error::Serialize {
+        what,
+        buffer_size: 1024 * 1024,
+    })
+}
+
+/// Build timestamp according to [TimestampSource]
+fn timestamp(info: &BuildInfo, source: TimestampSource) -> Result<DateTime<Utc>, Error> {
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    let source_date_epoch = std::env::var("SOURCE_DATE_EPOCH").ok();
+    timestamp_from(info, source, source_date_epoch)
 }
 
-fn crate_info(info: build_info_common::CrateInfo) -> CrateInfoOwned {
-    CrateInfoOwned {
+fn timestamp_from(
+    info: &BuildInfo,
+    source: TimestampSource,
+    source_date_epoch: Option<String>,
+) -> Result<DateTime<Utc>, Error> {
+    let commit_timestamp = || {
+        info.version_control.as_ref().map(|v| {
+            let build_info_common::VersionControl::Git(git) = v;
+            git.commit_timestamp
+        })
+    };
+    match source {
+        TimestampSource::BuildTime => Ok(info.timestamp),
+        TimestampSource::Commit => Ok(commit_timestamp().unwrap_or(info.timestamp)),
+        TimestampSource::Auto => match source_date_epoch {
+            Some(epoch) => epoch
+                .trim()
+                .parse::<i64>()
+                .ok()
+                .and_then(|secs| DateTime::from_timestamp(secs, 0))
+                .ok_or(Error::Timestamp {
+                    what: "SOURCE_DATE_EPOCH",
+                    value: epoch,
+                }),
+            None => Ok(info.timestamp),
+        },
+    }
+}
+
+fn crate_info(info: build_info_common::CrateInfo) -> Result<CrateInfoOwned, Error> {
+    Ok(CrateInfoOwned {
+        version: info
+            .version
+            .clone()
+            .try_into()
+            .map_err(|_| Error::Version {
+                what: format!("crate '{}'", info.name),
+                value: info.version.to_string(),
+            })?,
         name: info.name,
-        version: info.version.try_into().unwrap(),
         authors: info.authors,
         enabled_features: info.enabled_features,
+        ext: Some(CrateInfoExtOwned {
+            license: info.license,
+            source: None,
+        }),
         dependencies: info
             .dependencies
             .into_iter()
-            .map(|dep| crate_info(dep))
-            .collect(),
+            .map(crate_info)
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+/// Map cargo profile to [Profile], custom profiles (e.g. `release-lto` or `hil`) are recorded as the built-in profile
+/// they inherit from, and by name.
+///
+/// Cargo only reports the built-in profile through `PROFILE` (`inherits`), the real name is the output directory of the
+/// profile: `target/[<triple>/]<profile>/build/<crate>-<hash>/out`, `debug` for the `dev` profile.
+fn profile(inherits: &str, out_dir: Option<&Path>) -> (Profile, Option<String>) {
+    let name = out_dir.and_then(profile_dir).unwrap_or(inherits);
+    match name {
+        "release" => (Profile::Release, None),
+        "debug" => (Profile::Debug, None),
+        name => {
+            let profile = if inherits == "release" {
+                Profile::Release
+            } else {
+                Profile::Debug
+            };
+            (profile, Some(name.to_string()))
+        }
     }
 }
 
-fn profile(profile: String) -> Profile {
-    match profile.as_str() {
-        "release" => Profile::Release,
-        "debug" => Profile::Debug,
-        _ => panic!("Unrecognized profile: {}", profile),
+/// Profile directory name from build script `OUT_DIR`
+fn profile_dir(out_dir: &Path) -> Option<&str> {
+    let build = out_dir.parent()?.parent()?;
+    if build.file_name()? != "build" {
+        return None;
     }
+    build.parent()?.file_name()?.to_str()
 }
 
 fn optimization_level(level: build_info_common::OptimizationLevel) -> OptimizationLevel {
@@ -106,3 +341,238 @@ fn compiler_info(channel: build_info_common::CompilerChannel) -> CompilerChannel
         build_info_common::CompilerChannel::Stable => CompilerChannel::Stable,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bedrock_build_info::BedrockBuildInfo;
+    use build_info_common::semver::Version;
+    use build_info_common::{
+        CompilerInfo, CpuInfo, CrateInfo, Endianness, GitInfo, TargetInfo, VersionControl,
+    };
+    use chrono::NaiveDate;
+    use shrink_wrap::prelude::*;
+
+    fn crate_info(name: &str, version: Version) -> CrateInfo {
+        CrateInfo {
+            name: name.into(),
+            version,
+            authors: vec!["Jane Doe <jane@example.com>".into()],
+            license: Some("MIT OR Apache-2.0".into()),
+            enabled_features: vec!["default".into()],
+            available_features: vec!["default".into()],
+            dependencies: vec![],
+        }
+    }
+
+    /// Build info as collected by build-info-build for a firmware with one dependency
+    fn build_info() -> BuildInfo {
+        let mut firmware = crate_info("fw", Version::new(0, 1, 2));
+        firmware
+            .dependencies
+            .push(crate_info("embassy-executor", Version::new(0, 7, 0)));
+        BuildInfo {
+            timestamp: DateTime::from_timestamp(1_752_423_600, 0).unwrap(),
+            profile: "release".into(),
+            optimization_level: build_info_common::OptimizationLevel::Os,
+            crate_info: firmware,
+            target: TargetInfo {
+                triple: "thumbv7em-none-eabihf".into(),
+                family: "".into(),
+                os: "none".into(),
+                cpu: CpuInfo {
+                    arch: "arm".into(),
+                    pointer_width: 32,
+                    endianness: Endianness::Little,
+                    features: vec![],
+                },
+            },
+            compiler: CompilerInfo {
+                version: Version::new(1, 87, 0),
+                commit_id: None,
+                commit_date: NaiveDate::from_ymd_opt(2025, 5, 5),
+                channel: build_info_common::CompilerChannel::Stable,
+                host_triple: "x86_64-unknown-linux-gnu".into(),
+            },
+            version_control: Some(VersionControl::Git(GitInfo {
+                commit_id: "6b1d1c3a4b5e9f0e8d7c6b5a4f3e2d1c0b9a8f7e".into(),
+                commit_short_id: "6b1d1c3".into(),
+                commit_timestamp: DateTime::from_timestamp(1_752_337_200, 0).unwrap(),
+                dirty: false,
+                branch: Some("main".into()),
+                tags: vec!["v0.1.2".into()],
+            })),
+        }
+    }
+
+    /// Config that does not depend on the environment: no cargo metadata and no `SOURCE_DATE_EPOCH`
+    fn config() -> BuildInfoConfig {
+        BuildInfoConfig::new()
+            .field(BuildInfoField::Sources, FieldPolicy::Omit)
+            .timestamp(TimestampSource::BuildTime)
+    }
+
+    fn decode(bytes: &[u8]) -> BedrockBuildInfoOwned {
+        BedrockBuildInfo::from_ww_bytes(bytes).unwrap().make_owned()
+    }
+
+    /// Size of the compact info with its header, as compared to [BuildInfoConfig::max_compact_size]
+    fn compact_size(config: &BuildInfoConfig) -> usize {
+        let (_, compact) = shrink_wrap_build_info(build_info(), config).unwrap();
+        compact.len() + VERSIONED_HEADER_LEN
+    }
+
+    #[test]
+    fn fields_are_shed_in_order() {
+        let order = [
+            BuildInfoField::Tags,
+            BuildInfoField::Authors,
+            BuildInfoField::Branch,
+        ];
+        // fits once tags and authors are gone, but not with only tags gone
+        let max_size = compact_size(
+            &config()
+                .field(BuildInfoField::Tags, FieldPolicy::Full)
+                .field(BuildInfoField::Authors, FieldPolicy::Full),
+        );
+        let config = config().max_compact_size(max_size).shed_order(order);
+        let (full, compact) = shrink_wrap_build_info(build_info(), &config).unwrap();
+        assert_eq!(compact.len() + VERSIONED_HEADER_LEN, max_size);
+
+        let compact = decode(&compact);
+        let vc = compact.version_control.unwrap();
+        assert!(vc.tags.is_empty());
+        assert!(compact.crate_info.authors.is_empty());
+        assert_eq!(vc.branch.as_deref(), Some("main"));
+        // full info is not affected
+        let full = decode(&full);
+        assert_eq!(full.version_control.unwrap().tags, ["v0.1.2"]);
+        assert_eq!(full.crate_info.authors, ["Jane Doe <jane@example.com>"]);
+    }
+
+    #[test]
+    fn overflow_fails_without_shedding() {
+        let size = compact_size(&config());
+        let config = config()
+            .max_compact_size(size - 1)
+            .on_overflow(OnOverflow::Fail);
+        let err = shrink_wrap_build_info(build_info(), &config).unwrap_err();
+        let Error::CompactTooBig {
+            size: actual,
+            max_size,
+            shed,
+        } = &err
+        else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!((*actual, *max_size), (size, size - 1));
+        assert!(shed.is_empty());
+    }
+
+    #[test]
+    fn omitted_fields_stay_out() {
+        // shedding skips the omitted branch and removes authors instead
+        let config = config()
+            .field(BuildInfoField::Branch, FieldPolicy::Omit)
+            .field(BuildInfoField::CommitShortId, FieldPolicy::Omit)
+            .shed_order([BuildInfoField::Branch, BuildInfoField::Authors]);
+        let size = compact_size(&config);
+        let config = config.max_compact_size(size - 1);
+        let (full, compact) = shrink_wrap_build_info(build_info(), &config).unwrap();
+
+        let compact = decode(&compact);
+        assert!(compact.crate_info.authors.is_empty());
+        for info in [compact, decode(&full)] {
+            let vc = info.version_control.unwrap();
+            assert_eq!(vc.branch, None);
+            assert_eq!(vc.commit_short_id, None);
+        }
+    }
+
+    #[test]
+    fn timestamp_sources() {
+        let info = build_info();
+        let epoch = || Some("1700000000\n".to_string());
+        let from = |source, epoch| timestamp_from(&info, source, epoch).unwrap().timestamp();
+        assert_eq!(from(TimestampSource::Auto, epoch()), 1_700_000_000);
+        assert_eq!(from(TimestampSource::Auto, None), 1_752_423_600);
+        assert_eq!(from(TimestampSource::BuildTime, epoch()), 1_752_423_600);
+        assert_eq!(from(TimestampSource::Commit, epoch()), 1_752_337_200);
+
+        let mut info = build_info();
+        info.version_control = None;
+        let timestamp = timestamp_from(&info, TimestampSource::Commit, None).unwrap();
+        assert_eq!(timestamp.timestamp(), 1_752_423_600);
+    }
+
+    #[test]
+    fn invalid_source_date_epoch_is_an_error() {
+        for value in ["yesterday", "", "99999999999999999"] {
+            let err = timestamp_from(&build_info(), TimestampSource::Auto, Some(value.into()))
+                .unwrap_err();
+            let Error::Timestamp { what, value: v } = &err else {
+                panic!("unexpected error: {err}");
+            };
+            assert_eq!((*what, v.as_str()), ("SOURCE_DATE_EPOCH", value));
+        }
+    }
+
+    #[test]
+    fn builtin_profiles() {
+        let out_dir = Path::new("/fw/target/thumbv7em-none-eabihf/release/build/fw-0123abcd/out");
+        assert_eq!(profile("release", Some(out_dir)), (Profile::Release, None));
+        let out_dir = Path::new("/fw/target/debug/build/fw-0123abcd/out");
+        assert_eq!(profile("debug", Some(out_dir)), (Profile::Debug, None));
+        assert_eq!(profile("release", None), (Profile::Release, None));
+    }
+
+    #[test]
+    fn custom_profiles() {
+        let out_dir =
+            Path::new("/fw/target/thumbv7em-none-eabihf/release-lto/build/fw-0123abcd/out");
+        assert_eq!(
+            profile("release", Some(out_dir)),
+            (Profile::Release, Some("release-lto".into()))
+        );
+        let out_dir = Path::new("/fw/target/hil/build/fw-0123abcd/out");
+        assert_eq!(
+            profile("debug", Some(out_dir)),
+            (Profile::Debug, Some("hil".into()))
+        );
+        // not a cargo target directory layout
+        let out_dir = Path::new("/tmp/out");
+        assert_eq!(profile("release", Some(out_dir)), (Profile::Release, None));
+    }
+
+    #[test]
+    fn unsupported_version_is_an_error() {
+        let mut info = build_info();
+        info.crate_info.dependencies[0].version = Version::new(5_000_000_000, 0, 0);
+        let err = shrink_wrap_build_info(info, &config()).unwrap_err();
+        let Error::Version { what, value } = &err else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!(what, "crate 'embassy-executor'");
+        assert_eq!(value, "5000000000.0.0");
+    }
+
+    #[test]
+    fn unreadable_memory_x_is_an_error() {
+        // a directory exists, but cannot be read as a file
+        let config = config().memory_x(env!("CARGO_MANIFEST_DIR"));
+        let err = shrink_wrap_build_info(build_info(), &config).unwrap_err();
+        let Error::MemoryMap { path, .. } = &err else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!(path, env!("CARGO_MANIFEST_DIR"));
+        assert!(err.to_string().contains("BuildInfoField::FlashRegions"));
+    }
+
+    #[test]
+    fn missing_memory_x_is_not_an_error() {
+        let config = config().memory_x("does/not/exist/memory.x");
+        let (full, _) = shrink_wrap_build_info(build_info(), &config).unwrap();
+        let full = BedrockBuildInfo::from_ww_bytes(&full).unwrap();
+        assert!(full.memory_region("FLASH").is_none());
+    }
+}
@@ -0,0 +1,301 @@
+use crate::build_info::ExtraFields;
+use bedrock_build_info::{BedrockBuildInfoOwned, CrateInfoOwned, RegionKind};
+use std::path::PathBuf;
+
+/// Build info fields that can be moved between compact and full info or omitted completely
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BuildInfoField {
+    /// Authors of the firmware crate
+    Authors,
+    /// Authors of the dependencies (only relevant if dependencies are included)
+    DependencyAuthors,
+    EnabledFeatures,
+    /// Dependencies with their versions and enabled features
+    Dependencies,
+    CommitId,
+    CommitShortId,
+    Branch,
+    Tags,
+    TargetTriple,
+    TargetArch,
+    /// Compiler host triple
+    HostTriple,
+    /// Compiler build date
+    CompilerCommitDate,
+    /// Hash of the build info without volatile fields, see `BedrockBuildInfoOwned::compute_content_id`
+    ContentId,
+    /// FLASH regions and partitions from `memory.x` (bootloader, active, DFU, state, CONFIG)
+    FlashRegions,
+    /// RAM and other non-FLASH regions from `memory.x`
+    OtherRegions,
+    /// License of the crate and all the dependencies
+    Licenses,
+    /// Where dependencies came from (crates.io, git commit or path), needed for SBOM generation
+    Sources,
+}
+
+/// Where a build info field is stored
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FieldPolicy {
+    /// In the compact info in FLASH and in the full info in ELF
+    Compact,
+    /// Only in the full info in ELF
+    Full,
+    /// Not stored at all
+    Omit,
+}
+
+/// Which time to record as the firmware build timestamp
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TimestampSource {
+    /// `SOURCE_DATE_EPOCH` env variable if set (see https://reproducible-builds.org/specs/source-date-epoch/),
+    /// wall-clock build time otherwise
+    #[default]
+    Auto,
+    /// Wall-clock build time, two builds of the same sources will differ
+    BuildTime,
+    /// Time of the last git commit, falls back to build time if not in a git repository
+    Commit,
+}
+
+/// What to do when compact info does not fit into [BuildInfoConfig::max_compact_size]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OnOverflow {
+    /// Remove fields from the compact info one by one in [BuildInfoConfig::shed_order], until it fits
+    Shed,
+    /// Fail the build
+    Fail,
+}
+
+/// Controls what goes into the compact (FLASH) and full (ELF only) build info.
+///
+/// Defaults: dependencies with their licenses and sources, commit ID and non-FLASH memory regions only in full info,
+/// dependency authors omitted, everything else in both.
+///
+/// Example (in build.rs):
+/// ```ignore
+/// use bedrock_build::{BuildInfoConfig, BuildInfoField, FieldPolicy};
+///
+/// let config = BuildInfoConfig::new()
+///     .field(BuildInfoField::Authors, FieldPolicy::Omit)
+///     .field(BuildInfoField::Tags, FieldPolicy::Full)
+///     .max_compact_size(96);
+/// let info = bedrock_build::serialize_build_info_with_config(info, config);
+/// ```
+#[derive(Clone, Debug)]
+pub struct BuildInfoConfig {
+    policies: Vec<(BuildInfoField, FieldPolicy)>,
+    max_compact_size: Option<usize>,
+    on_overflow: OnOverflow,
+    shed_order: Vec<BuildInfoField>,
+    timestamp_source: TimestampSource,
+    memory_x: Option<PathBuf>,
+    signature_slot: bool,
+    pub(crate) extra: ExtraFields,
+}
+
+impl Default for BuildInfoConfig {
+    fn default() -> Self {
+        use BuildInfoField::*;
+        BuildInfoConfig {
+            policies: vec![
+                (Authors, FieldPolicy::Compact),
+                (DependencyAuthors, FieldPolicy::Omit),
+                (EnabledFeatures, FieldPolicy::Compact),
+                (Dependencies, FieldPolicy::Full),
+                (CommitId, FieldPolicy::Full),
+                (CommitShortId, FieldPolicy::Compact),
+                (Branch, FieldPolicy::Compact),
+                (Tags, FieldPolicy::Compact),
+                (TargetTriple, FieldPolicy::Compact),
+                (TargetArch, FieldPolicy::Compact),
+                (HostTriple, FieldPolicy::Compact),
+                (CompilerCommitDate, FieldPolicy::Compact),
+                (ContentId, FieldPolicy::Compact),
+                (FlashRegions, FieldPolicy::Compact),
+                (OtherRegions, FieldPolicy::Full),
+                (Licenses, FieldPolicy::Full),
+                (Sources, FieldPolicy::Full),
+            ],
+            max_compact_size: None,
+            on_overflow: OnOverflow::Shed,
+            shed_order: vec![
+                Dependencies,
+                DependencyAuthors,
+                Sources,
+                Licenses,
+                OtherRegions,
+                Authors,
+                HostTriple,
+                CompilerCommitDate,
+                Tags,
+                Branch,
+                TargetArch,
+                FlashRegions,
+                EnabledFeatures,
+                TargetTriple,
+                ContentId,
+                CommitId,
+                CommitShortId,
+            ],
+            timestamp_source: TimestampSource::Auto,
+            memory_x: None,
+            signature_slot: false,
+            extra: ExtraFields::new(),
+        }
+    }
+}
+
+impl BuildInfoConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn field(mut self, field: BuildInfoField, policy: FieldPolicy) -> Self {
+        if let Some((_, p)) = self.policies.iter_mut().find(|(f, _)| *f == field) {
+            *p = policy;
+        }
+        self
+    }
+
+    /// Maximum size of the compact info in FLASH in bytes, including marker, format version, length and CRC
+    pub fn max_compact_size(mut self, bytes: usize) -> Self {
+        self.max_compact_size = Some(bytes);
+        self
+    }
+
+    pub fn on_overflow(mut self, on_overflow: OnOverflow) -> Self {
+        self.on_overflow = on_overflow;
+        self
+    }
+
+    /// Order in which fields are removed from compact info if it is too big, first is removed first
+    pub fn shed_order(mut self, order: impl IntoIterator<Item = BuildInfoField>) -> Self {
+        self.shed_order = order.into_iter().collect();
+        self
+    }
+
+    /// Use `SOURCE_DATE_EPOCH` or commit time instead of wall-clock time for reproducible builds
+    pub fn timestamp(mut self, source: TimestampSource) -> Self {
+        self.timestamp_source = source;
+        self
+    }
+
+    /// Linker script to read memory regions from, `memory.x` next to `Cargo.toml` by default
+    pub fn memory_x(mut self, path: impl Into<PathBuf>) -> Self {
+        self.memory_x = Some(path.into());
+        self
+    }
+
+    /// Reserve space for an ed25519 signature record right after the compact info, to be filled in by `bedrock sign`
+    pub fn signature_slot(mut self, enabled: bool) -> Self {
+        self.signature_slot = enabled;
+        self
+    }
+
+    /// User-defined entries, see [ExtraFields]
+    pub fn extra(mut self, extra: ExtraFields) -> Self {
+        self.extra = extra;
+        self
+    }
+
+    pub(crate) fn policy(&self, field: BuildInfoField) -> FieldPolicy {
+        self.policies
+            .iter()
+            .find(|(f, _)| *f == field)
+            .map(|(_, p)| *p)
+            .unwrap_or(FieldPolicy::Compact)
+    }
+
+    pub(crate) fn timestamp_source(&self) -> TimestampSource {
+        self.timestamp_source
+    }
+
+    pub(crate) fn memory_x_path(&self) -> PathBuf {
+        match &self.memory_x {
+            Some(path) => path.clone(),
+            None => PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap_or_default())
+                .join("memory.x"),
+        }
+    }
+
+    pub(crate) fn has_signature_slot(&self) -> bool {
+        self.signature_slot
+    }
+
+    pub(crate) fn limit(&self) -> Option<(usize, OnOverflow)> {
+        self.max_compact_size.map(|size| (size, self.on_overflow))
+    }
+
+    pub(crate) fn shed_candidates(&self) -> impl Iterator<Item = BuildInfoField> + '_ {
+        self.shed_order
+            .iter()
+            .copied()
+            .filter(|f| self.policy(*f) == FieldPolicy::Compact)
+    }
+}
+
+impl BuildInfoField {
+    /// Remove the field from build info, returns false if it was already empty
+    pub(crate) fn remove(&self, info: &mut BedrockBuildInfoOwned) -> bool {
+        fn take_vec<T>(v: &mut Vec<T>) -> bool {
+            let was_present = !v.is_empty();
+            v.clear();
+            was_present
+        }
+        fn take_opt<T>(o: &mut Option<T>) -> bool {
+            o.take().is_some()
+        }
+        fn take_crate_fields(
+            info: &mut CrateInfoOwned,
+            take: fn(&mut CrateInfoOwned) -> bool,
+        ) -> bool {
+            let removed = take(info);
+            info.dependencies.iter_mut().fold(removed, |removed, dep| {
+                take_crate_fields(dep, take) || removed
+            })
+        }
+        fn take_regions(info: &mut BedrockBuildInfoOwned, flash: bool) -> bool {
+            let Some(ext) = &mut info.ext else {
+                return false;
+            };
+            let len = ext.memory_map.len();
+            ext.memory_map
+                .retain(|r| (r.kind == RegionKind::Flash) != flash);
+            ext.memory_map.len() != len
+        }
+        let vc = info.version_control.as_mut();
+        match self {
+            BuildInfoField::Authors => take_vec(&mut info.crate_info.authors),
+            BuildInfoField::DependencyAuthors => {
+                let mut removed = false;
+                for dep in &mut info.crate_info.dependencies {
+                    removed |= take_crate_fields(dep, |c| take_vec(&mut c.authors));
+                }
+                removed
+            }
+            BuildInfoField::EnabledFeatures => take_vec(&mut info.crate_info.enabled_features),
+            BuildInfoField::Dependencies => take_vec(&mut info.crate_info.dependencies),
+            BuildInfoField::CommitId => vc.is_some_and(|vc| take_opt(&mut vc.commit_id)),
+            BuildInfoField::CommitShortId => vc.is_some_and(|vc| take_opt(&mut vc.commit_short_id)),
+            BuildInfoField::Branch => vc.is_some_and(|vc| take_opt(&mut vc.branch)),
+            BuildInfoField::Tags => vc.is_some_and(|vc| take_vec(&mut vc.tags)),
+            BuildInfoField::TargetTriple => take_opt(&mut info.target_info.triple),
+            BuildInfoField::TargetArch => take_opt(&mut info.target_info.arch),
+            BuildInfoField::HostTriple => take_opt(&mut info.compiler_info.host_triple),
+            BuildInfoField::CompilerCommitDate => take_opt(&mut info.compiler_info.commit_date),
+            BuildInfoField::ContentId => info
+                .ext
+                .as_mut()
+                .is_some_and(|ext| take_opt(&mut ext.content_id)),
+            BuildInfoField::FlashRegions => take_regions(info, true),
+            BuildInfoField::OtherRegions => take_regions(info, false),
+            BuildInfoField::Licenses => take_crate_fields(&mut info.crate_info, |c| {
+                c.ext.as_mut().is_some_and(|ext| take_opt(&mut ext.license))
+            }),
+            BuildInfoField::Sources => take_crate_fields(&mut info.crate_info, |c| {
+                c.ext.as_mut().is_some_and(|ext| take_opt(&mut ext.source))
+            }),
+        }
+    }
+}
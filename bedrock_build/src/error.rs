@@ -0,0 +1,80 @@
+use crate::BuildInfoField;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug)]
+pub enum Error {
+    /// Version reported by cargo or rustc could not be converted
+    Version { what: String, value: String },
+    /// Timestamp could not be converted
+    Timestamp { what: &'static str, value: String },
+    /// Build info serialization failed even with the largest buffer
+    Serialize {
+        what: &'static str,
+        buffer_size: usize,
+    },
+    /// Linker script with memory regions could not be read
+    MemoryMap { path: String, reason: String },
+    /// Compact build info does not fit into the configured size
+    CompactTooBig {
+        size: usize,
+        max_size: usize,
+        shed: Vec<BuildInfoField>,
+    },
+    /// Build script environment is missing a variable cargo always sets
+    Env { var: &'static str },
+    /// Generated file could not be written to OUT_DIR
+    Write { path: String, reason: String },
+}
+
+impl Error {
+    /// Print the error as a cargo build script error, so that it is shown without a backtrace
+    /// and the build fails once the build script exits.
+    pub fn report(&self) {
+        println!("cargo::error={self}");
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Version { what, value } => write!(
+                f,
+                "bedrock_build: {what} version '{value}' is not supported in build info, \
+                use a plain MAJOR.MINOR.PATCH[-pre][+build] version"
+            ),
+            Error::Timestamp { what, value } => write!(
+                f,
+                "bedrock_build: {what} timestamp '{value}' is not a number of seconds in supported range"
+            ),
+            Error::Serialize { what, buffer_size } => write!(
+                f,
+                "bedrock_build: {what} build info does not fit into {buffer_size}B, \
+                reduce DependencyDepth or set BuildInfoField::Dependencies to FieldPolicy::Omit"
+            ),
+            Error::MemoryMap { path, reason } => write!(
+                f,
+                "bedrock_build: could not read memory regions from '{path}': {reason}, \
+                point BuildInfoConfig::memory_x to the linker script or omit BuildInfoField::FlashRegions and OtherRegions"
+            ),
+            Error::CompactTooBig {
+                size,
+                max_size,
+                shed,
+            } => write!(
+                f,
+                "bedrock_build: compact build info is {size}B, which exceeds the configured maximum of {max_size}B \
+                (removed: {shed:?}), increase BuildInfoConfig::max_compact_size, \
+                move more fields to FieldPolicy::Full or use OnOverflow::Shed"
+            ),
+            Error::Env { var } => write!(
+                f,
+                "bedrock_build: {var} is not set, bedrock_build functions must be called from a build script"
+            ),
+            Error::Write { path, reason } => {
+                write!(f, "bedrock_build: could not write '{path}': {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -1,23 +1,43 @@
 mod build_info;
+mod config;
+mod error;
+mod linker;
+mod memory_map;
+mod sources;
+
+pub use build_info::{ExtraFields, Placement};
+pub use config::{BuildInfoConfig, BuildInfoField, FieldPolicy, OnOverflow, TimestampSource};
+pub use error::Error;
 
 use base64::Engine;
 use base64::prelude::BASE64_STANDARD;
-use bedrock_build_info::{COMPACT_INFO_MAGIC, build_info_crc};
+use bedrock_build_info::envelope;
+use bedrock_build_info::envelope::{EnvelopeFlags, VERSIONED_HEADER_LEN};
+use bedrock_build_info::signature::SignatureRecord;
 use build_info_common::BuildInfo;
 use std::ffi::OsString;
 use std::path::PathBuf;
 use std::{env, fs};
 
+/// Link arguments common to all firmware, RAM linking when `RAM_LINK=1`.
+///
+/// Failures are reported with [Error::report], so that cargo shows a clean message and fails the build.
 pub fn common() {
+    if let Err(e) = link_args() {
+        e.report();
+    }
+}
+
+fn link_args() -> Result<(), Error> {
     println!("cargo:rerun-if-env-changed=RAM_LINK");
 
-    let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    let out = &PathBuf::from(env::var_os("OUT_DIR").ok_or(Error::Env { var: "OUT_DIR" })?);
     if env::var_os("RAM_LINK") == Some(OsString::from("1")) {
-        fs::write(
-            out.join("link_ram.x"),
-            include_bytes!("../link_ram_cortex_m.x"),
-        )
-        .unwrap();
+        let path = out.join("link_ram.x");
+        fs::write(&path, include_bytes!("../link_ram_cortex_m.x")).map_err(|e| Error::Write {
+            path: path.display().to_string(),
+            reason: e.to_string(),
+        })?;
         println!(
             "cargo::warning=⚠️ \x1b[1;33mUsing RAM linking, old code will be run from FLASH on power-cycle"
         );
@@ -33,47 +53,79 @@ pub fn common() {
     println!("cargo:rustc-link-arg=--nmagic");
 
     println!("cargo:rerun-if-changed=../link_ram_cortex_m.x");
+    Ok(())
 }
 
-pub fn serialize_build_info(info: BuildInfo) -> String {
-    let (info_full, mut info_pruned) = build_info::shrink_wrap_build_info(info);
+/// Convert build info into a Rust source file, defining `compact()` and `full()` functions.
+///
+/// On error, report it with [Error::report] instead of panicking, so that cargo shows a clean message.
+pub fn serialize_build_info(info: BuildInfo) -> Result<String, Error> {
+    serialize_build_info_with_extra(info, ExtraFields::new())
+}
+
+/// Same as [serialize_build_info], additionally embedding user-defined entries.
+pub fn serialize_build_info_with_extra(
+    info: BuildInfo,
+    extra: ExtraFields,
+) -> Result<String, Error> {
+    serialize_build_info_with_config(info, BuildInfoConfig::new().extra(extra))
+}
+
+/// Same as [serialize_build_info], with control over what goes into compact and full info.
+pub fn serialize_build_info_with_config(
+    info: BuildInfo,
+    config: BuildInfoConfig,
+) -> Result<String, Error> {
+    let (info_full, info_pruned) = build_info::shrink_wrap_build_info(info, &config)?;
     let info_full = BASE64_STANDARD.encode(&info_full);
 
-    let crc = build_info_crc(&info_pruned).to_le_bytes();
-
-    let pruned_size =
-        u16::try_from(info_pruned.len()).expect("Compact info should definitely fit into 65K");
-    let pruned_size_le = pruned_size.to_le_bytes();
-    let magic = COMPACT_INFO_MAGIC.to_be_bytes();
-    info_pruned.splice(
-        0..0,
-        [
-            magic[0],
-            magic[1],
-            magic[2],
-            magic[3],
-            pruned_size_le[0],
-            pruned_size_le[1],
-            crc[0],
-            crc[1],
-            crc[2],
-            crc[3],
-        ],
-    );
-    let total_flash_size = info_pruned.len();
-
-    format!(
-        "const COMPACT: &'static [u8] = &{info_pruned:?};
+    if info_pruned.len() > u16::MAX as usize {
+        return Err(Error::CompactTooBig {
+            size: info_pruned.len(),
+            max_size: u16::MAX as usize,
+            shed: Vec::new(),
+        });
+    }
+    let info_pruned = envelope::encode(&info_pruned, EnvelopeFlags(EnvelopeFlags::PRUNED));
+    let header_len = VERSIONED_HEADER_LEN;
+    let compact_end = info_pruned.len();
+
+    let compact_code = if config.has_signature_slot() {
+        let mut record = info_pruned;
+        record.extend_from_slice(&SignatureRecord::placeholder());
+        let total_flash_size = record.len();
+        format!(
+            "static COMPACT: [u8; {total_flash_size}] = {record:?};
 
 /// Build information to be embedded into MCU FLASH, optimized for size by omitting some fields.
-/// Size in FLASH with marker, length and CRC is {total_flash_size}B.
+/// Size in FLASH with marker, format version, length, CRC and signature slot is {total_flash_size}B.
 /// Ensure to either print it via defmt or use _ = core::hint::black_box(compact()) to ensure it is saved in FLASH.
-pub fn compact() -> &'static [u8] {{ core::hint::black_box(&COMPACT[10..]) }}
-    
+pub fn compact() -> &'static [u8] {{ &core::hint::black_box(&COMPACT)[{header_len}..{compact_end}] }}
+
+/// Signature record right after the compact info, filled in after linking by `bedrock sign`,
+/// see `bedrock_build_info::signature`.
+pub fn signature() -> &'static [u8] {{ &core::hint::black_box(&COMPACT)[{compact_end}..] }}
+"
+        )
+    } else {
+        let total_flash_size = compact_end;
+        format!(
+            "const COMPACT: &'static [u8] = &{info_pruned:?};
+
+/// Build information to be embedded into MCU FLASH, optimized for size by omitting some fields.
+/// Size in FLASH with marker, format version, length and CRC is {total_flash_size}B.
+/// Ensure to either print it via defmt or use _ = core::hint::black_box(compact()) to ensure it is saved in FLASH.
+pub fn compact() -> &'static [u8] {{ core::hint::black_box(&COMPACT[{header_len}..]) }}
+"
+        )
+    };
+
+    Ok(format!(
+        "{compact_code}    
 /// Full build information, only saved to the firmware ELF file through defmt string interning.
 /// Ensure to either print it via defmt or use _ = core::hint::black_box(full()) to ensure it is saved in ELF.
 pub fn full() -> defmt::Str {{ defmt::intern!(\"build_info:{info_full}\") }}"
-    )
+    ))
     // let info_full_len = info_full.len();
     // let info_pruned_len = info_pruned.len();
     // let info_full = to_hex_strings(&info_full);
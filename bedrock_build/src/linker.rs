@@ -0,0 +1,383 @@
+//! Detection of how the firmware is really linked: linker, RAM linking, target CPU and features, build-std.
+
+use bedrock_build_info::LinkMode;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct LinkerConfig {
+    pub linker: Option<String>,
+    pub link_mode: Option<LinkMode>,
+    pub target_cpu: Option<String>,
+    pub target_features: Vec<String>,
+    pub build_std: Vec<String>,
+}
+
+impl LinkerConfig {
+    pub(crate) fn is_flip_link(&self) -> bool {
+        self.linker.as_deref().is_some_and(|linker| {
+            Path::new(linker)
+                .file_stem()
+                .is_some_and(|stem| stem == "flip-link")
+        })
+    }
+}
+
+/// Collect linker configuration from rustflags, env variables and `.cargo/config.toml` files, in the same order of
+/// precedence as cargo uses.
+///
+/// build-std is only seen when it comes from `[unstable] build-std` or `CARGO_UNSTABLE_BUILD_STD`: cargo does not pass
+/// `-Zbuild-std` given on the command line to build scripts, so such builds are recorded without it.
+pub(crate) fn detect() -> LinkerConfig {
+    let target = env::var("TARGET").unwrap_or_default();
+    let target_env = target.to_uppercase().replace(['-', '.'], "_");
+    for var in [
+        "RAM_LINK".to_string(),
+        "CARGO_UNSTABLE_BUILD_STD".to_string(),
+        format!("CARGO_TARGET_{target_env}_LINKER"),
+    ] {
+        println!("cargo:rerun-if-env-changed={var}");
+    }
+    resolve(&target, &rustflags(), &config_files(), |var| {
+        env::var(var).ok()
+    })
+}
+
+fn resolve(
+    target: &str,
+    rustflags: &[String],
+    configs: &[toml::Table],
+    env: impl Fn(&str) -> Option<String>,
+) -> LinkerConfig {
+    let target_env = target.to_uppercase().replace(['-', '.'], "_");
+    let mut config = parse_rustflags(rustflags);
+    // rustflags are passed to rustc last, so they win over the linker set by cargo
+    config.linker = config
+        .linker
+        .or_else(|| env(&format!("CARGO_TARGET_{target_env}_LINKER")))
+        .or_else(|| {
+            configs
+                .iter()
+                .find_map(|c| c.get("target")?.get(target)?.get("linker")?.as_str())
+                .map(|s| s.to_string())
+        })
+        .or_else(|| {
+            // `[target.<triple>]` wins over `[target.'cfg(...)']` tables
+            configs
+                .iter()
+                .filter_map(|c| c.get("target")?.as_table())
+                .flat_map(|targets| targets.iter())
+                .filter(|(key, _)| cfg_matches(key, &env))
+                .find_map(|(_, table)| table.get("linker")?.as_str())
+                .map(|s| s.to_string())
+        });
+    config.link_mode = Some(if env("RAM_LINK").is_some_and(|v| v == "1") {
+        LinkMode::Ram
+    } else {
+        LinkMode::Flash
+    });
+    // cargo exports neither -Zbuild-std nor its config to build scripts, only the env variable form is visible besides
+    // the config files read here
+    config.build_std = match env("CARGO_UNSTABLE_BUILD_STD") {
+        Some(crates) => crates.split(',').map(|c| c.trim().to_string()).collect(),
+        None => configs
+            .iter()
+            .find_map(|c| c.get("unstable")?.get("build-std")?.as_array())
+            .map(|crates| {
+                crates
+                    .iter()
+                    .filter_map(|c| c.as_str().map(|s| s.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+    config
+}
+
+/// Whether a `cfg(...)` key of the `[target]` table matches the target, judged by the `CARGO_CFG_*` variables cargo
+/// sets for build scripts. Keys that are not a valid cfg expression never match.
+fn cfg_matches(key: &str, env: &impl Fn(&str) -> Option<String>) -> bool {
+    let Some(expr) = key.trim().strip_prefix("cfg(") else {
+        return false;
+    };
+    let mut parser = CfgParser { rest: expr, env };
+    parser
+        .expr()
+        .is_some_and(|matches| matches && parser.eat(')') && parser.rest.trim().is_empty())
+}
+
+struct CfgParser<'a, E> {
+    rest: &'a str,
+    env: &'a E,
+}
+
+impl<'a, E: Fn(&str) -> Option<String>> CfgParser<'a, E> {
+    fn expr(&mut self) -> Option<bool> {
+        let ident = self.ident()?;
+        match ident {
+            "all" | "any" | "not" => {
+                if !self.eat('(') {
+                    return None;
+                }
+                let mut values = Vec::new();
+                while !self.eat(')') {
+                    values.push(self.expr()?);
+                    if !self.eat(',') {
+                        if !self.eat(')') {
+                            return None;
+                        }
+                        break;
+                    }
+                }
+                match ident {
+                    "all" => Some(values.iter().all(|v| *v)),
+                    "any" => Some(values.iter().any(|v| *v)),
+                    _ => match values[..] {
+                        [value] => Some(!value),
+                        _ => None,
+                    },
+                }
+            }
+            name => {
+                let var = (self.env)(&format!("CARGO_CFG_{}", name.to_uppercase()));
+                if !self.eat('=') {
+                    return Some(var.is_some());
+                }
+                let value = self.string()?;
+                // multi-valued options like target_feature are joined with commas
+                Some(var.is_some_and(|var| var.split(',').any(|v| v == value)))
+            }
+        }
+    }
+
+    fn ident(&mut self) -> Option<&'a str> {
+        let rest = self.rest.trim_start();
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let (ident, rest) = rest.split_at(end);
+        self.rest = rest;
+        (!ident.is_empty()).then_some(ident)
+    }
+
+    fn string(&mut self) -> Option<&'a str> {
+        let (value, rest) = self.rest.trim_start().strip_prefix('"')?.split_once('"')?;
+        self.rest = rest;
+        Some(value)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        match self.rest.trim_start().strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Flags cargo passes to rustc, including the ones from `.cargo/config.toml`
+fn rustflags() -> Vec<String> {
+    match env::var("CARGO_ENCODED_RUSTFLAGS") {
+        Ok(flags) => flags
+            .split('\x1f')
+            .filter(|f| !f.is_empty())
+            .map(|f| f.to_string())
+            .collect(),
+        Err(_) => env::var("RUSTFLAGS")
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(|f| f.to_string())
+            .collect(),
+    }
+}
+
+fn parse_rustflags(flags: &[String]) -> LinkerConfig {
+    let mut config = LinkerConfig::default();
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        let codegen = match flag.as_str() {
+            "-C" | "--codegen" => flags.next().map(|s| s.as_str()),
+            flag => flag
+                .strip_prefix("-C")
+                .or_else(|| flag.strip_prefix("--codegen=")),
+        };
+        let Some((key, value)) = codegen.and_then(|c| c.split_once('=')) else {
+            continue;
+        };
+        match key {
+            "linker" => config.linker = Some(value.to_string()),
+            "target-cpu" => config.target_cpu = Some(value.to_string()),
+            "target-feature" => config.target_features.extend(
+                value
+                    .split(',')
+                    .filter(|f| !f.is_empty())
+                    .map(|f| f.to_string()),
+            ),
+            _ => {}
+        }
+    }
+    config
+}
+
+/// `.cargo/config.toml` files from the crate directory up, then from `CARGO_HOME`, closest first.
+///
+/// Build scripts usually do not get `CARGO_HOME`, so the default of `$HOME/.cargo` is used in that case.
+fn config_files() -> Vec<toml::Table> {
+    let mut dirs: Vec<_> = env::var_os("CARGO_MANIFEST_DIR")
+        .map(|dir| {
+            Path::new(&dir)
+                .ancestors()
+                .map(|d| d.join(".cargo"))
+                .collect()
+        })
+        .unwrap_or_default();
+    let cargo_home = env::var_os("CARGO_HOME").map(PathBuf::from).or_else(|| {
+        env::var_os("HOME")
+            .or_else(|| env::var_os("USERPROFILE"))
+            .map(|home| Path::new(&home).join(".cargo"))
+    });
+    // already one of the ancestors when the crate is under the home directory
+    if let Some(home) = cargo_home
+        && !dirs.contains(&home)
+    {
+        dirs.push(home);
+    }
+    dirs.iter()
+        .flat_map(|dir| [dir.join("config.toml"), dir.join("config")])
+        .filter_map(|path| {
+            let contents = fs::read_to_string(&path).ok()?;
+            println!("cargo:rerun-if-changed={}", path.display());
+            contents.parse::<toml::Table>().ok()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rustflags_from_config() {
+        let flags: Vec<String> = [
+            "-C",
+            "linker=flip-link",
+            "-Ctarget-cpu=cortex-m7",
+            "--codegen=target-feature=+fp-armv8d16,-dsp",
+            "-C",
+            "link-arg=-Tlink.x",
+        ]
+        .iter()
+        .map(|f| f.to_string())
+        .collect();
+        let config = parse_rustflags(&flags);
+        assert!(config.is_flip_link());
+        assert_eq!(config.target_cpu.as_deref(), Some("cortex-m7"));
+        assert_eq!(config.target_features, ["+fp-armv8d16", "-dsp"]);
+    }
+
+    const TARGET: &str = "thumbv7em-none-eabihf";
+
+    fn config(toml: &str) -> toml::Table {
+        toml.parse().unwrap()
+    }
+
+    #[test]
+    fn linker_from_config_file() {
+        let configs = [
+            config(
+                "[target.thumbv7em-none-eabihf]\nlinker = \"flip-link\"\nrunner = \"bedrock run\"",
+            ),
+            config("[target.thumbv7em-none-eabihf]\nlinker = \"rust-lld\""),
+        ];
+        let config = resolve(TARGET, &[], &configs, |_| None);
+        assert!(config.is_flip_link());
+        assert_eq!(config.link_mode, Some(LinkMode::Flash));
+        assert!(config.build_std.is_empty());
+
+        let other_target = resolve("thumbv6m-none-eabi", &[], &configs, |_| None);
+        assert_eq!(other_target.linker, None);
+    }
+
+    #[test]
+    fn linker_precedence() {
+        let configs = [config(
+            "[target.thumbv7em-none-eabihf]\nlinker = \"flip-link\"",
+        )];
+        let env = |var: &str| {
+            (var == "CARGO_TARGET_THUMBV7EM_NONE_EABIHF_LINKER")
+                .then(|| "/usr/bin/flip-link".to_string())
+        };
+        let config = resolve(TARGET, &[], &configs, env);
+        assert_eq!(config.linker.as_deref(), Some("/usr/bin/flip-link"));
+        assert!(config.is_flip_link());
+
+        let flags = ["-Clinker=rust-lld".to_string()];
+        let config = resolve(TARGET, &flags, &configs, env);
+        assert_eq!(config.linker.as_deref(), Some("rust-lld"));
+        assert!(!config.is_flip_link());
+    }
+
+    #[test]
+    fn ram_link() {
+        let env = |var: &str| (var == "RAM_LINK").then(|| "1".to_string());
+        assert_eq!(
+            resolve(TARGET, &[], &[], env).link_mode,
+            Some(LinkMode::Ram)
+        );
+        let env = |var: &str| (var == "RAM_LINK").then(|| "0".to_string());
+        assert_eq!(
+            resolve(TARGET, &[], &[], env).link_mode,
+            Some(LinkMode::Flash)
+        );
+    }
+
+    #[test]
+    fn build_std() {
+        let configs = [config("[unstable]\nbuild-std = [\"core\", \"alloc\"]")];
+        assert_eq!(
+            resolve(TARGET, &[], &configs, |_| None).build_std,
+            ["core", "alloc"]
+        );
+
+        // the env variable form of the config overrides the config file, -Zbuild-std on the command line is not
+        // visible to build scripts at all
+        let env = |var: &str| {
+            (var == "CARGO_UNSTABLE_BUILD_STD").then(|| "core, panic_abort".to_string())
+        };
+        assert_eq!(
+            resolve(TARGET, &[], &configs, env).build_std,
+            ["core", "panic_abort"]
+        );
+    }
+
+    #[test]
+    fn linker_from_cfg_table() {
+        let env = |var: &str| match var {
+            "CARGO_CFG_TARGET_ARCH" => Some("arm".to_string()),
+            "CARGO_CFG_TARGET_OS" => Some("none".to_string()),
+            "CARGO_CFG_TARGET_FEATURE" => Some("dsp,thumb2,v7".to_string()),
+            "CARGO_CFG_DEBUG_ASSERTIONS" => Some(String::new()),
+            _ => None,
+        };
+        let cfg = |toml: &str| resolve(TARGET, &[], &[config(toml)], env).linker;
+
+        let linker = "[target.'cfg(all(target_arch = \"arm\", target_os = \"none\"))']\nlinker = \"flip-link\"";
+        assert_eq!(cfg(linker).as_deref(), Some("flip-link"));
+        let linker =
+            "[target.'cfg(any(target_arch = \"riscv32\", not(unix),))']\nlinker = \"flip-link\"";
+        assert_eq!(cfg(linker).as_deref(), Some("flip-link"));
+        let linker = "[target.'cfg(all(target_feature = \"dsp\", debug_assertions))']\nlinker = \"flip-link\"";
+        assert_eq!(cfg(linker).as_deref(), Some("flip-link"));
+        let linker = "[target.'cfg(target_arch = \"riscv32\")']\nlinker = \"flip-link\"";
+        assert_eq!(cfg(linker), None);
+        let linker = "[target.'cfg(not(target_os = \"none\", unix))']\nlinker = \"flip-link\"";
+        assert_eq!(cfg(linker), None);
+
+        let linker = "[target.'cfg(target_os = \"none\")']\nlinker = \"flip-link\"\n\
+            [target.thumbv7em-none-eabihf]\nlinker = \"rust-lld\"";
+        assert_eq!(cfg(linker).as_deref(), Some("rust-lld"));
+    }
+}
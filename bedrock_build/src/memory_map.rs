@@ -0,0 +1,254 @@
+//! Linker memory regions from `memory.x`, so that host tools know where FLASH, RAM and partitions are.
+
+use crate::Error;
+use bedrock_build_info::{MemoryRegionOwned, RegionKind};
+use std::fs;
+use std::path::Path;
+
+/// Read memory regions from a linker script, returns an empty map if the file does not exist.
+pub(crate) fn read_memory_x(path: &Path) -> Result<Vec<MemoryRegionOwned>, Error> {
+    println!("cargo:rerun-if-changed={}", path.display());
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let script = fs::read_to_string(path).map_err(|e| Error::MemoryMap {
+        path: path.display().to_string(),
+        reason: e.to_string(),
+    })?;
+    let (regions, skipped) = parse_memory_x(&script);
+    for line in skipped {
+        println!(
+            "cargo::warning=bedrock_build: memory region '{line}' is not recorded in build info, \
+            only plain numbers, K/M suffixes, ORIGIN(), LENGTH(), + and - are supported"
+        );
+    }
+    Ok(regions)
+}
+
+/// Parse `MEMORY { .. }` block and `__name_start`/`__name_end` symbol pairs for regions not defined in it.
+///
+/// Returns regions and definitions that could not be evaluated.
+pub(crate) fn parse_memory_x(script: &str) -> (Vec<MemoryRegionOwned>, Vec<String>) {
+    let (code, kind_hints) = strip_comments(script);
+    let mut regions: Vec<MemoryRegionOwned> = Vec::new();
+    let mut skipped = Vec::new();
+
+    let mut rest = code.as_str();
+    if let Some((block, after)) = memory_block(&code) {
+        rest = after;
+        for line in block.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            match parse_region(line, &regions) {
+                Some((name, origin, length)) => regions.push(MemoryRegionOwned {
+                    kind: region_kind(&name, origin, &kind_hints),
+                    name,
+                    origin,
+                    length,
+                }),
+                None => skipped.push(line.to_string()),
+            }
+        }
+    }
+
+    // symbols defined outside of MEMORY, e.g. partitions generated by `generate_linker_consts`
+    let assignments: Vec<(&str, &str)> = rest
+        .split(';')
+        .filter_map(|stmt| stmt.split_once('='))
+        .map(|(name, expr)| (name.trim(), expr.trim()))
+        .collect();
+    for (symbol, start_expr) in &assignments {
+        let Some(name_lower) = symbol
+            .strip_prefix("__")
+            .and_then(|s| s.strip_suffix("_start"))
+        else {
+            continue;
+        };
+        let name = name_lower.to_uppercase();
+        if regions.iter().any(|r| r.name == name) {
+            continue;
+        }
+        let end_symbol = format!("__{name_lower}_end");
+        let Some((_, end_expr)) = assignments.iter().find(|(s, _)| *s == end_symbol) else {
+            continue;
+        };
+        match (eval(start_expr, &regions), eval(end_expr, &regions)) {
+            (Some(start), Some(end)) if end >= start => regions.push(MemoryRegionOwned {
+                kind: region_kind(&name, start, &kind_hints),
+                name,
+                origin: start,
+                length: end - start,
+            }),
+            _ => skipped.push(format!("{symbol} = {start_expr}")),
+        }
+    }
+    (regions, skipped)
+}
+
+/// Remove comments, collecting `NAME (kind)` hints left by the template generator
+fn strip_comments(script: &str) -> (String, Vec<(String, String)>) {
+    let mut code = String::with_capacity(script.len());
+    let mut hints = Vec::new();
+    let mut rest = script;
+    while let Some(start) = rest.find("/*") {
+        code.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find("*/") else {
+            rest = "";
+            break;
+        };
+        let comment = rest[start + 2..start + len].trim();
+        if let Some((name, kind)) = comment.split_once(" (")
+            && let Some(kind) = kind.strip_suffix(')')
+            && !name.contains(' ')
+        {
+            hints.push((name.to_string(), kind.to_string()));
+        }
+        rest = &rest[start + len + 2..];
+    }
+    code.push_str(rest);
+    (code, hints)
+}
+
+/// Contents of `MEMORY { .. }` and the rest of the script after it
+fn memory_block(code: &str) -> Option<(&str, &str)> {
+    let start = code.find("MEMORY")?;
+    let open = start + code[start..].find('{')?;
+    let close = open + code[open..].find('}')?;
+    Some((&code[open + 1..close], &code[close + 1..]))
+}
+
+/// `NAME (rwx) : ORIGIN = expr, LENGTH = expr`
+fn parse_region(line: &str, known: &[MemoryRegionOwned]) -> Option<(String, u32, u32)> {
+    let (name, attrs) = line.split_once(':')?;
+    let name = name.split('(').next()?.trim();
+    let (origin, length) = attrs.split_once(',')?;
+    let value = |assignment: &str, keys: &[&str]| {
+        let (key, expr) = assignment.split_once('=')?;
+        if !keys.contains(&key.trim()) {
+            return None;
+        }
+        eval(expr, known)
+    };
+    let origin = value(origin, &["ORIGIN", "org", "o"])?;
+    let length = value(length, &["LENGTH", "len", "l"])?;
+    Some((name.to_string(), origin, length))
+}
+
+/// Evaluate a sum of numbers, `ORIGIN(NAME)` and `LENGTH(NAME)`
+fn eval(expr: &str, known: &[MemoryRegionOwned]) -> Option<u32> {
+    let mut total: i64 = 0;
+    let mut sign = 1;
+    let mut rest = expr.trim();
+    while !rest.is_empty() {
+        let term_len = rest.find(['+', '-']).unwrap_or(rest.len());
+        let term = rest[..term_len].trim();
+        if !term.is_empty() {
+            total += sign * term_value(term, known)? as i64;
+        }
+        rest = &rest[term_len..];
+        if let Some(op) = rest.chars().next() {
+            sign = if op == '-' { -1 } else { 1 };
+            rest = rest[1..].trim_start();
+        }
+    }
+    u32::try_from(total).ok()
+}
+
+fn term_value(term: &str, known: &[MemoryRegionOwned]) -> Option<u32> {
+    let region = |name: &str| known.iter().find(|r| r.name == name.trim());
+    if let Some(name) = term
+        .strip_prefix("ORIGIN(")
+        .and_then(|t| t.strip_suffix(')'))
+    {
+        return region(name).map(|r| r.origin);
+    }
+    if let Some(name) = term
+        .strip_prefix("LENGTH(")
+        .and_then(|t| t.strip_suffix(')'))
+    {
+        return region(name).map(|r| r.length);
+    }
+    let (digits, multiplier) = match term.as_bytes().last()? {
+        b'K' | b'k' => (&term[..term.len() - 1], 1024),
+        b'M' | b'm' => (&term[..term.len() - 1], 1024 * 1024),
+        _ => (term, 1),
+    };
+    let digits = digits.replace('_', "");
+    let value = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => digits.parse().ok()?,
+    };
+    value.checked_mul(multiplier)
+}
+
+fn region_kind(name: &str, origin: u32, hints: &[(String, String)]) -> RegionKind {
+    if let Some((_, kind)) = hints.iter().find(|(n, _)| n == name) {
+        match kind.as_str() {
+            "flash" | "nvm" => return RegionKind::Flash,
+            "ram" => return RegionKind::Ram,
+            "reg" => return RegionKind::Register,
+            _ => {}
+        }
+    }
+    if name.contains("RAM") || name.contains("TCM") {
+        RegionKind::Ram
+    } else if name.contains("FLASH") || name.starts_with("BOOTLOADER") || name == "CONFIG" {
+        RegionKind::Flash
+    } else if name.ends_with("_REGS") {
+        RegionKind::Register
+    } else if origin < 0x2000_0000 {
+        RegionKind::Flash
+    } else {
+        RegionKind::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_memory_x() {
+        let script = "MEMORY
+{
+  /* FLASH and RAM are mandatory memory regions */
+
+  /* BOOTLOADER (flash) */
+  BOOTLOADER : ORIGIN = 0x8000000, LENGTH = 24K
+
+  /* FLASH (flash) */
+  FLASH : ORIGIN = 0x8006000, LENGTH = 0x2000 + 96K
+
+  /* AXISRAM (ram) */
+  /* AXISRAM : ORIGIN = 0x24000000, LENGTH = 512K */
+  RAM : ORIGIN = 0x24000000, LENGTH = 512K
+
+  /* TAMP_BKP_REGS (reg) */
+  TAMP_BKP_REGS : ORIGIN = 0x58004100, LENGTH = 128
+}
+
+/* Helper constants to access FLASH regions */
+__bootloader_start = ORIGIN(BOOTLOADER);
+__bootloader_end = ORIGIN(BOOTLOADER) + LENGTH(BOOTLOADER);
+__config_start = ORIGIN(FLASH) + LENGTH(FLASH);
+__config_end = ORIGIN(FLASH) + LENGTH(FLASH) + 8K;
+";
+        let (regions, skipped) = parse_memory_x(script);
+        assert!(skipped.is_empty(), "{skipped:?}");
+        let summary: Vec<_> = regions
+            .iter()
+            .map(|r| (r.name.as_str(), r.kind, r.origin, r.length))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("BOOTLOADER", RegionKind::Flash, 0x0800_0000, 24 * 1024),
+                ("FLASH", RegionKind::Flash, 0x0800_6000, 104 * 1024),
+                ("RAM", RegionKind::Ram, 0x2400_0000, 512 * 1024),
+                ("TAMP_BKP_REGS", RegionKind::Register, 0x5800_4100, 128),
+                ("CONFIG", RegionKind::Flash, 0x0802_0000, 8 * 1024),
+            ]
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! Crate licenses and sources from `cargo metadata`, for SBOM generation on the host.
+
+use bedrock_build_info::{CrateInfoOwned, CrateSourceOwned};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+const CRATES_IO_INDEX: [&str; 2] = [
+    "registry+https://github.com/rust-lang/crates.io-index",
+    "sparse+https://index.crates.io/",
+];
+
+#[derive(Default)]
+struct PackageFacts {
+    license: Option<String>,
+    source: Option<CrateSourceOwned>,
+}
+
+/// Fill in license and source of the crate and all of its dependencies.
+///
+/// Failure to run `cargo metadata` is reported as a warning, build info is left without sources in that case.
+pub(crate) fn add_licenses_and_sources(info: &mut CrateInfoOwned) {
+    match package_facts() {
+        Ok(facts) => fill(info, &facts),
+        Err(reason) => println!(
+            "cargo::warning=bedrock_build: crate sources are not recorded in build info, cargo metadata failed: {reason}"
+        ),
+    }
+}
+
+fn fill(info: &mut CrateInfoOwned, facts: &HashMap<(String, String), PackageFacts>) {
+    let version = &info.version;
+    let key = (
+        info.name.clone(),
+        format!(
+            "{}.{}.{}",
+            version.major.0, version.minor.0, version.patch.0
+        ),
+    );
+    if let Some(package) = facts.get(&key) {
+        let ext = info.ext_mut();
+        if ext.license.is_none() {
+            ext.license = package.license.clone();
+        }
+        ext.source = package.source.clone();
+    }
+    for dep in &mut info.dependencies {
+        fill(dep, facts);
+    }
+}
+
+fn package_facts() -> Result<HashMap<(String, String), PackageFacts>, String> {
+    let cargo = env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let mut cmd = Command::new(cargo);
+    cmd.args(["metadata", "--format-version", "1"]);
+    if let Some(dir) = env::var_os("CARGO_MANIFEST_DIR") {
+        cmd.arg("--manifest-path")
+            .arg(Path::new(&dir).join("Cargo.toml"));
+    }
+    let output = cmd.output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    let metadata: Value = serde_json::from_slice(&output.stdout).map_err(|e| e.to_string())?;
+    let workspace_root = metadata["workspace_root"].as_str().unwrap_or_default();
+    let packages = metadata["packages"]
+        .as_array()
+        .ok_or("no packages in cargo metadata output")?;
+
+    let mut facts = HashMap::new();
+    for package in packages {
+        let (Some(name), Some(version)) = (package["name"].as_str(), package["version"].as_str())
+        else {
+            continue;
+        };
+        // build info versions do not carry pre-release and build metadata
+        let version = version
+            .split(['-', '+'])
+            .next()
+            .unwrap_or(version)
+            .to_string();
+        let source = match package["source"].as_str() {
+            Some(source) => Some(parse_source(source)),
+            None => package["manifest_path"]
+                .as_str()
+                .and_then(|manifest| Path::new(manifest).parent())
+                .map(|dir| {
+                    let dir = dir.strip_prefix(workspace_root).unwrap_or(dir);
+                    CrateSourceOwned::Path(dir.display().to_string())
+                }),
+        };
+        facts.insert(
+            (name.to_string(), version),
+            PackageFacts {
+                license: package["license"].as_str().map(|l| l.to_string()),
+                source,
+            },
+        );
+    }
+    Ok(facts)
+}
+
+/// `registry+URL`, `sparse+URL` or `git+URL?branch=main#commit`
+fn parse_source(source: &str) -> CrateSourceOwned {
+    if CRATES_IO_INDEX.contains(&source) {
+        return CrateSourceOwned::CratesIo;
+    }
+    if let Some(git) = source.strip_prefix("git+") {
+        let url = git.split(['?', '#']).next().unwrap_or(git);
+        return match git.rsplit_once('#') {
+            Some((_, commit)) => CrateSourceOwned::Git(format!("{url}#{commit}")),
+            None => CrateSourceOwned::Git(url.to_string()),
+        };
+    }
+    let index = source
+        .strip_prefix("registry+")
+        .or_else(|| source.strip_prefix("sparse+"))
+        .unwrap_or(source);
+    CrateSourceOwned::Registry(index.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crates_io() {
+        for source in CRATES_IO_INDEX {
+            assert_eq!(parse_source(source), CrateSourceOwned::CratesIo);
+        }
+    }
+
+    #[test]
+    fn git_keeps_commit_drops_query() {
+        assert_eq!(
+            parse_source("git+https://github.com/embassy-rs/embassy?branch=main#0123abcd"),
+            CrateSourceOwned::Git("https://github.com/embassy-rs/embassy#0123abcd".into())
+        );
+        assert_eq!(
+            parse_source("git+https://github.com/embassy-rs/embassy"),
+            CrateSourceOwned::Git("https://github.com/embassy-rs/embassy".into())
+        );
+    }
+
+    #[test]
+    fn other_registry() {
+        assert_eq!(
+            parse_source("sparse+https://crates.example.com/index/"),
+            CrateSourceOwned::Registry("https://crates.example.com/index/".into())
+        );
+        assert_eq!(
+            parse_source("registry+https://example.com/index"),
+            CrateSourceOwned::Registry("https://example.com/index".into())
+        );
+    }
+}
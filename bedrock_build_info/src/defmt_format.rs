@@ -0,0 +1,186 @@
+//! [defmt::Format] implementations, so that firmware can log its own build info, for example at boot:
+//! ```ignore
+//! if let Ok(info) = BedrockBuildInfo::from_compact(build_info::compact()) {
+//!     defmt::info!("{}", info);
+//! }
+//! ```
+
+use crate::{
+    BedrockBuildInfo, CompilerInfo, CrateInfo, ExtraValue, KeyValue, LinkMode, MemoryRegion,
+    TargetInfo, VersionControl,
+};
+use defmt::{Format, Formatter, write};
+use ww_version::Version;
+
+struct FmtVersion<'a, 'i>(&'a Version<'i>);
+
+impl Format for FmtVersion<'_, '_> {
+    fn format(&self, f: Formatter) {
+        let v = self.0;
+        write!(f, "{}.{}.{}", v.major.0, v.minor.0, v.patch.0);
+        if let Some(pre) = v.pre {
+            write!(f, "-{=str}", pre);
+        }
+        if let Some(build) = v.build {
+            write!(f, "+{=str}", build);
+        }
+    }
+}
+
+impl Format for BedrockBuildInfo<'_> {
+    fn format(&self, f: Formatter) {
+        write!(f, "{}", self.crate_info);
+        if let Some(vc) = &self.version_control {
+            write!(f, " {}", vc);
+        }
+        write!(f, " {} {}", self.profile, self.optimization_level);
+        if let Some(name) = self.ext.as_ref().and_then(|ext| ext.profile_name) {
+            write!(f, " ({=str})", name);
+        }
+        write!(f, " {} {}", self.target_info, self.compiler_info);
+        for kv in self.ext.iter().flat_map(|ext| ext.extra.iter().flatten()) {
+            write!(f, " {}", kv);
+        }
+    }
+}
+
+impl Format for CrateInfo<'_> {
+    fn format(&self, f: Formatter) {
+        write!(f, "{=str} v{}", self.name, FmtVersion(&self.version));
+        let mut features = self.enabled_features.iter().flatten().peekable();
+        if features.peek().is_some() {
+            write!(f, " [");
+            for (i, feature) in features.enumerate() {
+                if i > 0 {
+                    write!(f, ", ");
+                }
+                write!(f, "{=str}", feature);
+            }
+            write!(f, "]");
+        }
+    }
+}
+
+impl Format for TargetInfo<'_> {
+    fn format(&self, f: Formatter) {
+        match self.triple {
+            Some(triple) => write!(f, "{=str}", triple),
+            None => write!(f, "?"),
+        }
+    }
+}
+
+impl Format for CompilerInfo<'_> {
+    fn format(&self, f: Formatter) {
+        write!(f, "rustc {} {}", FmtVersion(&self.version), self.channel);
+        if self.flip_link {
+            write!(f, " flip-link");
+        }
+        if let Some(ext) = &self.ext {
+            if ext.link_mode == Some(LinkMode::Ram) {
+                write!(f, " RAM-linked");
+            }
+            if let Some(cpu) = ext.target_cpu {
+                write!(f, " cpu={=str}", cpu);
+            }
+        }
+    }
+}
+
+impl Format for VersionControl<'_> {
+    fn format(&self, f: Formatter) {
+        match self.commit_short_id.or(self.commit_id) {
+            Some(id) => write!(f, "{=str}", id),
+            None => write!(f, "?"),
+        }
+        if self.dirty {
+            write!(f, "-dirty");
+        }
+        if let Some(branch) = self.branch {
+            write!(f, " ({=str})", branch);
+        }
+    }
+}
+
+impl Format for KeyValue<'_> {
+    fn format(&self, f: Formatter) {
+        write!(f, "{=str}=", self.key);
+        match &self.value {
+            ExtraValue::Str(s) => write!(f, "{=str}", s),
+            ExtraValue::U32(n) => write!(f, "{}", n),
+            ExtraValue::Bool(b) => write!(f, "{}", b),
+            ExtraValue::Version(v) => write!(f, "{}", FmtVersion(v)),
+        }
+    }
+}
+
+impl Format for MemoryRegion<'_> {
+    fn format(&self, f: Formatter) {
+        write!(
+            f,
+            "{=str} {} 0x{:08x}..0x{:08x}",
+            self.name,
+            self.kind,
+            self.origin,
+            self.end()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::v1_build_info;
+
+    /// Logged bytes: format string indices and arguments, strings are sent as they are
+    fn logged(value: &impl Format) -> Vec<u8> {
+        defmt::export::fetch_bytes();
+        value.format(defmt::export::make_formatter());
+        defmt::export::fetch_bytes()
+    }
+
+    /// Whether the strings were logged in this order
+    fn logged_in_order(bytes: &[u8], strings: &[&str]) -> bool {
+        let mut rest = bytes;
+        strings.iter().all(
+            |s| match rest.windows(s.len()).position(|w| w == s.as_bytes()) {
+                Some(at) => {
+                    rest = &rest[at + s.len()..];
+                    true
+                }
+                None => false,
+            },
+        )
+    }
+
+    #[test]
+    fn build_info() {
+        let bytes = logged(&v1_build_info());
+        assert!(logged_in_order(
+            &bytes,
+            &["awesome", "f_a", "f_b", "abc", "xyz"]
+        ));
+    }
+
+    #[test]
+    fn version_with_pre_release_and_build() {
+        let mut version = Version::new(1, 0, 0);
+        let plain = logged(&FmtVersion(&version));
+        version.pre = Some("rc.1");
+        version.build = Some("zstd.1.5.0");
+        let bytes = logged(&FmtVersion(&version));
+        assert!(logged_in_order(&bytes, &["rc.1", "zstd.1.5.0"]));
+        assert!(!logged_in_order(&plain, &["rc.1"]));
+    }
+
+    #[test]
+    fn short_commit_id_is_preferred() {
+        let mut vc = v1_build_info().version_control.unwrap();
+        vc.commit_id = Some("0123456789abcdef0123");
+        vc.commit_short_id = Some("0123456");
+        vc.branch = Some("main");
+        let bytes = logged(&vc);
+        assert!(logged_in_order(&bytes, &["0123456", "main"]));
+        assert!(!logged_in_order(&bytes, &["0123456789abcdef0123"]));
+    }
+}
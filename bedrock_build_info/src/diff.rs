@@ -0,0 +1,160 @@
+//! Comparison of two build infos, ignoring volatile fields like build timestamp.
+
+use crate::display::FmtVersion;
+use crate::{BedrockBuildInfoOwned, RegionKind, VersionControlOwned};
+use std::fmt::{Display, Formatter};
+use ww_version::VersionOwned;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub field: &'static str,
+    pub left: String,
+    pub right: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildInfoDiff {
+    pub changes: Vec<FieldDiff>,
+}
+
+impl BuildInfoDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    fn compare(&mut self, field: &'static str, left: String, right: String) {
+        if left != right {
+            self.changes.push(FieldDiff { field, left, right });
+        }
+    }
+}
+
+impl BedrockBuildInfoOwned {
+    /// Report differences in crate version, commit, dirty flag, features, profile, opt level, compiler, linking, target
+    /// and FLASH layout.
+    ///
+    /// Fields absent on either side (e.g. pruned from compact info) are not compared,
+    /// full commit ID is only compared if both sides have it, otherwise short ID is used.
+    pub fn diff(&self, other: &BedrockBuildInfoOwned) -> BuildInfoDiff {
+        let mut diff = BuildInfoDiff::default();
+        diff.compare(
+            "crate",
+            self.crate_info.name.clone(),
+            other.crate_info.name.clone(),
+        );
+        diff.compare(
+            "version",
+            version(&self.crate_info.version),
+            version(&other.crate_info.version),
+        );
+        match (&self.version_control, &other.version_control) {
+            (Some(a), Some(b)) => {
+                let (a_id, b_id) = match (&a.commit_id, &b.commit_id) {
+                    (Some(a_id), Some(b_id)) => (Some(a_id), Some(b_id)),
+                    _ => (a.commit_short_id.as_ref(), b.commit_short_id.as_ref()),
+                };
+                if let (Some(a_id), Some(b_id)) = (a_id, b_id) {
+                    diff.compare("commit", a_id.clone(), b_id.clone());
+                }
+                diff.compare("dirty", a.dirty.to_string(), b.dirty.to_string());
+            }
+            (a, b) => diff.compare("version control", has_vc(a), has_vc(b)),
+        }
+        let features = |info: &BedrockBuildInfoOwned| {
+            let mut features = info.crate_info.enabled_features.clone();
+            features.sort();
+            features.join(", ")
+        };
+        diff.compare("features", features(self), features(other));
+        let profile = |info: &BedrockBuildInfoOwned| match info.profile_name() {
+            Some(name) => format!("{:?} ({name})", info.profile),
+            None => format!("{:?}", info.profile),
+        };
+        diff.compare("profile", profile(self), profile(other));
+        diff.compare(
+            "opt level",
+            format!("{:?}", self.optimization_level),
+            format!("{:?}", other.optimization_level),
+        );
+        let compiler = |info: &BedrockBuildInfoOwned| {
+            format!(
+                "{} {:?}",
+                version(&info.compiler_info.version),
+                info.compiler_info.channel
+            )
+        };
+        diff.compare("compiler", compiler(self), compiler(other));
+        diff.compare(
+            "flip-link",
+            self.compiler_info.flip_link.to_string(),
+            other.compiler_info.flip_link.to_string(),
+        );
+        if let (Some(a), Some(b)) = (
+            self.compiler_info.link_mode(),
+            other.compiler_info.link_mode(),
+        ) {
+            diff.compare("link mode", format!("{a:?}"), format!("{b:?}"));
+        }
+        if let (Some(a), Some(b)) = (&self.target_info.triple, &other.target_info.triple) {
+            diff.compare("target", a.clone(), b.clone());
+        }
+        if !self.memory_map().is_empty() && !other.memory_map().is_empty() {
+            diff.compare("flash layout", flash_layout(self), flash_layout(other));
+        }
+        diff
+    }
+}
+
+/// SemVer with pre-release and build metadata, `1.0.0-rc.1` and `1.0.0` are different builds
+fn version(v: &VersionOwned) -> String {
+    FmtVersion(v).to_string()
+}
+
+/// FLASH regions only, RAM regions are usually not present in compact info
+fn flash_layout(info: &BedrockBuildInfoOwned) -> String {
+    info.memory_map()
+        .iter()
+        .filter(|r| r.kind == RegionKind::Flash)
+        .map(|r| format!("{}@0x{:08x}+{}K", r.name, r.origin, r.length / 1024))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn has_vc(vc: &Option<VersionControlOwned>) -> String {
+    if vc.is_some() { "present" } else { "absent" }.to_string()
+}
+
+impl Display for BuildInfoDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let width = self
+            .changes
+            .iter()
+            .map(|c| c.field.len())
+            .max()
+            .unwrap_or(0);
+        for (i, c) in self.changes.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{:width$}: {} != {}", c.field, c.left, c.right)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_with_pre_release_and_build() {
+        let mut v = VersionOwned::new(1, 0, 0);
+        assert_eq!(version(&v), "1.0.0");
+        v.pre = Some("rc.1".into());
+        assert_eq!(version(&v), "1.0.0-rc.1");
+        v.build = Some("zstd.1.5.0".into());
+        assert_eq!(version(&v), "1.0.0-rc.1+zstd.1.5.0");
+        v.pre = None;
+        assert_eq!(version(&v), "1.0.0+zstd.1.5.0");
+    }
+}
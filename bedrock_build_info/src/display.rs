@@ -0,0 +1,202 @@
+//! Human readable representation of the owned build info for host tools.
+
+use crate::{
+    BedrockBuildInfoOwned, CompilerInfoOwned, CrateInfoOwned, ExtraValueOwned, LinkMode,
+    MemoryRegionOwned, TargetInfoOwned, VersionControlOwned,
+};
+use std::fmt::{Display, Formatter, Result};
+use ww_version::VersionOwned;
+
+/// SemVer with pre-release and build metadata
+pub(crate) struct FmtVersion<'a>(pub &'a VersionOwned);
+
+impl Display for FmtVersion<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(
+            f,
+            "{}.{}.{}",
+            self.0.major.0, self.0.minor.0, self.0.patch.0
+        )?;
+        if let Some(pre) = &self.0.pre {
+            write!(f, "-{pre}")?;
+        }
+        if let Some(build) = &self.0.build {
+            write!(f, "+{build}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for BedrockBuildInfoOwned {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, "{}", self.crate_info)?;
+        writeln!(f, "  built:    {:?}", self.timestamp)?;
+        write!(
+            f,
+            "  profile:  {:?} {:?}",
+            self.profile, self.optimization_level
+        )?;
+        if let Some(name) = self.profile_name() {
+            write!(f, " ({name})")?;
+        }
+        writeln!(f)?;
+        if let Some(vc) = &self.version_control {
+            writeln!(f, "  commit:   {vc}")?;
+        }
+        writeln!(f, "  target:   {}", self.target_info)?;
+        write!(f, "  compiler: {}", self.compiler_info)?;
+        for kv in self.extra() {
+            write!(f, "\n  {}: {}", kv.key, kv.value)?;
+        }
+        if !self.memory_map().is_empty() {
+            write!(f, "\n  memory:")?;
+            for region in self.memory_map() {
+                write!(f, "\n    {region}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for CrateInfoOwned {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{} v{}", self.name, FmtVersion(&self.version))?;
+        if !self.enabled_features.is_empty() {
+            write!(f, " [{}]", self.enabled_features.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for TargetInfoOwned {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}", self.triple.as_deref().unwrap_or("?"))?;
+        if let Some(arch) = &self.arch {
+            write!(f, " ({arch})")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for CompilerInfoOwned {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "rustc {} {:?}", FmtVersion(&self.version), self.channel)?;
+        if let Some(date) = &self.commit_date {
+            write!(f, " ({date:?})")?;
+        }
+        if self.flip_link {
+            write!(f, ", flip-link")?;
+        } else if let Some(linker) = self.linker() {
+            write!(f, ", linker {linker}")?;
+        }
+        if self.link_mode() == Some(LinkMode::Ram) {
+            write!(f, ", RAM linked")?;
+        }
+        if let Some(cpu) = self.target_cpu() {
+            write!(f, ", cpu {cpu}")?;
+        }
+        if !self.target_features().is_empty() {
+            write!(f, ", features {}", self.target_features().join(","))?;
+        }
+        if !self.build_std().is_empty() {
+            write!(f, ", build-std {}", self.build_std().join(","))?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for VersionControlOwned {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let id = self.commit_id.as_ref().or(self.commit_short_id.as_ref());
+        write!(f, "{}", id.map(|id| id.as_str()).unwrap_or("?"))?;
+        if self.dirty {
+            write!(f, "-dirty")?;
+        }
+        if let Some(branch) = &self.branch {
+            write!(f, " ({branch})")?;
+        }
+        if !self.tags.is_empty() {
+            write!(f, " [{}]", self.tags.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for ExtraValueOwned {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            ExtraValueOwned::Str(s) => write!(f, "{s}"),
+            ExtraValueOwned::U32(n) => write!(f, "{n}"),
+            ExtraValueOwned::Bool(b) => write!(f, "{b}"),
+            ExtraValueOwned::Version(v) => write!(f, "{}", FmtVersion(v)),
+        }
+    }
+}
+
+impl Display for MemoryRegionOwned {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(
+            f,
+            "{:<18} {:<8} 0x{:08x}..0x{:08x} ({} KiB)",
+            self.name,
+            format!("{:?}", self.kind),
+            self.origin,
+            self.end(),
+            self.length / 1024
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{v1_build_info, v1_ext_build_info};
+
+    #[test]
+    fn build_info() {
+        let mut info = v1_ext_build_info();
+        // dates are shown as their Debug representation, which belongs to ww_date_time
+        info.compiler_info.commit_date = None;
+        let text = info.to_string();
+        let lines: Vec<_> = text
+            .lines()
+            .filter(|l| !l.starts_with("  built:"))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "awesome v0.1.2 [f_a, f_b]",
+                "  profile:  Release O2 (release-lto)",
+                "  commit:   abc-dirty",
+                "  target:   xyz (arm)",
+                "  compiler: rustc 1.87.0 Nightly, linker flip-link, cpu cortex-m4, features +fp-armv8d16sp, build-std core",
+                "  hw_rev: C",
+                "  memory:",
+                "    FLASH              Flash    0x08000000..0x08040000 (256 KiB)",
+            ]
+        );
+    }
+
+    #[test]
+    fn optional_parts() {
+        let mut info = v1_build_info().make_owned();
+        info.crate_info.enabled_features.clear();
+        info.crate_info.version.pre = Some("rc.1".into());
+        assert_eq!(info.crate_info.to_string(), "awesome v0.1.2-rc.1");
+        info.target_info.arch = None;
+        assert_eq!(info.target_info.to_string(), "xyz");
+
+        let vc = info.version_control.as_mut().unwrap();
+        vc.dirty = false;
+        vc.commit_id = Some("0123456789abcdef".into());
+        vc.branch = Some("main".into());
+        vc.tags = vec!["v0.1.2".into(), "latest".into()];
+        assert_eq!(vc.to_string(), "0123456789abcdef (main) [v0.1.2, latest]");
+
+        info.compiler_info.commit_date = None;
+        info.compiler_info.flip_link = true;
+        assert_eq!(
+            info.compiler_info.to_string(),
+            "rustc 1.87.0 Nightly, flip-link"
+        );
+    }
+}
@@ -0,0 +1,190 @@
+//! Framing of the compact build info record as it is stored in MCU FLASH.
+//!
+//! Two layouts exist in the field:
+//! * Legacy (unversioned): `magic | len: u16 LE | crc: u32 LE | payload`, crc covers the payload only.
+//! * Versioned: `magic | version: u8 | flags: u8 | len: u16 LE | crc: u32 LE | payload`,
+//!   crc covers version, flags, len and the payload.
+//!
+//! Layouts are told apart by the magic value, so old firmwares stay decodable by new host tools.
+//! Payload `version` describes [BedrockBuildInfo](crate::BedrockBuildInfo) serialization format,
+//! legacy records are always version 1.
+//!
+//! Adding fields does not change the version. Each struct ends with a single `#[default = None] ext` field holding
+//! everything added after the first release: it is read as None from older records, and older readers skip it,
+//! since nested structs are length delimited and trailing bytes are ignored. New fields go into the `ext` structs,
+//! once those are released, again through a trailing `#[default = None]` field. shrink_wrap only allows one such
+//! field per struct. `fixtures/` keeps one record per released layout.
+
+#[cfg(feature = "std")]
+use crate::{BedrockBuildInfo, build_info_crc};
+#[cfg(feature = "std")]
+use wire_weaver::prelude::*;
+
+/// Marker of the legacy, unversioned record
+pub const COMPACT_INFO_MAGIC: u32 = 0xB17D_14F0;
+/// Marker of the versioned record
+pub const COMPACT_INFO_MAGIC_VERSIONED: u32 = 0xB17D_14F1;
+
+/// Current payload format version, bump on any incompatible change to [BedrockBuildInfo](crate::BedrockBuildInfo),
+/// but not when fields are added as described above
+pub const COMPACT_INFO_VERSION: u8 = 1;
+
+/// magic, len, crc
+pub const LEGACY_HEADER_LEN: usize = 4 + 2 + 4;
+/// magic, version, flags, len, crc
+pub const VERSIONED_HEADER_LEN: usize = 4 + 1 + 1 + 2 + 4;
+
+/// Bit flags stored in the versioned header
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct EnvelopeFlags(pub u8);
+
+impl EnvelopeFlags {
+    /// Payload has some of the fields omitted to save space (as opposed to full build info)
+    pub const PRUNED: u8 = 1 << 0;
+
+    pub fn is_pruned(&self) -> bool {
+        self.0 & Self::PRUNED != 0
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EnvelopeLayout {
+    Legacy,
+    Versioned,
+}
+
+impl EnvelopeLayout {
+    /// Determine layout from the first 4 bytes of a record
+    pub fn from_magic(magic: [u8; 4]) -> Option<Self> {
+        match u32::from_be_bytes(magic) {
+            COMPACT_INFO_MAGIC => Some(EnvelopeLayout::Legacy),
+            COMPACT_INFO_MAGIC_VERSIONED => Some(EnvelopeLayout::Versioned),
+            _ => None,
+        }
+    }
+
+    pub fn magic(&self) -> u32 {
+        match self {
+            EnvelopeLayout::Legacy => COMPACT_INFO_MAGIC,
+            EnvelopeLayout::Versioned => COMPACT_INFO_MAGIC_VERSIONED,
+        }
+    }
+
+    pub fn header_len(&self) -> usize {
+        match self {
+            EnvelopeLayout::Legacy => LEGACY_HEADER_LEN,
+            EnvelopeLayout::Versioned => VERSIONED_HEADER_LEN,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum EnvelopeError {
+    /// Bytes do not start with any of the known magic values
+    BadMagic,
+    /// At least this many bytes (counting from magic) are needed to continue
+    Truncated {
+        needed: usize,
+    },
+    CrcMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    /// Record was produced by a newer bedrock_build, update host tools
+    UnsupportedVersion(u8),
+    /// Header is fine, but payload could not be deserialized
+    Malformed,
+}
+
+/// Compact build info record located in FLASH, with header fields decoded and CRC checked
+#[derive(Debug, PartialEq, Eq)]
+pub struct CompactEnvelope<'i> {
+    pub layout: EnvelopeLayout,
+    pub version: u8,
+    pub flags: EnvelopeFlags,
+    pub payload: &'i [u8],
+}
+
+#[cfg(feature = "std")]
+impl<'i> CompactEnvelope<'i> {
+    /// Parse a record, `bytes` must start at the magic value, trailing bytes are ignored.
+    pub fn parse(bytes: &'i [u8]) -> Result<Self, EnvelopeError> {
+        if bytes.len() < 4 {
+            return Err(EnvelopeError::Truncated { needed: 4 });
+        }
+        let layout = EnvelopeLayout::from_magic([bytes[0], bytes[1], bytes[2], bytes[3]])
+            .ok_or(EnvelopeError::BadMagic)?;
+        let header_len = layout.header_len();
+        if bytes.len() < header_len {
+            return Err(EnvelopeError::Truncated { needed: header_len });
+        }
+        let (version, flags, len_at) = match layout {
+            EnvelopeLayout::Legacy => (1, EnvelopeFlags(EnvelopeFlags::PRUNED), 4),
+            EnvelopeLayout::Versioned => (bytes[4], EnvelopeFlags(bytes[5]), 6),
+        };
+        let len = u16::from_le_bytes([bytes[len_at], bytes[len_at + 1]]) as usize;
+        let crc_at = len_at + 2;
+        let expected = u32::from_le_bytes([
+            bytes[crc_at],
+            bytes[crc_at + 1],
+            bytes[crc_at + 2],
+            bytes[crc_at + 3],
+        ]);
+        let needed = header_len + len;
+        if bytes.len() < needed {
+            return Err(EnvelopeError::Truncated { needed });
+        }
+        let payload = &bytes[header_len..needed];
+        let actual = match layout {
+            EnvelopeLayout::Legacy => build_info_crc(payload),
+            EnvelopeLayout::Versioned => {
+                let mut covered = bytes[4..crc_at].to_vec();
+                covered.extend_from_slice(payload);
+                build_info_crc(&covered)
+            }
+        };
+        if actual != expected {
+            return Err(EnvelopeError::CrcMismatch { expected, actual });
+        }
+        Ok(CompactEnvelope {
+            layout,
+            version,
+            flags,
+            payload,
+        })
+    }
+
+    /// Total length of the record in FLASH, including header
+    pub fn total_len(&self) -> usize {
+        self.layout.header_len() + self.payload.len()
+    }
+
+    /// Deserialize payload according to its format version
+    pub fn decode(&self) -> Result<BedrockBuildInfo<'i>, EnvelopeError> {
+        match self.version {
+            1 => {
+                BedrockBuildInfo::from_ww_bytes(self.payload).map_err(|_| EnvelopeError::Malformed)
+            }
+            v => Err(EnvelopeError::UnsupportedVersion(v)),
+        }
+    }
+}
+
+/// Wrap serialized build info into a versioned record.
+#[cfg(feature = "std")]
+pub fn encode(payload: &[u8], flags: EnvelopeFlags) -> Vec<u8> {
+    let len = u16::try_from(payload.len()).expect("Compact info should definitely fit into 65K");
+    let mut covered = Vec::with_capacity(4 + payload.len());
+    covered.push(COMPACT_INFO_VERSION);
+    covered.push(flags.0);
+    covered.extend_from_slice(&len.to_le_bytes());
+    covered.extend_from_slice(payload);
+    let crc = build_info_crc(&covered);
+
+    let mut record = Vec::with_capacity(VERSIONED_HEADER_LEN + payload.len());
+    record.extend_from_slice(&COMPACT_INFO_MAGIC_VERSIONED.to_be_bytes());
+    record.extend_from_slice(&covered[..4]);
+    record.extend_from_slice(&crc.to_le_bytes());
+    record.extend_from_slice(payload);
+    record
+}
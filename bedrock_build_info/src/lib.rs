@@ -1,5 +1,17 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
+#[cfg(feature = "defmt-extended")]
+mod defmt_format;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "std")]
+mod display;
+pub mod envelope;
+pub mod server;
+#[cfg(feature = "signature")]
+pub mod signature;
+#[cfg(feature = "tracing-extended")]
+mod tracing_ext;
 pub mod traits;
 
 use wire_weaver::prelude::*;
@@ -8,7 +20,7 @@ use ww_version::Version;
 #[cfg(feature = "std")]
 use ww_version::VersionOwned;
 
-pub const COMPACT_INFO_MAGIC: u32 = 0xB17D_14F0;
+pub use envelope::{COMPACT_INFO_MAGIC, COMPACT_INFO_MAGIC_VERSIONED};
 
 #[cfg(feature = "std")]
 pub fn build_info_crc(bytes: &[u8]) -> u32 {
@@ -19,7 +31,7 @@ pub fn build_info_crc(bytes: &[u8]) -> u32 {
 }
 
 #[derive_shrink_wrap]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 #[shrink_wrap(no_alloc)]
 #[owned = "std"]
 pub struct BedrockBuildInfo<'i> {
@@ -33,19 +45,45 @@ pub struct BedrockBuildInfo<'i> {
     pub target_info: TargetInfo<'i>,
     pub compiler_info: CompilerInfo<'i>,
     pub version_control: Option<VersionControl<'i>>,
+    /// Fields added after the first release, None in older records, see [envelope] on format evolution.
+    #[default = None]
+    pub ext: Option<BuildInfoExt<'i>>,
+}
+
+#[derive_shrink_wrap]
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[shrink_wrap(no_alloc)]
+#[owned = "std"]
+pub struct BuildInfoExt<'i> {
+    /// User-defined fields added from build.rs, e.g. hardware revision or product ID.
+    pub extra: RefVec<'i, KeyValue<'i>>,
+    /// Name of a custom cargo profile (e.g. `release-lto`), [Profile] is the built-in one it inherits from.
+    /// Readers from before custom profiles were recorded see that one.
+    pub profile_name: Option<&'i str>,
+    /// Identity of the build contents, computed without volatile fields (build timestamp),
+    /// equal for two builds of the same sources with the same configuration.
+    /// See [BedrockBuildInfoOwned::compute_content_id].
+    pub content_id: Option<u64>,
+    /// Linker memory regions (FLASH, RAM, bootloader and CONFIG partitions) from `memory.x`,
+    /// compact info usually only carries FLASH regions.
+    pub memory_map: RefVec<'i, MemoryRegion<'i>>,
 }
 
 #[derive_shrink_wrap]
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-extended", derive(defmt::Format))]
 #[ww_repr(u2)]
 #[sized]
 pub enum Profile {
+    /// `release` or a custom profile inheriting from it, named in [BuildInfoExt::profile_name]
     Release,
+    /// `dev` or a custom profile inheriting from it
     Debug,
 }
 
 #[derive_shrink_wrap]
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-extended", derive(defmt::Format))]
 #[ww_repr(u3)]
 #[sized]
 pub enum OptimizationLevel {
@@ -67,10 +105,40 @@ pub struct CrateInfo<'i> {
     pub authors: RefVec<'i, &'i str>,
     pub enabled_features: RefVec<'i, &'i str>,
     pub dependencies: RefVec<'i, CrateInfo<'i>>,
+    /// Fields added after the first release, None in older records and when all of them are empty.
+    #[default = None]
+    pub ext: Option<CrateInfoExt<'i>>,
+}
+
+#[derive_shrink_wrap]
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[shrink_wrap(no_alloc)]
+#[owned = "std"]
+pub struct CrateInfoExt<'i> {
+    /// SPDX license expression from `Cargo.toml`, only in full info by default.
+    pub license: Option<&'i str>,
+    /// Where the crate came from, only recorded for dependencies in full info by default.
+    pub source: Option<CrateSource<'i>>,
 }
 
+/// Origin of a crate, as reported by `cargo metadata`
 #[derive_shrink_wrap]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[ww_repr(u2)]
+#[shrink_wrap(no_alloc)]
+#[owned = "std"]
+pub enum CrateSource<'i> {
+    CratesIo,
+    /// Alternative registry index URL
+    Registry(&'i str),
+    /// Repository URL and commit, `https://github.com/org/repo#0123abcd`
+    Git(&'i str),
+    /// Local path, relative to the workspace root if inside of it
+    Path(&'i str),
+}
+
+#[derive_shrink_wrap]
+#[derive(Debug, PartialEq, Eq, Clone)]
 #[shrink_wrap(no_alloc)]
 #[owned = "std"]
 pub struct TargetInfo<'i> {
@@ -81,7 +149,7 @@ pub struct TargetInfo<'i> {
 }
 
 #[derive_shrink_wrap]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 #[shrink_wrap(no_alloc)]
 #[owned = "std"]
 pub struct CompilerInfo<'i> {
@@ -90,11 +158,44 @@ pub struct CompilerInfo<'i> {
     pub host_triple: Option<&'i str>,
     /// Compiler build time
     pub commit_date: Option<NaiveDate>,
+    /// Firmware was linked with flip-link (detected from the actual linker since `link_mode` was introduced)
     pub flip_link: bool,
+    /// Fields added after the first release, None in older records and when all of them are empty.
+    #[default = None]
+    pub ext: Option<CompilerInfoExt<'i>>,
+}
+
+#[derive_shrink_wrap]
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[shrink_wrap(no_alloc)]
+#[owned = "std"]
+pub struct CompilerInfoExt<'i> {
+    /// Linker set through `-C linker`, `CARGO_TARGET_<triple>_LINKER` or `.cargo/config.toml`, None if default.
+    pub linker: Option<&'i str>,
+    pub link_mode: Option<LinkMode>,
+    /// `-C target-cpu`
+    pub target_cpu: Option<&'i str>,
+    /// `-C target-feature`, as given, e.g. `+fp-armv8d16sp`
+    pub target_features: RefVec<'i, &'i str>,
+    /// Crates built with `-Zbuild-std`, empty if prebuilt std was used
+    pub build_std: RefVec<'i, &'i str>,
 }
 
+/// Where the firmware was linked to run from
 #[derive_shrink_wrap]
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-extended", derive(defmt::Format))]
+#[ww_repr(u2)]
+#[sized]
+pub enum LinkMode {
+    Flash,
+    /// `RAM_LINK=1`, loaded into RAM by the debugger, FLASH contents are unrelated
+    Ram,
+}
+
+#[derive_shrink_wrap]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-extended", derive(defmt::Format))]
 #[ww_repr(u3)]
 #[sized]
 pub enum CompilerChannel {
@@ -105,7 +206,7 @@ pub enum CompilerChannel {
 }
 
 #[derive_shrink_wrap]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 #[shrink_wrap(no_alloc)]
 #[owned = "std"]
 pub struct VersionControl<'i> {
@@ -123,6 +224,103 @@ pub struct VersionControl<'i> {
     pub tags: RefVec<'i, &'i str>,
 }
 
+#[derive_shrink_wrap]
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[shrink_wrap(no_alloc)]
+#[owned = "std"]
+pub struct KeyValue<'i> {
+    pub key: &'i str,
+    pub value: ExtraValue<'i>,
+}
+
+#[derive_shrink_wrap]
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[ww_repr(u4)]
+#[shrink_wrap(no_alloc)]
+#[owned = "std"]
+pub enum ExtraValue<'i> {
+    Str(&'i str),
+    U32(u32),
+    Bool(bool),
+    Version(Version<'i>),
+}
+
+impl core::fmt::Display for ExtraValue<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ExtraValue::Str(s) => write!(f, "{s}"),
+            ExtraValue::U32(n) => write!(f, "{n}"),
+            ExtraValue::Bool(b) => write!(f, "{b}"),
+            ExtraValue::Version(v) => {
+                write!(f, "{}.{}.{}", v.major.0, v.minor.0, v.patch.0)?;
+                if let Some(pre) = v.pre {
+                    write!(f, "-{pre}")?;
+                }
+                if let Some(build) = v.build {
+                    write!(f, "+{build}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Linker memory region, `NAME : ORIGIN = .., LENGTH = ..` in `memory.x`
+#[derive_shrink_wrap]
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[shrink_wrap(no_alloc)]
+#[owned = "std"]
+pub struct MemoryRegion<'i> {
+    pub name: &'i str,
+    pub kind: RegionKind,
+    pub origin: u32,
+    pub length: u32,
+}
+
+#[derive_shrink_wrap]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-extended", derive(defmt::Format))]
+#[ww_repr(u2)]
+#[sized]
+pub enum RegionKind {
+    Flash,
+    Ram,
+    /// Peripheral registers used as memory, e.g. backup registers
+    Register,
+    Other,
+}
+
+impl MemoryRegion<'_> {
+    pub fn end(&self) -> u64 {
+        self.origin as u64 + self.length as u64
+    }
+
+    pub fn contains(&self, address: u64) -> bool {
+        address >= self.origin as u64 && address < self.end()
+    }
+
+    #[cfg(feature = "std")]
+    pub fn make_owned(&self) -> MemoryRegionOwned {
+        MemoryRegionOwned {
+            name: self.name.to_string(),
+            kind: self.kind,
+            origin: self.origin,
+            length: self.length,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl MemoryRegionOwned {
+    pub fn end(&self) -> u64 {
+        self.origin as u64 + self.length as u64
+    }
+
+    pub fn contains(&self, address: u64) -> bool {
+        address >= self.origin as u64 && address < self.end()
+    }
+}
+
 // requires Borrow, which is a bit tricky to implement
 // #[cfg(feature = "std")]
 // impl ToOwned for BedrockBuildInfo<'_> {
@@ -134,6 +332,23 @@ pub struct VersionControl<'i> {
 //     }
 // }
 
+impl<'i> BedrockBuildInfo<'i> {
+    /// Deserialize payload returned from generated `build_info::compact()`, works in no_std as well
+    pub fn from_compact(payload: &'i [u8]) -> Result<Self, ShrinkWrapError> {
+        Self::from_ww_bytes(payload)
+    }
+
+    /// Find a memory region by its linker name, e.g. `BOOTLOADER_DFU` or `CONFIG`
+    pub fn memory_region(&self, name: &str) -> Option<MemoryRegion<'i>> {
+        self.ext
+            .as_ref()?
+            .memory_map
+            .iter()
+            .flatten()
+            .find(|region| region.name == name)
+    }
+}
+
 #[cfg(feature = "std")]
 impl BedrockBuildInfo<'_> {
     pub fn make_owned(&self) -> BedrockBuildInfoOwned {
@@ -145,8 +360,155 @@ impl BedrockBuildInfo<'_> {
             target_info: self.target_info.make_owned(),
             compiler_info: self.compiler_info.make_owned(),
             version_control: self.version_control.as_ref().map(|v| v.make_owned()),
+            ext: self.ext.as_ref().map(|ext| BuildInfoExtOwned {
+                extra: ext
+                    .extra
+                    .iter()
+                    .map(|kv| kv.unwrap().make_owned())
+                    .collect(),
+                profile_name: ext.profile_name.map(|n| n.to_string()),
+                content_id: ext.content_id,
+                memory_map: ext
+                    .memory_map
+                    .iter()
+                    .map(|r| r.unwrap().make_owned())
+                    .collect(),
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl BedrockBuildInfoOwned {
+    /// Serialize into a buffer that is grown as needed, full info with many dependencies can be quite big
+    pub fn to_ww_vec(&self) -> Result<Vec<u8>, ShrinkWrapError> {
+        const MAX_BUFFER_SIZE: usize = 1024 * 1024;
+        let mut buffer_size = 16_384;
+        loop {
+            let mut buf = vec![0u8; buffer_size];
+            let mut wr = BufWriter::new(&mut buf);
+            let result = self
+                .ser_shrink_wrap(&mut wr)
+                .and_then(|_| wr.finish_and_take().map(|bytes| bytes.to_vec()));
+            if result.is_ok() || buffer_size >= MAX_BUFFER_SIZE {
+                return result;
+            }
+            buffer_size *= 4;
+        }
+    }
+
+    /// Find a memory region by its linker name, e.g. `BOOTLOADER_DFU` or `CONFIG`
+    pub fn memory_region(&self, name: &str) -> Option<&MemoryRegionOwned> {
+        self.memory_map().iter().find(|region| region.name == name)
+    }
+
+    pub fn extra(&self) -> &[KeyValueOwned] {
+        self.ext
+            .as_ref()
+            .map(|ext| &ext.extra[..])
+            .unwrap_or_default()
+    }
+
+    pub fn profile_name(&self) -> Option<&str> {
+        self.ext.as_ref()?.profile_name.as_deref()
+    }
+
+    pub fn content_id(&self) -> Option<u64> {
+        self.ext.as_ref()?.content_id
+    }
+
+    pub fn memory_map(&self) -> &[MemoryRegionOwned] {
+        self.ext
+            .as_ref()
+            .map(|ext| &ext.memory_map[..])
+            .unwrap_or_default()
+    }
+
+    /// Fields added after the first release, created empty if not present
+    pub fn ext_mut(&mut self) -> &mut BuildInfoExtOwned {
+        self.ext.get_or_insert_with(BuildInfoExtOwned::default)
+    }
+
+    /// Set `ext` fields to None where all their contents are empty, so that they take no space at all
+    pub fn drop_empty_ext(&mut self) {
+        if self.ext.as_ref().is_some_and(BuildInfoExtOwned::is_empty) {
+            self.ext = None;
+        }
+        self.crate_info.drop_empty_ext();
+        if self
+            .compiler_info
+            .ext
+            .as_ref()
+            .is_some_and(CompilerInfoExtOwned::is_empty)
+        {
+            self.compiler_info.ext = None;
         }
     }
+
+    /// First 8 bytes of SHA-256 of the serialized info, with build timestamp set to UNIX epoch
+    /// and content ID itself removed.
+    pub fn compute_content_id(&self) -> Result<u64, ShrinkWrapError> {
+        use sha2::Digest;
+
+        let mut info = self.clone();
+        info.timestamp = DateTime::from_ymd_hms_utc_opt(1970, 1, 1, 0, 0, 0, 0).unwrap();
+        info.ext_mut().content_id = None;
+        info.drop_empty_ext();
+        let hash = sha2::Sha256::digest(info.to_ww_vec()?);
+        let mut id = [0u8; 8];
+        id.copy_from_slice(&hash[..8]);
+        Ok(u64::from_le_bytes(id))
+    }
+}
+
+impl KeyValue<'_> {
+    #[cfg(feature = "std")]
+    pub fn make_owned(&self) -> KeyValueOwned {
+        KeyValueOwned {
+            key: self.key.to_string(),
+            value: match &self.value {
+                ExtraValue::Str(s) => ExtraValueOwned::Str(s.to_string()),
+                ExtraValue::U32(n) => ExtraValueOwned::U32(*n),
+                ExtraValue::Bool(b) => ExtraValueOwned::Bool(*b),
+                ExtraValue::Version(v) => ExtraValueOwned::Version(v.make_owned()),
+            },
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<&str> for ExtraValueOwned {
+    fn from(value: &str) -> Self {
+        ExtraValueOwned::Str(value.to_string())
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<String> for ExtraValueOwned {
+    fn from(value: String) -> Self {
+        ExtraValueOwned::Str(value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<u32> for ExtraValueOwned {
+    fn from(value: u32) -> Self {
+        ExtraValueOwned::U32(value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<bool> for ExtraValueOwned {
+    fn from(value: bool) -> Self {
+        ExtraValueOwned::Bool(value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<VersionOwned> for ExtraValueOwned {
+    fn from(value: VersionOwned) -> Self {
+        ExtraValueOwned::Version(value)
+    }
 }
 
 impl CrateInfo<'_> {
@@ -170,6 +532,44 @@ impl CrateInfo<'_> {
                 .iter()
                 .map(|s| s.unwrap().make_owned())
                 .collect(),
+            ext: self.ext.as_ref().map(|ext| CrateInfoExtOwned {
+                license: ext.license.map(|l| l.to_string()),
+                source: ext.source.as_ref().map(|s| match s {
+                    CrateSource::CratesIo => CrateSourceOwned::CratesIo,
+                    CrateSource::Registry(url) => CrateSourceOwned::Registry(url.to_string()),
+                    CrateSource::Git(url) => CrateSourceOwned::Git(url.to_string()),
+                    CrateSource::Path(path) => CrateSourceOwned::Path(path.to_string()),
+                }),
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl CrateInfoOwned {
+    pub fn license(&self) -> Option<&str> {
+        self.ext.as_ref()?.license.as_deref()
+    }
+
+    pub fn source(&self) -> Option<&CrateSourceOwned> {
+        self.ext.as_ref()?.source.as_ref()
+    }
+
+    /// Fields added after the first release, created empty if not present
+    pub fn ext_mut(&mut self) -> &mut CrateInfoExtOwned {
+        self.ext.get_or_insert_with(CrateInfoExtOwned::default)
+    }
+
+    fn drop_empty_ext(&mut self) {
+        if self
+            .ext
+            .as_ref()
+            .is_some_and(|ext| ext.license.is_none() && ext.source.is_none())
+        {
+            self.ext = None;
+        }
+        for dep in &mut self.dependencies {
+            dep.drop_empty_ext();
         }
     }
 }
@@ -193,10 +593,72 @@ impl CompilerInfo<'_> {
             host_triple: self.host_triple.map(|t| t.to_string()),
             commit_date: self.commit_date,
             flip_link: self.flip_link,
+            ext: self.ext.as_ref().map(|ext| CompilerInfoExtOwned {
+                linker: ext.linker.map(|l| l.to_string()),
+                link_mode: ext.link_mode,
+                target_cpu: ext.target_cpu.map(|c| c.to_string()),
+                target_features: ext
+                    .target_features
+                    .iter()
+                    .map(|f| f.unwrap().to_string())
+                    .collect(),
+                build_std: ext
+                    .build_std
+                    .iter()
+                    .map(|c| c.unwrap().to_string())
+                    .collect(),
+            }),
         }
     }
 }
 
+#[cfg(feature = "std")]
+impl CompilerInfoOwned {
+    pub fn linker(&self) -> Option<&str> {
+        self.ext.as_ref()?.linker.as_deref()
+    }
+
+    pub fn link_mode(&self) -> Option<LinkMode> {
+        self.ext.as_ref()?.link_mode
+    }
+
+    pub fn target_cpu(&self) -> Option<&str> {
+        self.ext.as_ref()?.target_cpu.as_deref()
+    }
+
+    pub fn target_features(&self) -> &[String] {
+        self.ext
+            .as_ref()
+            .map(|ext| &ext.target_features[..])
+            .unwrap_or_default()
+    }
+
+    pub fn build_std(&self) -> &[String] {
+        self.ext
+            .as_ref()
+            .map(|ext| &ext.build_std[..])
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(feature = "std")]
+impl BuildInfoExtOwned {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[cfg(feature = "std")]
+impl CompilerInfoExtOwned {
+    pub fn is_empty(&self) -> bool {
+        self.linker.is_none()
+            && self.link_mode.is_none()
+            && self.target_cpu.is_none()
+            && self.target_features.is_empty()
+            && self.build_std.is_empty()
+    }
+}
+
 impl VersionControl<'_> {
     #[cfg(feature = "std")]
     pub fn make_owned(&self) -> VersionControlOwned {
@@ -214,19 +676,16 @@ impl VersionControl<'_> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::envelope::{COMPACT_INFO_VERSION, CompactEnvelope, EnvelopeError, EnvelopeFlags};
     use hex_literal::hex;
     // use tracing_subscriber::layer::SubscriberExt;
     // use tracing_subscriber::util::SubscriberInitExt;
     // use tracing_subscriber::{EnvFilter, fmt};
     use ww_date_time::NaiveDate;
 
-    #[test]
-    fn v1_version_compatibility_not_broken() {
-        // tracing_subscriber::registry()
-        //     .with(fmt::layer())
-        //     .with(EnvFilter::new("trace"))
-        //     .init();
-        let build_info = BedrockBuildInfo {
+    /// Build info stored in all the `compact_v1*.bin` fixtures, without fields added after the first release
+    pub(crate) fn v1_build_info() -> BedrockBuildInfo<'static> {
+        BedrockBuildInfo {
             timestamp: DateTime::from_ymd_hms_utc_opt(2025, 7, 13, 16, 20, 0, 0).unwrap(),
             profile: Profile::Release,
             optimization_level: OptimizationLevel::O2,
@@ -236,6 +695,7 @@ mod tests {
                 authors: RefVec::new(),
                 enabled_features: RefVec::new_str_slice(&["f_a", "f_b"]),
                 dependencies: RefVec::new(),
+                ext: None,
             },
             target_info: TargetInfo {
                 triple: Some("xyz"),
@@ -246,6 +706,8 @@ mod tests {
                 channel: CompilerChannel::Nightly,
                 host_triple: None,
                 commit_date: Some(NaiveDate::from_ymd_opt(2025, 5, 5).unwrap()),
+                flip_link: false,
+                ext: None,
             },
             version_control: Some(VersionControl {
                 dirty: true,
@@ -256,7 +718,32 @@ mod tests {
                 branch: None,
                 tags: RefVec::new(),
             }),
-        };
+            ext: None,
+        }
+    }
+
+    /// [v1_build_info] as serialized by bedrock_build before `ext` fields were added
+    const V1_PAYLOAD: [u8; 50] = hex!(
+        "076B96C0 14" // DateTime profile,optimization,version_control_flag
+        "617765736F6D65 0120 665F61 665F62 033207" // CrateInfo: "awesome" 0.1.2 "f_a" "f_b" lengths: 7, 0, 2, 3, 3, 0
+        "C0 78797A 61726D 33" // TargetInfo: flags "xyz" "arm" lengths: 3, 3
+        "1FA100A0 5280" // CompilerInfo: Version: 1,87,0 channel host_triple Some(Date) length of Version=2
+        "50 616263 077396C0 03" // VersionControl: flags "abc" DateTime lengths: 3, 0
+        "0 1 9 6 09 2A" // lengths from the back: 18, 8, 6, 9
+    );
+
+    #[test]
+    fn v1_version_compatibility_not_broken() {
+        // tracing_subscriber::registry()
+        //     .with(fmt::layer())
+        //     .with(EnvFilter::new("trace"))
+        //     .init();
+        let build_info = v1_build_info();
+        // records made before `ext` fields were added are read with all of them set to None
+        let mut rd = BufReader::new(&V1_PAYLOAD[..]);
+        let build_info_des = BedrockBuildInfo::des_shrink_wrap(&mut rd).unwrap();
+        assert_eq!(build_info, build_info_des);
+
         let mut buf = [0u8; 256];
         let mut wr = BufWriter::new(&mut buf);
         build_info.ser_shrink_wrap(&mut wr).unwrap();
@@ -266,15 +753,15 @@ mod tests {
             bytes,
             hex!(
                 "076B96C0 14" // DateTime profile,optimization,version_control_flag
-                "617765736F6D65 0120 665F61 665F62 033207" // CrateInfo: "awesome" 0.1.2 "f_a" "f_b" lengths: 7, 0, 2, 3, 3, 0
+                "617765736F6D65 0120 665F61 665F62 00 033207" // CrateInfo: as in V1_PAYLOAD, plus ext: None flag
                 "C0 78797A 61726D 33" // TargetInfo: flags "xyz" "arm" lengths: 3, 3
-                "1FA100A0 5280" // CompilerInfo: Version: 1,87,0 channel host_triple Some(Date) length of Version=2
+                "1FA100A0 5280" // CompilerInfo: as in V1_PAYLOAD, ext: None flag fits into existing padding
                 "50 616263 077396C0 03" // VersionControl: flags "abc" DateTime lengths: 3, 0
-                "0 1 9 6 09 2A" // lengths from the back: 18, 8, 6, 9
+                "0 1 9 6 09 3A" // lengths from the back: 19, 8, 6, 9
             )
         );
-        assert_eq!(bytes.len(), 50);
-        let mut rd = BufReader::new(&bytes[..]);
+        assert_eq!(bytes.len(), 51);
+        let mut rd = BufReader::new(bytes);
         let build_info_des = BedrockBuildInfo::des_shrink_wrap(&mut rd).unwrap();
         assert_eq!(build_info, build_info_des);
 
@@ -293,6 +780,7 @@ mod tests {
             authors: vec!["a1".into(), "a2".into()],
             enabled_features: vec!["f1".into(), "f2".into(), "f3".into()],
             dependencies: vec![],
+            ext: None,
         });
         owned.crate_info.dependencies.push(CrateInfoOwned {
             name: "dep2_".to_string(),
@@ -300,6 +788,7 @@ mod tests {
             authors: vec!["a3".into(), "a4".into()],
             enabled_features: vec!["f4".into(), "f5".into(), "f6".into()],
             dependencies: vec![],
+            ext: None,
         });
         let mut buf = [0u8; 256];
         let mut wr = BufWriter::new(&mut buf);
@@ -316,21 +805,394 @@ mod tests {
                 "617765736F6D65 0120 665F61 665F62" // awesome 0.1.2 [] [f_a, f_b]
                 "64657031 0120 6131 6132 6631 6632 6633 0022232224" // dep1 0.1.2 [a1, a2] [f1, f2, f3] 0 0 2 2 2 3 2 2 2 4
                 "646570325F 4560 6133 6134 6634 6635 6636 0022232225" // dep2 4.5.6 [a3, a4] [f4, f5, f6] 0 0 2 2 2 3 2 2 2 5
-                "6A 5A 2 3 3 2 0 7" // 22 21 2 3 3 2 0 7
+                "00 6A 5A 2 3 3 2 0 7" // ext: None flag, 22 21 2 3 3 2 0 7
                 "C0 78797A 61726D 33"
                 "1FA100A0 5280"
                 "50 616263 077396C0 03"
-                "0 1 9 6 09 7F"
+                "00 1 9 6 09 0 8 9"
             )
         );
-        assert_eq!(bytes_owned_multi_dep.len(), 95);
+        assert_eq!(bytes_owned_multi_dep.len(), 97);
 
-        let mut rd = BufReader::new(&bytes_owned_multi_dep);
+        let mut rd = BufReader::new(bytes_owned_multi_dep);
         let build_info_des = BedrockBuildInfoOwned::des_shrink_wrap(&mut rd).unwrap();
         assert_eq!(build_info_des, owned);
         // println!("{build_info_des:#?}");
     }
 
+    /// [v1_build_info] with all the fields added after the first release
+    pub(crate) fn v1_ext_build_info() -> BedrockBuildInfoOwned {
+        let mut info = v1_build_info().make_owned();
+        info.ext = Some(BuildInfoExtOwned {
+            extra: vec![KeyValueOwned {
+                key: "hw_rev".into(),
+                value: "C".into(),
+            }],
+            profile_name: Some("release-lto".into()),
+            content_id: Some(0x0123_4567_89AB_CDEF),
+            memory_map: vec![MemoryRegionOwned {
+                name: "FLASH".into(),
+                kind: RegionKind::Flash,
+                origin: 0x0800_0000,
+                length: 256 * 1024,
+            }],
+        });
+        info.crate_info.ext = Some(CrateInfoExtOwned {
+            license: Some("MIT OR Apache-2.0".into()),
+            source: Some(CrateSourceOwned::Path("firmware".into())),
+        });
+        info.compiler_info.ext = Some(CompilerInfoExtOwned {
+            linker: Some("flip-link".into()),
+            link_mode: Some(LinkMode::Flash),
+            target_cpu: Some("cortex-m4".into()),
+            target_features: vec!["+fp-armv8d16sp".into()],
+            build_std: vec!["core".into()],
+        });
+        info
+    }
+
+    /// Compact records as they were written into FLASH by released bedrock_build versions, with the info they hold.
+    /// Never modify existing files, add a new one when envelope or payload format changes.
+    fn compact_fixtures() -> [(&'static [u8], BedrockBuildInfoOwned); 3] {
+        [
+            (
+                include_bytes!("../fixtures/compact_v1_legacy.bin"),
+                v1_build_info().make_owned(),
+            ),
+            (
+                include_bytes!("../fixtures/compact_v1.bin"),
+                v1_build_info().make_owned(),
+            ),
+            (
+                include_bytes!("../fixtures/compact_v1_ext.bin"),
+                v1_ext_build_info(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn released_records_decode() {
+        for (fixture, expected) in compact_fixtures() {
+            let envelope = CompactEnvelope::parse(fixture).unwrap();
+            assert_eq!(envelope.version, 1);
+            assert_eq!(envelope.total_len(), fixture.len());
+            assert_eq!(envelope.decode().unwrap().make_owned(), expected);
+        }
+        assert_eq!(
+            envelope::encode(&V1_PAYLOAD, EnvelopeFlags(EnvelopeFlags::PRUNED)),
+            include_bytes!("../fixtures/compact_v1.bin")
+        );
+        assert_eq!(
+            envelope::encode(
+                &v1_ext_build_info().to_ww_vec().unwrap(),
+                EnvelopeFlags(EnvelopeFlags::PRUNED)
+            ),
+            include_bytes!("../fixtures/compact_v1_ext.bin")
+        );
+    }
+
+    /// Type definitions of the first release, as host tools built before `ext` fields were added have them
+    mod first_release {
+        use wire_weaver::prelude::*;
+        use ww_date_time::{DateTime, NaiveDate};
+        use ww_version::Version;
+
+        #[derive_shrink_wrap]
+        #[derive(Debug, PartialEq, Eq)]
+        #[shrink_wrap(no_alloc)]
+        pub struct BedrockBuildInfo<'i> {
+            pub timestamp: DateTime,
+            pub profile: Profile,
+            pub optimization_level: OptimizationLevel,
+            #[flag]
+            version_control: bool,
+            pub crate_info: CrateInfo<'i>,
+            pub target_info: TargetInfo<'i>,
+            pub compiler_info: CompilerInfo<'i>,
+            pub version_control: Option<VersionControl<'i>>,
+        }
+
+        #[derive_shrink_wrap]
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        #[ww_repr(u2)]
+        #[sized]
+        pub enum Profile {
+            Release,
+            Debug,
+        }
+
+        #[derive_shrink_wrap]
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        #[ww_repr(u3)]
+        #[sized]
+        pub enum OptimizationLevel {
+            O0,
+            O1,
+            O2,
+            O3,
+            Os,
+            Oz,
+        }
+
+        #[derive_shrink_wrap]
+        #[derive(Debug, PartialEq, Eq, Clone)]
+        #[shrink_wrap(no_alloc)]
+        pub struct CrateInfo<'i> {
+            pub name: &'i str,
+            pub version: Version<'i>,
+            pub authors: RefVec<'i, &'i str>,
+            pub enabled_features: RefVec<'i, &'i str>,
+            pub dependencies: RefVec<'i, CrateInfo<'i>>,
+        }
+
+        #[derive_shrink_wrap]
+        #[derive(Debug, PartialEq, Eq)]
+        #[shrink_wrap(no_alloc)]
+        pub struct TargetInfo<'i> {
+            #[flag]
+            arch: bool,
+            pub triple: Option<&'i str>,
+            pub arch: Option<&'i str>,
+        }
+
+        #[derive_shrink_wrap]
+        #[derive(Debug, PartialEq, Eq)]
+        #[shrink_wrap(no_alloc)]
+        pub struct CompilerInfo<'i> {
+            pub version: Version<'i>,
+            pub channel: CompilerChannel,
+            pub host_triple: Option<&'i str>,
+            pub commit_date: Option<NaiveDate>,
+            pub flip_link: bool,
+        }
+
+        #[derive_shrink_wrap]
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        #[ww_repr(u3)]
+        #[sized]
+        pub enum CompilerChannel {
+            Dev,
+            Nightly,
+            Beta,
+            Stable,
+        }
+
+        #[derive_shrink_wrap]
+        #[derive(Debug, PartialEq, Eq)]
+        #[shrink_wrap(no_alloc)]
+        pub struct VersionControl<'i> {
+            #[flag]
+            branch: bool,
+            #[flag]
+            commit_short_id: bool,
+            #[flag]
+            commit_id: bool,
+            pub dirty: bool,
+            pub commit_id: Option<&'i str>,
+            pub commit_short_id: Option<&'i str>,
+            pub commit_timestamp: DateTime,
+            pub branch: Option<&'i str>,
+            pub tags: RefVec<'i, &'i str>,
+        }
+    }
+
+    #[test]
+    fn first_release_readers_skip_ext_fields() {
+        let mut info = v1_ext_build_info();
+        let mut dependency = info.crate_info.clone();
+        dependency.name = "heapless".into();
+        info.crate_info.dependencies.push(dependency);
+        let bytes = info.to_ww_vec().unwrap();
+
+        let old = first_release::BedrockBuildInfo::from_ww_bytes(&bytes).unwrap();
+        // a custom profile is seen as the one it inherits from
+        assert_eq!(old.profile, first_release::Profile::Release);
+        let dependencies: Vec<_> = old
+            .crate_info
+            .dependencies
+            .iter()
+            .map(|d| d.unwrap().name)
+            .collect();
+        assert_eq!(dependencies, ["heapless"]);
+
+        // every first release field is read as it was written
+        let mut buf = [0u8; 256];
+        let mut wr = BufWriter::new(&mut buf);
+        old.ser_shrink_wrap(&mut wr).unwrap();
+        let old_bytes = wr.finish_and_take().unwrap();
+        let mut expected = v1_build_info().make_owned();
+        let mut dependency = expected.crate_info.clone();
+        dependency.name = "heapless".into();
+        expected.crate_info.dependencies.push(dependency);
+        assert_eq!(
+            BedrockBuildInfo::from_ww_bytes(old_bytes)
+                .unwrap()
+                .make_owned(),
+            expected
+        );
+    }
+
+    #[test]
+    fn extra_fields_round_trip() {
+        let mut owned = v1_build_info().make_owned();
+        assert!(owned.extra().is_empty());
+
+        owned.ext_mut().extra.push(KeyValueOwned {
+            key: "hw_rev".into(),
+            value: "C".into(),
+        });
+        owned.ext_mut().extra.push(KeyValueOwned {
+            key: "product_id".into(),
+            value: 0x1234_u32.into(),
+        });
+        let mut protocol = VersionOwned::new(2, 0, 1);
+        protocol.pre = Some("rc.1".into());
+        owned.ext_mut().extra.push(KeyValueOwned {
+            key: "protocol".into(),
+            value: protocol.into(),
+        });
+        owned.ext_mut().extra.push(KeyValueOwned {
+            key: "secure_boot".into(),
+            value: true.into(),
+        });
+        let mut buf = [0u8; 256];
+        let mut wr = BufWriter::new(&mut buf);
+        owned.ser_shrink_wrap(&mut wr).unwrap();
+        let bytes = wr.finish_and_take().unwrap();
+
+        let build_info = BedrockBuildInfo::from_ww_bytes(bytes).unwrap();
+        let extra: Vec<_> = build_info
+            .ext
+            .as_ref()
+            .unwrap()
+            .extra
+            .iter()
+            .map(|kv| {
+                let kv = kv.unwrap();
+                format!("{}={}", kv.key, kv.value)
+            })
+            .collect();
+        let expected = [
+            "hw_rev=C",
+            "product_id=4660",
+            "protocol=2.0.1-rc.1",
+            "secure_boot=true",
+        ];
+        assert_eq!(extra, expected);
+        assert_eq!(build_info.make_owned(), owned);
+        let owned_extra: Vec<_> = owned
+            .extra()
+            .iter()
+            .map(|kv| format!("{}={}", kv.key, kv.value))
+            .collect();
+        assert_eq!(owned_extra, expected);
+    }
+
+    #[test]
+    fn diff_ignores_timestamp_and_pruned_fields() {
+        let compact = v1_build_info().make_owned();
+
+        let mut other = compact.clone();
+        other.timestamp = DateTime::from_ymd_hms_utc_opt(2025, 8, 1, 0, 0, 0, 0).unwrap();
+        other.target_info.triple = None;
+        assert!(compact.diff(&other).is_empty());
+
+        other.crate_info.version = VersionOwned::new(0, 1, 3);
+        other.crate_info.enabled_features.reverse();
+        other.optimization_level = OptimizationLevel::Oz;
+        let vc = other.version_control.as_mut().unwrap();
+        vc.dirty = false;
+        vc.commit_id = Some("abcdef0123".into());
+        let diff = compact.diff(&other);
+        let fields: Vec<_> = diff.changes.iter().map(|c| c.field).collect();
+        assert_eq!(fields, ["version", "dirty", "opt level"]);
+        assert_eq!(
+            diff.to_string(),
+            "version  : 0.1.2 != 0.1.3\ndirty    : true != false\nopt level: O2 != Oz"
+        );
+    }
+
+    #[test]
+    fn content_id_ignores_build_timestamp() {
+        let mut info = v1_build_info().make_owned();
+        let id = info.compute_content_id().unwrap();
+
+        info.timestamp = DateTime::from_ymd_hms_utc_opt(2026, 1, 2, 3, 4, 5, 0).unwrap();
+        info.ext_mut().content_id = Some(id);
+        assert_eq!(info.compute_content_id().unwrap(), id);
+
+        info.version_control.as_mut().unwrap().dirty = false;
+        assert_ne!(info.compute_content_id().unwrap(), id);
+    }
+
+    #[test]
+    fn memory_map_lookup() {
+        let mut owned = v1_build_info().make_owned();
+        assert!(owned.memory_map().is_empty());
+
+        owned.ext_mut().memory_map.push(MemoryRegionOwned {
+            name: "FLASH".into(),
+            kind: RegionKind::Flash,
+            origin: 0x0800_0000,
+            length: 256 * 1024,
+        });
+        owned.ext_mut().memory_map.push(MemoryRegionOwned {
+            name: "BOOTLOADER_DFU".into(),
+            kind: RegionKind::Flash,
+            origin: 0x0804_0000,
+            length: 260 * 1024,
+        });
+        let bytes = owned.to_ww_vec().unwrap();
+        let build_info = BedrockBuildInfo::from_ww_bytes(&bytes).unwrap();
+        let dfu = build_info.memory_region("BOOTLOADER_DFU").unwrap();
+        assert_eq!(dfu.origin, 0x0804_0000);
+        assert!(dfu.contains(0x0804_0000 + 260 * 1024 - 1));
+        assert!(!dfu.contains(0x0804_0000 + 260 * 1024));
+        assert!(build_info.memory_region("CONFIG").is_none());
+        assert_eq!(build_info.make_owned(), owned);
+    }
+
+    #[test]
+    fn compact_envelope_errors() {
+        let record = envelope::encode(&V1_PAYLOAD, EnvelopeFlags(0));
+        assert_eq!(
+            CompactEnvelope::parse(&record[..7]),
+            Err(EnvelopeError::Truncated {
+                needed: envelope::VERSIONED_HEADER_LEN
+            })
+        );
+        assert_eq!(
+            CompactEnvelope::parse(&record[..20]),
+            Err(EnvelopeError::Truncated {
+                needed: record.len()
+            })
+        );
+
+        let mut corrupted = record.clone();
+        corrupted[5] ^= 0x80; // flags are covered by CRC
+        assert!(matches!(
+            CompactEnvelope::parse(&corrupted),
+            Err(EnvelopeError::CrcMismatch { .. })
+        ));
+
+        let mut bad_magic = record.clone();
+        bad_magic[3] = 0xFF;
+        assert_eq!(
+            CompactEnvelope::parse(&bad_magic),
+            Err(EnvelopeError::BadMagic)
+        );
+
+        let mut future = record.clone();
+        future[4] = COMPACT_INFO_VERSION + 1;
+        let crc_covered = [&future[4..8], &future[envelope::VERSIONED_HEADER_LEN..]].concat();
+        future[8..12].copy_from_slice(&build_info_crc(&crc_covered).to_le_bytes());
+        let envelope = CompactEnvelope::parse(&future).unwrap();
+        assert_eq!(
+            envelope.decode(),
+            Err(EnvelopeError::UnsupportedVersion(COMPACT_INFO_VERSION + 1))
+        );
+    }
+
     #[test]
     fn v1_read() {
         // tracing_subscriber::registry()
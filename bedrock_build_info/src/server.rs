@@ -0,0 +1,86 @@
+//! On-device server for the [BuildInfo](crate::traits) API, so that a host can ask "what are you running?" without a
+//! probe. Request handling is generated by `ww_api!` from the trait, the server plugs into any wire_weaver transport
+//! (USB link, UART with COBS, RTT down channel, etc.) as a [WireWeaverAsyncApiBackend].
+//!
+//! No allocations are performed, all buffers are provided by the caller.
+//!
+//! Example:
+//! ```ignore
+//! let mut server = BuildInfoServer::new(build_info::compact());
+//! let mut args = [0u8; 512];
+//! let mut event = [0u8; 512];
+//! let mut err = [0u8; 32];
+//! // for every frame received from the link:
+//! let response = server.process_bytes(frame, &mut args, &mut event, &mut err).await?;
+//! link.send(response).await?;
+//! ```
+
+use wire_weaver::WireWeaverAsyncApiBackend;
+use wire_weaver::prelude::*;
+
+pub struct BuildInfoServer {
+    compact: &'static [u8],
+    full: Option<&'static [u8]>,
+    fw_sha: Option<[u8; 32]>,
+}
+
+impl BuildInfoServer {
+    /// `compact` is the payload returned from generated `build_info::compact()`
+    pub const fn new(compact: &'static [u8]) -> Self {
+        BuildInfoServer {
+            compact,
+            full: None,
+            fw_sha: None,
+        }
+    }
+
+    /// Serve full build info as well, only makes sense if it is stored in FLASH
+    pub const fn with_full(mut self, full: &'static [u8]) -> Self {
+        self.full = Some(full);
+        self
+    }
+
+    /// Serve the SHA-256 of the firmware binary, the server keeps its own copy
+    pub const fn with_fw_sha(mut self, fw_sha: [u8; 32]) -> Self {
+        self.fw_sha = Some(fw_sha);
+        self
+    }
+
+    async fn compact(&mut self) -> RefVec<'static, u8> {
+        RefVec::new_bytes(self.compact)
+    }
+
+    async fn full(&mut self) -> Option<RefVec<'static, u8>> {
+        self.full.map(RefVec::new_bytes)
+    }
+
+    async fn fw_sha(&mut self) -> Option<RefVec<'_, u8>> {
+        self.fw_sha.as_ref().map(|sha| RefVec::new_bytes(sha))
+    }
+}
+
+impl WireWeaverAsyncApiBackend for BuildInfoServer {
+    /// `scratch_args` and `scratch_event` must be big enough to hold the biggest response (compact or full info with a
+    /// few bytes overhead)
+    async fn process_bytes<'a>(
+        &mut self,
+        data: &[u8],
+        scratch_args: &'a mut [u8],
+        scratch_event: &'a mut [u8],
+        scratch_err: &'a mut [u8],
+    ) -> Result<&'a [u8], ShrinkWrapError> {
+        self.process_request_bytes(data, scratch_args, scratch_event, scratch_err)
+            .await
+    }
+
+    fn version(&self) -> FullVersion<'_> {
+        full_version!()
+    }
+}
+
+ww_api!(
+    "./src/traits.rs" as bedrock_build_info::BuildInfo for BuildInfoServer,
+    server = true,
+    no_alloc = true,
+    use_async = true,
+);
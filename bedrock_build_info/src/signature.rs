@@ -0,0 +1,348 @@
+//! Optional ed25519 signature record, stored in FLASH next to the compact build info.
+//!
+//! Firmware reserves a slot (see `bedrock_build::BuildInfoConfig::signature_slot`), which is filled in
+//! after linking by `bedrock sign`. Record layout:
+//! `magic | version: u8 | flags: u8 | reserved: u16 | key_id: [u8; 8] | image_len: u32 LE | image_hash: [u8; 32] | signature: [u8; 64]`
+//!
+//! * `image_hash` is SHA-256 of the first `image_len` bytes of the FLASH image, with the slot itself
+//!   treated as erased (0xFF), so that the hash does not depend on the signature.
+//! * `signature` is over SHA-256 of a domain tag, key ID, image length, image hash and the whole compact
+//!   build info record.
+//!
+//! Verification works in no_std and without allocations, so that a bootloader can check an update before
+//! swapping to it.
+
+use crate::envelope::EnvelopeLayout;
+use ed25519_dalek::{Signature, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+/// Marker of the signature record
+pub const SIGNATURE_MAGIC: u32 = 0xB17D_5160;
+pub const SIGNATURE_VERSION: u8 = 1;
+/// magic, version, flags, reserved, key_id, image_len, image_hash, signature
+pub const SIGNATURE_RECORD_LEN: usize = 4 + 1 + 1 + 2 + 8 + 4 + 32 + 64;
+
+const DOMAIN: &[u8] = b"bedrock-signature-v1";
+
+/// Bit flags stored in the record
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SignatureFlags(pub u8);
+
+impl SignatureFlags {
+    /// Slot was filled by `bedrock sign`, otherwise it is an empty placeholder
+    pub const SIGNED: u8 = 1 << 0;
+
+    pub fn is_signed(&self) -> bool {
+        self.0 & Self::SIGNED != 0
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SignatureRecord {
+    pub flags: SignatureFlags,
+    /// First 8 bytes of SHA-256 of the public key, see [key_id]
+    pub key_id: [u8; 8],
+    pub image_len: u32,
+    pub image_hash: [u8; 32],
+    pub signature: [u8; 64],
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SignatureError {
+    /// Firmware was built without a signature slot
+    NoSlot,
+    /// Slot is present, but `bedrock sign` was not run on this image
+    NotSigned,
+    UnsupportedVersion(u8),
+    /// Image was signed with a different key
+    UnknownKey,
+    /// Recorded image length does not fit into the provided image, or the slot is outside of it
+    ImageLength,
+    /// Image contents changed after signing
+    HashMismatch,
+    /// Compact build info record is not present in the image
+    NoCompactInfo,
+    BadSignature,
+}
+
+impl core::fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SignatureError::NoSlot => {
+                write!(f, "no signature slot, not built with signature_slot(true)")
+            }
+            SignatureError::NotSigned => write!(f, "not signed, run bedrock sign"),
+            SignatureError::UnsupportedVersion(version) => {
+                write!(f, "unsupported signature record version {version}")
+            }
+            SignatureError::UnknownKey => write!(f, "signed with another key"),
+            SignatureError::ImageLength => write!(f, "signed image length does not fit the image"),
+            SignatureError::HashMismatch => write!(f, "image changed after signing"),
+            SignatureError::NoCompactInfo => write!(f, "no compact build info in the image"),
+            SignatureError::BadSignature => write!(f, "bad signature"),
+        }
+    }
+}
+
+/// Verified firmware image
+#[derive(Debug, PartialEq, Eq)]
+pub struct SignedImage<'i> {
+    pub record: SignatureRecord,
+    /// Offset of the signature record in the image
+    pub record_offset: usize,
+    /// Whole compact build info record (with header), covered by the signature
+    pub compact_record: &'i [u8],
+}
+
+impl SignatureRecord {
+    /// Empty record to be placed into FLASH by the firmware, filled in later by `bedrock sign`
+    pub const fn placeholder() -> [u8; SIGNATURE_RECORD_LEN] {
+        let mut record = [0xFF; SIGNATURE_RECORD_LEN];
+        let magic = SIGNATURE_MAGIC.to_be_bytes();
+        record[0] = magic[0];
+        record[1] = magic[1];
+        record[2] = magic[2];
+        record[3] = magic[3];
+        record[4] = SIGNATURE_VERSION;
+        record[5] = 0;
+        record[6] = 0;
+        record[7] = 0;
+        record
+    }
+
+    /// Parse a record, `bytes` must start at the magic value
+    pub fn parse(bytes: &[u8]) -> Result<Self, SignatureError> {
+        if bytes.len() < SIGNATURE_RECORD_LEN || bytes[..4] != SIGNATURE_MAGIC.to_be_bytes() {
+            return Err(SignatureError::NoSlot);
+        }
+        if bytes[4] != SIGNATURE_VERSION {
+            return Err(SignatureError::UnsupportedVersion(bytes[4]));
+        }
+        let flags = SignatureFlags(bytes[5]);
+        if !flags.is_signed() {
+            return Err(SignatureError::NotSigned);
+        }
+        let mut record = SignatureRecord {
+            flags,
+            key_id: [0; 8],
+            image_len: u32::from_le_bytes([bytes[16], bytes[17], bytes[18], bytes[19]]),
+            image_hash: [0; 32],
+            signature: [0; 64],
+        };
+        record.key_id.copy_from_slice(&bytes[8..16]);
+        record.image_hash.copy_from_slice(&bytes[20..52]);
+        record.signature.copy_from_slice(&bytes[52..116]);
+        Ok(record)
+    }
+
+    pub fn to_bytes(&self) -> [u8; SIGNATURE_RECORD_LEN] {
+        let mut bytes = Self::placeholder();
+        bytes[5] = self.flags.0;
+        bytes[8..16].copy_from_slice(&self.key_id);
+        bytes[16..20].copy_from_slice(&self.image_len.to_le_bytes());
+        bytes[20..52].copy_from_slice(&self.image_hash);
+        bytes[52..116].copy_from_slice(&self.signature);
+        bytes
+    }
+
+    /// Digest that is actually signed
+    fn digest(&self, compact_record: &[u8]) -> [u8; 32] {
+        Sha256::new()
+            .chain_update(DOMAIN)
+            .chain_update(self.key_id)
+            .chain_update(self.image_len.to_le_bytes())
+            .chain_update(self.image_hash)
+            .chain_update(compact_record)
+            .finalize()
+            .into()
+    }
+}
+
+/// First 8 bytes of SHA-256 of the public key, to tell keys apart without storing the whole key
+pub fn key_id(public_key: &[u8; 32]) -> [u8; 8] {
+    let hash = Sha256::digest(public_key);
+    let mut id = [0u8; 8];
+    id.copy_from_slice(&hash[..8]);
+    id
+}
+
+/// Offset of the signature slot (signed or not) in a FLASH image
+pub fn find_slot(image: &[u8]) -> Option<usize> {
+    let magic = SIGNATURE_MAGIC.to_be_bytes();
+    // version and reserved bytes are checked as well, to skip the magic constant in code using this module
+    image
+        .windows(8)
+        .position(|w| w[..4] == magic && w[4] == SIGNATURE_VERSION && w[6..8] == [0, 0])
+}
+
+/// Whole compact build info record in a FLASH image, CRC is not checked as the record is covered by the signature
+pub fn find_compact_record(image: &[u8]) -> Option<&[u8]> {
+    let mut search_from = 0;
+    while search_from + 4 <= image.len() {
+        let at = search_from
+            + image[search_from..]
+                .windows(4)
+                .position(|b| EnvelopeLayout::from_magic([b[0], b[1], b[2], b[3]]).is_some())?;
+        let layout =
+            EnvelopeLayout::from_magic([image[at], image[at + 1], image[at + 2], image[at + 3]])?;
+        let len_at = match layout {
+            EnvelopeLayout::Legacy => at + 4,
+            EnvelopeLayout::Versioned => at + 6,
+        };
+        if let Some(len) = image.get(len_at..len_at + 2) {
+            let end = at + layout.header_len() + u16::from_le_bytes([len[0], len[1]]) as usize;
+            if end <= image.len() {
+                return Some(&image[at..end]);
+            }
+        }
+        search_from = at + 1;
+    }
+    None
+}
+
+/// SHA-256 of the first `image_len` bytes of the image, with the signature slot treated as erased
+pub fn image_hash(
+    image: &[u8],
+    image_len: usize,
+    slot_offset: usize,
+) -> Result<[u8; 32], SignatureError> {
+    let slot_end = slot_offset + SIGNATURE_RECORD_LEN;
+    if image_len > image.len() || slot_end > image_len {
+        return Err(SignatureError::ImageLength);
+    }
+    Ok(Sha256::new()
+        .chain_update(&image[..slot_offset])
+        .chain_update([0xFF; SIGNATURE_RECORD_LEN])
+        .chain_update(&image[slot_end..image_len])
+        .finalize()
+        .into())
+}
+
+/// Check that the image was signed by the owner of `public_key` and was not modified since.
+///
+/// `image` must start at the first FLASH address of the firmware (e.g. beginning of the DFU partition),
+/// trailing bytes after the signed length are ignored.
+pub fn verify_image<'i>(
+    image: &'i [u8],
+    public_key: &[u8; 32],
+) -> Result<SignedImage<'i>, SignatureError> {
+    let record_offset = find_slot(image).ok_or(SignatureError::NoSlot)?;
+    let record = SignatureRecord::parse(&image[record_offset..])?;
+    if record.key_id != key_id(public_key) {
+        return Err(SignatureError::UnknownKey);
+    }
+    let hash = image_hash(image, record.image_len as usize, record_offset)?;
+    if hash != record.image_hash {
+        return Err(SignatureError::HashMismatch);
+    }
+    let compact_record = find_compact_record(&image[..record.image_len as usize])
+        .ok_or(SignatureError::NoCompactInfo)?;
+    let key = VerifyingKey::from_bytes(public_key).map_err(|_| SignatureError::UnknownKey)?;
+    let signature = Signature::from_bytes(&record.signature);
+    key.verify_strict(&record.digest(compact_record), &signature)
+        .map_err(|_| SignatureError::BadSignature)?;
+    Ok(SignedImage {
+        record,
+        record_offset,
+        compact_record,
+    })
+}
+
+/// Sign a FLASH image in place, filling in the signature slot. Whole `image` is covered.
+#[cfg(feature = "std")]
+pub fn sign_image(
+    image: &mut [u8],
+    secret_key: &[u8; 32],
+) -> Result<SignatureRecord, SignatureError> {
+    use ed25519_dalek::{Signer, SigningKey};
+
+    let slot_offset = find_slot(image).ok_or(SignatureError::NoSlot)?;
+    let compact_record = find_compact_record(image).ok_or(SignatureError::NoCompactInfo)?;
+    let image_len = u32::try_from(image.len()).map_err(|_| SignatureError::ImageLength)?;
+    let signing_key = SigningKey::from_bytes(secret_key);
+    let mut record = SignatureRecord {
+        flags: SignatureFlags(SignatureFlags::SIGNED),
+        key_id: key_id(signing_key.verifying_key().as_bytes()),
+        image_len,
+        image_hash: image_hash(image, image.len(), slot_offset)?,
+        signature: [0; 64],
+    };
+    record.signature = signing_key.sign(&record.digest(compact_record)).to_bytes();
+    image[slot_offset..slot_offset + SIGNATURE_RECORD_LEN].copy_from_slice(&record.to_bytes());
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+
+    /// Test keys only, never use for real firmware
+    const TEST_SECRET: [u8; 32] = [0x42; 32];
+    const OTHER_SECRET: [u8; 32] = [0x17; 32];
+
+    fn public_key(secret: &[u8; 32]) -> [u8; 32] {
+        SigningKey::from_bytes(secret).verifying_key().to_bytes()
+    }
+
+    /// Vector table, compact info, some code, signature slot and more code
+    fn test_image() -> Vec<u8> {
+        let mut image = vec![0x11u8; 256];
+        image.extend_from_slice(include_bytes!("../fixtures/compact_v1.bin"));
+        image.extend_from_slice(&[0x22; 100]);
+        image.extend_from_slice(&SignatureRecord::placeholder());
+        image.extend_from_slice(&[0x33; 50]);
+        image
+    }
+
+    #[test]
+    fn signed_image_verifies() {
+        let mut image = test_image();
+        assert_eq!(
+            verify_image(&image, &public_key(&TEST_SECRET)),
+            Err(SignatureError::NotSigned)
+        );
+
+        let record = sign_image(&mut image, &TEST_SECRET).unwrap();
+        // erased FLASH after the image does not matter
+        image.extend_from_slice(&[0xFF; 64]);
+        let signed = verify_image(&image, &public_key(&TEST_SECRET)).unwrap();
+        assert_eq!(signed.record, record);
+        assert_eq!(
+            signed.compact_record,
+            include_bytes!("../fixtures/compact_v1.bin")
+        );
+        assert_eq!(
+            verify_image(&image, &public_key(&OTHER_SECRET)),
+            Err(SignatureError::UnknownKey)
+        );
+    }
+
+    #[test]
+    fn tampered_image_is_rejected() {
+        let mut image = test_image();
+        sign_image(&mut image, &TEST_SECRET).unwrap();
+        let key = public_key(&TEST_SECRET);
+
+        let mut code_changed = image.clone();
+        code_changed[10] ^= 1;
+        assert_eq!(
+            verify_image(&code_changed, &key),
+            Err(SignatureError::HashMismatch)
+        );
+
+        let slot = find_slot(&image).unwrap();
+        let mut signature_changed = image.clone();
+        signature_changed[slot + SIGNATURE_RECORD_LEN - 1] ^= 1;
+        assert_eq!(
+            verify_image(&signature_changed, &key),
+            Err(SignatureError::BadSignature)
+        );
+
+        assert_eq!(
+            verify_image(&image[..image.len() - 1], &key),
+            Err(SignatureError::ImageLength)
+        );
+        assert_eq!(verify_image(&[0x11; 64], &key), Err(SignatureError::NoSlot));
+    }
+}
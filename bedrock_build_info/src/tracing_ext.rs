@@ -0,0 +1,21 @@
+//! tracing spans tagging host tool logs with the firmware they are about.
+
+use crate::BedrockBuildInfoOwned;
+use crate::display::FmtVersion;
+
+impl BedrockBuildInfoOwned {
+    /// Span carrying the most important facts about a firmware as fields,
+    /// enter it while working with a device to tag all the log messages.
+    pub fn span(&self) -> tracing::Span {
+        let vc = self.version_control.as_ref();
+        tracing::info_span!(
+            "firmware",
+            name = %self.crate_info.name,
+            version = %FmtVersion(&self.crate_info.version),
+            commit = vc.and_then(|vc| vc.commit_short_id.as_deref()),
+            dirty = vc.map(|vc| vc.dirty),
+            profile = ?self.profile,
+            target = self.target_info.triple.as_deref(),
+        )
+    }
+}
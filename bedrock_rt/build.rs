@@ -0,0 +1,18 @@
+use std::env;
+
+fn main() {
+    let target = env::var("TARGET").unwrap_or_default();
+    println!("cargo::rustc-check-cfg=cfg(cortex_m)");
+    println!("cargo::rustc-check-cfg=cfg(armv6m)");
+    println!("cargo::rustc-check-cfg=cfg(armv8m_base)");
+    if target.starts_with("thumb") {
+        println!("cargo:rustc-cfg=cortex_m");
+    }
+    // cores with HardFault only, without MemManage, BusFault, UsageFault and the fault status registers
+    if target.starts_with("thumbv6m") {
+        println!("cargo:rustc-cfg=armv6m");
+    }
+    if target.starts_with("thumbv8m.base") {
+        println!("cargo:rustc-cfg=armv8m_base");
+    }
+}
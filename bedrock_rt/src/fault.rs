@@ -0,0 +1,383 @@
+//! Fault handlers that keep the evidence over reset.
+//!
+//! Linking this crate (`use bedrock_rt as _;`) installs `HardFault`, and on cores that have them `MemoryManagement`,
+//! `BusFault` and `UsageFault`, which are only taken after [crate::faults::enable]. Do not define these with
+//! `#[exception]` in the firmware. On a fault the handler saves a [FaultRecord] and resets the MCU, without logging or
+//! anything else that could fault again or wait on a lock the faulting code holds. Call [report_last] (or
+//! [take_last]) once early in `main` to count the reset, add the firmware SHA and log the fault that caused it, the
+//! record stays in RAM for `bedrock fault --last` until the next fault or power loss.
+//!
+//! The record is `_BEDROCK_FAULT_RECORD` in `.uninit`, which `cortex-m-rt` does not zero on reset, or with the `bkp`
+//! feature in `.fault_record_bkp`, to be placed in backup RAM in `memory.x`. Layout, u32 LE each:
+//! ```text
+//!  0  magic          AREA_MAGIC once initialized after power-on
+//!  4  resets         resets since power-on, counted by take_last()
+//!  8  state          0 no fault, 1 fault not reported yet, 2 reported
+//! 12  resets         at the time of the fault
+//! 16  exception      active exception number: 3 HardFault, 4 MemManage, 5 BusFault, 6 UsageFault
+//! 20  exc_return
+//! 24  frame_address  stack pointer with the exception frame
+//! 28  frame          r0, r1, r2, r3, r12, lr, pc, xpsr
+//! 60  cfsr, hfsr, mmfar, bfar
+//! 76  flags          FLAG_FRAME, FLAG_STATUS, FLAG_FW_SHA
+//! 80  fw_sha         SHA-256 of the FLASH image, [u8; 32]
+//! ```
+
+use core::mem::MaybeUninit;
+use core::ptr::{addr_of_mut, read_volatile, write_volatile};
+
+/// Marks the record area as initialized, anything else is RAM content after power-on
+pub const AREA_MAGIC: u32 = 0xB17D_FA17;
+
+/// Exception frame was read, it is not when stacking it failed, e.g. on stack overflow
+pub const FLAG_FRAME: u32 = 1 << 0;
+/// Fault status registers were read, Armv6-M and Armv8-M Baseline do not have them
+pub const FLAG_STATUS: u32 = 1 << 1;
+/// Firmware SHA was computed, by [take_last] at the boot after the fault
+pub const FLAG_FW_SHA: u32 = 1 << 2;
+
+const STATE_PENDING: u32 = 1;
+const STATE_REPORTED: u32 = 2;
+
+#[cfg(cortex_m)]
+const ICSR: *const u32 = 0xE000_ED04 as *const u32;
+/// Stacking the exception frame failed: MSTKERR, STKERR and STKOF
+#[cfg(cortex_m)]
+const CFSR_STACKING: u32 = (1 << 4) | (1 << 12) | (1 << 20);
+
+/// Fault saved by the handlers before resetting
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct FaultRecord {
+    /// Resets since power-on when the fault happened, as counted by [take_last]
+    pub resets: u32,
+    /// Active exception number: 3 HardFault, 4 MemManage, 5 BusFault, 6 UsageFault
+    pub exception: u32,
+    pub exc_return: u32,
+    /// Where the exception frame was on the stack
+    pub frame_address: u32,
+    /// r0, r1, r2, r3, r12, lr, pc, xpsr
+    pub frame: [u32; 8],
+    pub cfsr: u32,
+    pub hfsr: u32,
+    pub mmfar: u32,
+    pub bfar: u32,
+    pub flags: u32,
+    /// SHA-256 of the FLASH image, as `bedrock` computes it to find the firmware in the local registry
+    pub fw_sha: [u8; 32],
+}
+
+impl FaultRecord {
+    pub fn pc(&self) -> u32 {
+        self.frame[6]
+    }
+
+    pub fn lr(&self) -> u32 {
+        self.frame[5]
+    }
+
+    /// Name of the exception the fault was handled in
+    pub fn exception_name(&self) -> &'static str {
+        match self.exception {
+            3 => "HardFault",
+            4 => "MemManage",
+            5 => "BusFault",
+            6 => "UsageFault",
+            7 => "SecureFault",
+            _ => "unknown exception",
+        }
+    }
+
+    /// Names of the fault status bits set, HardFault first
+    pub fn causes(&self) -> impl Iterator<Item = &'static str> {
+        const HFSR_BITS: [(u32, &str); 3] = [(1, "VECTTBL"), (30, "FORCED"), (31, "DEBUGEVT")];
+        const CFSR_BITS: [(u32, &str); 18] = [
+            (0, "IACCVIOL"),
+            (1, "DACCVIOL"),
+            (3, "MUNSTKERR"),
+            (4, "MSTKERR"),
+            (5, "MLSPERR"),
+            (8, "IBUSERR"),
+            (9, "PRECISERR"),
+            (10, "IMPRECISERR"),
+            (11, "UNSTKERR"),
+            (12, "STKERR"),
+            (13, "LSPERR"),
+            (16, "UNDEFINSTR"),
+            (17, "INVSTATE"),
+            (18, "INVPC"),
+            (19, "NOCP"),
+            (20, "STKOF"),
+            (24, "UNALIGNED"),
+            (25, "DIVBYZERO"),
+        ];
+        let hfsr = self.hfsr;
+        let cfsr = self.cfsr;
+        let hardfault = HFSR_BITS
+            .into_iter()
+            .filter(move |(bit, _)| hfsr & (1 << bit) != 0);
+        let configurable = CFSR_BITS
+            .into_iter()
+            .filter(move |(bit, _)| cfsr & (1 << bit) != 0);
+        hardfault.chain(configurable).map(|(_, name)| name)
+    }
+
+    /// Data address that caused the fault: MMFAR or BFAR, when valid
+    pub fn fault_address(&self) -> Option<u32> {
+        const MMARVALID: u32 = 1 << 7;
+        const BFARVALID: u32 = 1 << 15;
+        if self.cfsr & MMARVALID != 0 {
+            Some(self.mmfar)
+        } else if self.cfsr & BFARVALID != 0 {
+            Some(self.bfar)
+        } else {
+            None
+        }
+    }
+}
+
+#[repr(C)]
+struct Area {
+    magic: u32,
+    resets: u32,
+    state: u32,
+    record: FaultRecord,
+}
+
+#[unsafe(no_mangle)]
+#[cfg_attr(not(feature = "bkp"), unsafe(link_section = ".uninit.bedrock_rt"))]
+#[cfg_attr(feature = "bkp", unsafe(link_section = ".fault_record_bkp"))]
+static mut _BEDROCK_FAULT_RECORD: MaybeUninit<Area> = MaybeUninit::uninit();
+
+fn area() -> *mut Area {
+    (&raw mut _BEDROCK_FAULT_RECORD).cast()
+}
+
+/// Count this reset and take the fault that caused it, if any. Call once early at boot, before anything that might
+/// fault again. The record stays readable by `bedrock fault --last`.
+///
+/// The firmware SHA is added to the record here rather than in the handler, hashing the FLASH image there would delay
+/// the reset and could fault again. It is the SHA of the firmware running now, which is the one that faulted unless it
+/// was replaced before this boot.
+pub fn take_last() -> Option<FaultRecord> {
+    let area = area();
+    // Safety: only called from thread mode at boot, the fault handlers reset without returning
+    unsafe {
+        if read_volatile(addr_of_mut!((*area).magic)) != AREA_MAGIC {
+            write_volatile(addr_of_mut!((*area).resets), 0);
+            write_volatile(addr_of_mut!((*area).state), 0);
+            write_volatile(addr_of_mut!((*area).magic), AREA_MAGIC);
+            return None;
+        }
+        let resets = read_volatile(addr_of_mut!((*area).resets));
+        write_volatile(addr_of_mut!((*area).resets), resets.wrapping_add(1));
+        if read_volatile(addr_of_mut!((*area).state)) != STATE_PENDING {
+            return None;
+        }
+        write_volatile(addr_of_mut!((*area).state), STATE_REPORTED);
+        #[cfg(cortex_m)]
+        {
+            write_volatile(addr_of_mut!((*area).record.fw_sha), fw_sha());
+            let flags = read_volatile(addr_of_mut!((*area).record.flags));
+            write_volatile(addr_of_mut!((*area).record.flags), flags | FLAG_FW_SHA);
+        }
+        Some(read_volatile(addr_of_mut!((*area).record)))
+    }
+}
+
+/// [take_last] and log the fault with defmt
+#[cfg(feature = "defmt")]
+pub fn report_last() -> Option<FaultRecord> {
+    let record = take_last()?;
+    defmt::error!(
+        "Reset by {=str} at PC {=u32:#010x}, LR {=u32:#010x}, after {=u32} resets since power-on, see `bedrock fault --last`",
+        record.exception_name(),
+        record.pc(),
+        record.lr(),
+        record.resets
+    );
+    log_causes(&record);
+    Some(record)
+}
+
+#[cfg(feature = "defmt")]
+pub(crate) fn log_causes(record: &FaultRecord) {
+    for cause in record.causes() {
+        defmt::error!("  {=str}", cause);
+    }
+    if let Some(address) = record.fault_address() {
+        defmt::error!("  fault address {=u32:#010x}", address);
+    }
+}
+
+// Entered from the vector table with LR holding EXC_RETURN, which tells the stack the frame is on. Only Armv6-M
+// instructions are used. `bl` is fine for the call, the handler does not return.
+//
+// The faulting stack cannot be trusted after a stack overflow: under flip-link SP is at the bottom of RAM, otherwise it
+// may point anywhere, and the Rust handler would fault again in its prologue and lock up the core. Unless SP is within
+// HANDLER_STACK below `_stack_start`, the handler runs on the top of the stack instead, reusing it: whatever was
+// there is lost, the exception frame is further down and stays intact. Within HANDLER_STACK the stack is not
+// overflowing, provided it is larger than that, and the handler runs on it as it is, not to overwrite the frame.
+#[cfg(cortex_m)]
+core::arch::global_asm!(
+    ".section .HardFault.bedrock_rt, \"ax\"",
+    ".global HardFault",
+    ".type HardFault, %function",
+    ".thumb_func",
+    "HardFault:",
+    "    mov r0, lr",
+    "    movs r1, #4",
+    "    tst r0, r1",
+    "    bne 1f",
+    "    mrs r1, MSP",
+    "    b 2f",
+    "1:",
+    "    mrs r1, PSP",
+    "2:",
+    "    ldr r2, =_stack_start",
+    "    subs r3, r2, r1",
+    "    movs r0, #{handler_stack_kib}",
+    "    lsls r0, r0, #10",
+    "    cmp r3, r0",
+    "    bls 3f",
+    "    mov sp, r2",
+    "3:",
+    "    mov r0, lr",
+    "    bl bedrock_rt_on_fault",
+    ".ltorg",
+    handler_stack_kib = const HANDLER_STACK / 1024,
+);
+
+/// Stack the fault handler needs, with a good margin
+#[cfg(cortex_m)]
+const HANDLER_STACK: u32 = 1024;
+
+/// Save the fault record and reset
+#[cfg(cortex_m)]
+#[unsafe(no_mangle)]
+unsafe extern "C" fn bedrock_rt_on_fault(exc_return: u32, sp: u32) -> ! {
+    let area = area();
+    // Safety: fault handlers run at a priority nothing else preempts, and reset without returning
+    unsafe {
+        let mut record = capture(exc_return, sp);
+        record.resets = if read_volatile(addr_of_mut!((*area).magic)) == AREA_MAGIC {
+            read_volatile(addr_of_mut!((*area).resets))
+        } else {
+            write_volatile(addr_of_mut!((*area).resets), 0);
+            write_volatile(addr_of_mut!((*area).magic), AREA_MAGIC);
+            0
+        };
+        write_volatile(addr_of_mut!((*area).record), record);
+        write_volatile(addr_of_mut!((*area).state), STATE_PENDING);
+    }
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+#[cfg(cortex_m)]
+unsafe fn capture(exc_return: u32, sp: u32) -> FaultRecord {
+    let mut record = FaultRecord {
+        resets: 0,
+        exception: unsafe { read_volatile(ICSR) } & 0x1FF,
+        exc_return,
+        frame_address: sp,
+        frame: [0; 8],
+        cfsr: 0,
+        hfsr: 0,
+        mmfar: 0,
+        bfar: 0,
+        flags: 0,
+        fw_sha: [0; 32],
+    };
+    #[cfg(not(any(armv6m, armv8m_base)))]
+    unsafe {
+        const CFSR: *const u32 = 0xE000_ED28 as *const u32;
+        const HFSR: *const u32 = 0xE000_ED2C as *const u32;
+        const MMFAR: *const u32 = 0xE000_ED34 as *const u32;
+        const BFAR: *const u32 = 0xE000_ED38 as *const u32;
+        record.cfsr = read_volatile(CFSR);
+        record.hfsr = read_volatile(HFSR);
+        record.mmfar = read_volatile(MMFAR);
+        record.bfar = read_volatile(BFAR);
+        record.flags |= FLAG_STATUS;
+    }
+    // Armv8-M: integrity signature and r4-r11 stacked before the frame when going from Secure to Non-secure state
+    if exc_return & (1 << 5) == 0 {
+        record.frame_address += 40;
+    }
+    if record.cfsr & CFSR_STACKING == 0 {
+        let frame = record.frame_address as *const u32;
+        for (n, word) in record.frame.iter_mut().enumerate() {
+            *word = unsafe { read_volatile(frame.add(n)) };
+        }
+        record.flags |= FLAG_FRAME;
+    }
+    record
+}
+
+/// SHA-256 from the vector table to the end of the `.data` initial values, the FLASH image as `cortex-m-rt` links it
+#[cfg(cortex_m)]
+fn fw_sha() -> [u8; 32] {
+    use sha2::{Digest, Sha256};
+    unsafe extern "C" {
+        static __vector_table: u32;
+        static __sidata: u32;
+        static __sdata: u32;
+        static __edata: u32;
+    }
+    let start = &raw const __vector_table as usize;
+    let data_len = &raw const __edata as usize - &raw const __sdata as usize;
+    let end = &raw const __sidata as usize + data_len;
+    // Safety: linker symbols of the image this code runs from
+    let image = unsafe { core::slice::from_raw_parts(start as *const u8, end - start) };
+    Sha256::digest(image).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(cfsr: u32, hfsr: u32) -> FaultRecord {
+        FaultRecord {
+            resets: 0,
+            exception: 3,
+            exc_return: 0xFFFF_FFF9,
+            frame_address: 0x2001_FFA0,
+            frame: [0; 8],
+            cfsr,
+            hfsr,
+            mmfar: 0x2000_0000,
+            bfar: 0x3000_0000,
+            flags: FLAG_FRAME | FLAG_STATUS,
+            fw_sha: [0; 32],
+        }
+    }
+
+    #[test]
+    fn causes_hardfault_first() {
+        let fault = record((1 << 25) | (1 << 15) | (1 << 9) | 1, 1 << 30);
+        assert!(
+            fault
+                .causes()
+                .eq(["FORCED", "IACCVIOL", "PRECISERR", "DIVBYZERO"])
+        );
+        assert_eq!(record(0, 0).causes().count(), 0);
+    }
+
+    #[test]
+    fn fault_address_only_when_valid() {
+        assert_eq!(record(1 << 1, 0).fault_address(), None);
+        assert_eq!(
+            record((1 << 9) | (1 << 15), 0).fault_address(),
+            Some(0x3000_0000)
+        );
+        assert_eq!(
+            record((1 << 1) | (1 << 7), 0).fault_address(),
+            Some(0x2000_0000)
+        );
+        // MMFAR first, a MemManage fault escalated to HardFault with a bus fault pending
+        assert_eq!(
+            record((1 << 7) | (1 << 15), 0).fault_address(),
+            Some(0x2000_0000)
+        );
+    }
+}
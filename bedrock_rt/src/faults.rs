@@ -0,0 +1,77 @@
+//! Configurable fault exceptions: MemManage, BusFault and UsageFault.
+//!
+//! After reset these are disabled and every fault escalates to HardFault, where only `FORCED` in HFSR says that it
+//! did. [enable] lets each of them be taken by its own handler, installed by this crate next to HardFault. The handlers
+//! save the fault record with the precise fault type and reset like HardFault does, see [crate::fault].
+//!
+//! Armv6-M and Armv8-M Baseline only have HardFault, there [enable] does nothing and no handlers are installed.
+
+#[cfg(all(cortex_m, not(any(armv6m, armv8m_base))))]
+use core::ptr::{read_volatile, write_volatile};
+
+/// Optional traps for operations that are not faults by default
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Traps {
+    /// UsageFault on integer division by zero, instead of a result of 0. Rust checks the divisor and panics before
+    /// dividing, so this only catches C and assembly code.
+    pub div_by_zero: bool,
+    /// UsageFault on unaligned word and halfword accesses. Code compiled for Armv7-M and Armv8-M Mainline may use them
+    /// for packed data and copies on purpose, enable only to find such accesses, e.g. to memory that does not allow them.
+    pub unaligned: bool,
+}
+
+/// Enable MemManage, BusFault and UsageFault, and the optional traps. Call early in `main`, faults before that go to
+/// HardFault. A configurable fault that happens while its handler cannot run, e.g. with interrupts disabled by a
+/// critical section, still escalates to HardFault.
+#[cfg(all(cortex_m, not(any(armv6m, armv8m_base))))]
+pub fn enable(traps: Traps) {
+    const CCR: *mut u32 = 0xE000_ED14 as *mut u32;
+    const SHCSR: *mut u32 = 0xE000_ED24 as *mut u32;
+    const UNALIGN_TRP: u32 = 1 << 3;
+    const DIV_0_TRP: u32 = 1 << 4;
+    const MEMFAULTENA: u32 = 1 << 16;
+    const BUSFAULTENA: u32 = 1 << 17;
+    const USGFAULTENA: u32 = 1 << 18;
+    // Safety: read-modify-write of System Control Block registers, from thread mode at boot
+    unsafe {
+        let mut ccr = read_volatile(CCR) & !(UNALIGN_TRP | DIV_0_TRP);
+        if traps.unaligned {
+            ccr |= UNALIGN_TRP;
+        }
+        if traps.div_by_zero {
+            ccr |= DIV_0_TRP;
+        }
+        write_volatile(CCR, ccr);
+        write_volatile(
+            SHCSR,
+            read_volatile(SHCSR) | MEMFAULTENA | BUSFAULTENA | USGFAULTENA,
+        );
+    }
+    cortex_m::asm::dsb();
+    cortex_m::asm::isb();
+}
+
+/// Does nothing, Armv6-M and Armv8-M Baseline only have HardFault
+#[cfg(not(all(cortex_m, not(any(armv6m, armv8m_base)))))]
+pub fn enable(_traps: Traps) {}
+
+// The handlers share the HardFault entry, which reads the active exception number from ICSR
+#[cfg(all(cortex_m, not(any(armv6m, armv8m_base))))]
+core::arch::global_asm!(
+    ".section .HardFault.bedrock_rt, \"ax\"",
+    ".global MemoryManagement",
+    ".type MemoryManagement, %function",
+    ".thumb_func",
+    "MemoryManagement:",
+    "    b HardFault",
+    ".global BusFault",
+    ".type BusFault, %function",
+    ".thumb_func",
+    "BusFault:",
+    "    b HardFault",
+    ".global UsageFault",
+    ".type UsageFault, %function",
+    ".thumb_func",
+    "UsageFault:",
+    "    b HardFault",
+);
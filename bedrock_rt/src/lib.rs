@@ -0,0 +1,11 @@
+//! Firmware side of embedded bedrock.
+//!
+//! [fault]: HardFault, MemManage, BusFault and UsageFault handlers that save a fault record in RAM that survives the
+//! reset they do, to be reported on the next boot and read with `bedrock fault --last`.
+//!
+//! [faults]: enable MemManage, BusFault and UsageFault to have them handled separately instead of as HardFault.
+
+#![no_std]
+
+pub mod fault;
+pub mod faults;
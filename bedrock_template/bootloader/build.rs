@@ -2,6 +2,9 @@ use std::env;
 use std::fs;
 use std::fs::File;
 use std::io::Write;
+{% if signed_updates -%}
+use std::path::Path;
+{% endif -%}
 use std::path::PathBuf;
 
 fn main() {
@@ -26,10 +29,46 @@ fn main() {
         println!("cargo:rustc-link-arg-bins=-Tdefmt.x");
     }
     
+    // Direct dependencies only. For a complete `bedrock sbom`, collect the whole tree with
+    // `DependencyDepth::Full` and keep dependency authors with
+    // `bedrock_build::BuildInfoConfig::new().field(BuildInfoField::DependencyAuthors, FieldPolicy::Full)`.
     let info = build_info_build::build_script()
         .collect_dependencies(build_info_build::DependencyDepth::Depth(0))
         .build();
-    let info = bedrock_build::serialize_build_info(info);
-    let info_file_path = out.join("build_info.rs");
-    fs::write(info_file_path, info).unwrap();
-}
\ No newline at end of file
+    match bedrock_build::serialize_build_info(info) {
+        Ok(info) => fs::write(out.join("build_info.rs"), info).unwrap(),
+        Err(e) => e.report(),
+    }
+    {%- if signed_updates %}
+
+    // Updates are checked against this key, the build fails without it: a bootloader with a made up key
+    // would erase every update
+    let key_path = Path::new(&env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("../public.key");
+    println!("cargo:rerun-if-changed={}", key_path.display());
+    match public_key(&key_path) {
+        Ok(key) => fs::write(
+            out.join("public_key.rs"),
+            format!("const PUBLIC_KEY: [u8; 32] = {key:?};\n"),
+        )
+        .unwrap(),
+        Err(e) => println!("cargo::error={}: {e}", key_path.display()),
+    }
+    {%- endif %}
+}
+{%- if signed_updates %}
+
+/// Key file of `bedrock keygen`, 32 bytes in hex
+fn public_key(path: &Path) -> Result<[u8; 32], String> {
+    let hex = fs::read_to_string(path).map_err(|e| {
+        format!("{e}, create the update signing keys with `bedrock keygen secret.key public.key` in the project directory")
+    })?;
+    let hex = hex.trim();
+    let bytes: Option<Vec<u8>> = (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect();
+    bytes
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| "expected 32 bytes in hex, as written by `bedrock keygen`".into())
+}
+{%- endif %}
\ No newline at end of file
@@ -11,6 +11,11 @@ use defmt_rtt as _;
 use embassy_boot_stm32::*;
 use embassy_stm32::Config;
 use embassy_stm32::flash::{Flash, BANK1_REGION};
+{% if signed_updates -%}
+use bedrock_build_info::signature;
+use embassy_stm32::flash::WRITE_SIZE;
+use embedded_storage::nor_flash::NorFlash;
+{% endif -%}
 use embassy_sync::blocking_mutex::Mutex;
 {% if supply_config != "" %}
 use embassy_stm32::rcc::SupplyConfig;
@@ -46,13 +51,37 @@ fn main() -> ! {
     let layout = Flash::new_blocking(p.FLASH).into_blocking_regions();
     let flash = Mutex::new(RefCell::new(layout.bank1_region));
 
-    let config = BootLoaderConfig::from_linkerfile_blocking(&flash, &flash, &flash);
+    let {% if signed_updates %}mut {% endif %}config = BootLoaderConfig::from_linkerfile_blocking(&flash, &flash, &flash);
+    {% if signed_updates -%}
+    // Only swap in updates signed with `bedrock sign`, the application is built with
+    // BuildInfoConfig::signature_slot(true). An update that is not is erased and the active firmware booted as it is.
+    let mut aligned = [0; WRITE_SIZE];
+    let mut state = BlockingFirmwareState::new(&mut config.state, &mut aligned);
+    if matches!(state.get_state(), Ok(State::Swap)) {
+        let dfu = unsafe {
+            core::slice::from_raw_parts(
+                (BANK1_REGION.base + config.dfu.offset()) as *const u8,
+                config.dfu.size() as usize,
+            )
+        };
+        if signature::verify_image(dfu, &PUBLIC_KEY).is_err() {
+            let dfu_size = config.dfu.size();
+            _ = config.dfu.erase(0, dfu_size);
+            _ = state.mark_booted();
+        }
+    }
+    {% endif -%}
     let active_offset = config.active.offset();
     let bl = BootLoader::prepare::<_, _, _, 2048>(config);
 
     unsafe { bl.load(BANK1_REGION.base + active_offset) }
 }
 
+{% if signed_updates -%}
+// `PUBLIC_KEY` from `public.key` of `bedrock keygen`, the secret key signs updates with `bedrock sign`
+include!(concat!(env!("OUT_DIR"), "/public_key.rs"));
+
+{% endif -%}
 // fn blink(led: &mut Output, n: u32) {
 //     for _ in 0..n {
 //         led.set_low();
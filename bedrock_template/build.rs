@@ -9,20 +9,31 @@ fn main() {
     println!("cargo:rustc-link-search={}", out.display());
     println!("cargo:rerun-if-changed=memory.x");
 
-    {% if use_flip_link -%}
-    println!("cargo:rustc-linker=flip-link");
-    {% endif -%}
-    
     {% if use_counters -%}
     println!("cargo:rustc-link-arg=-Tcnt.x");
     {% endif -%}
 
     bedrock_build::common();
 
+    // Direct dependencies only. For a complete `bedrock sbom`, collect the whole tree with
+    // `DependencyDepth::Full` and keep dependency authors with
+    // `bedrock_build::BuildInfoConfig::new().field(BuildInfoField::DependencyAuthors, FieldPolicy::Full)`.
     let info = build_info_build::build_script()
         .collect_dependencies(build_info_build::DependencyDepth::Depth(0))
         .build();
-    let info = bedrock_build::serialize_build_info(info);
-    let info_file_path = out.join("build_info.rs");
-    fs::write(info_file_path, info).unwrap();
+    // Product specific facts can be added next to the crate and compiler info, for example:
+    // let extra = bedrock_build::ExtraFields::new()
+    //     .add("hw_rev", "A", bedrock_build::Placement::Both)
+    //     .add("product_id", 0x0001_u32, bedrock_build::Placement::Compact);
+    // and then use bedrock_build::serialize_build_info_with_extra(info, extra) below.
+    {% if signed_updates -%}
+    // empty signature record for `bedrock sign`, the bootloader only swaps in signed updates
+    let config = bedrock_build::BuildInfoConfig::new().signature_slot(true);
+    match bedrock_build::serialize_build_info_with_config(info, config) {
+    {% else -%}
+    match bedrock_build::serialize_build_info(info) {
+    {% endif -%}
+        Ok(info) => fs::write(out.join("build_info.rs"), info).unwrap(),
+        Err(e) => e.report(),
+    }
 }
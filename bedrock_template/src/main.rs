@@ -42,6 +42,15 @@ async fn main(_spawner: embassy_executor::Spawner) {
     init_ram::init_ram();
     {% endif -%}
     info!("{{project-name}} starting...");
+    {% if use_bkp_counters -%}
+    if bedrock_rt::fault::report_last().is_some() {
+        bkp_cnt_if!(true, hard_faults: u32);
+    }
+    {% else -%}
+    bedrock_rt::fault::report_last(); // logs the fault that caused the last reset, if any
+    {% endif -%}
+    // MemManage, BusFault and UsageFault handled separately instead of escalating to HardFault, no-op on Cortex-M0
+    bedrock_rt::faults::enable(bedrock_rt::faults::Traps::default());
     {% if supply_config != "" -%}
     let mut config = embassy_stm32::Config::default();
     {% if smps_supply_voltage == "" -%}
@@ -60,6 +69,10 @@ async fn main(_spawner: embassy_executor::Spawner) {
     info!("RCC and RAM init done");
     _ = core::hint::black_box(build_info::compact()); // ensure compact build info is in FLASH
     _ = core::hint::black_box(build_info::full()); // ensure full build info is in ELF
+    match bedrock_build_info::BedrockBuildInfo::from_compact(build_info::compact()) {
+        Ok(info) => info!("{}", info),
+        Err(_) => warn!("Failed to decode compact build info"),
+    }
 
     {% if chip contains "stm32h7" %}
     let mut cp = cortex_m::Peripherals::take().unwrap();
@@ -105,16 +118,6 @@ unsafe fn DefaultHandler(irqn: i16) {
     error!("Unhandled exception (IRQn = {})", irqn);
 }
 
-#[exception]
-unsafe fn HardFault(ef: &cortex_m_rt::ExceptionFrame) -> ! {
-    {% if use_bkp_counters -%}
-    bkp_cnt_if!(true, hard_faults: u32);
-    {% endif -%}
-    error!("HardFault {}", defmt::Debug2Format(ef));
-
-    loop {}
-}
-
 //NonMaskableInt (CSS?)
 // NOTE that at this point we don't check if the exception is available on the target (e.g.
 // MemoryManagement is not available on Cortex-M0)
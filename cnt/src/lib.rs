@@ -53,24 +53,40 @@ fn counters_bkp_buffer_mut() -> &'static mut [u32] {
 }
 
 // TODO: Use atomics? and if not available - critical section
+/// Called from code generated by `cnt_if!`/`bkp_cnt_if!`, not meant to be used directly.
+///
+/// # Safety
+/// Counter indices must be the ones assigned by the macros, and increments must not race with each other.
 #[inline(always)]
 pub unsafe fn increment_u32_ram(counter_idx: usize) {
     let buffer = counters_ram_buffer_mut();
     buffer[counter_idx] = buffer[counter_idx].saturating_add(1);
 }
 
+/// Called from code generated by `cnt_if!`/`bkp_cnt_if!`, not meant to be used directly.
+///
+/// # Safety
+/// Counter indices must be the ones assigned by the macros, and increments must not race with each other.
 #[inline(always)]
 pub unsafe fn increment_u32_bkp(counter_idx: usize) {
     let buffer = counters_bkp_buffer_mut();
     buffer[counter_idx] = buffer[counter_idx].saturating_add(1);
 }
 
+/// Called from code generated by `cnt_if!`/`bkp_cnt_if!`, not meant to be used directly.
+///
+/// # Safety
+/// Counter indices must be the ones assigned by the macros, and increments must not race with each other.
 #[inline(always)]
 pub unsafe fn increment_u64_ram(counter_idx_lo: usize, counter_idx_hi: usize) {
     let buffer = counters_ram_buffer_mut();
     increment_u64_inner(buffer, counter_idx_lo, counter_idx_hi);
 }
 
+/// Called from code generated by `cnt_if!`/`bkp_cnt_if!`, not meant to be used directly.
+///
+/// # Safety
+/// Counter indices must be the ones assigned by the macros, and increments must not race with each other.
 #[inline(always)]
 pub unsafe fn increment_u64_bkp(counter_idx_lo: usize, counter_idx_hi: usize) {
     let buffer = counters_bkp_buffer_mut();
@@ -5,11 +5,11 @@ use quote::quote;
 use syn::parse2;
 
 pub(crate) fn cnt_if(args: TokenStream) -> syn::Result<TokenStream> {
-    inner(args, CounterKind::RAM)
+    inner(args, CounterKind::Ram)
 }
 
 pub(crate) fn bkp_cnt_if(args: TokenStream) -> syn::Result<TokenStream> {
-    inner(args, CounterKind::BKP)
+    inner(args, CounterKind::Bkp)
 }
 
 fn inner(args: TokenStream, counter_kind: CounterKind) -> syn::Result<TokenStream> {
@@ -23,11 +23,11 @@ fn inner(args: TokenStream, counter_kind: CounterKind) -> syn::Result<TokenStrea
         ));
     }
     let ram_or_bkp = match counter_kind {
-        CounterKind::RAM => "ram",
-        CounterKind::BKP => "bkp",
+        CounterKind::Ram => "ram",
+        CounterKind::Bkp => "bkp",
     };
     let increment_fn = Ident::new(
-        format!("increment_{}_{ram_or_bkp}", input.ty.to_string()).as_str(),
+        format!("increment_{}_{ram_or_bkp}", input.ty).as_str(),
         Span::call_site(),
     );
     let tokens = match input.ty.to_string().as_str() {
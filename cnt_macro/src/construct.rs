@@ -35,16 +35,16 @@ pub(crate) fn linker_section(
     }
 
     let section = match kind {
-        CounterKind::RAM => "cnt_ram",
-        CounterKind::BKP => "cnt_bkp",
+        CounterKind::Ram => "cnt_ram",
+        CounterKind::Bkp => "cnt_bkp",
     };
     format!(".{section}{sub_section}")
 }
 
 #[derive(Copy, Clone)]
 pub enum CounterKind {
-    RAM,
-    BKP,
+    Ram,
+    Bkp,
 }
 
 // impl Into<&str> for CounterKind {
@@ -60,8 +60,8 @@ pub enum CounterKind {
 impl CounterKind {
     fn tag(&self) -> &'static str {
         match self {
-            CounterKind::RAM => "cnt_ram",
-            CounterKind::BKP => "cnt_bkp",
+            CounterKind::Ram => "cnt_ram",
+            CounterKind::Bkp => "cnt_bkp",
         }
     }
 }
@@ -12,7 +12,7 @@ mod symbol;
 /// the tool, then you can check manually, for example, by inspecting cargo nm output.
 ///
 /// Example:
-/// ```
+/// ```ignore
 /// use cnt_macro::cnt_if;
 ///
 /// cnt_if!(true, blink_count: u32); // increment unconditionally
@@ -39,7 +39,7 @@ pub fn cnt_if(args: TokenStream) -> TokenStream {
 /// the tool, then you can check manually, for example, by inspecting cargo nm output.
 ///
 /// Example:
-/// ```
+/// ```ignore
 /// use cnt_macro::bkp_cnt_if;
 ///
 /// bkp_cnt_if!(true, hard_fault_count_total: u32); // increment unconditionally
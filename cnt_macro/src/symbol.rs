@@ -33,7 +33,7 @@ impl<'a> Symbol<'a> {
             // `CARGO_PKG_NAME` is set to the invoking package's name.
             package: env::var("CARGO_PKG_NAME").unwrap_or_else(|_| "<unknown>".to_string()),
             disambiguator: crate::construct::crate_local_disambiguator(),
-            tag: format!("{}", tag),
+            tag: tag.to_string(),
             data,
             crate_name: env::var("CARGO_CRATE_NAME").unwrap_or_else(|_| "<unknown>".to_string()),
         }
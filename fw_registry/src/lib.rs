@@ -0,0 +1,160 @@
+//! Local firmware registry: ELF files of built firmware keyed by firmware SHA, so that host tools
+//! can decode defmt logs and build info of a device without having the right build at hand.
+//!
+//! Layout: `<root>/<sha in hex>/firmware.elf` and `build_info.ww` next to it (full build info, if known).
+//! SHA is the SHA-256 of the FLASH image, as computed by `bedrock`.
+
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+const ELF_FILE_NAME: &str = "firmware.elf";
+const BUILD_INFO_FILE_NAME: &str = "build_info.ww";
+
+pub struct Registry {
+    root: PathBuf,
+}
+
+/// Firmware stored in the registry
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub sha: String,
+    pub dir: PathBuf,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    NotFound(String),
+    /// SHA prefix matches several entries
+    Ambiguous(String, Vec<String>),
+    Io(io::Error),
+}
+
+impl Registry {
+    pub fn open(root: impl Into<PathBuf>) -> Self {
+        Registry { root: root.into() }
+    }
+
+    /// `BEDROCK_REGISTRY` env variable if set, `~/.bedrock/registry` otherwise
+    pub fn open_default() -> Self {
+        let root = match env::var_os("BEDROCK_REGISTRY") {
+            Some(root) => PathBuf::from(root),
+            None => PathBuf::from(env::var_os("HOME").unwrap_or_default())
+                .join(".bedrock")
+                .join("registry"),
+        };
+        Self::open(root)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Store firmware, replacing an existing entry with the same SHA.
+    ///
+    /// Files are written under temporary names first, so that a concurrent `find` never sees half written ELF.
+    pub fn push(&self, sha: &str, elf: &[u8], build_info: Option<&[u8]>) -> Result<Entry, Error> {
+        let sha = sha.to_ascii_lowercase();
+        let entry = Entry {
+            dir: self.root.join(&sha),
+            sha,
+        };
+        fs::create_dir_all(&entry.dir).map_err(Error::Io)?;
+        if let Some(build_info) = build_info {
+            write_replace(&entry.build_info_path(), build_info)?;
+        }
+        write_replace(&entry.elf_path(), elf)?;
+        Ok(entry)
+    }
+
+    /// Find firmware by full SHA or an unambiguous prefix of it (like git short IDs)
+    pub fn find(&self, sha_prefix: &str) -> Result<Entry, Error> {
+        let sha_prefix = sha_prefix.to_ascii_lowercase();
+        let mut matches: Vec<_> = self
+            .entries()?
+            .into_iter()
+            .filter(|entry| entry.sha.starts_with(&sha_prefix))
+            .collect();
+        match matches.len() {
+            0 => Err(Error::NotFound(sha_prefix)),
+            1 => Ok(matches.remove(0)),
+            _ => Err(Error::Ambiguous(
+                sha_prefix,
+                matches.into_iter().map(|m| m.sha).collect(),
+            )),
+        }
+    }
+
+    /// All stored firmware, in no particular order. Empty if the registry was never written to.
+    pub fn entries(&self) -> Result<Vec<Entry>, Error> {
+        let dir = match fs::read_dir(&self.root) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(Error::Io(e)),
+        };
+        let mut entries = Vec::new();
+        for entry in dir {
+            let entry = entry.map_err(Error::Io)?;
+            if entry.path().join(ELF_FILE_NAME).exists() {
+                entries.push(Entry {
+                    sha: entry.file_name().to_string_lossy().to_string(),
+                    dir: entry.path(),
+                });
+            }
+        }
+        Ok(entries)
+    }
+}
+
+impl Entry {
+    pub fn elf_path(&self) -> PathBuf {
+        self.dir.join(ELF_FILE_NAME)
+    }
+
+    /// Full build info serialized with wire_weaver, it is not there for firmware built without bedrock_build
+    pub fn build_info_path(&self) -> PathBuf {
+        self.dir.join(BUILD_INFO_FILE_NAME)
+    }
+}
+
+fn write_replace(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents).map_err(Error::Io)?;
+    fs::rename(&tmp, path).map_err(Error::Io)
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::NotFound(sha) => write!(f, "firmware {sha} is not in the registry"),
+            Error::Ambiguous(sha, matches) => {
+                write!(f, "firmware SHA prefix {sha} is ambiguous: {}", matches.join(", "))
+            }
+            Error::Io(e) => write!(f, "registry I/O error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_and_find() {
+        let root = env::temp_dir().join(format!("fw_registry_test_{}", std::process::id()));
+        let registry = Registry::open(&root);
+        let sha = "ab12cd34ef56";
+        registry.push(sha, b"\x7fELF", Some(b"info")).unwrap();
+        registry.push("ab99", b"\x7fELF", None).unwrap();
+
+        let entry = registry.find("AB12").unwrap();
+        assert_eq!(entry.sha, sha);
+        assert_eq!(fs::read(entry.build_info_path()).unwrap(), b"info");
+        assert!(matches!(registry.find("ab"), Err(Error::Ambiguous(..))));
+        assert!(matches!(registry.find("ff"), Err(Error::NotFound(_))));
+        assert_eq!(registry.entries().unwrap().len(), 2);
+        fs::remove_dir_all(root).unwrap();
+    }
+}